@@ -0,0 +1,226 @@
+// src/category.rs
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// coarse content type of a file, derived from its extension
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Images,
+    Video,
+    Audio,
+    Documents,
+    Archives,
+    Code,
+    Other,
+}
+
+impl Category {
+    /// label shown in the GUI summary
+    pub fn label(self) -> &'static str {
+        match self {
+            Category::Images => "Images",
+            Category::Video => "Video",
+            Category::Audio => "Audio",
+            Category::Documents => "Documents",
+            Category::Archives => "Archives",
+            Category::Code => "Code",
+            Category::Other => "Other",
+        }
+    }
+}
+
+/// multi-part extensions, checked against the end of the file name before
+/// the plain extension (so "x.tar.gz" is an archive, not just "gz")
+/// - also how extension_count groups them, see compound_extension
+const COMPOUND_EXTENSIONS: &[(&str, Category)] = &[
+    (".tar.gz", Category::Archives),
+    (".tar.bz2", Category::Archives),
+    (".tar.xz", Category::Archives),
+    (".tar.zst", Category::Archives),
+    (".d.ts", Category::Code),
+    (".min.js", Category::Code),
+];
+
+/// the compound suffix `name` (already lowercase) ends with, if any
+/// - the name must have something before the suffix: "tar.gz" alone is not one
+fn compound_suffix(name: &str) -> Option<&'static (&'static str, Category)> {
+    COMPOUND_EXTENSIONS
+        .iter()
+        .find(|(suffix, _)| name.len() > suffix.len() && name.ends_with(suffix))
+}
+
+/// compound extension of a file, lowercase and without the leading dot
+/// ("Backup.TAR.GZ" -> "tar.gz"), or None for plain extensions
+pub fn compound_extension(path: &Path) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    compound_suffix(&name).map(|(suffix, _)| &suffix[1..])
+}
+
+/// extension (lowercase, no dot) -> category; anything missing is Other
+const EXTENSIONS: &[(&str, Category)] = &[
+    // images
+    ("jpg", Category::Images),
+    ("jpeg", Category::Images),
+    ("png", Category::Images),
+    ("gif", Category::Images),
+    ("bmp", Category::Images),
+    ("webp", Category::Images),
+    ("tif", Category::Images),
+    ("tiff", Category::Images),
+    ("svg", Category::Images),
+    ("heic", Category::Images),
+    ("raw", Category::Images),
+    ("cr2", Category::Images),
+    ("nef", Category::Images),
+    ("ico", Category::Images),
+    ("psd", Category::Images),
+    // video
+    ("mp4", Category::Video),
+    ("mkv", Category::Video),
+    ("avi", Category::Video),
+    ("mov", Category::Video),
+    ("wmv", Category::Video),
+    ("flv", Category::Video),
+    ("webm", Category::Video),
+    ("m4v", Category::Video),
+    ("mpg", Category::Video),
+    ("mpeg", Category::Video),
+    ("3gp", Category::Video),
+    // not "ts"/"mts": those go to TypeScript, far more common than MPEG
+    // transport streams in the trees this tool is pointed at
+    ("m2ts", Category::Video),
+    // audio
+    ("mp3", Category::Audio),
+    ("wav", Category::Audio),
+    ("flac", Category::Audio),
+    ("aac", Category::Audio),
+    ("ogg", Category::Audio),
+    ("opus", Category::Audio),
+    ("m4a", Category::Audio),
+    ("wma", Category::Audio),
+    ("aiff", Category::Audio),
+    // documents
+    ("pdf", Category::Documents),
+    ("doc", Category::Documents),
+    ("docx", Category::Documents),
+    ("xls", Category::Documents),
+    ("xlsx", Category::Documents),
+    ("ppt", Category::Documents),
+    ("pptx", Category::Documents),
+    ("odt", Category::Documents),
+    ("ods", Category::Documents),
+    ("odp", Category::Documents),
+    ("rtf", Category::Documents),
+    ("txt", Category::Documents),
+    ("md", Category::Documents),
+    ("csv", Category::Documents),
+    ("epub", Category::Documents),
+    // archives
+    ("zip", Category::Archives),
+    ("rar", Category::Archives),
+    ("7z", Category::Archives),
+    ("tar", Category::Archives),
+    ("gz", Category::Archives),
+    ("tgz", Category::Archives),
+    ("bz2", Category::Archives),
+    ("xz", Category::Archives),
+    ("zst", Category::Archives),
+    ("iso", Category::Archives),
+    ("dmg", Category::Archives),
+    ("deb", Category::Archives),
+    ("rpm", Category::Archives),
+    // code
+    ("rs", Category::Code),
+    ("c", Category::Code),
+    ("h", Category::Code),
+    ("cpp", Category::Code),
+    ("hpp", Category::Code),
+    ("cc", Category::Code),
+    ("py", Category::Code),
+    ("js", Category::Code),
+    ("jsx", Category::Code),
+    ("ts", Category::Code),
+    ("tsx", Category::Code),
+    ("mts", Category::Code),
+    ("cts", Category::Code),
+    ("java", Category::Code),
+    ("kt", Category::Code),
+    ("go", Category::Code),
+    ("rb", Category::Code),
+    ("php", Category::Code),
+    ("cs", Category::Code),
+    ("swift", Category::Code),
+    ("sh", Category::Code),
+    ("html", Category::Code),
+    ("css", Category::Code),
+    ("json", Category::Code),
+    ("toml", Category::Code),
+    ("yaml", Category::Code),
+    ("yml", Category::Code),
+    ("xml", Category::Code),
+    ("sql", Category::Code),
+];
+
+/// category of a file by name, case-insensitive
+/// - compound extensions (".tar.gz") win over the last extension
+/// - no extension or an unlisted one -> Other
+pub fn categorize(path: &Path) -> Category {
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(n) => n.to_lowercase(),
+        None => return Category::Other,
+    };
+
+    if let Some((_, cat)) = compound_suffix(&name) {
+        return *cat;
+    }
+
+    let ext = match Path::new(&name).extension().and_then(|e| e.to_str()) {
+        Some(e) => e,
+        None => return Category::Other,
+    };
+    EXTENSIONS
+        .iter()
+        .find(|(e, _)| *e == ext)
+        .map(|(_, cat)| *cat)
+        .unwrap_or(Category::Other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn category(name: &str) -> Category {
+        categorize(Path::new(name))
+    }
+
+    #[test]
+    fn categories_ignore_case() {
+        assert_eq!(category("IMG_0001.JPG"), Category::Images);
+        assert_eq!(category("Clip.Mkv"), Category::Video);
+        assert_eq!(category("notes.TXT"), Category::Documents);
+        assert_eq!(category("Makefile"), Category::Other);
+        assert_eq!(category("x.unknown"), Category::Other);
+    }
+
+    #[test]
+    fn compound_extensions_win_over_the_last_one() {
+        assert_eq!(category("x.TAR.GZ"), Category::Archives);
+        assert_eq!(compound_extension(Path::new("x.TAR.GZ")), Some("tar.gz"));
+        assert_eq!(category("app.min.js"), Category::Code);
+        assert_eq!(category("index.d.ts"), Category::Code);
+        assert_eq!(compound_extension(Path::new("index.d.ts")), Some("d.ts"));
+        // a bare suffix is an ordinary name with one extension
+        assert_eq!(compound_extension(Path::new("tar.gz")), None);
+        assert_eq!(category("tar.gz"), Category::Archives);
+        assert_eq!(compound_extension(Path::new("x.gz")), None);
+    }
+
+    #[test]
+    fn typescript_is_code() {
+        for name in ["foo.ts", "index.d.ts", "mod.mts", "mod.cts", "App.tsx"] {
+            assert_eq!(category(name), Category::Code, "{}", name);
+        }
+        assert_eq!(category("concert.m2ts"), Category::Video);
+    }
+}
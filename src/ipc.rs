@@ -1,23 +1,3345 @@
 // src/ipc.rs
-use crate::scan::FolderStats;
-use serde_json;
-use std::process::Command;
-
-/// Spawn worker process (same exe) with args: --worker <path> <min_bytes>
-/// Returns parsed FolderStats or error message
-pub fn run_worker_scan(exe_path: &std::path::PathBuf, folder: &str, min_bytes: u64) -> Result<FolderStats, String> {
-    let output = Command::new(exe_path)
-        .arg("--worker")
-        .arg(folder)
-        .arg(min_bytes.to_string())
-        .output()
-        .map_err(|e| format!("failed to spawn worker: {}", e))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("worker failed: {}", stderr.trim()));
-    }
-
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    serde_json::from_str::<FolderStats>(&stdout).map_err(|e| format!("invalid JSON from worker: {}", e))
+use crate::scan::{
+    self, CancellationToken, FOLDER_STATS_SCHEMA, FolderStats, ScanControl, ScanError,
+    ScanErrorKind, ScanOptions, ScanProgress, StopReason,
+};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// how long cancel() gives the worker after SIGTERM before SIGKILL
+const CANCEL_GRACE: Duration = Duration::from_secs(3);
+
+/// how long WorkerPool::terminate_all waits after SIGTERM, and again after
+/// SIGKILL; short, as the application is quitting meanwhile
+const EXIT_GRACE: Duration = Duration::from_secs(1);
+
+/// longest a streaming worker goes without a stdout line: it sends a
+/// WorkerEvent::Heartbeat when it has nothing else to say
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// scans a WorkerPool runs at once by default: how many the disks take
+/// without slowing each other down can't be known, so keep it small
+pub const DEFAULT_POOL_SIZE: usize = 2;
+
+/// environment variable naming the worker executable to run instead of this
+/// program (a debug build); see worker_exe
+/// - inside a Flatpak sandbox it names the host-side worker instead, by
+///   its host path (SpawnStrategy::choose)
+pub const WORKER_PATH_ENV: &str = "FSCAN_WORKER_PATH";
+
+/// what an elevated worker (ScanRequest::elevated) is started through
+const PKEXEC: &str = "pkexec";
+
+/// what a host-side worker (SpawnStrategy::FlatpakHost) is started through
+const FLATPAK_SPAWN: &str = "flatpak-spawn";
+
+/// top-level directories a Flatpak sandbox has of its own: the host's ones
+/// of the same name are hidden or different, so no path below them can be
+/// handed to the host as is
+const SANDBOX_ONLY_DIRS: [&str; 13] = [
+    "bin", "dev", "etc", "lib", "lib32", "lib64", "proc", "root", "run", "sbin", "sys", "tmp",
+    "var",
+];
+
+/// pkexec's exit code when the password dialog was dismissed; 127 when
+/// authorization failed otherwise (IpcError::ElevationFailed)
+pub const PKEXEC_DISMISSED: i32 = 126;
+const PKEXEC_NOT_AUTHORIZED: i32 = 127;
+
+/// version of the GUI <-> worker protocol: the ScanRequest on stdin and
+/// the "protocol_version" of every JSON document the worker prints
+/// - bump it whenever either side changes shape
+/// - 0 = a worker from before the field existed
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// what `--worker-json` reads from stdin: one JSON document, then EOF
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScanRequest {
+    /// PROTOCOL_VERSION of the sender; a worker refuses newer ones
+    pub version: u32,
+    pub options: ScanOptions,
+    /// run the worker as root through pkexec (WorkerPool only); never sent
+    /// or read back, so only the caller can ask for it
+    #[serde(skip)]
+    pub elevated: bool,
+}
+
+impl ScanRequest {
+    pub fn new(options: ScanOptions) -> Self {
+        ScanRequest {
+            version: PROTOCOL_VERSION,
+            options,
+            elevated: false,
+        }
+    }
+
+    /// this request with administrator rights: a worker of its own, started
+    /// through pkexec, which asks for the password (see WorkerPool)
+    pub fn elevated(mut self) -> Self {
+        self.elevated = true;
+        self
+    }
+}
+
+/// a worker JSON document: `body`'s fields plus "protocol_version"
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Versioned<T> {
+    #[serde(default)]
+    pub protocol_version: u32,
+    #[serde(flatten)]
+    pub body: T,
+}
+
+impl<T> Versioned<T> {
+    pub fn new(body: T) -> Self {
+        Versioned {
+            protocol_version: PROTOCOL_VERSION,
+            body,
+        }
+    }
+}
+
+/// why a worker run gave no result
+/// - the worker's exit code (see run_worker in main.rs) is mapped back by
+///   validate_worker_success: 1 Rejected, 4 Cancelled, 3/5/6/7 ScanFailed,
+///   anything else (2 = serialization, a signal) WorkerFailed
+#[derive(Debug, thiserror::Error)]
+pub enum IpcError {
+    /// the worker executable couldn't be started
+    #[error("failed to spawn worker: {0}")]
+    SpawnFailed(#[source] io::Error),
+    /// WORKER_PATH_ENV names no usable executable
+    #[error("{}={}: {}", WORKER_PATH_ENV, path.display(), reason)]
+    InvalidWorkerPath { path: PathBuf, reason: String },
+    /// writing the request to, reading from or reaping a running worker
+    #[error("failed to talk to worker: {0}")]
+    Io(#[source] io::Error),
+    /// the worker refused its arguments or request (exit code 1)
+    #[error("worker rejected the request: {0}")]
+    Rejected(String),
+    /// the scan ran and failed; `message` is the worker's own (English)
+    /// - `path` and `detail` as in ScanError::path / detail, from the
+    ///   worker's error line; None from a worker that printed none
+    #[error("{message} [{}]", kind.name())]
+    ScanFailed {
+        kind: ScanErrorKind,
+        message: String,
+        path: Option<String>,
+        detail: Option<String>,
+    },
+    /// any other failed exit: `exit_code` None and `signal` Some when it was
+    /// killed (e.g. 9 by the OOM killer); `retried` = this is how the retry
+    /// of a crashed run ended too (RetryPolicy)
+    #[error("{}", worker_failed_text(*exit_code, *signal, *retried, stderr))]
+    WorkerFailed {
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+        stderr: String,
+        retried: bool,
+    },
+    /// exited successfully without printing a result
+    #[error("worker exited without a result")]
+    NoResult,
+    #[error("invalid JSON from worker: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    #[error("invalid MessagePack from worker: {0}")]
+    InvalidMessagePack(#[from] rmp_serde::decode::Error),
+    /// the worker's document breaks off or isn't JSON/MessagePack at all,
+    /// `offset` bytes into it (after --compress is undone); InvalidJson /
+    /// InvalidMessagePack are for one that parses but doesn't fit
+    #[error("malformed worker output at byte {offset}: {message}")]
+    Malformed { offset: u64, message: String },
+    /// stopped through WorkerCancel, or the worker was sent SIGTERM, and
+    /// nothing of the scan came back; otherwise the answer is Ok, with
+    /// FolderStats::partial set
+    #[error("scan cancelled")]
+    Cancelled,
+    /// no result within this long; the worker was stopped, and nothing of
+    /// the scan came back (see Cancelled)
+    #[error("no result from worker after {0:?}")]
+    TimedOut(Duration),
+    /// the worker binary is from another build (replaced while the GUI ran)
+    #[error("worker speaks protocol version {theirs}, this build {ours}")]
+    VersionMismatch { ours: u32, theirs: u32 },
+    /// the result's FolderStats is from a newer build (schema_version); its
+    /// new fields would be lost, so it isn't used (see check_schema)
+    #[error("worker result has FolderStats schema {theirs}, this build reads up to {ours}")]
+    SchemaTooNew { ours: u32, theirs: u32 },
+    /// the elevated worker (ScanRequest::elevated) never ran: pkexec exited
+    /// with PKEXEC_DISMISSED, or 127 (not authorized, no polkit agent);
+    /// `stderr` is what pkexec printed
+    #[error("{}", elevation_failed_text(*exit_code, stderr))]
+    ElevationFailed { exit_code: i32, stderr: String },
+    /// the elevated worker wasn't started: someone other than root could
+    /// swap it for another program (elevation_refusal)
+    #[error("not scanning as administrator: {0}")]
+    ElevationRefused(String),
+}
+
+fn worker_failed_text(
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+    retried: bool,
+    stderr: &str,
+) -> String {
+    let how = match (exit_code, signal) {
+        (Some(code), _) => format!("exit code {}", code),
+        (None, Some(signal)) => format!("killed by signal {}", signal),
+        (None, None) => "killed by a signal".to_string(),
+    };
+    let retry = if retried { ", also after a retry" } else { "" };
+    match stderr {
+        "" => format!("worker failed ({}{})", how, retry),
+        _ => format!("worker failed ({}{}): {}", how, retry, stderr),
+    }
+}
+
+fn elevation_failed_text(exit_code: i32, stderr: &str) -> String {
+    let what = match exit_code {
+        PKEXEC_DISMISSED => "administrator authentication was cancelled".to_string(),
+        _ => format!("not allowed to scan as administrator (pkexec exit code {})", exit_code),
+    };
+    match stderr {
+        "" => what,
+        _ => format!("{}: {}", what, stderr),
+    }
+}
+
+impl IpcError {
+    /// the worker died on its own without an answer: a signal nobody here
+    /// sent, or an exit code the worker never uses (2 is a deterministic
+    /// serialization failure, not a crash)
+    fn is_crash(&self) -> bool {
+        matches!(self, IpcError::WorkerFailed { exit_code, .. } if *exit_code != Some(2))
+    }
+
+    /// `err` as the worker would have reported it (WorkerEvent::scan_error)
+    fn from_scan_error(err: &ScanError) -> IpcError {
+        let path = err.path().map(|p| p.to_string_lossy().into_owned());
+        reported_error(Some(err.kind()), err.to_string(), path, err.detail())
+    }
+
+    /// how an elevated worker ended, with pkexec's own exit codes turned
+    /// into ElevationFailed; the worker itself never exits with those
+    fn into_elevation_error(self) -> IpcError {
+        match self {
+            IpcError::WorkerFailed {
+                exit_code: Some(exit_code @ (PKEXEC_DISMISSED | PKEXEC_NOT_AUTHORIZED)),
+                stderr,
+                ..
+            } => IpcError::ElevationFailed { exit_code, stderr },
+            err => err,
+        }
+    }
+}
+
+/// the IpcError a WorkerEvent::Error line stands for
+fn reported_error(
+    kind: Option<ScanErrorKind>,
+    message: String,
+    path: Option<String>,
+    detail: Option<String>,
+) -> IpcError {
+    match kind {
+        Some(ScanErrorKind::Cancelled) => IpcError::Cancelled,
+        Some(kind) => IpcError::ScanFailed {
+            kind,
+            message,
+            path,
+            detail,
+        },
+        None => IpcError::Rejected(message),
+    }
+}
+
+/// what happens when a worker crashes mid-scan (IpcError::is_crash)
+/// - `retries` extra attempts, each on a fresh worker; 0 = never retry
+/// - `threads`: the retries scan on this many threads (--threads), as the
+///   usual crash is running out of memory on a huge directory
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub retries: usize,
+    pub threads: Option<usize>,
+}
+
+/// one retry, single-threaded
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 1,
+            threads: Some(1),
+        }
+    }
+}
+
+/// where a WorkerPool runs a scan
+/// - Subprocess: on a --worker-serve process, so a crash or a hung
+///   filesystem takes only the worker down; falls back to InProcess when
+///   no worker can be started
+/// - InProcess: on the pool's own thread, without spawning or encoding the
+///   result; for small folders, or where starting a process is blocked
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScanBackend {
+    #[default]
+    Subprocess,
+    InProcess,
+}
+
+/// how a worker process is scheduled (worker_process)
+/// - Background: lowest CPU priority and the idle I/O class (idle_priority),
+///   for ScanOptions::low_priority scans; same result, it just yields to
+///   everything else on the desktop
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScanPriority {
+    #[default]
+    Normal,
+    Background,
+}
+
+impl ScanPriority {
+    /// Background for low_priority scans: the GUI's "Scan gently" asks for
+    /// both, fewer threads and a background worker, with no box of its own
+    fn of(opts: &ScanOptions) -> ScanPriority {
+        if opts.low_priority {
+            ScanPriority::Background
+        } else {
+            ScanPriority::Normal
+        }
+    }
+}
+
+/// how a WorkerPool starts its Subprocess workers (WorkerPool::set_spawn)
+/// - Direct: the worker executable itself, next to the GUI; inside a
+///   Flatpak sandbox it sees only what the sandbox shares
+///   (ScanMeta::sandboxed)
+/// - FlatpakHost: on the host, through `flatpak-spawn --host`; `exe` is the
+///   worker's host path and each request's roots are translated with
+///   `sandbox` first, and the result's paths back (sandbox_paths); a root
+///   without a host path is scanned inside the sandbox instead, with a
+///   warning
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum SpawnStrategy {
+    #[default]
+    Direct,
+    FlatpakHost {
+        sandbox: FlatpakSandbox,
+        exe: PathBuf,
+    },
+}
+
+impl SpawnStrategy {
+    /// FlatpakHost when `host_requested` (a user setting) and the GUI runs
+    /// in `sandbox` (FlatpakSandbox::detect) with a `host_worker` (what
+    /// WORKER_PATH_ENV says); Direct when not requested
+    /// - the host worker is an installation of this program on the host: the
+    ///   sandbox's own binary needs the runtime's libraries, which the host
+    ///   hasn't got; its absolute host path can't be checked from in here
+    /// - Err says why host-side scanning isn't possible; scans then stay
+    ///   Direct
+    pub fn choose(
+        sandbox: Option<&FlatpakSandbox>,
+        host_requested: bool,
+        host_worker: Option<&std::ffi::OsStr>,
+    ) -> Result<SpawnStrategy, String> {
+        if !host_requested {
+            return Ok(SpawnStrategy::Direct);
+        }
+        let Some(sandbox) = sandbox else {
+            return Err("not running in a Flatpak sandbox".to_string());
+        };
+        let Some(exe) = host_worker.filter(|p| !p.is_empty()).map(Path::new) else {
+            return Err(format!("no host worker; set {} to its path on the host", WORKER_PATH_ENV));
+        };
+        if !exe.is_absolute() {
+            return Err(format!("{} is not an absolute path", exe.display()));
+        }
+        Ok(SpawnStrategy::FlatpakHost {
+            sandbox: sandbox.clone(),
+            exe: exe.to_path_buf(),
+        })
+    }
+
+    /// `request` with its roots as the host sees them, and the host worker
+    /// to send it to; None for Direct
+    /// - Err names the first root without a host path
+    fn host_request(&self, request: &ScanRequest) -> Result<Option<(ScanRequest, &Path)>, String> {
+        let SpawnStrategy::FlatpakHost { sandbox, exe } = self else {
+            return Ok(None);
+        };
+        let mut request = request.clone();
+        let opts = &mut request.options;
+        opts.path = sandbox.host_path(&opts.path)?;
+        for root in &mut opts.extra_roots {
+            *root = sandbox.host_path(root)?;
+        }
+        Ok(Some((request, exe)))
+    }
+}
+
+/// `stats` of `host`, the host_request made of `request`, with its paths
+/// as the sandbox sees them: the part below each host root put below the
+/// sandbox root it came from (anything else is left as the host has it)
+fn sandbox_paths(stats: &mut FolderStats, host: &ScanRequest, request: &ScanRequest) {
+    let map = |path: &Path| sandbox_path(path, host, request);
+    stats.map_paths(&map);
+}
+
+/// `path` from a host scan of `host` as seen from `request`'s roots; None
+/// when it's below none of them
+fn sandbox_path(path: &Path, host: &ScanRequest, request: &ScanRequest) -> Option<PathBuf> {
+    let roots = |r: &ScanRequest| {
+        let opts = &r.options;
+        std::iter::once(opts.path.clone()).chain(opts.extra_roots.clone()).collect::<Vec<_>>()
+    };
+    roots(host)
+        .into_iter()
+        .zip(roots(request))
+        .find_map(|(host, sandbox)| {
+            path.strip_prefix(&host).ok().map(|rest| join_below(&sandbox, rest))
+        })
+}
+
+/// the Flatpak sandbox the GUI runs in, from scan::FLATPAK_INFO
+/// - `app_path` / `runtime_path`: the host directories mounted at /app and
+///   /usr; empty when the file doesn't say
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FlatpakSandbox {
+    pub app_path: PathBuf,
+    pub runtime_path: PathBuf,
+}
+
+impl FlatpakSandbox {
+    /// the sandbox this process runs in; None outside one
+    pub fn detect() -> Option<FlatpakSandbox> {
+        scan::in_flatpak_sandbox().then(|| {
+            FlatpakSandbox::parse(&std::fs::read_to_string(scan::FLATPAK_INFO).unwrap_or_default())
+        })
+    }
+
+    /// the [Instance] group of a FLATPAK_INFO keyfile
+    pub fn parse(info: &str) -> FlatpakSandbox {
+        let mut sandbox = FlatpakSandbox::default();
+        let mut group = "";
+        for line in info.lines().map(str::trim) {
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                group = name;
+            } else if group == "Instance"
+                && let Some((key, value)) = line.split_once('=')
+            {
+                match key.trim() {
+                    "app-path" => sandbox.app_path = PathBuf::from(value.trim()),
+                    "runtime-path" => sandbox.runtime_path = PathBuf::from(value.trim()),
+                    _ => {}
+                }
+            }
+        }
+        sandbox
+    }
+
+    /// `path`, as seen inside the sandbox, as the host sees it
+    /// - /app and /usr: below app_path and runtime_path
+    /// - /run/host/X: the host's /X (shared with --filesystem=host-os etc.)
+    /// - /run/user/UID/doc/ID/NAME (a folder shared through the document
+    ///   portal): below the host path the portal recorded for it
+    /// - other SANDBOX_ONLY_DIRS, "..", relative paths: Err
+    /// - anything else (home, /media, /mnt, ...) is the same path on the
+    ///   host
+    pub fn host_path(&self, path: &Path) -> Result<PathBuf, String> {
+        let fail = |why: &str| Err(format!("{}: {}", path.display(), why));
+        if !path.is_absolute() {
+            return fail("not an absolute path");
+        }
+        if path.iter().any(|part| part == "..") {
+            return fail("contains \"..\"");
+        }
+        for (mount, host) in [("/app", &self.app_path), ("/usr", &self.runtime_path)] {
+            if let Ok(rest) = path.strip_prefix(mount) {
+                if host.as_os_str().is_empty() {
+                    let why = format!("{} has no host path in {}", mount, scan::FLATPAK_INFO);
+                    return fail(&why);
+                }
+                return Ok(join_below(host, rest));
+            }
+        }
+        if let Ok(rest) = path.strip_prefix("/run/host") {
+            return Ok(join_below(Path::new("/"), rest));
+        }
+        if let Some((entry, rest)) = portal_entry(path) {
+            return match portal_host_path(&entry) {
+                Some(host) => Ok(join_below(&host, &rest)),
+                None => fail("the document portal gives no host path for it"),
+            };
+        }
+        let top = path.iter().nth(1);
+        if top.is_some_and(|top| SANDBOX_ONLY_DIRS.iter().any(|dir| top == *dir)) {
+            return fail("exists only inside the sandbox");
+        }
+        Ok(path.to_path_buf())
+    }
+}
+
+/// `base` joined with `rest`, without the trailing separator an empty
+/// `rest` adds
+fn join_below(base: &Path, rest: &Path) -> PathBuf {
+    if rest.as_os_str().is_empty() {
+        base.to_path_buf()
+    } else {
+        base.join(rest)
+    }
+}
+
+/// for a path in the document portal (/run/user/UID/doc/ID/NAME/...), the
+/// shared entry (up to NAME) and the rest below it
+fn portal_entry(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let parts: Vec<_> = path.strip_prefix("/run/user").ok()?.iter().collect();
+    if parts.len() < 4 || parts[1] != "doc" {
+        return None;
+    }
+    let entry = Path::new("/run/user").join(parts[..4].iter().collect::<PathBuf>());
+    Some((entry, parts[4..].iter().collect()))
+}
+
+/// Ok for a worker that exited 0, otherwise the IpcError its exit code
+/// stands for; `stderr` is what it printed before exiting, `reported` its
+/// error line (parse_error), if any
+/// - the exit code decides the kind; the error line adds the details when
+///   it agrees
+fn validate_worker_success(
+    status: ExitStatus,
+    stderr: &str,
+    reported: Option<IpcError>,
+) -> Result<(), IpcError> {
+    if status.success() {
+        return Ok(());
+    }
+    let stderr = stderr.trim().to_string();
+    Err(match status.code() {
+        Some(1) => IpcError::Rejected(stderr),
+        Some(code) => match ScanErrorKind::from_exit_code(code) {
+            Some(ScanErrorKind::Cancelled) => IpcError::Cancelled,
+            Some(kind) => match reported {
+                Some(err @ IpcError::ScanFailed { kind: theirs, .. }) if theirs == kind => err,
+                _ => IpcError::ScanFailed {
+                    kind,
+                    message: stderr,
+                    path: None,
+                    detail: None,
+                },
+            },
+            None => IpcError::WorkerFailed {
+                exit_code: Some(code),
+                signal: None,
+                stderr,
+                retried: false,
+            },
+        },
+        None => IpcError::WorkerFailed {
+            exit_code: None,
+            signal: exit_signal(status),
+            stderr,
+            retried: false,
+        },
+    })
+}
+
+#[cfg(unix)]
+fn exit_signal(status: ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_status: ExitStatus) -> Option<i32> {
+    None
+}
+
+/// one `--worker-serve` stdin line: a ScanRequest plus the id its answer
+/// lines carry
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServeRequest {
+    pub id: u64,
+    #[serde(flatten)]
+    pub request: ScanRequest,
+}
+
+/// one stdout line of a `--worker --stream` run: progress lines while it
+/// scans, then one result line
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum WorkerEvent {
+    Progress(WorkerProgress),
+    Result(Box<Versioned<FolderStats>>),
+    /// the result document as `len` bytes right after this line: MessagePack
+    /// with --worker-format bin, gzipped with --compress
+    Binary { len: usize },
+    /// the request failed but the worker keeps serving (--worker-serve);
+    /// a one-shot worker exits with its code, after this line on stderr
+    /// - `kind` None = the request itself was refused
+    /// - `path`, `detail`: ScanError::path / detail, for the GUI's own wording
+    Error {
+        kind: Option<ScanErrorKind>,
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        detail: Option<String>,
+    },
+    /// something the scan got past but the user should know about; written
+    /// to stderr (never stdout), before the result
+    Warning(WorkerWarning),
+    /// sent after HEARTBEAT_INTERVAL without another line
+    Heartbeat(WorkerHeartbeat),
+}
+
+impl WorkerEvent {
+    /// the Error line for a failed scan
+    pub fn scan_error(err: &ScanError) -> WorkerEvent {
+        WorkerEvent::Error {
+            kind: Some(err.kind()),
+            message: err.to_string(),
+            path: err.path().map(|p| p.to_string_lossy().into_owned()),
+            detail: err.detail(),
+        }
+    }
+}
+
+/// a WorkerEvent line, with the ServeRequest id it answers in --worker-serve
+/// mode (none in a one-shot --stream run)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaggedEvent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    /// on a result line: how many warning lines for this id went to stderr
+    /// before it, so a --worker-serve client knows when it has them all
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub warnings: usize,
+    #[serde(flatten)]
+    pub event: WorkerEvent,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// {"type":"warning","message":"143 directories unreadable"} on stderr
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WorkerWarning {
+    pub message: String,
+}
+
+/// things a finished scan got past, as one-line messages for the GUI
+pub fn scan_warnings(stats: &FolderStats) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if stats.error_count > stats.errors.len() {
+        warnings.push(format!("{} paths unreadable", stats.error_count));
+    } else {
+        let dirs = stats.errors.iter().filter(|e| e.is_dir).count();
+        let files = stats.errors.len() - dirs;
+        if dirs > 0 {
+            warnings.push(format!("{} directories unreadable", dirs));
+        }
+        if files > 0 {
+            warnings.push(format!("{} files unreadable", files));
+        }
+    }
+    if stats.metadata_errors > 0 {
+        warnings.push(format!(
+            "{} files without a readable size (total size is a lower bound)",
+            stats.metadata_errors
+        ));
+    }
+    if stats.vanished_files > 0 {
+        warnings.push(format!("{} files vanished during the scan", stats.vanished_files));
+    }
+    if stats.hash_errors > 0 {
+        warnings.push(format!("{} files could not be hashed", stats.hash_errors));
+    }
+    if stats.scan_meta.sandboxed {
+        warnings.push("scanned inside sandbox; some paths may be hidden".to_string());
+    }
+    warnings
+}
+
+/// a stderr line that is a warning, with the id it belongs to
+fn parse_warning(line: &str) -> Option<(Option<u64>, WorkerWarning)> {
+    match serde_json::from_str::<TaggedEvent>(line) {
+        Ok(TaggedEvent {
+            id,
+            event: WorkerEvent::Warning(warning),
+            ..
+        }) => Some((id, warning)),
+        _ => None,
+    }
+}
+
+/// a stderr line that is a one-shot worker's error report, as the IpcError
+/// it stands for
+fn parse_error(line: &str) -> Option<IpcError> {
+    match serde_json::from_str::<TaggedEvent>(line) {
+        Ok(TaggedEvent {
+            event:
+                WorkerEvent::Error {
+                    kind,
+                    message,
+                    path,
+                    detail,
+                },
+            ..
+        }) => Some(reported_error(kind, message, path, detail)),
+        _ => None,
+    }
+}
+
+/// {"type":"progress","files":N,"bytes":B,"dir":"..."}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WorkerProgress {
+    pub files: usize,
+    pub bytes: u64,
+    /// directory of the most recently seen file, or the directory just entered
+    pub dir: String,
+}
+
+/// {"type":"heartbeat","dir":"...","idle_ms":N}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WorkerHeartbeat {
+    /// `dir` of the last progress line (the scan root before the first)
+    pub dir: String,
+    /// how long that progress line is ago; 0 in the analysis stages, which
+    /// send none (but the hashing among them does)
+    pub idle_ms: u64,
+}
+
+/// when a scan last made progress, where, and how far it got (the running
+/// totals of its last progress line), as far as its stdout tells
+#[derive(Clone, Debug)]
+struct Activity {
+    at: Instant,
+    dir: String,
+    files: usize,
+    bytes: u64,
+}
+
+impl Activity {
+    fn new(dir: &std::path::Path) -> Arc<Mutex<Activity>> {
+        Arc::new(Mutex::new(Activity {
+            at: Instant::now(),
+            dir: dir.to_string_lossy().into_owned(),
+            files: 0,
+            bytes: 0,
+        }))
+    }
+}
+
+fn lock_activity(activity: &Mutex<Activity>) -> MutexGuard<'_, Activity> {
+    activity.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// a spawned worker, with stderr drained on the side while its result
+/// channel is read (or a worker with lots of warnings would block on a
+/// full pipe)
+/// - warning lines arrive on `warnings` as they're written; an error line
+///   is kept for validate_worker_success, the rest of stderr for the error
+///   message
+/// - `stdin` is None once closed (cancel, stop), or when it was never piped
+struct Process {
+    child: Arc<Mutex<Child>>,
+    stdin: Arc<Mutex<Option<ChildStdin>>>,
+    cancelled: Arc<AtomicBool>,
+    stderr_reader: JoinHandle<(String, Option<IpcError>)>,
+    warnings: mpsc::Receiver<(Option<u64>, WorkerWarning)>,
+}
+
+impl Process {
+    fn new(mut child: Child) -> Process {
+        let stdin = child.stdin.take();
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let (warning_tx, warnings) = mpsc::channel();
+        let stderr_reader = thread::spawn(move || {
+            let mut text = String::new();
+            let mut reported = None;
+            for line in BufReader::new(stderr).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if let Some(warning) = parse_warning(&line) {
+                    let _ = warning_tx.send(warning);
+                } else if let Some(err) = parse_error(&line) {
+                    reported = Some(err);
+                } else {
+                    text.push_str(&line);
+                    text.push('\n');
+                }
+            }
+            (text, reported)
+        });
+        Process {
+            child: Arc::new(Mutex::new(child)),
+            stdin: Arc::new(Mutex::new(stdin)),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            stderr_reader,
+            warnings,
+        }
+    }
+
+    fn canceller(&self) -> WorkerCancel {
+        WorkerCancel {
+            child: Arc::clone(&self.child),
+            stdin: Arc::clone(&self.stdin),
+            cancelled: Arc::clone(&self.cancelled),
+        }
+    }
+
+    /// one line to the worker's stdin; BrokenPipe once that was closed
+    fn send(&self, line: &str) -> io::Result<()> {
+        let mut stdin = lock_stdin(&self.stdin);
+        let Some(stdin) = stdin.as_mut() else {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        };
+        writeln!(stdin, "{}", line).and_then(|()| stdin.flush())
+    }
+
+    fn close_stdin(&self) {
+        lock_stdin(&self.stdin).take();
+    }
+
+    /// wait for the worker to exit, polling instead of wait(): the lock must
+    /// stay free for cancel()
+    fn reap(&self) -> io::Result<ExitStatus> {
+        loop {
+            match lock_child(&self.child).try_wait() {
+                Ok(Some(status)) => return Ok(status),
+                Ok(None) => thread::sleep(Duration::from_millis(20)),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// reap as above, giving up after `timeout`; false if it's still running
+    fn reap_within(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match lock_child(&self.child).try_wait() {
+                Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(20)),
+                Ok(None) => return false,
+                _ => return true,
+            }
+        }
+    }
+
+    /// reap the worker once its result channel is done, and turn how it ended into
+    /// the answer: Cancelled, its exit code, or `result` with every warning
+    fn finish<T>(
+        self,
+        result: Option<Result<T, IpcError>>,
+    ) -> Result<(T, Vec<WorkerWarning>), IpcError> {
+        let status = self.reap().map_err(IpcError::Io)?;
+        let (stderr, reported) = self.stderr_reader.join().unwrap_or_default();
+
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(IpcError::Cancelled);
+        }
+        validate_worker_success(status, &stderr, reported)?;
+        let value = result.unwrap_or(Err(IpcError::NoResult))?;
+        Ok((value, self.warnings.try_iter().map(|(_, w)| w).collect()))
+    }
+}
+
+/// stops the worker of a ClientScan; cheap to clone and Send
+#[derive(Clone)]
+pub struct WorkerCancel {
+    child: Arc<Mutex<Child>>,
+    stdin: Arc<Mutex<Option<ChildStdin>>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// a scan that went `last_activity` without progress, last seen in `dir`
+/// (WorkerPool::stall_after)
+#[derive(Clone, Debug)]
+pub struct Stall {
+    pub last_activity: Duration,
+    pub dir: String,
+}
+
+/// run `wait`, cancelling through `canceller` once `timeout` has passed
+/// (Err(TimedOut) if that actually stopped the worker), and telling
+/// `on_stall` when `activity` got older than `stall_after`
+/// - a timed-out scan that still answered keeps its result: the partial one
+///   marked StopReason::TimedOut (or a whole one that just made it)
+/// - a stall is only reported, once until the scan moves on again: whether
+///   it's given up is up to whoever watches it
+fn with_watchdog(
+    canceller: WorkerCancel,
+    activity: Arc<Mutex<Activity>>,
+    timeout: Option<Duration>,
+    stall_after: Option<Duration>,
+    on_stall: &(dyn Fn(Stall) + Sync),
+    wait: impl FnOnce() -> Result<(FolderStats, Vec<WorkerWarning>), IpcError>,
+) -> Result<(FolderStats, Vec<WorkerWarning>), IpcError> {
+    let started = Instant::now();
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    thread::scope(|threads| {
+        let watchdog = threads.spawn(move || {
+            let mut reported = false;
+            loop {
+                let now = Instant::now();
+                let last = lock_activity(&activity).clone();
+                if let Some(after) = timeout
+                    && now >= started + after
+                {
+                    return canceller.cancel();
+                }
+                let stale = stall_after.is_some_and(|after| now >= last.at + after);
+                if stale && !reported {
+                    let last_activity = now - last.at;
+                    on_stall(Stall { last_activity, dir: last.dir });
+                }
+                reported = stale;
+                // re-checked then: the activity may have moved on meanwhile,
+                // which a stalled scan shows by its next heartbeat at the latest
+                let next_stall = if stale {
+                    Some(now + HEARTBEAT_INTERVAL)
+                } else {
+                    stall_after.map(|s| last.at + s)
+                };
+                let next = [timeout.map(|t| started + t), next_stall];
+                let next = next.into_iter().flatten().min().expect("a limit is set");
+                if !matches!(done_rx.recv_timeout(next - now), Err(RecvTimeoutError::Timeout)) {
+                    return false;
+                }
+            }
+        });
+
+        let result = wait();
+        drop(done_tx);
+        match (watchdog.join(), result) {
+            (Ok(true), Ok((stats, warnings))) => Ok((mark_timed_out(stats), warnings)),
+            (Ok(true), Err(_)) => Err(IpcError::TimedOut(timeout.expect("timed out"))),
+            (_, result) => result,
+        }
+    })
+}
+
+/// the result of a scan cancelled because its time was up; a complete one
+/// stays as it is
+fn mark_timed_out(mut stats: FolderStats) -> FolderStats {
+    if let Some(partial) = &mut stats.partial {
+        partial.reason = StopReason::TimedOut;
+    }
+    stats
+}
+
+impl WorkerCancel {
+    /// close its stdin and send SIGTERM, then SIGKILL once CANCEL_GRACE has
+    /// passed and the worker is still running; wait() then returns what the
+    /// scan had found so far
+    /// - false (no-op) when the worker already exited, so its own result
+    ///   stands, or was already cancelled
+    /// - an elevated worker runs as root and takes no signals from here; it
+    ///   stops on the closed stdin alone (--cancel-on-eof)
+    pub fn cancel(&self) -> bool {
+        let mut child = lock_child(&self.child);
+        if !matches!(child.try_wait(), Ok(None)) || self.cancelled.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        lock_stdin(&self.stdin).take();
+        terminate(&mut child);
+        drop(child);
+
+        let child = Arc::clone(&self.child);
+        thread::spawn(move || {
+            thread::sleep(CANCEL_GRACE);
+            let mut child = lock_child(&child);
+            if matches!(child.try_wait(), Ok(None)) {
+                let _ = child.kill();
+            }
+        });
+        true
+    }
+
+    /// SIGKILL at once, for a worker still running after cancel()
+    fn kill(&self) {
+        let mut child = lock_child(&self.child);
+        if matches!(child.try_wait(), Ok(None)) {
+            self.cancelled.store(true, Ordering::SeqCst);
+            lock_stdin(&self.stdin).take();
+            let _ = child.kill();
+        }
+    }
+}
+
+/// a panic elsewhere doesn't make the child unreachable
+fn lock_child(child: &Mutex<Child>) -> MutexGuard<'_, Child> {
+    child.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn lock_stdin(stdin: &Mutex<Option<ChildStdin>>) -> MutexGuard<'_, Option<ChildStdin>> {
+    stdin.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// the worker treats SIGTERM as a cancelled scan and exits on its own
+#[cfg(unix)]
+fn terminate(child: &mut Child) {
+    // SAFETY: plain kill(2); the child is not reaped yet (try_wait said
+    // so under the same lock), so the pid is still ours
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+    }
+}
+
+#[cfg(not(unix))]
+fn terminate(child: &mut Child) {
+    let _ = child.kill();
+}
+
+/// one long-lived `--worker-serve` process, reused for every scan
+/// - started on first use, and again when it died (crash, cancel, timeout)
+/// - one request at a time; answers are matched by request id
+pub struct WorkerClient {
+    exe_path: PathBuf,
+    server: Option<Server>,
+    next_id: u64,
+}
+
+struct Server {
+    process: Process,
+    settings: ServerSettings,
+    results: BufReader<ResultStream>,
+}
+
+/// the ScanPriority, --threads, --compress, pkexec and flatpak-spawn are per
+/// process: a request that wants other settings gets a new server
+/// - `host_exe`: the worker's host path, for one started on the host of a
+///   Flatpak sandbox
+#[derive(Clone, PartialEq, Eq)]
+struct ServerSettings {
+    priority: ScanPriority,
+    threads: Option<usize>,
+    compress: bool,
+    elevated: bool,
+    host_exe: Option<PathBuf>,
+}
+
+/// a request sent to the WorkerClient's server
+/// - `wait` reads up to this request's answer; always call it
+/// - `canceller` gives a handle that stops it from any other thread
+pub struct ClientScan<'a> {
+    client: &'a mut WorkerClient,
+    id: u64,
+    canceller: WorkerCancel,
+    activity: Arc<Mutex<Activity>>,
+}
+
+impl WorkerClient {
+    pub fn new(exe_path: PathBuf) -> Self {
+        WorkerClient {
+            exe_path,
+            server: None,
+            next_id: 1,
+        }
+    }
+
+    /// send `request` to the server, starting (or restarting) it as needed;
+    /// `threads` limits the server's scan threads (None = one per CPU)
+    /// - an elevated request gets a server of its own, used for it alone,
+    ///   and none at all (ElevationRefused) for a worker elevation_refusal
+    ///   objects to
+    /// - `host_exe`: run the server on the host of the Flatpak sandbox,
+    ///   from that host path; `request` must have host paths too
+    ///   (SpawnStrategy)
+    pub fn start(
+        &mut self,
+        request: &ScanRequest,
+        threads: Option<usize>,
+        host_exe: Option<&Path>,
+    ) -> Result<ClientScan<'_>, IpcError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let opts = &request.options;
+        let line = serde_json::to_string(&ServeRequest {
+            id,
+            request: request.clone(),
+        })?;
+        if request.elevated
+            && let Some(reason) = elevation_refusal(host_exe.unwrap_or(&self.exe_path))
+        {
+            return Err(IpcError::ElevationRefused(reason));
+        }
+        let settings = ServerSettings {
+            priority: ScanPriority::of(opts),
+            threads,
+            compress: compress_result(opts),
+            elevated: request.elevated,
+            host_exe: host_exe.map(Path::to_path_buf),
+        };
+
+        // a server that died since the last request only shows up when the
+        // write fails: start a fresh one and send once more
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let sent = self.server(&settings)?.process.send(&line);
+            match sent {
+                Ok(()) => break,
+                // pkexec gave up already: say why instead of asking for the
+                // password once more
+                Err(e) if settings.elevated => return Err(self.refused(e)),
+                Err(e) => {
+                    self.stop();
+                    if attempts == 2 {
+                        return Err(IpcError::Io(e));
+                    }
+                }
+            }
+        }
+
+        let canceller = self.server.as_ref().expect("just sent to it").process.canceller();
+        Ok(ClientScan {
+            client: self,
+            id,
+            canceller,
+            activity: Activity::new(&opts.path),
+        })
+    }
+
+    /// the running server, restarted when it exited or was started with
+    /// other `settings`
+    fn server(&mut self, settings: &ServerSettings) -> Result<&mut Server, IpcError> {
+        let usable = self.server.as_ref().is_some_and(|server| {
+            server.settings == *settings
+                && matches!(lock_child(&server.process.child).try_wait(), Ok(None))
+        });
+        if !usable {
+            self.stop();
+            let on_host = settings.host_exe.is_some();
+            let exe_path = settings.host_exe.as_ref().unwrap_or(&self.exe_path);
+            let mut cmd =
+                worker_process(exe_path, settings.priority, settings.elevated, on_host);
+            cmd.args(["--worker-serve", "--worker-format", "bin", "--cancel-on-eof"]);
+            // flatpak-spawn's host process is no child of ours: it lowers
+            // its own priority
+            if on_host && settings.priority == ScanPriority::Background {
+                cmd.arg("--idle-priority");
+            }
+            if let Some(threads) = settings.threads {
+                cmd.arg("--threads").arg(threads.to_string());
+            }
+            if settings.compress {
+                cmd.arg("--compress");
+            }
+            let cmd = cmd.stdin(Stdio::piped()).stderr(Stdio::piped());
+            let (child, results) = spawn_worker(cmd, settings.elevated || on_host)?;
+            self.server = Some(Server {
+                process: Process::new(child),
+                settings: settings.clone(),
+                results: BufReader::new(results),
+            });
+        }
+        Ok(self.server.as_mut().expect("started above"))
+    }
+
+    /// kill and reap the server, if any
+    /// - an elevated one can't be killed from here (EPERM), but exits on its
+    ///   own once its stdin is closed; one still running after EXIT_GRACE is
+    ///   reaped on a thread of its own instead of holding up the caller
+    fn stop(&mut self) {
+        if let Some(server) = self.server.take() {
+            server.process.close_stdin();
+            let _ = lock_child(&server.process.child).kill();
+            if !server.process.reap_within(EXIT_GRACE) {
+                let process = server.process;
+                thread::spawn(move || process.reap());
+            }
+        }
+    }
+
+    /// the server a request couldn't be written to, reaped: why it exited
+    /// (ElevationFailed for pkexec's codes), else `err`
+    fn refused(&mut self, err: io::Error) -> IpcError {
+        let server = self.server.take().expect("just sent to it");
+        server.process.close_stdin();
+        match server.process.finish::<()>(None) {
+            Ok(_) | Err(IpcError::NoResult) => IpcError::Io(err),
+            Err(failed) => failed.into_elevation_error(),
+        }
+    }
+}
+
+impl Drop for WorkerClient {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl ClientScan<'_> {
+    pub fn canceller(&self) -> WorkerCancel {
+        self.canceller.clone()
+    }
+
+    /// each progress line goes to `on_progress` as it arrives; returns the
+    /// answer, or what the scan had found when cancel() stopped the worker:
+    /// its partial result (FolderStats::partial), else the totals of its
+    /// last progress line, else Err(Cancelled)
+    /// - the server keeps running unless it was cancelled, died or was
+    ///   elevated, and is then restarted by the next start()
+    pub fn wait(
+        self,
+        on_progress: &mut dyn FnMut(WorkerProgress),
+    ) -> Result<(FolderStats, Vec<WorkerWarning>), IpcError> {
+        let Some(server) = self.client.server.as_mut() else {
+            return Err(IpcError::NoResult);
+        };
+        let result = read_events(&mut server.results, self.id, &self.activity, on_progress);
+        let cancelled = self.canceller.cancelled.load(Ordering::SeqCst);
+        let elevated = server.settings.elevated;
+        let result = match result {
+            // a cancelled server answers with what it has, then exits
+            Some((answer, warning_count)) if !cancelled || answer.is_ok() => {
+                // written to stderr before the answer, so they're already
+                // on their way; recv() only fails once stderr has closed
+                let mut warnings = Vec::new();
+                while warnings.len() < warning_count {
+                    match server.process.warnings.recv() {
+                        Ok((id, warning)) if id == Some(self.id) => warnings.push(warning),
+                        Ok(_) => {}
+                        Err(_) => break,
+                    }
+                }
+                // the next elevated scan asks for the password again
+                if cancelled || elevated {
+                    self.client.stop();
+                }
+                return answer
+                    .and_then(|stats| check_schema(stats, &mut warnings))
+                    .map(|stats| (stats, warnings));
+            }
+            result => result.map(|(result, _)| result),
+        };
+        // stdout ended (the server died) or the scan was stopped: reap it
+        // so finish() can say why, and start over next time
+        let server = self.client.server.take().expect("checked above");
+        let process = server.process;
+        process.close_stdin();
+        if result.is_some() {
+            // cancelled with an error line instead: its exit isn't waited for
+            let _ = lock_child(&process.child).kill();
+        }
+        match process.finish(result) {
+            // killed before it could send its partial result
+            Err(IpcError::Cancelled) => {
+                let last = lock_activity(&self.activity);
+                if last.files == 0 {
+                    return Err(IpcError::Cancelled);
+                }
+                let stats =
+                    FolderStats::partial_totals(last.files, last.bytes, StopReason::Cancelled);
+                Ok((stats, Vec::new()))
+            }
+            Err(failed) if elevated => Err(failed.into_elevation_error()),
+            answer => answer,
+        }
+    }
+
+    /// wait, with a watchdog that cancels the worker when no answer arrived
+    /// within `timeout` (Err(TimedOut)), and tells `on_stall` when the scan
+    /// made no progress for `stall_after`; both None is a plain wait
+    /// - progress = a progress line, or a heartbeat saying how long ago the
+    ///   last one was; a worker that sends nothing at all stalls too
+    /// - a worker that finishes just as the watchdog fires keeps its result
+    pub fn wait_limited(
+        self,
+        timeout: Option<Duration>,
+        stall_after: Option<Duration>,
+        on_stall: &(dyn Fn(Stall) + Sync),
+        on_progress: &mut dyn FnMut(WorkerProgress),
+    ) -> Result<(FolderStats, Vec<WorkerWarning>), IpcError> {
+        if timeout.is_none() && stall_after.is_none() {
+            return self.wait(on_progress);
+        }
+        let (canceller, activity) = (self.canceller(), Arc::clone(&self.activity));
+        let wait = || self.wait(on_progress);
+        with_watchdog(canceller, activity, timeout, stall_after, on_stall, wait)
+    }
+}
+
+/// what a WorkerPool sends back for a submitted request, tagged with the id
+/// submit() returned; Done is always the last one
+#[derive(Debug)]
+pub enum PoolEvent {
+    /// the request waits for `ahead` others on the same device to finish
+    /// first; sent whenever that number changes, never for one that
+    /// started right away
+    Queued {
+        id: u64,
+        ahead: usize,
+    },
+    Progress {
+        id: u64,
+        progress: WorkerProgress,
+    },
+    /// the scan went WorkerPool::stall_after without progress; it goes on
+    /// until cancelled, and is reported again should it stall once more
+    /// after some progress
+    Stalled {
+        id: u64,
+        stall: Stall,
+    },
+    Done {
+        id: u64,
+        result: Box<Result<(FolderStats, Vec<WorkerWarning>), IpcError>>,
+    },
+}
+
+/// runs ScanRequests on up to `size` --worker-serve processes at once
+/// - requests start in submit order as soon as a worker is free, but one
+///   at a time per device: two scans of one disk only slow each other down
+/// - one WorkerClient per runner thread, so each worker is reused
+/// - a crashed worker is retried as RetryPolicy::default says: once
+/// - a scan without progress is reported per stall_after (default: never)
+/// - set_backend runs the scans in-process instead, set_spawn on the host
+///   of a Flatpak sandbox
+/// - an elevated request (ScanRequest::elevated) always runs on a worker
+///   started through pkexec, never in-process, and without the retry and
+///   the stall_after check: both would outlast or repeat the password
+///   dialog
+/// - dropping the pool shuts it down and waits for its threads
+pub struct WorkerPool {
+    shared: Arc<PoolShared>,
+    runners: Vec<JoinHandle<()>>,
+    retry: RetryPolicy,
+    stall_after: Option<Duration>,
+}
+
+struct PoolShared {
+    state: Mutex<PoolState>,
+    wake: Condvar,
+}
+
+#[derive(Default)]
+struct PoolState {
+    next_id: u64,
+    pending: VecDeque<PoolJob>,
+    running: HashMap<u64, RunningJob>,
+    shut_down: bool,
+    backend: ScanBackend,
+    spawn: SpawnStrategy,
+    /// runner threads not yet ended, their worker reaped (terminate_all)
+    live_runners: usize,
+}
+
+struct PoolJob {
+    id: u64,
+    request: ScanRequest,
+    /// devices of the scan roots (scan_devices)
+    devices: Vec<u64>,
+    /// `ahead` of the last Queued event sent for it
+    ahead: usize,
+    timeout: Option<Duration>,
+    stall_after: Option<Duration>,
+    retry: RetryPolicy,
+    backend: ScanBackend,
+    spawn: SpawnStrategy,
+    events: mpsc::Sender<PoolEvent>,
+}
+
+/// a job taken by a runner; `worker` is None until its scan has started
+/// - cancel_requested: cancelled before that, the runner stops it at once
+/// - `local`: the token of a scan_in_process run instead
+#[derive(Default)]
+struct RunningJob {
+    devices: Vec<u64>,
+    cancel_requested: bool,
+    worker: Option<WorkerCancel>,
+    local: Option<CancellationToken>,
+}
+
+impl RunningJob {
+    fn cancel(&mut self) {
+        self.cancel_requested = true;
+        if let Some(worker) = &self.worker {
+            worker.cancel();
+        }
+        if let Some(local) = &self.local {
+            local.cancel();
+        }
+    }
+}
+
+impl WorkerPool {
+    /// `size` runner threads (at least 1); workers start on first use
+    pub fn new(exe_path: PathBuf, size: usize) -> Self {
+        let size = size.max(1);
+        let shared = Arc::new(PoolShared {
+            state: Mutex::new(PoolState {
+                live_runners: size,
+                ..PoolState::default()
+            }),
+            wake: Condvar::new(),
+        });
+        let runners = (0..size)
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                let exe_path = exe_path.clone();
+                thread::spawn(move || run_jobs(WorkerClient::new(exe_path), &shared))
+            })
+            .collect();
+        WorkerPool {
+            shared,
+            runners,
+            retry: RetryPolicy::default(),
+            stall_after: None,
+        }
+    }
+
+    /// requests submitted from now on get a PoolEvent::Stalled once their
+    /// scan went this long without progress; None = never
+    pub fn stall_after(mut self, stall_after: Option<Duration>) -> Self {
+        self.stall_after = stall_after;
+        self
+    }
+
+    /// backend for requests submitted from now on; unlike the settings above
+    /// it can change while the pool runs (a user preference)
+    pub fn set_backend(&self, backend: ScanBackend) {
+        self.shared.lock().backend = backend;
+    }
+
+    /// how workers for requests submitted from now on are started; like
+    /// set_backend it can change while the pool runs
+    pub fn set_spawn(&self, spawn: SpawnStrategy) {
+        self.shared.lock().spawn = spawn;
+    }
+
+    /// queue `request`; its PoolEvents go to `events`, which may be shared
+    /// between requests (tell them apart by the returned id)
+    /// - after shutdown() the request is answered Err(Cancelled) right away
+    pub fn submit(
+        &self,
+        request: ScanRequest,
+        timeout: Option<Duration>,
+        events: mpsc::Sender<PoolEvent>,
+    ) -> u64 {
+        // before locking: a stat can take a while on a slow mount
+        let devices = scan_devices(&request.options);
+        let mut state = self.shared.lock();
+        state.next_id += 1;
+        let id = state.next_id;
+        let job = PoolJob {
+            id,
+            request,
+            devices,
+            ahead: 0,
+            timeout,
+            stall_after: self.stall_after,
+            retry: self.retry,
+            backend: state.backend,
+            spawn: state.spawn.clone(),
+            events,
+        };
+        if state.shut_down {
+            drop(state);
+            job.cancelled();
+        } else {
+            state.pending.push_back(job);
+            state.report_queue();
+            drop(state);
+            self.shared.wake.notify_one();
+        }
+        id
+    }
+
+    /// submit, then block until its Done; progress goes to `on_progress`
+    pub fn run(
+        &self,
+        request: ScanRequest,
+        timeout: Option<Duration>,
+        on_progress: &mut dyn FnMut(WorkerProgress),
+    ) -> Result<(FolderStats, Vec<WorkerWarning>), IpcError> {
+        let (events_tx, events) = mpsc::channel();
+        let id = self.submit(request, timeout, events_tx);
+        wait_for_done(&events, id, on_progress)
+    }
+
+    /// stop one request: a queued one is answered Err(Cancelled) without
+    /// running, a running one is cancelled like WorkerCancel::cancel
+    /// - false when the id is unknown or already finished
+    pub fn cancel(&self, id: u64) -> bool {
+        let mut state = self.shared.lock();
+        if let Some(index) = state.pending.iter().position(|job| job.id == id) {
+            let job = state.pending.remove(index).expect("index from position");
+            state.report_queue();
+            drop(state);
+            job.cancelled();
+            return true;
+        }
+        match state.running.get_mut(&id) {
+            Some(job) => {
+                job.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// cancel everything, queued and running, and refuse new requests; the
+    /// runner threads end (and stop their workers) once their scan is done
+    pub fn shutdown(&self) {
+        let mut state = self.shared.lock();
+        state.shut_down = true;
+        let pending: Vec<PoolJob> = state.pending.drain(..).collect();
+        for job in state.running.values_mut() {
+            job.cancel();
+        }
+        drop(state);
+        self.shared.wake.notify_all();
+        for job in pending {
+            job.cancelled();
+        }
+    }
+
+    /// shutdown(), then wait for every worker process to exit and be
+    /// reaped, busy or idle: after SIGTERM they get EXIT_GRACE, then
+    /// survivors are sent SIGKILL; for an application about to quit
+    /// - an in-process scan stuck in a filesystem call can't be stopped,
+    ///   it's given up on after the second grace
+    pub fn terminate_all(&self) {
+        self.shutdown();
+        if self.shared.wait_for_runners(EXIT_GRACE) {
+            return;
+        }
+        for job in self.shared.lock().running.values() {
+            if let Some(worker) = &job.worker {
+                worker.kill();
+            }
+        }
+        self.shared.wait_for_runners(EXIT_GRACE);
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.shutdown();
+        for runner in self.runners.drain(..) {
+            let _ = runner.join();
+        }
+    }
+}
+
+impl PoolShared {
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// block until every runner has ended or `timeout` passed; true if they
+    /// all did
+    fn wait_for_runners(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.lock();
+        while state.live_runners > 0 {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return false;
+            }
+            state = self.wake.wait_timeout(state, left).unwrap_or_else(|e| e.into_inner()).0;
+        }
+        true
+    }
+
+    /// the first queued job whose devices no running job scans, registered
+    /// as running; None once shut down
+    fn next_job(&self) -> Option<PoolJob> {
+        let mut state = self.lock();
+        loop {
+            if state.shut_down {
+                return None;
+            }
+            let free = state.pending.iter().position(|job| !state.device_busy(&job.devices));
+            if let Some(index) = free {
+                let job = state.pending.remove(index).expect("index from position");
+                let running = RunningJob {
+                    devices: job.devices.clone(),
+                    ..RunningJob::default()
+                };
+                state.running.insert(job.id, running);
+                return Some(job);
+            }
+            state = self.wake.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+impl PoolState {
+    fn device_busy(&self, devices: &[u64]) -> bool {
+        self.running.values().any(|job| job.devices.iter().any(|d| devices.contains(d)))
+    }
+
+    /// a Queued event to each pending job whose `ahead` changed: the running
+    /// and earlier pending jobs sharing a device with it
+    fn report_queue(&mut self) {
+        for index in 0..self.pending.len() {
+            let job = &self.pending[index];
+            let shares = |devices: &Vec<u64>| devices.iter().any(|d| job.devices.contains(d));
+            let running = self.running.values().filter(|r| shares(&r.devices)).count();
+            let earlier = self.pending.range(..index).filter(|p| shares(&p.devices)).count();
+            let ahead = running + earlier;
+            let job = &mut self.pending[index];
+            if job.ahead != ahead {
+                job.ahead = ahead;
+                let _ = job.events.send(PoolEvent::Queued { id: job.id, ahead });
+            }
+        }
+    }
+}
+
+/// st_dev of each scan root that can be stat'ed, deduplicated
+#[cfg(unix)]
+fn scan_devices(opts: &ScanOptions) -> Vec<u64> {
+    use std::os::unix::fs::MetadataExt;
+    let roots = std::iter::once(&opts.path).chain(&opts.extra_roots);
+    let mut devices: Vec<u64> =
+        roots.filter_map(|root| std::fs::metadata(root).ok()).map(|m| m.dev()).collect();
+    devices.sort_unstable();
+    devices.dedup();
+    devices
+}
+
+/// no device numbers: requests only wait for a free worker
+#[cfg(not(unix))]
+fn scan_devices(_opts: &ScanOptions) -> Vec<u64> {
+    Vec::new()
+}
+
+impl PoolJob {
+    fn cancelled(self) {
+        let _ = self.events.send(PoolEvent::Done {
+            id: self.id,
+            result: Box::new(Err(IpcError::Cancelled)),
+        });
+    }
+}
+
+/// a pool runner: one job at a time on its own WorkerClient
+fn run_jobs(mut client: WorkerClient, shared: &PoolShared) {
+    while let Some(job) = shared.next_job() {
+        let id = job.id;
+        let mut result = execute_scan(&mut client, shared, &job, None);
+        let mut retries = 0;
+        while retries < job.retry.retries
+            && !job.request.elevated
+            && result.as_ref().is_err_and(IpcError::is_crash)
+            && !shared.lock().running.get(&id).is_some_and(|r| r.cancel_requested)
+        {
+            retries += 1;
+            result = execute_scan(&mut client, shared, &job, job.retry.threads);
+        }
+        if retries > 0
+            && let Err(IpcError::WorkerFailed { retried, .. }) = &mut result
+        {
+            *retried = true;
+        }
+        {
+            let mut state = shared.lock();
+            state.running.remove(&id);
+            state.report_queue();
+        }
+        // a job waiting for this one's device may go now, on any runner
+        shared.wake.notify_all();
+        let result = Box::new(result);
+        let _ = job.events.send(PoolEvent::Done { id, result });
+    }
+    // stops and reaps the idle server, before terminate_all is told
+    drop(client);
+    shared.lock().live_runners -= 1;
+    shared.wake.notify_all();
+}
+
+/// one attempt at `job`, cancellable through the pool while it runs: on
+/// the client's worker, or in this thread (scan_in_process) for
+/// ScanBackend::InProcess or when no worker can be started; callers get
+/// the same answer either way
+/// - an elevated job only ever runs on its worker
+/// - under SpawnStrategy::FlatpakHost the worker runs on the host, unless a
+///   root has no host path: then inside the sandbox, saying why
+fn execute_scan(
+    client: &mut WorkerClient,
+    shared: &PoolShared,
+    job: &PoolJob,
+    threads: Option<usize>,
+) -> Result<(FolderStats, Vec<WorkerWarning>), IpcError> {
+    let elevated = job.request.elevated;
+    if job.backend == ScanBackend::InProcess && !elevated {
+        return scan_in_process(shared, job);
+    }
+    let id = job.id;
+    let mut notes = Vec::new();
+    let host = match job.spawn.host_request(&job.request) {
+        Ok(host) => host,
+        Err(reason) => {
+            let message = format!("not scanned on the host: {}", reason);
+            notes.push(WorkerWarning { message });
+            None
+        }
+    };
+    let (request, host_exe) = match &host {
+        Some((request, exe)) => (request, Some(*exe)),
+        None => (&job.request, None),
+    };
+    let scan = match client.start(request, threads, host_exe) {
+        Ok(scan) => scan,
+        // a crash now takes the application down too: say so
+        Err(IpcError::SpawnFailed(e)) if !elevated => {
+            let message = format!(
+                "worker could not be started ({}); scanned inside the application instead",
+                e
+            );
+            notes.push(WorkerWarning { message });
+            return scan_in_process(shared, job).map(|(stats, warnings)| {
+                notes.extend(warnings);
+                (stats, notes)
+            });
+        }
+        Err(e) => return Err(e),
+    };
+    {
+        let mut state = shared.lock();
+        let running = state.running.entry(id).or_default();
+        if running.cancel_requested {
+            scan.canceller().cancel();
+        }
+        running.worker = Some(scan.canceller());
+    }
+    // a host worker's paths, as the GUI in the sandbox knows them
+    let in_sandbox = |path: String| match &host {
+        Some((host, _)) => sandbox_path(Path::new(&path), host, &job.request)
+            .map_or(path, |p| p.to_string_lossy().into_owned()),
+        None => path,
+    };
+    let mut on_progress = |mut progress: WorkerProgress| {
+        progress.dir = in_sandbox(progress.dir);
+        let _ = job.events.send(PoolEvent::Progress { id, progress });
+    };
+    // no progress comes while pkexec's password dialog is up
+    let stall_after = if elevated { None } else { job.stall_after };
+    let events = &job.events;
+    let on_stall = |mut stall: Stall| {
+        stall.dir = in_sandbox(stall.dir);
+        let _ = events.send(PoolEvent::Stalled { id, stall });
+    };
+    let result = scan.wait_limited(job.timeout, stall_after, &on_stall, &mut on_progress);
+    let (mut stats, warnings) = match result {
+        Err(IpcError::ScanFailed {
+            kind,
+            message,
+            path,
+            detail,
+        }) => {
+            let path = path.map(in_sandbox);
+            return Err(IpcError::ScanFailed {
+                kind,
+                message,
+                path,
+                detail,
+            });
+        }
+        result => result?,
+    };
+    if let Some((host, _)) = &host {
+        sandbox_paths(&mut stats, host, &job.request);
+    }
+    notes.extend(warnings);
+    Ok((stats, notes))
+}
+
+/// `job` scanned right here, with the answer a worker would have given:
+/// the same errors, warnings (scan_warnings) and partial results
+/// - cancel() and the timeout stop it through a CancellationToken;
+///   stall_after doesn't apply, a hung filesystem call can't be undone
+///   from here
+fn scan_in_process(
+    shared: &PoolShared,
+    job: &PoolJob,
+) -> Result<(FolderStats, Vec<WorkerWarning>), IpcError> {
+    let id = job.id;
+    let cancel = CancellationToken::default();
+    {
+        let mut state = shared.lock();
+        let running = state.running.entry(id).or_default();
+        if running.cancel_requested {
+            cancel.cancel();
+        }
+        running.local = Some(cancel.clone());
+    }
+
+    let deadline = job.timeout.map(|timeout| Instant::now() + timeout);
+    let (tx, rx) = mpsc::channel::<ScanProgress>();
+    let (result, timed_out) = thread::scope(|threads| {
+        // forwards progress, and cancels the scan once the deadline passed
+        let (cancel, events) = (&cancel, &job.events);
+        let forwarder = threads.spawn(move || {
+            loop {
+                let now = Instant::now();
+                let wait = deadline.map_or(Duration::MAX, |at| at.saturating_duration_since(now));
+                match rx.recv_timeout(wait) {
+                    Ok(p) => {
+                        let progress = WorkerProgress {
+                            files: p.files_seen,
+                            bytes: p.bytes_seen,
+                            dir: p.current_dir,
+                        };
+                        let _ = events.send(PoolEvent::Progress { id, progress });
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        cancel.cancel();
+                        return true;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return false,
+                }
+            }
+        });
+        let control =
+            ScanControl::default().cancel_token(cancel.clone()).progress(tx).keep_partial();
+        let result = scan::scan_folder_with(&job.request.options, &control);
+        drop(control);
+        (result, forwarder.join().unwrap_or(false))
+    });
+
+    let stats = match result {
+        Ok(stats) if timed_out => mark_timed_out(stats),
+        Ok(stats) => stats,
+        Err(_) if timed_out => {
+            return Err(IpcError::TimedOut(job.timeout.expect("deadline set")));
+        }
+        Err(err) => return Err(IpcError::from_scan_error(&err)),
+    };
+    let warnings = scan_warnings(&stats)
+        .into_iter()
+        .map(|message| WorkerWarning { message })
+        .collect();
+    Ok((stats, warnings))
+}
+
+/// PoolEvents of request `id` up to its Done; those of other requests on
+/// the same channel are dropped, and so are Queued and Stalled events
+/// - a pool dropped before answering counts as cancelled
+pub fn wait_for_done(
+    events: &mpsc::Receiver<PoolEvent>,
+    id: u64,
+    on_progress: &mut dyn FnMut(WorkerProgress),
+) -> Result<(FolderStats, Vec<WorkerWarning>), IpcError> {
+    wait_for_done_queued(events, id, &mut |_| {}, &mut |_| {}, on_progress)
+}
+
+/// wait_for_done, with each Queued event's `ahead` going to `on_queued`
+/// and each Stalled event to `on_stall`
+pub fn wait_for_done_queued(
+    events: &mpsc::Receiver<PoolEvent>,
+    id: u64,
+    on_queued: &mut dyn FnMut(usize),
+    on_stall: &mut dyn FnMut(Stall),
+    on_progress: &mut dyn FnMut(WorkerProgress),
+) -> Result<(FolderStats, Vec<WorkerWarning>), IpcError> {
+    for event in events {
+        match event {
+            PoolEvent::Queued { id: of, ahead } if of == id => on_queued(ahead),
+            PoolEvent::Progress { id: of, progress } if of == id => on_progress(progress),
+            PoolEvent::Stalled { id: of, stall } if of == id => on_stall(stall),
+            PoolEvent::Done { id: of, result } if of == id => return *result,
+            _ => {}
+        }
+    }
+    Err(IpcError::Cancelled)
+}
+
+/// read the WorkerEvent lines of request `id` up to its answer, skipping
+/// lines for other ids; progress and heartbeats are noted in `activity`
+/// - None = stdout ended first; otherwise the answer and the warning count
+///   of its line
+fn read_events(
+    reader: &mut dyn BufRead,
+    id: u64,
+    activity: &Mutex<Activity>,
+    on_progress: &mut dyn FnMut(WorkerProgress),
+) -> Option<(Result<FolderStats, IpcError>, usize)> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(e) => return Some((Err(IpcError::Io(e)), 0)),
+        }
+        let answer = match serde_json::from_str::<TaggedEvent>(&line) {
+            Ok(tagged) if tagged.id != Some(id) => continue,
+            Ok(TaggedEvent { event, warnings, .. }) => match event {
+                WorkerEvent::Progress(p) => {
+                    let mut last = lock_activity(activity);
+                    last.at = Instant::now();
+                    last.dir.clone_from(&p.dir);
+                    (last.files, last.bytes) = (p.files, p.bytes);
+                    drop(last);
+                    on_progress(p);
+                    continue;
+                }
+                WorkerEvent::Heartbeat(beat) => {
+                    let now = Instant::now();
+                    let idle = Duration::from_millis(beat.idle_ms);
+                    let mut last = lock_activity(activity);
+                    last.at = now.checked_sub(idle).unwrap_or(now);
+                    last.dir = beat.dir;
+                    continue;
+                }
+                // belongs on stderr; a stray one isn't an answer
+                WorkerEvent::Warning(_) => continue,
+                WorkerEvent::Result(stats) => {
+                    (check_version(stats.protocol_version).map(|()| stats.body), warnings)
+                }
+                WorkerEvent::Binary { len } => {
+                    // decoded as it arrives: the payload is never held
+                    // whole next to the FolderStats built from it
+                    let mut payload = (&mut *reader).take(len as u64);
+                    let parsed = parse_worker_output(&mut payload);
+                    // rest of a payload that failed half-way, so the next
+                    // line is read from where it starts
+                    let _ = io::copy(&mut payload, &mut io::sink());
+                    if payload.limit() > 0 {
+                        // stdout ended inside it: the worker died
+                        return None;
+                    }
+                    (parsed, warnings)
+                }
+                WorkerEvent::Error {
+                    kind,
+                    message,
+                    path,
+                    detail,
+                } => (Err(reported_error(kind, message, path, detail)), warnings),
+            },
+            Err(e) => (Err(unparsable(probe_version(line.as_bytes()), e.into())), 0),
+        };
+        return Some(answer);
+    }
+}
+
+/// a worker's FolderStats against FOLDER_STATS_SCHEMA: one from a newer
+/// build is refused, an older one (migrated while parsing) is kept with a
+/// warning, as the fields added since then are empty
+fn check_schema(
+    stats: FolderStats,
+    warnings: &mut Vec<WorkerWarning>,
+) -> Result<FolderStats, IpcError> {
+    let theirs = stats.schema_version;
+    if theirs > FOLDER_STATS_SCHEMA {
+        return Err(IpcError::SchemaTooNew {
+            ours: FOLDER_STATS_SCHEMA,
+            theirs,
+        });
+    }
+    if theirs < FOLDER_STATS_SCHEMA {
+        warnings.push(WorkerWarning {
+            message: format!(
+                "result from an older worker (FolderStats schema {}, current {}); \
+                 fields added since then are empty",
+                theirs, FOLDER_STATS_SCHEMA
+            ),
+        });
+    }
+    Ok(stats)
+}
+
+/// a worker document as T: MessagePack (--worker-format bin) or JSON
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, IpcError> {
+    if looks_like_map(bytes) {
+        Ok(rmp_serde::from_slice(bytes)?)
+    } else {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// "protocol_version" field of a worker document, 0 when it has none;
+/// None = not a JSON/MessagePack map at all
+fn probe_version(doc: &[u8]) -> Option<u32> {
+    #[derive(Deserialize)]
+    struct Probe {
+        #[serde(default)]
+        protocol_version: u32,
+    }
+    decode::<Probe>(doc).ok().map(|p| p.protocol_version)
+}
+
+/// a worker of another version is refused; 0 (no field) is accepted as long
+/// as its output still parses
+fn check_version(theirs: u32) -> Result<(), IpcError> {
+    if theirs == 0 || theirs == PROTOCOL_VERSION {
+        Ok(())
+    } else {
+        Err(IpcError::VersionMismatch {
+            ours: PROTOCOL_VERSION,
+            theirs,
+        })
+    }
+}
+
+/// a document that doesn't parse: from an unversioned or other-version
+/// worker that is a version problem, otherwise plain bad output
+fn unparsable(version: Option<u32>, err: IpcError) -> IpcError {
+    match version {
+        Some(theirs) if theirs != PROTOCOL_VERSION => IpcError::VersionMismatch {
+            ours: PROTOCOL_VERSION,
+            theirs,
+        },
+        _ => err,
+    }
+}
+
+/// first byte of every gzip stream; neither JSON nor a MessagePack map
+/// starts with it
+const GZIP_MAGIC: u8 = 0x1f;
+
+/// parse one worker document (Versioned<T> on the worker side) straight
+/// off `stdout`, JSON or MessagePack, plain or gzipped (--compress), told
+/// apart by the first byte
+/// - read as it arrives: no copy of the whole document is kept while T is
+///   built; whatever follows the document (a library's stray output) is
+///   left unread, callers drain it
+/// - a syntax error is Malformed with its byte offset
+/// - the version is checked first, so another build's output gives
+///   VersionMismatch instead of a serde error about some field
+/// - NoResult when there's no document at all
+pub fn parse_worker_output<T: DeserializeOwned>(
+    stdout: &mut dyn BufRead,
+) -> Result<T, IpcError> {
+    let first = loop {
+        let buf = stdout.fill_buf().map_err(IpcError::Io)?;
+        match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(at) => {
+                let first = buf[at];
+                stdout.consume(at);
+                break first;
+            }
+            None if buf.is_empty() => return Err(IpcError::NoResult),
+            None => {
+                let skipped = buf.len();
+                stdout.consume(skipped);
+            }
+        }
+    };
+
+    if first == GZIP_MAGIC {
+        // bufread: takes only the bytes of the gzip stream off `stdout`
+        let mut decoded = BufReader::new(flate2::bufread::GzDecoder::new(stdout));
+        return parse_worker_output(&mut decoded);
+    }
+
+    let version = Cell::new(None);
+    let seed = VersionFirst {
+        version: &version,
+        body: PhantomData::<T>,
+    };
+    let mut counted = Counting {
+        inner: stdout,
+        read: 0,
+    };
+    let result = if looks_like_map(&[first]) {
+        // reads only what the value needs, so what follows stays unread
+        let value = seed.deserialize(&mut rmp_serde::Deserializer::new(&mut counted));
+        value.map_err(|e| match e {
+            rmp_serde::decode::Error::InvalidMarkerRead(e)
+            | rmp_serde::decode::Error::InvalidDataRead(e)
+                if e.kind() != io::ErrorKind::UnexpectedEof =>
+            {
+                IpcError::Io(e)
+            }
+            e if msgpack_data_error(&e) => e.into(),
+            e => IpcError::Malformed {
+                offset: counted.read,
+                message: e.to_string(),
+            },
+        })
+    } else {
+        let mut de = serde_json::Deserializer::from_reader(&mut counted);
+        seed.deserialize(&mut de).map_err(|e| match e.classify() {
+            serde_json::error::Category::Io => IpcError::Io(e.into()),
+            serde_json::error::Category::Data => e.into(),
+            _ => IpcError::Malformed {
+                offset: counted.read,
+                message: e.to_string(),
+            },
+        })
+    };
+    result.map_err(|err| match version.get() {
+        // broken off or not a JSON/MessagePack document at all: that says
+        // nothing about an unversioned worker
+        Some(0) if !is_data_error(&err) => err,
+        Some(theirs) => check_version(theirs)
+            .err()
+            .unwrap_or_else(|| unparsable(Some(theirs), err)),
+        None => err,
+    })
+}
+
+/// counts the bytes read through it, for Malformed's offset
+struct Counting<R> {
+    inner: R,
+    read: u64,
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        Ok(n)
+    }
+}
+
+/// well-formed input that just doesn't fit the expected type
+fn is_data_error(err: &IpcError) -> bool {
+    match err {
+        IpcError::InvalidJson(e) => e.classify() == serde_json::error::Category::Data,
+        IpcError::InvalidMessagePack(e) => msgpack_data_error(e),
+        _ => false,
+    }
+}
+
+/// a MessagePack error from serde (a missing field, a wrong type, a number
+/// that doesn't fit) rather than from bytes that aren't MessagePack
+/// - the reserved marker 0xc1 never starts a value: that's garbage
+fn msgpack_data_error(err: &rmp_serde::decode::Error) -> bool {
+    use rmp_serde::decode::Error;
+    match err {
+        Error::TypeMismatch(marker) => *marker != rmp::Marker::Reserved,
+        Error::Syntax(_) | Error::OutOfRange | Error::LengthMismatch(_) => true,
+        _ => false,
+    }
+}
+
+/// true when `bytes` starts like a MessagePack map, the only top-level
+/// value the worker writes; JSON starts with '{' or whitespace instead
+fn looks_like_map(bytes: &[u8]) -> bool {
+    matches!(bytes.first(), Some(0x80..=0x8f | 0xde | 0xdf))
+}
+
+/// Versioned<T> in one pass: the worker writes "protocol_version" first, so
+/// it's known (and refused when wrong) before the body is parsed
+/// - `version` is Some(0) when the first key is something else (a worker
+///   from before the field) and stays None when there's no map at all
+struct VersionFirst<'a, T> {
+    version: &'a Cell<Option<u32>>,
+    body: PhantomData<T>,
+}
+
+impl<'de, T: Deserialize<'de>> DeserializeSeed<'de> for VersionFirst<'_, T> {
+    type Value = T;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<T, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for VersionFirst<'_, T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a worker document")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+        let mut first: Option<String> = map.next_key()?;
+        if first.as_deref() == Some("protocol_version") {
+            let theirs: u32 = map.next_value()?;
+            self.version.set(Some(theirs));
+            if check_version(theirs).is_err() {
+                return Err(de::Error::custom("protocol version mismatch"));
+            }
+            first = None;
+        } else {
+            self.version.set(Some(0));
+        }
+        T::deserialize(de::value::MapAccessDeserializer::new(Replay { first, map }))
+    }
+}
+
+/// `map` with a key that was already read put back in front
+struct Replay<A> {
+    first: Option<String>,
+    map: A,
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for Replay<A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        match self.first.take() {
+            Some(key) => seed.deserialize(key.into_deserializer()).map(Some),
+            None => self.map.next_key_seed(seed),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, A::Error> {
+        self.map.next_value_seed(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.map.size_hint().map(|n| n + self.first.is_some() as usize)
+    }
+}
+
+/// the worker executable: the one WORKER_PATH_ENV names when it's set (and
+/// not empty), else this program
+/// - the override must be an existing executable file; it's used as an
+///   absolute path, never looked up in PATH
+/// - inside a Flatpak sandbox always this program: there the variable
+///   names the host worker (SpawnStrategy::choose)
+pub fn worker_exe() -> Result<PathBuf, IpcError> {
+    let value = std::env::var_os(WORKER_PATH_ENV).filter(|_| !scan::in_flatpak_sandbox());
+    worker_exe_from(value.as_deref())
+}
+
+/// worker_exe with `value` as WORKER_PATH_ENV's
+fn worker_exe_from(value: Option<&std::ffi::OsStr>) -> Result<PathBuf, IpcError> {
+    let Some(path) = value.filter(|p| !p.is_empty()) else {
+        return std::env::current_exe().map_err(IpcError::SpawnFailed);
+    };
+    let path = PathBuf::from(path);
+    let invalid = |reason: String| IpcError::InvalidWorkerPath {
+        path: path.clone(),
+        reason,
+    };
+    let meta = std::fs::metadata(&path).map_err(|e| invalid(e.to_string()))?;
+    if !meta.is_file() {
+        return Err(invalid("not a file".to_string()));
+    }
+    if !is_executable(&meta) {
+        return Err(invalid("not executable".to_string()));
+    }
+    std::path::absolute(&path).map_err(|e| invalid(e.to_string()))
+}
+
+#[cfg(unix)]
+fn is_executable(meta: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_meta: &std::fs::Metadata) -> bool {
+    true
+}
+
+/// whether ScanRequest::elevated can work here: pkexec is on PATH
+#[cfg(unix)]
+pub fn elevation_available() -> bool {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path).any(|dir| dir.join(PKEXEC).is_file())
+}
+
+/// no pkexec off Unix
+#[cfg(not(unix))]
+pub fn elevation_available() -> bool {
+    false
+}
+
+/// why the worker at `exe` must not run as root through pkexec, if so:
+/// whoever can replace it would get root from the user's password
+/// - one named by WORKER_PATH_ENV is refused outright
+/// - otherwise it, and every directory above it, must be root's and
+///   writable by nobody else
+pub fn elevation_refusal(exe: &Path) -> Option<String> {
+    let overridden = std::env::var_os(WORKER_PATH_ENV).is_some_and(|p| !p.is_empty());
+    elevation_refusal_for(exe, overridden)
+}
+
+/// elevation_refusal, told whether WORKER_PATH_ENV chose `exe`
+fn elevation_refusal_for(exe: &Path, overridden: bool) -> Option<String> {
+    if overridden {
+        return Some(format!("the worker was chosen through {}", WORKER_PATH_ENV));
+    }
+    let exe = match std::fs::canonicalize(exe) {
+        Ok(exe) => exe,
+        Err(e) => return Some(format!("{}: {}", exe.display(), e)),
+    };
+    exe.ancestors()
+        .find_map(|path| replaceable(path).map(|why| format!("{} {}", path.display(), why)))
+}
+
+/// how a user other than root could replace `path`, if one could
+#[cfg(unix)]
+fn replaceable(path: &Path) -> Option<&'static str> {
+    use std::os::unix::fs::MetadataExt;
+    match std::fs::metadata(path) {
+        Err(_) => Some("can't be checked"),
+        Ok(meta) if meta.uid() != 0 => Some("isn't owned by root"),
+        Ok(meta) if meta.mode() & 0o022 != 0 => Some("is writable by other users"),
+        Ok(_) => None,
+    }
+}
+
+/// no elevation off Unix (elevation_available), so nothing to check
+#[cfg(not(unix))]
+fn replaceable(_path: &Path) -> Option<&'static str> {
+    None
+}
+
+/// the host path the document portal recorded for one of its entries, in
+/// its user.document-portal.host-path xattr (xdg-desktop-portal 1.17+)
+#[cfg(target_os = "linux")]
+fn portal_host_path(entry: &Path) -> Option<PathBuf> {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    let entry = std::ffi::CString::new(entry.as_os_str().as_bytes()).ok()?;
+    let name = c"user.document-portal.host-path";
+    let mut value = vec![0u8; libc::PATH_MAX as usize];
+    let buf = value.as_mut_ptr().cast();
+    // SAFETY: both names are NUL-terminated and `buf` is value.len() long
+    let len = unsafe { libc::getxattr(entry.as_ptr(), name.as_ptr(), buf, value.len()) };
+    if len <= 0 {
+        return None;
+    }
+    value.truncate(len as usize);
+    // stored with its terminating NUL
+    if value.last() == Some(&0) {
+        value.pop();
+    }
+    Some(PathBuf::from(std::ffi::OsString::from_vec(value)))
+}
+
+/// no document portal off Linux
+#[cfg(not(target_os = "linux"))]
+fn portal_host_path(_entry: &Path) -> Option<PathBuf> {
+    None
+}
+
+/// whether to ask for a gzipped result (--compress): only when it can run
+/// into hundreds of MB, i.e. every matching file is listed (no
+/// max_results, the GUI's list limit 0); smaller ones aren't worth the CPU
+/// on both sides
+fn compress_result(opts: &ScanOptions) -> bool {
+    opts.max_results.is_none()
+}
+
+/// the worker executable, scheduled as `priority` says, and as root through
+/// pkexec when `elevated` (with the same arguments; pkexec wants the
+/// absolute path worker_exe gives)
+/// - `on_host`: outside the Flatpak sandbox, through `flatpak-spawn --host`
+///   (before pkexec); `exe_path` is the host's path then
+///   - --watch-bus: the host process is killed with flatpak-spawn, which
+///     passes SIGTERM on but can't catch cancel()'s late SIGKILL
+///   - its idle priority is left to the caller (--idle-priority): one set
+///     here would only slow flatpak-spawn down
+fn worker_process(
+    exe_path: &std::path::PathBuf,
+    priority: ScanPriority,
+    elevated: bool,
+    on_host: bool,
+) -> Command {
+    let mut launcher = Vec::new();
+    if on_host {
+        launcher.extend([FLATPAK_SPAWN, "--host", "--watch-bus"]);
+    }
+    if elevated {
+        launcher.push(PKEXEC);
+    }
+    let mut cmd = match launcher.split_first() {
+        Some((program, args)) => {
+            let mut cmd = Command::new(program);
+            cmd.args(args).arg(exe_path);
+            cmd
+        }
+        None => Command::new(exe_path),
+    };
+    if priority == ScanPriority::Background && !on_host {
+        idle_priority(&mut cmd);
+    }
+    cmd
+}
+
+/// where a worker's answer is read from (spawn_worker)
+#[cfg(unix)]
+type ResultStream = io::PipeReader;
+#[cfg(not(unix))]
+type ResultStream = std::process::ChildStdout;
+
+/// spawn `cmd` with its answer on a pipe of its own, passed as --result-fd:
+/// a stray println! anywhere in the worker then goes to its stdout (the
+/// GUI's) instead of into the JSON
+/// - `on_stdout`: the answer comes on stdout, for a worker under pkexec or
+///   flatpak-spawn, which pass on no other descriptors
+#[cfg(unix)]
+fn spawn_worker(cmd: &mut Command, on_stdout: bool) -> Result<(Child, ResultStream), IpcError> {
+    use std::os::fd::AsRawFd;
+    use std::os::unix::process::CommandExt;
+
+    if on_stdout {
+        let mut child = cmd.stdout(Stdio::piped()).spawn().map_err(IpcError::SpawnFailed)?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        return Ok((child, std::os::fd::OwnedFd::from(stdout).into()));
+    }
+    // both ends are close-on-exec, so workers spawned meanwhile by other
+    // threads don't get the write end; only ours keeps it, below
+    let (reader, writer) = io::pipe().map_err(IpcError::SpawnFailed)?;
+    let fd = writer.as_raw_fd();
+    cmd.arg("--result-fd").arg(fd.to_string()).stdout(Stdio::inherit());
+    // SAFETY: fcntl is async-signal-safe and only touches the child's copy
+    unsafe {
+        cmd.pre_exec(move || match libc::fcntl(fd, libc::F_SETFD, 0) {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        });
+    }
+    let child = cmd.spawn().map_err(IpcError::SpawnFailed)?;
+    // the reader sees EOF once the worker, the last holder, exits
+    drop(writer);
+    Ok((child, reader))
+}
+
+/// no fd passing: the answer comes on stdout, as before --result-fd
+#[cfg(not(unix))]
+fn spawn_worker(cmd: &mut Command, _on_stdout: bool) -> Result<(Child, ResultStream), IpcError> {
+    let mut child = cmd.stdout(Stdio::piped()).spawn().map_err(IpcError::SpawnFailed)?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    Ok((child, stdout))
+}
+
+/// run the worker at the lowest CPU priority and in the idle I/O class, so
+/// only otherwise unused disk time goes to it (ionice -c3 / nice -n19)
+#[cfg(target_os = "linux")]
+fn idle_priority(cmd: &mut Command) {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: between fork and exec only async-signal-safe calls are
+    // allowed; these are two plain syscalls on the child itself
+    unsafe {
+        cmd.pre_exec(|| {
+            lower_own_priority();
+            Ok(())
+        });
+    }
+}
+
+/// no scheduling hints off Linux: ScanPriority::Background only slows the
+/// scan itself (ScanOptions::low_priority)
+#[cfg(not(target_os = "linux"))]
+fn idle_priority(_cmd: &mut Command) {}
+
+/// the priority idle_priority gives a worker, for this process: a worker
+/// started with --idle-priority, where no pre_exec reaches
+/// - best effort: a refused call leaves the priority as it was
+#[cfg(target_os = "linux")]
+pub fn lower_own_priority() {
+    const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_long = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+
+    // SAFETY: two plain syscalls on this process, no memory passed
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, 0, 19);
+        libc::syscall(
+            libc::SYS_ioprio_set,
+            IOPRIO_WHO_PROCESS,
+            0,
+            IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+        );
+    }
+}
+
+/// nothing to lower off Linux, as for idle_priority
+#[cfg(not(target_os = "linux"))]
+pub fn lower_own_priority() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::{FileEntry, PartialScan};
+    use crate::testutil::synthetic_stats;
+    use std::collections::BTreeMap;
+
+    use crate::testutil::{TempDir, app_exe};
+
+    #[cfg(unix)]
+    use crate::testutil::fake_worker;
+
+    /// the JSON form, to compare types without PartialEq
+    fn json<T: Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    /// what the worker writes with --worker-format bin
+    fn msgpack<T: Serialize>(value: &T) -> Vec<u8> {
+        rmp_serde::to_vec_named(value).unwrap()
+    }
+
+    #[test]
+    fn folder_stats_round_trip_through_msgpack() {
+        let stats = synthetic_stats(5000);
+        let bytes = msgpack(&Versioned::new(stats.clone()));
+        assert!(looks_like_map(&bytes));
+
+        let back: FolderStats = parse_worker_output(&mut &bytes[..]).unwrap();
+        assert_eq!(json(&back), json(&stats));
+        assert_eq!(back.filtered_files[7].path, stats.filtered_files[7].path);
+        let back: Versioned<FolderStats> = decode(&bytes).unwrap();
+        assert_eq!(back.protocol_version, PROTOCOL_VERSION);
+        assert_eq!(json(&back.body), json(&stats));
+    }
+
+    #[test]
+    fn flattened_events_round_trip_through_msgpack() {
+        let events = [
+            TaggedEvent {
+                id: Some(3),
+                warnings: 2,
+                event: WorkerEvent::Result(Box::new(Versioned::new(synthetic_stats(20)))),
+            },
+            TaggedEvent {
+                id: None,
+                warnings: 0,
+                event: WorkerEvent::Error {
+                    kind: Some(ScanErrorKind::RootNotFound),
+                    message: "folder not found".to_string(),
+                    path: None,
+                    detail: Some("ENOENT".to_string()),
+                },
+            },
+            TaggedEvent {
+                id: Some(u64::MAX),
+                warnings: 0,
+                event: WorkerEvent::Binary { len: 70_000 },
+            },
+        ];
+        for event in &events {
+            let back: TaggedEvent = decode(&msgpack(event)).unwrap();
+            assert_eq!(json(&back), json(event));
+        }
+    }
+
+    #[test]
+    fn options_round_trip_through_msgpack() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Optional {
+            a: Option<u32>,
+            b: Option<String>,
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            c: Option<Vec<u8>>,
+            d: Option<Option<bool>>,
+        }
+        for value in [
+            Optional {
+                a: None,
+                b: None,
+                c: None,
+                d: None,
+            },
+            Optional {
+                a: Some(0),
+                b: Some(String::new()),
+                c: Some(vec![0, 255]),
+                d: Some(Some(false)),
+            },
+        ] {
+            assert_eq!(decode::<Optional>(&msgpack(&value)).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn older_shapes_read_from_msgpack() {
+        // schema 0: extensions as ["ext", count] pairs (untagged
+        // ExtensionStatWire), files without display/rel_path (FileEntryWire)
+        let old = serde_json::json!({
+            "total_size": 5,
+            "total_files": 1,
+            "extension_count": [["txt", 1]],
+            "filtered_files": [{"path": "/d/a.txt", "size": 5}],
+        });
+        let stats: FolderStats = parse_worker_output(&mut &msgpack(&old)[..]).unwrap();
+        assert_eq!(stats.schema_version, 0);
+        assert_eq!(stats.extension_count[0].extension, "txt");
+        assert_eq!(stats.extension_count[0].count, 1);
+        assert_eq!(stats.filtered_files[0].display, "/d/a.txt");
+        assert_eq!(stats.filtered_files[0].rel_path, "/d/a.txt");
+
+        // os_path's untagged {"bytes": [..]} form
+        let raw = serde_json::json!({"path": {"bytes": [47, 100]}, "size": 1});
+        let entry: FileEntry = decode(&msgpack(&raw)).unwrap();
+        assert_eq!(entry.path, Path::new("/d"));
+    }
+
+    #[test]
+    fn maps_of_unknown_length_get_the_right_header() {
+        // flatten makes serde_derive write a map of unknown length: its
+        // header is only known once every entry is written
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Flat {
+            id: u64,
+            #[serde(flatten)]
+            rest: BTreeMap<String, u64>,
+        }
+        for (len, marker) in [(3, 0x84), (20, 0xde), (70_000, 0xdf)] {
+            let value = Flat {
+                id: 1,
+                rest: (0..len).map(|i| (format!("k{}", i), i)).collect(),
+            };
+            let bytes = msgpack(&value);
+            assert_eq!(bytes[0], marker, "{} entries", len);
+            assert_eq!(decode::<Flat>(&bytes).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn broken_msgpack_is_malformed_and_misfits_are_not() {
+        let bytes = msgpack(&Versioned::new(synthetic_stats(50)));
+        let cut = &bytes[..bytes.len() / 2];
+        match parse_worker_output::<FolderStats>(&mut &cut[..]) {
+            Err(IpcError::Malformed { offset, .. }) => assert_eq!(offset, cut.len() as u64),
+            other => panic!("expected Malformed, got {:?}", other.map(|_| ())),
+        }
+
+        let misfit = serde_json::json!({"protocol_version": PROTOCOL_VERSION, "total_size": "x"});
+        let err = parse_worker_output::<FolderStats>(&mut &msgpack(&misfit)[..]).unwrap_err();
+        assert!(matches!(err, IpcError::InvalidMessagePack(_)), "{:?}", err);
+        assert!(is_data_error(&err));
+
+        let other = serde_json::json!({"protocol_version": PROTOCOL_VERSION + 1, "x": 1});
+        let err = parse_worker_output::<FolderStats>(&mut &msgpack(&other)[..]).unwrap_err();
+        assert!(matches!(err, IpcError::VersionMismatch { .. }), "{:?}", err);
+    }
+
+    /// `bytes` gzipped, as the worker writes them with --compress
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn large_results_round_trip_gzipped() {
+        let stats = synthetic_stats(50_000);
+        let document = Versioned::new(stats.clone());
+        for plain in [msgpack(&document), serde_json::to_vec(&document).unwrap()] {
+            let bytes = gzip(&plain);
+            assert_eq!(bytes[0], GZIP_MAGIC);
+            assert!(bytes.len() < plain.len());
+
+            let back: FolderStats = parse_worker_output(&mut &bytes[..]).unwrap();
+            assert_eq!(json(&back), json(&stats));
+            assert_eq!(back.filtered_files[17].path, stats.filtered_files[17].path);
+            let cut_off = parse_worker_output::<FolderStats>(&mut &bytes[..bytes.len() / 2]);
+            assert!(cut_off.is_err());
+        }
+    }
+
+    /// one WorkerClient scan by the fake worker `body`
+    #[cfg(unix)]
+    fn fake_scan(body: &str) -> Result<(FolderStats, Vec<WorkerWarning>), IpcError> {
+        let dir = TempDir::new("fake-scan");
+        let mut client = WorkerClient::new(fake_worker(&dir, "worker", body));
+        let request = ScanRequest::new(ScanOptions::new(dir.path()));
+        client.start(&request, None, None)?.wait(&mut |_| {})
+    }
+
+    /// a fake worker that takes its request, prints `stderr` and exits
+    /// `code`; reading first keeps the client's write from failing
+    #[cfg(unix)]
+    fn exiting(code: i32, stderr: &str) -> IpcError {
+        fake_scan(&format!("read request\necho '{}' >&2\nexit {}", stderr, code))
+            .map(|_| ())
+            .unwrap_err()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exit_codes_map_to_ipc_errors() {
+        match exiting(1, "unknown flag: --bogus") {
+            IpcError::Rejected(message) => assert_eq!(message, "unknown flag: --bogus"),
+            err => panic!("exit 1: {:?}", err),
+        }
+        match exiting(2, "serialization error") {
+            err @ IpcError::WorkerFailed {
+                exit_code: Some(2),
+                signal: None,
+                retried: false,
+                ..
+            } => assert!(!err.is_crash()),
+            err => panic!("exit 2: {:?}", err),
+        }
+        match exiting(3, "bad glob") {
+            IpcError::ScanFailed {
+                kind: ScanErrorKind::InvalidOptions,
+                message,
+                path: None,
+                detail: None,
+            } => assert_eq!(message, "bad glob"),
+            err => panic!("exit 3: {:?}", err),
+        }
+        assert!(matches!(exiting(4, ""), IpcError::Cancelled));
+        match exiting(7, "") {
+            IpcError::ScanFailed { kind: ScanErrorKind::Io, .. } => {}
+            err => panic!("exit 7: {:?}", err),
+        }
+        assert!(matches!(exiting(0, ""), IpcError::NoResult));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn error_line_adds_details_when_the_exit_code_agrees() {
+        let line = r#"{"type":"error","kind":"root_not_found","message":"gone","path":"/x"}"#;
+        match exiting(5, line) {
+            IpcError::ScanFailed {
+                kind: ScanErrorKind::RootNotFound,
+                message,
+                path,
+                ..
+            } => assert_eq!((message.as_str(), path.as_deref()), ("gone", Some("/x"))),
+            err => panic!("exit 5: {:?}", err),
+        }
+        // the exit code wins over a line that disagrees
+        match exiting(3, line) {
+            IpcError::ScanFailed {
+                kind: ScanErrorKind::InvalidOptions,
+                path: None,
+                ..
+            } => {}
+            err => panic!("exit 3: {:?}", err),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn killed_worker_is_a_crash() {
+        match fake_scan("read request\nkill -KILL $$").map(|_| ()).unwrap_err() {
+            err @ IpcError::WorkerFailed {
+                exit_code: None,
+                signal: Some(9),
+                ..
+            } => assert!(err.is_crash()),
+            err => panic!("killed: {:?}", err),
+        }
+    }
+
+    /// the arguments of every run of the fake worker `body`, which records
+    /// them first, and how a one-runner pool's scan by it ended
+    #[cfg(unix)]
+    fn pool_scan(body: &str) -> (Vec<String>, Result<FolderStats, IpcError>) {
+        let dir = TempDir::new("pool-scan");
+        let script = format!("echo \"$@\" >> \"$0.args\"\nread request\n{}", body);
+        let exe = fake_worker(&dir, "worker", &script);
+        let pool = WorkerPool::new(exe.clone(), 1);
+        let result = pool.run(ScanRequest::new(ScanOptions::new(dir.path())), None, &mut |_| {});
+        let args = std::fs::read_to_string(exe.with_extension("args")).unwrap_or_default();
+        (args.lines().map(str::to_string).collect(), result.map(|(stats, _)| stats))
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn crashed_worker_is_retried_on_one_thread() {
+        let crash_once = "[ -e \"$0.crashed\" ] || { touch \"$0.crashed\"; kill -KILL $$; }\n\
+                          echo 'second run' >&2\nexit 3";
+        let (args, result) = pool_scan(crash_once);
+        match result {
+            Err(IpcError::ScanFailed { message, .. }) => assert_eq!(message, "second run"),
+            other => panic!("after the retry: {:?}", other.map(|_| ())),
+        }
+        assert_eq!(args.len(), 2);
+        assert!(!args[0].contains("--threads"), "{}", args[0]);
+        assert!(args[1].contains("--threads 1"), "{}", args[1]);
+
+        let (args, result) = pool_scan("kill -KILL $$");
+        assert!(
+            matches!(result, Err(IpcError::WorkerFailed { retried: true, .. })),
+            "{:?}",
+            result.map(|_| ())
+        );
+        assert_eq!(args.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serialization_failure_is_not_retried() {
+        let (args, result) = pool_scan("exit 2");
+        match result {
+            Err(IpcError::WorkerFailed {
+                exit_code: Some(2),
+                retried: false,
+                ..
+            }) => {}
+            other => panic!("exit 2: {:?}", other.map(|_| ())),
+        }
+        assert_eq!(args.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unlimited_file_list_asks_for_gzip() {
+        let dir = TempDir::new("compress");
+        let exe = fake_worker(&dir, "worker", "echo \"$@\" >> \"$0.args\"\nread request\nexit 3");
+        let mut client = WorkerClient::new(exe.clone());
+        for max_results in [Some(500), None] {
+            let request = ScanRequest::new(ScanOptions::new(dir.path()).max_results(max_results));
+            let scan = client.start(&request, None, None).unwrap();
+            assert!(scan.wait(&mut |_| {}).is_err());
+        }
+        let args = std::fs::read_to_string(exe.with_extension("args")).unwrap();
+        let args: Vec<&str> = args.lines().collect();
+        assert_eq!(args.len(), 2);
+        assert!(!args[0].contains("--compress"), "{}", args[0]);
+        assert!(args[1].contains("--compress"), "{}", args[1]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stalled_scan_is_reported_and_keeps_running() {
+        let dir = TempDir::new("stall");
+        // takes the request, then says nothing
+        let exe = fake_worker(&dir, "worker", "read request\nexec sleep 30");
+        let pool = WorkerPool::new(exe, 1).stall_after(Some(Duration::from_millis(200)));
+        let (events_tx, events) = mpsc::channel();
+        let id = pool.submit(ScanRequest::new(ScanOptions::new(dir.path())), None, events_tx);
+        let stall = loop {
+            match events.recv_timeout(Duration::from_secs(10)).expect("a Stalled event") {
+                PoolEvent::Stalled { id: of, stall } if of == id => break stall,
+                PoolEvent::Done { .. } => panic!("the stall ended the scan"),
+                _ => {}
+            }
+        };
+        assert_eq!(stall.dir, dir.path().to_string_lossy());
+        assert!(stall.last_activity >= Duration::from_millis(200));
+
+        // reported once, and left running until cancelled
+        assert!(events.recv_timeout(Duration::from_millis(500)).is_err());
+        pool.cancel(id);
+        let result = wait_for_done(&events, id, &mut |_| {});
+        assert!(matches!(result, Err(IpcError::Cancelled)), "{:?}", result.map(|_| ()));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn background_priority_reaches_the_worker() {
+        let dir = TempDir::new("priority");
+        // field 19 of /proc/PID/stat is the nice value
+        let script = "read request\ncut -d' ' -f19 /proc/$$/stat >> \"$0.nice\"\nexit 3";
+        let exe = fake_worker(&dir, "worker", script);
+        let mut client = WorkerClient::new(exe.clone());
+        for gentle in [false, true] {
+            let request = ScanRequest::new(ScanOptions::new(dir.path()).low_priority(gentle));
+            let scan = client.start(&request, None, None).unwrap();
+            assert!(scan.wait(&mut |_| {}).is_err());
+        }
+        // SAFETY: getpriority has no preconditions
+        let ours = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+        let nice = std::fs::read_to_string(exe.with_extension("nice")).unwrap();
+        assert_eq!(nice.lines().collect::<Vec<_>>(), [ours.to_string(), "19".to_string()]);
+    }
+
+    const BACKENDS: [ScanBackend; 2] = [ScanBackend::Subprocess, ScanBackend::InProcess];
+
+    /// a small tree with a bit of everything the options below look at
+    fn backend_fixture() -> TempDir {
+        let dir = TempDir::new("backends");
+        dir.file("a.txt", b"hello");
+        dir.file("empty.log", b"");
+        dir.file("sub/b.rs", &[7; 3000]);
+        dir.file("sub/deeper/c.jpg", &[1; 70_000]);
+        dir.file(".hidden/d.txt", b"x");
+        dir
+    }
+
+    /// `request` on a one-runner pool per backend: a real worker process,
+    /// then in-process
+    fn on_both_backends(
+        request: &ScanRequest,
+    ) -> Vec<Result<(FolderStats, Vec<String>), IpcError>> {
+        BACKENDS
+            .iter()
+            .map(|&backend| {
+                let pool = WorkerPool::new(app_exe(), 1);
+                pool.set_backend(backend);
+                let result = pool.run(request.clone(), None, &mut |_| {});
+                result.map(|(stats, warnings)| {
+                    (stats, warnings.into_iter().map(|w| w.message).collect())
+                })
+            })
+            .collect()
+    }
+
+    /// a result as JSON, without the timings and thread count of scan_meta
+    fn comparable(stats: &FolderStats) -> serde_json::Value {
+        let mut value = json(stats);
+        value.as_object_mut().expect("an object").remove("scan_meta");
+        value
+    }
+
+    #[test]
+    fn backends_give_the_same_results() {
+        let dir = backend_fixture();
+        let base = || ScanOptions::new(dir.path()).use_cache(false);
+        for opts in [
+            base(),
+            base().skip_hidden(true).exclude("*.log"),
+            base().max_depth(Some(1)).max_results(Some(1)),
+            base().hash_filtered(true).find_empty_files(true).find_empty_dirs(true),
+            base().only_extensions(Some(vec!["rs".to_string()])).min_size(1),
+        ] {
+            let results: Vec<_> = on_both_backends(&ScanRequest::new(opts))
+                .into_iter()
+                .map(|result| result.map(|(stats, warnings)| (comparable(&stats), warnings)))
+                .collect::<Result<_, _>>()
+                .unwrap();
+            // a Subprocess that fell back to InProcess would also add a warning
+            assert_eq!(results[0], results[1]);
+        }
+    }
+
+    #[test]
+    fn backends_fail_the_same_way() {
+        let dir = backend_fixture();
+        for opts in [
+            ScanOptions::new(dir.path().join("missing")),
+            ScanOptions::new(dir.path().join("a.txt")),
+            ScanOptions::new(dir.path()).min_size(10).max_size(Some(5)),
+        ] {
+            let request = ScanRequest::new(opts.use_cache(false));
+            let errors: Vec<String> = on_both_backends(&request)
+                .into_iter()
+                .map(|result| match result {
+                    Err(err @ IpcError::ScanFailed { .. }) => format!("{:?}", err),
+                    other => panic!("not a scan error: {:?}", other.map(|_| ())),
+                })
+                .collect();
+            assert_eq!(errors[0], errors[1]);
+        }
+    }
+
+    #[test]
+    fn backends_stop_the_same_way() {
+        let dir = TempDir::new("backends-cancel");
+        for i in 0..3000 {
+            dir.file(&format!("d{}/e{}/f.txt", i % 100, i), b"x");
+        }
+        // gentle, so the walk takes a while after its first progress line
+        let opts = ScanOptions::new(dir.path()).use_cache(false).low_priority(true);
+        for backend in BACKENDS {
+            let pool = WorkerPool::new(app_exe(), 1);
+            pool.set_backend(backend);
+            let (events_tx, events) = mpsc::channel();
+            let id = pool.submit(ScanRequest::new(opts.clone()), None, events_tx);
+            let result = wait_for_done(&events, id, &mut |_| {
+                pool.cancel(id);
+            });
+            let (stats, _) = result.unwrap_or_else(|e| panic!("{:?}: {}", backend, e));
+            let partial = stats.partial.expect("a partial result");
+            assert_eq!(partial.reason, StopReason::Cancelled, "{:?}", backend);
+            assert!(!partial.totals_only, "{:?}", backend);
+            assert!(stats.total_files < 3000, "{:?}: {}", backend, stats.total_files);
+        }
+    }
+
+    /// a result of each FolderStats schema there has been, as saved then
+    const SCHEMA_FIXTURES: [(u32, &str); 4] = [
+        (0, include_str!("../tests/fixtures/schema_v0.json")),
+        (1, include_str!("../tests/fixtures/schema_v1.json")),
+        (2, include_str!("../tests/fixtures/schema_v2.json")),
+        (3, include_str!("../tests/fixtures/schema_v3.json")),
+    ];
+
+    fn fixture(text: &str) -> FolderStats {
+        parse_worker_output(&mut text.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn every_schema_fixture_loads() {
+        // a bump of FOLDER_STATS_SCHEMA needs a fixture of its own
+        assert_eq!(SCHEMA_FIXTURES.last().map(|f| f.0), Some(FOLDER_STATS_SCHEMA));
+        for (version, text) in SCHEMA_FIXTURES {
+            let stats = fixture(text);
+            assert_eq!(stats.schema_version, version);
+            assert_eq!((stats.total_files, stats.total_size), (2, 1500));
+            let exts: Vec<_> =
+                stats.extension_count.iter().map(|e| (e.extension.as_str(), e.count)).collect();
+            assert_eq!(exts, [("jpg", 1), ("txt", 1)], "schema {}", version);
+            let photo = &stats.filtered_files[0];
+            assert_eq!((photo.path.as_path(), photo.size), (Path::new("/data/photos/a.jpg"), 1000));
+            let mtime = std::time::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+            assert_eq!(photo.mtime, Some(mtime));
+
+            let mut warnings = Vec::new();
+            check_schema(stats, &mut warnings).unwrap();
+            let warned = warnings.iter().any(|w| w.message.contains("older worker"));
+            assert_eq!(warned, version < FOLDER_STATS_SCHEMA, "schema {}", version);
+        }
+    }
+
+    #[test]
+    fn older_schemas_migrate_what_they_lack() {
+        // 0: ["ext", count] pairs without sizes, files without display/rel_path
+        let v0 = fixture(SCHEMA_FIXTURES[0].1);
+        let jpg = &v0.extension_count[0];
+        assert_eq!((jpg.total_size, jpg.median_size, jpg.median_exact), (0, 0, false));
+        let photo = &v0.filtered_files[0];
+        assert_eq!(photo.display, "/data/photos/a.jpg");
+        assert_eq!(photo.rel_path, "/data/photos/a.jpg");
+        assert_eq!(v0.filtered_files[1].mtime, None);
+
+        // 1: full ExtensionStat (defaults for what an entry leaves out), no partial
+        let v1 = fixture(SCHEMA_FIXTURES[1].1);
+        let txt = &v1.extension_count[1];
+        assert_eq!((txt.total_size, txt.mean_size, txt.median_exact), (500, 0, true));
+        assert_eq!(v1.filtered_files[0].rel_path, "photos/a.jpg");
+        assert_eq!(v1.partial, None);
+
+        // 2: partial; 3: scan_meta.sandboxed
+        let v2 = fixture(SCHEMA_FIXTURES[2].1);
+        assert_eq!(v2.partial.map(|p| p.reason), Some(StopReason::Cancelled));
+        assert!(!v2.scan_meta.sandboxed);
+        assert!(fixture(SCHEMA_FIXTURES[3].1).scan_meta.sandboxed);
+    }
+
+    #[test]
+    fn too_new_schema_is_refused() {
+        // its unknown field parses (and is dropped): check_schema is what refuses it
+        let stats = fixture(include_str!("../tests/fixtures/schema_too_new.json"));
+        match check_schema(stats, &mut Vec::new()) {
+            Err(IpcError::SchemaTooNew { ours, theirs: 4 }) if ours == FOLDER_STATS_SCHEMA => {}
+            other => panic!("not SchemaTooNew: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn terminate_all_reaps_a_worker_that_ignores_sigterm() {
+        let dir = TempDir::new("terminate");
+        let script = "trap '' TERM\necho $$ > \"$0.pid\"\nread request\nexec sleep 30";
+        let exe = fake_worker(&dir, "worker", script);
+        let pool = WorkerPool::new(exe.clone(), 1);
+        let (events_tx, events) = mpsc::channel();
+        let id = pool.submit(ScanRequest::new(ScanOptions::new(dir.path())), None, events_tx);
+        let pid_file = exe.with_extension("pid");
+        let pid = loop {
+            match std::fs::read_to_string(&pid_file).map(|text| text.trim().parse::<i32>()) {
+                Ok(Ok(pid)) => break pid,
+                _ => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        // the runner registers the worker with its job once it's spawned
+        thread::sleep(Duration::from_millis(100));
+
+        let started = Instant::now();
+        pool.terminate_all();
+        assert!(started.elapsed() < 3 * EXIT_GRACE, "{:?}", started.elapsed());
+        // reaped, not just killed: a zombie would still take signal 0
+        assert_eq!(unsafe { libc::kill(pid, 0) }, -1);
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::ESRCH));
+        match wait_for_done(&events, id, &mut |_| {}) {
+            Err(IpcError::Cancelled) => {}
+            other => panic!("not cancelled: {:?}", other.map(|_| ())),
+        }
+    }
+
+    /// what the worker writes by default, --worker-format json
+    fn worker_json(stats: &FolderStats) -> Vec<u8> {
+        serde_json::to_vec(&Versioned::new(stats)).unwrap()
+    }
+
+    #[test]
+    fn junk_after_the_document_is_left_unread() {
+        let stats = synthetic_stats(20);
+        let mut bytes = worker_json(&stats);
+        bytes.extend_from_slice(b"\nlibfoo: warning: \xff\n{\"not\": \"this\"}\n");
+        let mut rest = &bytes[..];
+        let parsed: FolderStats = parse_worker_output(&mut rest).unwrap();
+        assert_eq!(json(&parsed), json(&stats));
+        assert_eq!(rest, b"\nlibfoo: warning: \xff\n{\"not\": \"this\"}\n");
+    }
+
+    #[test]
+    fn junk_before_the_document_is_malformed() {
+        let stats = synthetic_stats(20);
+        let mut bytes = b" \n\t".to_vec();
+        bytes.extend_from_slice(&worker_json(&stats));
+        let parsed: FolderStats = parse_worker_output(&mut &bytes[..]).unwrap();
+        assert_eq!(json(&parsed), json(&stats));
+
+        let mut bytes = b"libfoo: warning\n".to_vec();
+        bytes.extend_from_slice(&worker_json(&stats));
+        match parse_worker_output::<FolderStats>(&mut &bytes[..]) {
+            Err(IpcError::Malformed { offset: 1, .. }) => {}
+            other => panic!("expected Malformed at 1, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn malformed_json_says_where() {
+        let bytes = worker_json(&synthetic_stats(20));
+        let at = bytes.windows(13).position(|w| w == b"\"total_files\"").unwrap();
+        let mut broken = bytes.clone();
+        broken[at] = b'#';
+        match parse_worker_output::<FolderStats>(&mut &broken[..]) {
+            // up to and including the byte that broke it
+            Err(IpcError::Malformed { offset, .. }) => assert_eq!(offset, at as u64 + 1),
+            other => panic!("expected Malformed, got {:?}", other.map(|_| ())),
+        }
+
+        let cut = &bytes[..bytes.len() / 2];
+        match parse_worker_output::<FolderStats>(&mut &cut[..]) {
+            Err(IpcError::Malformed { offset, .. }) => assert_eq!(offset, cut.len() as u64),
+            other => panic!("expected Malformed, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names_survive_json() {
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+        let mut stats = synthetic_stats(1);
+        let name = b"/d/caf\xe9.txt".to_vec();
+        stats.filtered_files[0].path = PathBuf::from(std::ffi::OsString::from_vec(name.clone()));
+        // written escaped (os_path's {"bytes": [..]}), so the document is UTF-8
+        let bytes = worker_json(&stats);
+        assert!(std::str::from_utf8(&bytes).is_ok());
+        let parsed: FolderStats = parse_worker_output(&mut &bytes[..]).unwrap();
+        assert_eq!(parsed.filtered_files[0].path.as_os_str().as_bytes(), &name[..]);
+
+        // a raw invalid byte in a string is an error, not a silent U+FFFD
+        let raw = format!(
+            "{{\"protocol_version\":{},\"total_size\":1,\"total_files\":1,\
+             \"extension_count\":[[\"caf?\",1]],\"filtered_files\":[]}}",
+            PROTOCOL_VERSION
+        );
+        let mut raw = raw.into_bytes();
+        let at = raw.iter().position(|&b| b == b'?').unwrap();
+        raw[at] = 0xe9;
+        match parse_worker_output::<FolderStats>(&mut &raw[..]) {
+            Err(IpcError::Malformed { offset, .. }) => assert!(offset > at as u64, "{}", offset),
+            other => panic!("expected Malformed, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn worker_killed_before_its_partial_result_leaves_the_totals() {
+        let dir = TempDir::new("totals-only");
+        // one progress line on --result-fd, then deaf to SIGTERM until
+        // CANCEL_GRACE's SIGKILL
+        let script = "while [ \"$1\" != --result-fd ]; do shift; done\neval \"exec >&$2\"\n\
+                      read request\nid=${request#*'\"id\":'}\nid=${id%%,*}\n\
+                      printf '{\"id\":%s,\"type\":\"progress\",\"files\":42,\"bytes\":4200,\
+                      \"dir\":\"/d\"}\\n' \"$id\"\ntrap '' TERM\nexec sleep 30";
+        let exe = fake_worker(&dir, "worker", script);
+        let pool = WorkerPool::new(exe, 1);
+        let (events_tx, events) = mpsc::channel();
+        let id = pool.submit(ScanRequest::new(ScanOptions::new(dir.path())), None, events_tx);
+        let (stats, _) = wait_for_done(&events, id, &mut |_| {
+            pool.cancel(id);
+        })
+        .unwrap();
+        let partial = PartialScan {
+            reason: StopReason::Cancelled,
+            totals_only: true,
+        };
+        assert_eq!(stats.partial, Some(partial));
+        assert_eq!((stats.total_files, stats.total_size), (42, 4200));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn worker_path_override_is_checked() {
+        let dir = TempDir::new("worker-path");
+        let exe = std::env::current_exe().unwrap();
+        assert_eq!(worker_exe_from(None).unwrap(), exe);
+        assert_eq!(worker_exe_from(Some("".as_ref())).unwrap(), exe);
+
+        let plain = dir.file("plain", b"#!/bin/sh\n");
+        for (path, reason) in [
+            (dir.path().join("missing"), "os error 2"),
+            (dir.path().to_path_buf(), "not a file"),
+            (plain, "not executable"),
+        ] {
+            match worker_exe_from(Some(path.as_os_str())) {
+                Err(IpcError::InvalidWorkerPath { path: p, reason: r }) if p == path => {
+                    assert!(r.contains(reason), "{}: {}", path.display(), r);
+                }
+                other => panic!("{} accepted: {:?}", path.display(), other),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn worker_path_override_is_what_runs() {
+        let dir = TempDir::new("worker-override");
+        let body = format!("touch \"$0.ran\"\nexec '{}' \"$@\"", app_exe().display());
+        let script = fake_worker(&dir, "worker", &body);
+        let exe = worker_exe_from(Some(script.as_os_str())).unwrap();
+        assert_eq!(exe, script);
+
+        dir.file("data/a.txt", b"hello");
+        let pool = WorkerPool::new(exe, 1);
+        let request = ScanRequest::new(ScanOptions::new(dir.path().join("data")).use_cache(false));
+        let (stats, warnings) = pool.run(request, None, &mut |_| {}).unwrap();
+        assert_eq!((stats.total_files, stats.total_size), (1, 5));
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert!(script.with_extension("ran").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn elevation_needs_a_worker_only_root_can_replace() {
+        let dir = TempDir::new("elevation");
+        let script = fake_worker(&dir, "worker", "exit 0");
+        let refused = elevation_refusal_for(&script, false).expect("a temp file");
+        // the temp dir, or /tmp above it, is the weak spot
+        assert!(refused.contains("owned by root") || refused.contains("writable"), "{}", refused);
+        let refused = elevation_refusal_for(&dir.path().join("missing"), false);
+        assert!(refused.is_some_and(|r| r.contains("missing")));
+
+        let system = Path::new("/bin/sh");
+        assert_eq!(elevation_refusal_for(system, false), None);
+        let refused = elevation_refusal_for(system, true).expect("an override");
+        assert!(refused.contains(WORKER_PATH_ENV), "{}", refused);
+
+        let mut client = WorkerClient::new(script);
+        let request = ScanRequest::new(ScanOptions::new(dir.path())).elevated();
+        match client.start(&request, None, None) {
+            Err(IpcError::ElevationRefused(_)) => {}
+            other => panic!("not refused: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reaping_a_process_can_give_up() {
+        let child = Command::new("sleep")
+            .arg("30")
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let process = Process::new(child);
+        let started = Instant::now();
+        assert!(!process.reap_within(Duration::from_millis(100)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        let _ = lock_child(&process.child).kill();
+        assert!(process.reap_within(EXIT_GRACE));
+    }
+
+    /// a /.flatpak-info as Flatpak writes it, with keys of other groups that
+    /// must not count
+    const FLATPAK_INFO_TEXT: &str = "[Application]\nname=org.example.Fm\n\n[Instance]\n\
+        instance-id=1234\napp-path=/var/lib/flatpak/app/org.example.Fm/current/files\n\
+        runtime-path = /var/lib/flatpak/runtime/org.gnome.Platform/47/files \n\n\
+        [Context]\napp-path=/not/this\n";
+
+    fn sandbox() -> FlatpakSandbox {
+        FlatpakSandbox::parse(FLATPAK_INFO_TEXT)
+    }
+
+    #[test]
+    fn flatpak_info_gives_the_host_mounts() {
+        let expected = FlatpakSandbox {
+            app_path: PathBuf::from("/var/lib/flatpak/app/org.example.Fm/current/files"),
+            runtime_path: PathBuf::from("/var/lib/flatpak/runtime/org.gnome.Platform/47/files"),
+        };
+        assert_eq!(sandbox(), expected);
+        assert_eq!(FlatpakSandbox::parse(""), FlatpakSandbox::default());
+        let odd = FlatpakSandbox::parse("app-path=/before/any/group\n[Instance]\nno equals sign\n");
+        assert_eq!(odd, FlatpakSandbox::default());
+    }
+
+    #[test]
+    fn sandbox_paths_translate_to_the_host() {
+        let sandbox = sandbox();
+        let host = |path: &str| sandbox.host_path(Path::new(path));
+        let app = &sandbox.app_path;
+        assert_eq!(host("/app/share/icons").unwrap(), app.join("share/icons"));
+        assert_eq!(host("/app").unwrap(), *app);
+        assert_eq!(host("/usr/lib").unwrap(), sandbox.runtime_path.join("lib"));
+        assert_eq!(host("/run/host/etc/fstab").unwrap(), Path::new("/etc/fstab"));
+        assert_eq!(host("/run/host").unwrap(), Path::new("/"));
+        for same in ["/home/u/Documents", "/media/disk", "/mnt/nas/share"] {
+            assert_eq!(host(same).unwrap(), Path::new(same));
+        }
+        for (path, why) in [
+            ("docs/a", "absolute"),
+            ("/home/u/../../etc", ".."),
+            ("/tmp/x", "only inside"),
+            ("/etc", "only inside"),
+            ("/run/user/1000/bus", "only inside"),
+            // no portal here to have recorded a host path
+            ("/run/user/1000/doc/a1b2/Photos/2024", "document portal"),
+        ] {
+            let err = host(path).unwrap_err();
+            assert!(err.starts_with(path) && err.contains(why), "{}", err);
+        }
+        let err = FlatpakSandbox::default().host_path(Path::new("/app/x")).unwrap_err();
+        assert!(err.contains("no host path"), "{}", err);
+    }
+
+    #[test]
+    fn host_scans_need_a_sandbox_and_a_host_worker() {
+        let sandbox = sandbox();
+        let choose = |requested, worker: Option<&str>| {
+            SpawnStrategy::choose(Some(&sandbox), requested, worker.map(std::ffi::OsStr::new))
+        };
+        let worker = "/opt/fm/foldermanager-app";
+        assert_eq!(choose(false, Some(worker)), Ok(SpawnStrategy::Direct));
+        assert!(SpawnStrategy::choose(None, true, Some(worker.as_ref())).is_err());
+        for missing in [None, Some("")] {
+            assert!(choose(true, missing).unwrap_err().contains(WORKER_PATH_ENV));
+        }
+        assert!(choose(true, Some("bin/fm")).unwrap_err().contains("absolute"));
+        let host = SpawnStrategy::FlatpakHost {
+            sandbox: sandbox.clone(),
+            exe: PathBuf::from(worker),
+        };
+        assert_eq!(choose(true, Some(worker)), Ok(host));
+    }
+
+    #[test]
+    fn host_results_come_back_with_sandbox_paths() {
+        let spawn =
+            SpawnStrategy::choose(Some(&sandbox()), true, Some("/opt/fm".as_ref())).unwrap();
+        let options = ScanOptions::new("/run/host/srv/data").extra_root("/home/u");
+        let request = ScanRequest::new(options);
+        let (host, exe) = spawn.host_request(&request).unwrap().expect("FlatpakHost");
+        assert_eq!(exe, Path::new("/opt/fm"));
+        assert_eq!(host.options.path, Path::new("/srv/data"));
+        assert_eq!(host.options.extra_roots, [PathBuf::from("/home/u")]);
+
+        let mut stats = synthetic_stats(2);
+        stats.filtered_files[0].path = PathBuf::from("/srv/data/a.txt");
+        stats.filtered_files[1].path = PathBuf::from("/elsewhere/b.txt");
+        stats.filtered_files[1].display = "/elsewhere/b.txt".to_string();
+        stats.empty_dirs = vec!["/srv/data/empty".to_string(), "/home/u/e".to_string()];
+        stats.deepest_path = Some(PathBuf::from("/srv/data/x/y"));
+        sandbox_paths(&mut stats, &host, &request);
+        let moved = &stats.filtered_files[0];
+        assert_eq!(moved.path, Path::new("/run/host/srv/data/a.txt"));
+        assert_eq!(moved.display, "/run/host/srv/data/a.txt");
+        assert_eq!(stats.filtered_files[1].display, "/elsewhere/b.txt");
+        assert_eq!(stats.empty_dirs, ["/run/host/srv/data/empty", "/home/u/e"]);
+        assert_eq!(stats.deepest_path.as_deref(), Some(Path::new("/run/host/srv/data/x/y")));
+        // a longer name isn't below the root
+        assert_eq!(sandbox_path(Path::new("/srv/database"), &host, &request), None);
+    }
+
+    #[test]
+    fn host_workers_go_through_flatpak_spawn() {
+        let exe = PathBuf::from("/opt/fm");
+        let cmd = worker_process(&exe, ScanPriority::Background, false, true);
+        assert_eq!(cmd.get_program(), FLATPAK_SPAWN);
+        assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["--host", "--watch-bus", "/opt/fm"]);
+        let cmd = worker_process(&exe, ScanPriority::Normal, true, true);
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(args, ["--host", "--watch-bus", PKEXEC, "/opt/fm"]);
+    }
 }
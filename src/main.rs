@@ -1,7 +1,10 @@
 // src/main.rs
+mod category;
 mod scan;
 mod ipc;
 mod ui;
+#[cfg(test)]
+mod testutil;
 
 use gtk4::prelude::*;
 use gtk4::Application;
@@ -10,45 +13,1007 @@ use std::env;
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    // Worker mode: --worker <folder> <min_bytes>
-    if args.len() > 1 && args[1] == "--worker" {
+    // Worker mode: --worker <folder> <min_bytes> [max_depth] [max_size] [flags]
+    //            | --worker --options <json> [flags]
+    //            | --worker-json [flags]   (ipc::ScanRequest on stdin)
+    //            | --worker-serve [--worker-format F] [--threads N] [--compress]
+    //              [--cancel-on-eof]   (ipc::WorkerClient)
+    //            | --diff <before.json> <after.json>
+    if args.len() > 1 && (args[1] == "--worker" || args[1] == "--worker-json") {
         run_worker(&args);
         return;
     }
+    if args.len() > 1 && args[1] == "--worker-serve" {
+        run_worker_serve(&args[2..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "--diff" {
+        run_diff(&args[2..]);
+        return;
+    }
 
-    // GUI mode
+    // GUI mode; its workers run this program again, or the one
+    // FSCAN_WORKER_PATH names (ipc::worker_exe)
     let app = Application::new(Some("com.example.fscan_gui_stats"), Default::default());
     app.connect_activate(ui::build_ui);
     app.run();
 }
 
-fn run_worker(args: &[String]) {
-    use crate::scan::scan_folder;
-    use serde_json::to_string;
+const WORKER_USAGE: &str = "Usage: --worker <folder_path> <min_size_bytes> [max_depth] [max_size] [flags]
+       --worker --options <scan_options_json> [flags]
+       --worker-json [flags]   (reads {\"version\":2,\"options\":{...}} from stdin)
+       --worker-serve [--worker-format F] [--threads N] [--compress]   (one
+                      {\"id\":N,\"version\":2,\"options\":{...}} per stdin line, answered
+                      like --stream with \"id\":N on each line)
+                      [--cancel-on-eof]   (stdin closing cancels the running scan,
+                      like SIGTERM; for a worker the GUI can't signal)
+                      [--idle-priority]   (lowest CPU and I/O priority, for a
+                      worker started where the GUI can't set it)
+       --diff <before.json> <after.json>   (two saved json results of the same
+                      folder -> what changed between them, as one JSON line)
+  max_depth           optional; 1 = only files directly inside the folder
+  max_size            optional; like --max-size
+Flags:
+  --root PATH         scan another folder into the same report, repeatable
+  --max-size SIZE     list only files up to SIZE (bytes, or e.g. '4 KB'; must be >= min)
+  --sort-by KEY[:ORD] order filtered_files by size|name|path|mtime, asc|desc (default size:desc)
+  --max-results N     keep only the first N matching files (in --sort-by order)
+  --limit N           stop after N files and report those (\"truncated\": true)
+  --top-dirs N        number of largest subdirectories to report (default 20)
+  --recent N          number of most recently modified files to report (default 20)
+  --tree              print a per-directory size tree (DirNode) instead of FolderStats
+  --format FMT        json (default), csv (extension and file tables), ndjson
+                      (one line per matching file/read error as found, then a
+                      summary; each line has \"type\": file|error|summary) or tree
+                      (the --tree result as indented text); only json/tree with --tree
+  --tree-depth N      levels kept in the tree before aggregating into the parent (default 4)
+  --tree-min SIZE     with --format tree: fold directories under SIZE into one line
+  --exclude PATTERN   glob of directories/files to skip, repeatable (e.g. 'target/', '*.iso')
+  --include PATTERN   only count files matching this glob, repeatable (exclude wins)
+  --ext LIST          only count files with these extensions, e.g. mp4,mkv,avi
+                      ('gz' also takes .tar.gz; 'tar.gz' takes only those)
+  --ext-case          group extensions case-sensitively (.JPG apart from .jpg)
+  --ext-sort MODE     order extension_count by size (default), count or name
+  --ext-min SIZE      leave files under SIZE out of the extension/category breakdown
+                      (totals still count them)
+  --detail-ext EXT    also list the largest files of extension EXT in extension_detail,
+                      whatever the size filter
+  --detail-limit N    number of files in extension_detail (default 100)
+  --name-regex RE     only list files whose name matches RE in filtered_files
+  --match-full-path   apply --name-regex to the full path instead of the file name
+  --name-contains TXT only list files whose name contains TXT (ignoring case)
+  --follow-symlinks   follow symlinks (loop-safe, each real file counted once)
+  --dedupe-hardlinks  count each hardlinked file once (Unix only)
+  --skip-preset NAME  skip a preset's directories, repeatable: dev (node_modules,
+                      .git, target, __pycache__, .venv) or caches (.cache, Caches, ...)
+  --skip-hidden       skip dotfiles and don't descend into dot-directories
+  --gitignore         honor .gitignore/.git/info/exclude files and skip .git
+  --include-virtual   walk /proc, /sys and other virtual mounts below the folder
+                      (skipped by default and listed in virtual_mounts_skipped)
+  --modified-before T list only files modified before T (RFC3339 or age like 2y, 90d)
+  --modified-after T  list only files modified at/after T (RFC3339 or age like 2y, 90d)
+  --empty-files       list zero-byte files
+  --empty-limit N     max zero-byte files listed (default 1000)
+  --empty-dirs        list directories that are empty all the way down
+  --empty-dirs-limit N max empty directories listed (default 1000)
+  --by-owner          total size/file count per file owner (Unix only)
+  --check-permissions list world-writable, setuid/setgid and foreign-owned files (Unix only)
+  --error-limit N     max unreadable paths listed in errors (default 500)
+  --hash              add a SHA-256 of each listed file's content (reads them all)
+  --cache             reuse the metadata of files in unchanged directories from
+                      ~/.cache/fscan: faster rescans, but a file changed in place
+                      (appended to, rewritten) may keep its old size
+  --no-cache          stat every file, don't read or update ~/.cache/fscan (default)
+  --serial-walk       read directories one at a time (default: in parallel)
+  --nice              scan gently: 2 threads and short pauses, same result
+  --threads N         scan on at most N threads (default one per CPU; the GUI
+                      retries a crashed scan with 1)
+  --progress          report progress lines on stderr while scanning
+  --stream            print {\"type\":\"progress\",\"files\":N,\"bytes\":B,\"dir\":...}
+                      lines on stdout while scanning, then {\"type\":\"result\", ...FolderStats}
+  --worker-format F   json (default) or bin: the json/--tree/--stream result as
+                      MessagePack, for the GUI; --stream then ends with
+                      {\"type\":\"binary\",\"len\":N} and N payload bytes
+  --compress          gzip the json/--tree/--stream result (the GUI asks for it
+                      when the file list has no --max-results); --stream then
+                      sends it as a {\"type\":\"binary\"} payload
+  --result-fd N       print the result (and --stream lines) to file descriptor N
+                      instead of stdout, which is then free for logs; also for
+                      --worker-serve (Unix; the GUI passes a pipe)
+  --bench N           scan N times and print timings (BenchReport) instead of FolderStats;
+                      always without the scan cache, --cache is ignored
+  --bench-warmup      with --bench: do one more, untimed run first";
+
+/// how the worker prints its result on stdout
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Json,
+    /// FolderStats::to_csv, for piping into sort/awk
+    Csv,
+    /// scan::StreamRecord lines, printed while the scan runs
+    Ndjson,
+    /// scan::render_tree of the --tree result
+    Tree,
+}
+
+/// everything the worker needs: what to scan and what to print
+struct WorkerArgs {
+    opts: scan::ScanOptions,
+    tree: bool,
+    progress: bool,
+    format: OutputFormat,
+    /// render_tree's min_size (--format tree)
+    tree_min: u64,
+    /// --bench iterations
+    bench: Option<usize>,
+    bench_warmup: bool,
+    /// ipc::WorkerEvent lines on stdout instead of one document
+    stream: bool,
+    /// --worker-format bin: the result document as MessagePack
+    binary: bool,
+    /// --compress: the result document gzipped
+    compress: bool,
+    /// --threads: size of the scan thread pool (None = one per CPU)
+    threads: Option<usize>,
+    /// --result-fd: where the answer goes instead of stdout
+    result_fd: Option<i32>,
+}
+
+fn flag_takes_value(flag: &str) -> bool {
+    matches!(
+        flag,
+        "--options"
+            | "--root"
+            | "--max-size"
+            | "--sort-by"
+            | "--format"
+            | "--max-results"
+            | "--ext-sort"
+            | "--ext-min"
+            | "--detail-ext"
+            | "--detail-limit"
+            | "--limit"
+            | "--top-dirs"
+            | "--recent"
+            | "--tree-depth"
+            | "--tree-min"
+            | "--bench"
+            | "--worker-format"
+            | "--exclude"
+            | "--include"
+            | "--ext"
+            | "--skip-preset"
+            | "--name-regex"
+            | "--name-contains"
+            | "--modified-before"
+            | "--modified-after"
+            | "--empty-limit"
+            | "--empty-dirs-limit"
+            | "--error-limit"
+            | "--threads"
+            | "--result-fd"
+    )
+}
+
+fn parse_flag_value<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+    let v = value.ok_or_else(|| format!("missing value for {}", flag))?;
+    v.parse::<T>().map_err(|_| format!("invalid {}: {}", flag, v))
+}
+
+/// comma-separated flag value, blanks dropped ("mp4, mkv," -> [mp4, mkv])
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// --max-size/--tree-min value: plain bytes, or a human size like "4 KB"
+fn parse_size(flag: &str, value: Option<&String>) -> Result<u64, String> {
+    let v = value.ok_or_else(|| format!("missing value for {}", flag))?;
+    v.trim()
+        .parse::<u64>()
+        .ok()
+        .or_else(|| scan::parse_human_input_to_bytes(v))
+        .ok_or_else(|| format!("invalid {}: {}", flag, v))
+}
+
+/// --sort-by value; FileSort's own message says what was wrong
+fn parse_sort(value: Option<&String>) -> Result<scan::FileSort, String> {
+    let v = value.ok_or("missing value for --sort-by")?;
+    v.parse::<scan::FileSort>().map_err(|e| format!("invalid --sort-by: {}", e))
+}
+
+/// --format value
+fn parse_format(value: Option<&String>) -> Result<OutputFormat, String> {
+    match value.map(|v| v.as_str()) {
+        Some("json") => Ok(OutputFormat::Json),
+        Some("csv") => Ok(OutputFormat::Csv),
+        Some("ndjson") => Ok(OutputFormat::Ndjson),
+        Some("tree") => Ok(OutputFormat::Tree),
+        Some(v) => Err(format!("invalid --format: {} (expected json, csv, ndjson or tree)", v)),
+        None => Err("missing value for --format".to_string()),
+    }
+}
+
+/// --worker-format value: true = bin (MessagePack)
+fn parse_worker_format(value: Option<&String>) -> Result<bool, String> {
+    match value.map(|v| v.as_str()) {
+        Some("json") => Ok(false),
+        Some("bin") => Ok(true),
+        Some(v) => Err(format!("invalid --worker-format: {} (expected json or bin)", v)),
+        None => Err("missing value for --worker-format".to_string()),
+    }
+}
+
+/// parse everything after `--worker` into WorkerArgs; `request` = the
+/// options already read from stdin (--worker-json)
+fn parse_worker_args(
+    args: &[String],
+    request: Option<scan::ScanOptions>,
+) -> Result<WorkerArgs, String> {
+    use crate::scan::ScanOptions;
+
+    // pass 1: split positional arguments from flags (and their values)
+    let mut positional: Vec<&String> = Vec::new();
+    let mut flags: Vec<(&str, Option<&String>)> = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg.starts_with("--") {
+            let value = if flag_takes_value(arg) { iter.next() } else { None };
+            flags.push((arg.as_str(), value));
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    // pass 2: base options: the stdin request, --options <json> or positional form
+    let options_json = flags.iter().find(|(f, _)| *f == "--options").map(|(_, v)| *v);
+    let mut opts = match (request, options_json) {
+        (Some(_), Some(_)) => {
+            return Err("--worker-json reads its options from stdin; drop --options".to_string());
+        }
+        (Some(_), None) if !positional.is_empty() => {
+            return Err("--worker-json takes no <folder_path>/<min_size_bytes>".to_string());
+        }
+        (Some(request_opts), None) => request_opts,
+        (None, Some(json)) => {
+            let json = json.ok_or("missing JSON after --options")?;
+            serde_json::from_str::<ScanOptions>(json)
+                .map_err(|e| format!("invalid options JSON: {}", e))?
+        }
+        (None, None) => {
+            if positional.len() < 2 {
+                return Err("missing <folder_path> or <min_size_bytes>".to_string());
+            }
+
+            let min_bytes = positional[1].parse::<u64>().unwrap_or(0);
+            // 5th argument: max depth, 1 = only files directly inside the folder
+            let max_depth = match positional.get(2) {
+                Some(d) => Some(
+                    d.parse::<usize>()
+                        .map_err(|_| format!("invalid max_depth: {}", d))?,
+                ),
+                None => None,
+            };
+
+            // 6th argument: max size, same syntax as --max-size
+            let max_size = match positional.get(3) {
+                Some(s) => Some(parse_size("--max-size", Some(s))?),
+                None => None,
+            };
+
+            ScanOptions::new(positional[0])
+                .min_size(min_bytes)
+                .max_size(max_size)
+                .max_depth(max_depth)
+        }
+    };
+
+    // pass 3: flags override the base options
+    let mut tree = false;
+    let mut progress = false;
+    let mut format = OutputFormat::Json;
+    let mut tree_min = 0;
+    let mut bench = None;
+    let mut bench_warmup = false;
+    let mut stream = false;
+    let mut binary = false;
+    let mut compress = false;
+    let mut threads = None;
+    let mut result_fd = None;
+    for (flag, value) in flags {
+        match flag {
+            "--options" => {}
+            "--root" => opts = opts.extra_root(parse_flag_value::<String>(flag, value)?),
+            "--max-size" => opts = opts.max_size(Some(parse_size(flag, value)?)),
+            "--sort-by" => opts = opts.sort_by(parse_sort(value)?),
+            "--max-results" => opts = opts.max_results(Some(parse_flag_value(flag, value)?)),
+            "--limit" => opts = opts.file_limit(Some(parse_flag_value(flag, value)?)),
+            "--top-dirs" => opts = opts.top_dirs(parse_flag_value(flag, value)?),
+            "--recent" => opts = opts.recent_files_limit(parse_flag_value(flag, value)?),
+            "--detail-ext" => opts = opts.detail_extension(Some(parse_flag_value(flag, value)?)),
+            "--detail-limit" => opts = opts.detail_limit(parse_flag_value(flag, value)?),
+            "--tree" => tree = true,
+            "--progress" => progress = true,
+            "--format" => format = parse_format(value)?,
+            "--tree-depth" => opts = opts.tree_depth(parse_flag_value(flag, value)?),
+            "--tree-min" => tree_min = parse_size(flag, value)?,
+            "--bench" => bench = Some(parse_flag_value(flag, value)?),
+            "--bench-warmup" => bench_warmup = true,
+            "--stream" => stream = true,
+            "--worker-format" => binary = parse_worker_format(value)?,
+            "--compress" => compress = true,
+            "--threads" => threads = Some(parse_threads(value)?),
+            "--result-fd" => result_fd = Some(parse_flag_value(flag, value)?),
+            "--exclude" => opts = opts.exclude(parse_flag_value::<String>(flag, value)?),
+            "--include" => opts = opts.include(parse_flag_value::<String>(flag, value)?),
+            "--ext" => {
+                let list: String = parse_flag_value(flag, value)?;
+                opts = opts.only_extensions(Some(split_list(&list)));
+            }
+            "--ext-case" => opts = opts.case_insensitive_extensions(false),
+            "--ext-sort" => opts = opts.ext_sort(parse_flag_value(flag, value)?),
+            "--ext-min" => opts = opts.ext_stats_min_size(Some(parse_size(flag, value)?)),
+            "--name-regex" => opts = opts.name_regex(Some(parse_flag_value(flag, value)?)),
+            "--match-full-path" => opts = opts.match_full_path(true),
+            "--name-contains" => opts = opts.name_contains(Some(parse_flag_value(flag, value)?)),
+            "--follow-symlinks" => opts = opts.follow_symlinks(true),
+            "--dedupe-hardlinks" => opts = opts.dedupe_hardlinks(true),
+            "--skip-hidden" => opts = opts.skip_hidden(true),
+            "--skip-preset" => {
+                let name: String = parse_flag_value(flag, value)?;
+                let preset = name.parse().map_err(|e| format!("{}: {}", flag, e))?;
+                opts = opts.skip_preset(preset);
+            }
+            "--gitignore" => opts = opts.respect_gitignore(true),
+            "--include-virtual" => opts = opts.include_virtual(true),
+            "--empty-files" => opts = opts.find_empty_files(true),
+            "--empty-limit" => opts = opts.empty_files_limit(parse_flag_value(flag, value)?),
+            "--empty-dirs" => opts = opts.find_empty_dirs(true),
+            "--by-owner" => opts = opts.group_by_owner(true),
+            "--check-permissions" => opts = opts.check_permissions(true),
+            "--cache" => opts = opts.use_cache(true),
+            "--no-cache" => opts = opts.use_cache(false),
+            "--serial-walk" => opts = opts.parallel_walk(false),
+            "--nice" => opts = opts.low_priority(true),
+            "--hash" => opts = opts.hash_filtered(true),
+            "--error-limit" => opts = opts.error_limit(parse_flag_value(flag, value)?),
+            "--empty-dirs-limit" => opts = opts.empty_dirs_limit(parse_flag_value(flag, value)?),
+            "--modified-before" => {
+                let spec: String = parse_flag_value(flag, value)?;
+                let t = scan::parse_time_spec(&spec).map_err(|e| format!("{}: {}", flag, e))?;
+                opts = opts.modified_before(Some(t));
+            }
+            "--modified-after" => {
+                let spec: String = parse_flag_value(flag, value)?;
+                let t = scan::parse_time_spec(&spec).map_err(|e| format!("{}: {}", flag, e))?;
+                opts = opts.modified_after(Some(t));
+            }
+            _ => return Err(format!("unknown flag: {}", flag)),
+        }
+    }
+
+    if stream && (tree || bench.is_some() || progress || format != OutputFormat::Json) {
+        return Err("--stream only wraps the FolderStats JSON; drop the other output flags"
+            .to_string());
+    }
+    if binary && (bench.is_some() || format != OutputFormat::Json) {
+        return Err("--worker-format bin only encodes the json result; drop --bench/--format"
+            .to_string());
+    }
+    if compress && (bench.is_some() || format != OutputFormat::Json) {
+        return Err("--compress only wraps the json result; drop --bench/--format".to_string());
+    }
+    if bench.is_some() && (tree || format != OutputFormat::Json) {
+        return Err("--bench only prints its JSON report; drop --tree/--format".to_string());
+    }
+    if tree && !matches!(format, OutputFormat::Json | OutputFormat::Tree) {
+        return Err("--tree only prints JSON or text; use --format json or tree".to_string());
+    }
+
+    Ok(WorkerArgs {
+        opts,
+        tree: tree || format == OutputFormat::Tree,
+        progress,
+        format,
+        tree_min,
+        bench,
+        bench_warmup,
+        stream,
+        binary,
+        compress,
+        threads,
+        result_fd,
+    })
+}
+
+fn parse_threads(value: Option<&String>) -> Result<usize, String> {
+    match parse_flag_value("--threads", value)? {
+        0 => Err("--threads must be at least 1".to_string()),
+        n => Ok(n),
+    }
+}
+
+/// --threads: the most threads a scan may use, --nice or not; must happen
+/// before the first scan
+fn limit_threads(threads: Option<usize>) {
+    if let Some(n) = threads {
+        scan::limit_threads(n);
+    }
+}
+
+/// token the signal handler cancels; set once before the scan starts
+#[cfg(unix)]
+static WORKER_CANCEL: std::sync::OnceLock<scan::CancellationToken> = std::sync::OnceLock::new();
+
+/// SIGTERM/SIGINT: ask the scan to stop, and restore the default action so
+/// a second signal still kills a worker that doesn't stop fast enough
+#[cfg(unix)]
+extern "C" fn on_terminate(sig: libc::c_int) {
+    if let Some(token) = WORKER_CANCEL.get() {
+        token.cancel();
+    }
+    unsafe {
+        libc::signal(sig, libc::SIG_DFL);
+    }
+}
+
+#[cfg(unix)]
+fn install_cancel_handler(token: &scan::CancellationToken) {
+    let _ = WORKER_CANCEL.set(token.clone());
+    let handler = on_terminate as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGINT, handler);
+    }
+}
+
+#[cfg(not(unix))]
+fn install_cancel_handler(_token: &scan::CancellationToken) {}
+
+/// --worker-json: the ipc::ScanRequest on stdin, up to EOF
+fn read_scan_request() -> Result<scan::ScanOptions, String> {
+    let request: ipc::ScanRequest = serde_json::from_reader(std::io::stdin().lock())
+        .map_err(|e| format!("invalid request on stdin: {}", e))?;
+    check_request_version(&request)?;
+    Ok(request.options)
+}
+
+fn check_request_version(request: &ipc::ScanRequest) -> Result<(), String> {
+    if request.version > ipc::PROTOCOL_VERSION {
+        return Err(format!(
+            "request version {} is newer than this worker ({})",
+            request.version,
+            ipc::PROTOCOL_VERSION
+        ));
+    }
+    Ok(())
+}
 
-    if args.len() < 4 {
-        eprintln!("Usage: --worker <folder_path> <min_size_bytes>");
+/// --result-fd: the GUI's pipe for everything the worker prints as its
+/// answer; None = stdout
+static RESULT_FD: std::sync::OnceLock<std::sync::Mutex<std::fs::File>> =
+    std::sync::OnceLock::new();
+
+/// --result-fd N: write the answer to fd N from now on, so a stray
+/// println! anywhere in the worker ends up on stdout instead of in it
+#[cfg(unix)]
+fn use_result_fd(fd: i32) -> Result<(), String> {
+    use std::os::fd::FromRawFd;
+
+    // SAFETY: F_GETFD only asks whether `fd` is open
+    if fd < 0 || unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return Err(format!("--result-fd {}: not an open file descriptor", fd));
+    }
+    // SAFETY: the fd is open and was handed to the worker for this alone
+    let file = unsafe { std::fs::File::from_raw_fd(fd) };
+    let _ = RESULT_FD.set(std::sync::Mutex::new(file));
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn use_result_fd(_fd: i32) -> Result<(), String> {
+    Err("--result-fd is only supported on Unix".to_string())
+}
+
+/// run `write` on the answer channel (--result-fd, or stdout), buffered
+/// and flushed as one piece; other threads' writes wait for it
+fn write_output(
+    write: impl FnOnce(&mut dyn std::io::Write) -> Result<(), String>,
+) -> Result<(), String> {
+    use std::io::Write;
+
+    let mut file;
+    let mut stdout;
+    let target: &mut dyn Write = match RESULT_FD.get() {
+        Some(fd) => {
+            file = fd.lock().unwrap_or_else(|e| e.into_inner());
+            &mut *file
+        }
+        None => {
+            stdout = std::io::stdout().lock();
+            &mut stdout
+        }
+    };
+    let mut out = std::io::BufWriter::new(target);
+    write(&mut out)?;
+    out.flush().map_err(|e| e.to_string())
+}
+
+/// one JSON document on one output line
+fn print_json<T: serde::Serialize>(value: &T) -> Result<(), String> {
+    write_output(|out| write_plain(out, value, false))
+}
+
+/// the result document: one JSON line, or raw MessagePack (--worker-format
+/// bin); gzipped as it's written with --compress
+fn print_result<T: serde::Serialize>(
+    value: &T,
+    binary: bool,
+    compress: bool,
+) -> Result<(), String> {
+    write_output(|out| write_document(out, value, binary, compress).map(drop))
+}
+
+/// `value` as one JSON line or MessagePack into `out`, through a gzip
+/// encoder with `compress`
+fn write_document<T: serde::Serialize, W: std::io::Write>(
+    mut out: W,
+    value: &T,
+    binary: bool,
+    compress: bool,
+) -> Result<W, String> {
+    if !compress {
+        write_plain(&mut out, value, binary)?;
+        return Ok(out);
+    }
+    let mut encoder = flate2::write::GzEncoder::new(out, flate2::Compression::fast());
+    write_plain(&mut encoder, value, binary)?;
+    encoder.finish().map_err(|e| e.to_string())
+}
+
+fn write_plain<T: serde::Serialize>(
+    out: &mut dyn std::io::Write,
+    value: &T,
+    binary: bool,
+) -> Result<(), String> {
+    if binary {
+        let bytes = rmp_serde::to_vec_named(value).map_err(|e| e.to_string())?;
+        return out.write_all(&bytes).map_err(|e| e.to_string());
+    }
+    serde_json::to_writer(&mut *out, value).map_err(|e| e.to_string())?;
+    out.write_all(b"\n").map_err(|e| e.to_string())
+}
+
+fn write_bytes(bytes: &[u8]) -> Result<(), String> {
+    write_output(|out| out.write_all(bytes).map_err(|e| e.to_string()))
+}
+
+/// one --stream/--worker-serve line; `id` = the request it answers
+fn print_event(id: Option<u64>, event: ipc::WorkerEvent) -> Result<(), String> {
+    print_json(&ipc::TaggedEvent {
+        id,
+        warnings: 0,
+        event,
+    })
+}
+
+/// the last --stream/--worker-serve answer: a result line, or with
+/// --worker-format bin / --compress a WorkerEvent::Binary line and the
+/// document's bytes (write_document)
+/// - the scan's warnings go to stderr first; the line says how many
+fn print_stream_result(
+    id: Option<u64>,
+    stats: scan::FolderStats,
+    binary: bool,
+    compress: bool,
+) -> Result<(), String> {
+    use crate::ipc::{TaggedEvent, Versioned, WorkerEvent};
+
+    let warnings = print_warnings(id, &stats);
+    let (event, bytes) = if binary || compress {
+        let bytes = write_document(Vec::new(), &Versioned::new(stats), binary, compress)?;
+        (WorkerEvent::Binary { len: bytes.len() }, Some(bytes))
+    } else {
+        (WorkerEvent::Result(Box::new(Versioned::new(stats))), None)
+    };
+    print_json(&TaggedEvent {
+        id,
+        warnings,
+        event,
+    })?;
+    match bytes {
+        Some(bytes) => write_bytes(&bytes),
+        None => Ok(()),
+    }
+}
+
+/// ipc::scan_warnings as ipc::WorkerEvent::Warning lines on stderr; returns how
+/// many were written
+fn print_warnings(id: Option<u64>, stats: &scan::FolderStats) -> usize {
+    let mut written = 0;
+    for message in ipc::scan_warnings(stats) {
+        let line = ipc::TaggedEvent {
+            id,
+            warnings: 0,
+            event: ipc::WorkerEvent::Warning(ipc::WorkerWarning { message }),
+        };
+        if let Ok(json) = serde_json::to_string(&line) {
+            eprintln!("{}", json);
+            written += 1;
+        }
+    }
+    written
+}
+
+fn progress_event(p: scan::ScanProgress) -> ipc::WorkerEvent {
+    ipc::WorkerEvent::Progress(ipc::WorkerProgress {
+        files: p.files_seen,
+        bytes: p.bytes_seen,
+        dir: p.current_dir,
+    })
+}
+
+/// progress from `rx` as WorkerEvent lines for request `id`, with a
+/// Heartbeat whenever none was written for ipc::HEARTBEAT_INTERVAL, so a
+/// walk that finds nothing (or hangs) still shows it's alive
+/// - idle_ms counts from the last progress while walking or hashing; the
+///   analysis stages in between touch no files and send none, so they're
+///   never idle
+/// - ends when `rx` closes or stdout is gone
+fn print_progress(
+    id: Option<u64>,
+    rx: std::sync::mpsc::Receiver<scan::ScanProgress>,
+    root: &std::path::Path,
+) {
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::time::Instant;
+
+    let mut dir = root.to_string_lossy().into_owned();
+    let mut reading_since = Some(Instant::now());
+    loop {
+        let event = match rx.recv_timeout(ipc::HEARTBEAT_INTERVAL) {
+            Ok(p) => {
+                reading_since = (p.phase != scan::ScanPhase::Analyzing).then(Instant::now);
+                dir.clone_from(&p.current_dir);
+                progress_event(p)
+            }
+            Err(RecvTimeoutError::Timeout) => ipc::WorkerEvent::Heartbeat(ipc::WorkerHeartbeat {
+                dir: dir.clone(),
+                idle_ms: reading_since.map_or(0, |at| at.elapsed().as_millis() as u64),
+            }),
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if print_event(id, event).is_err() {
+            break;
+        }
+    }
+}
+
+/// --diff: FolderStats::diff of two saved results (a --worker json result
+/// or FolderStats::save_to) as one JSON line
+/// - exit 1 when either file can't be read or isn't a FolderStats
+fn run_diff(args: &[String]) {
+    let [before, after] = args else {
+        eprintln!("--diff takes <before.json> <after.json>");
+        eprintln!("{}", WORKER_USAGE);
         std::process::exit(1);
+    };
+    let load = |path: &String| {
+        scan::FolderStats::load_from(std::path::Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        })
+    };
+    let diff = load(before).diff(&load(after));
+    if let Err(e) = print_json(&diff) {
+        eprintln!("serialization error: {}", e);
+        std::process::exit(2);
     }
+}
+
+/// exit codes: 0 ok, 1 usage, 2 serialization, then one per ScanErrorKind:
+/// 3 invalid options, 4 cancelled, 5 folder not found, 6 not a folder,
+/// 7 folder unreadable
+/// - a cancelled FolderStats scan still writes what it found (marked
+///   "partial") before it exits with 4
+fn run_worker(args: &[String]) {
+    use crate::ipc::Versioned;
+    use crate::scan::{
+        CancellationToken, ScanControl, ScanError, StreamRecord, bench_scan, render_tree,
+        scan_folder_stream, scan_folder_tree, scan_folder_with,
+    };
+
+    let request = if args[1] == "--worker-json" {
+        match read_scan_request() {
+            Ok(opts) => Some(opts),
+            Err(e) => {
+                eprintln!("{}", e);
+                eprintln!("{}", WORKER_USAGE);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
 
-    let folder = std::path::PathBuf::from(&args[2]);
-    let min_bytes = args[3].parse::<u64>().unwrap_or(0);
+    let worker_args = match parse_worker_args(&args[2..], request) {
+        Ok(wa) => wa,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("{}", WORKER_USAGE);
+            std::process::exit(1);
+        }
+    };
 
-    match scan_folder(&folder, min_bytes) {
-        Ok(stats) => {
-            match to_string(&stats) {
-                Ok(json) => {
-                    println!("{}", json);
+    if let Some(fd) = worker_args.result_fd
+        && let Err(e) = use_result_fd(fd)
+    {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    limit_threads(worker_args.threads);
+    let cancel = CancellationToken::default();
+    install_cancel_handler(&cancel);
+    let mut control = ScanControl::default().cancel_token(cancel.clone()).keep_partial();
+    let mut stream_printer = None;
+    if worker_args.stream {
+        let (tx, rx) = std::sync::mpsc::channel::<scan::ScanProgress>();
+        let root = worker_args.opts.path.clone();
+        stream_printer = Some(std::thread::spawn(move || print_progress(None, rx, &root)));
+        control = control.progress(tx);
+    } else if worker_args.progress {
+        // stdout carries only the final JSON, so progress goes to stderr
+        let (tx, rx) = std::sync::mpsc::channel::<scan::ScanProgress>();
+        std::thread::spawn(move || {
+            for p in rx {
+                eprintln!(
+                    "progress: {} files, {} ({})",
+                    p.files_seen,
+                    scan::format_bytes(p.bytes_seen),
+                    p.current_dir
+                );
+            }
+        });
+        control = control.progress(tx);
+    }
+
+    // each branch prints its own output; Ok(Err(_)) is a serialization failure
+    let mut partial = false;
+    let result: Result<Result<(), String>, ScanError> = if let Some(n) = worker_args.bench {
+        let opts = &worker_args.opts;
+        bench_scan(&opts.path, opts, n, worker_args.bench_warmup, &cancel)
+            .map(|report| print_json(&report))
+    } else if worker_args.tree {
+        scan_folder_tree(&worker_args.opts, &control).map(|tree| match worker_args.format {
+            OutputFormat::Tree => {
+                let (depth, min) = (worker_args.opts.tree_depth, worker_args.tree_min);
+                let text = render_tree(&tree, depth, min);
+                write_output(|out| out.write_all(text.as_bytes()).map_err(|e| e.to_string()))
+            }
+            _ => {
+                let (binary, compress) = (worker_args.binary, worker_args.compress);
+                print_result(&Versioned::new(tree), binary, compress)
+            }
+        })
+    } else if let Some(printer) = stream_printer {
+        // the result line must come after every progress line: dropping the
+        // control drops the sender, which ends the printer
+        let stats = scan_folder_with(&worker_args.opts, &control);
+        drop(control);
+        let _ = printer.join();
+        stats.map(|stats| {
+            partial = stats.partial.is_some();
+            print_stream_result(None, stats, worker_args.binary, worker_args.compress)
+        })
+    } else if worker_args.format == OutputFormat::Ndjson {
+        // lines go out as they're found; after a record that can't be
+        // encoded nothing more is printed
+        let mut failed: Result<(), String> = Ok(());
+        let mut emit = |rec: StreamRecord| {
+            if failed.is_ok() {
+                failed = print_json(&rec);
+            }
+        };
+        scan_folder_stream(&worker_args.opts, &control, &mut emit)
+            .map(|summary| emit(StreamRecord::Summary(summary)))
+            .map(|()| failed)
+    } else {
+        scan_folder_with(&worker_args.opts, &control).map(|stats| {
+            partial = stats.partial.is_some();
+            match worker_args.format {
+                OutputFormat::Csv => {
+                    write_output(|out| stats.to_csv(out).map_err(|e| e.to_string()))
                 }
-                Err(e) => {
-                    eprintln!("serialization error: {}", e);
-                    std::process::exit(2);
+                _ => {
+                    print_warnings(None, &stats);
+                    print_result(&Versioned::new(stats), worker_args.binary, worker_args.compress)
                 }
             }
+        })
+    };
+
+    match result {
+        Ok(Ok(())) if partial => exit_scan_failed(&ScanError::Cancelled),
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            eprintln!("serialization error: {}", e);
+            std::process::exit(2);
         }
-        Err(err) => {
-            eprintln!("scan error: {}", err);
-            std::process::exit(3);
+        Err(err) => exit_scan_failed(&err),
+    }
+}
+
+/// a failed one-shot scan on stderr, then exit with its kind's code: the
+/// human line, and the same as an ipc::WorkerEvent::Error line, which
+/// carries the details (path, OS error) the exit code can't
+fn exit_scan_failed(err: &scan::ScanError) -> ! {
+    match err {
+        scan::ScanError::Cancelled => eprintln!("{}", err),
+        _ => eprintln!("scan error: {}", err),
+    }
+    let line = ipc::TaggedEvent {
+        id: None,
+        warnings: 0,
+        event: ipc::WorkerEvent::scan_error(err),
+    };
+    if let Ok(json) = serde_json::to_string(&line) {
+        eprintln!("{}", json);
+    }
+    std::process::exit(err.kind().exit_code());
+}
+
+/// --worker-serve: answer one ipc::ServeRequest per stdin line with
+/// id-tagged --stream lines (progress, then the result or an error line)
+/// until stdin closes
+/// - a cancelled scan (SIGTERM) ends the server, after its partial result:
+///   the token stays cancelled
+/// - --cancel-on-eof: stdin closing cancels it too (ipc::WorkerCancel for
+///   a worker run as root through pkexec)
+fn run_worker_serve(args: &[String]) {
+    use crate::scan::{CancellationToken, ScanControl, ScanErrorKind, scan_folder_with};
+
+    let mut binary = Ok(false);
+    let mut threads = Ok(None);
+    let mut compress = false;
+    let mut cancel_on_eof = false;
+    let mut result_fd = Ok(None);
+    let mut rest = args;
+    loop {
+        rest = match rest {
+            [flag, tail @ ..] if flag == "--compress" => {
+                compress = true;
+                tail
+            }
+            [flag, tail @ ..] if flag == "--cancel-on-eof" => {
+                cancel_on_eof = true;
+                tail
+            }
+            [flag, tail @ ..] if flag == "--idle-priority" => {
+                ipc::lower_own_priority();
+                tail
+            }
+            [flag, value, tail @ ..] if flag == "--worker-format" => {
+                binary = parse_worker_format(Some(value));
+                tail
+            }
+            [flag, value, tail @ ..] if flag == "--threads" => {
+                threads = parse_threads(Some(value)).map(Some);
+                tail
+            }
+            [flag, value, tail @ ..] if flag == "--result-fd" => {
+                result_fd = parse_flag_value(flag, Some(value)).map(Some);
+                tail
+            }
+            _ => break,
+        };
+    }
+    let parsed = match rest {
+        [] => binary.and_then(|binary| Ok((binary, threads?, result_fd?))),
+        _ => Err(
+            "--worker-serve only takes --worker-format, --threads, --compress, --cancel-on-eof, \
+             --idle-priority and --result-fd"
+                .to_string(),
+        ),
+    };
+    let (binary, threads, result_fd) = parsed.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        eprintln!("{}", WORKER_USAGE);
+        std::process::exit(1);
+    });
+    if let Some(fd) = result_fd
+        && let Err(e) = use_result_fd(fd)
+    {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    limit_threads(threads);
+
+    let cancel = CancellationToken::default();
+    install_cancel_handler(&cancel);
+
+    for line in serve_lines(cancel_on_eof.then(|| cancel.clone())) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, opts) = match parse_serve_request(&line) {
+            Ok(request) => request,
+            Err((id, message)) => {
+                let event = ipc::WorkerEvent::Error {
+                    kind: None,
+                    message,
+                    path: None,
+                    detail: None,
+                };
+                let _ = print_event(id, event);
+                continue;
+            }
+        };
+
+        let (tx, rx) = std::sync::mpsc::channel::<scan::ScanProgress>();
+        let root = opts.path.clone();
+        let printer = std::thread::spawn(move || print_progress(Some(id), rx, &root));
+        let control =
+            ScanControl::default().cancel_token(cancel.clone()).progress(tx).keep_partial();
+        let stats = scan_folder_with(&opts, &control);
+        drop(control);
+        let _ = printer.join();
+
+        let printed = match stats {
+            Ok(stats) => print_stream_result(Some(id), stats, binary, compress),
+            Err(err) => print_event(Some(id), ipc::WorkerEvent::scan_error(&err)),
+        };
+        if cancel.is_cancelled() {
+            std::process::exit(ScanErrorKind::Cancelled.exit_code());
+        }
+        if let Err(e) = printed {
+            eprintln!("serialization error: {}", e);
+            std::process::exit(2);
         }
     }
 }
+
+/// --worker-serve's stdin lines, read on a thread of their own so that EOF
+/// is seen while a scan runs; it then cancels `cancel_on_eof`, if given
+fn serve_lines(
+    cancel_on_eof: Option<scan::CancellationToken>,
+) -> std::sync::mpsc::Receiver<String> {
+    use std::io::BufRead;
+    use std::sync::mpsc;
+
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if tx.send(line).is_err() {
+                return;
+            }
+        }
+        if let Some(token) = cancel_on_eof {
+            token.cancel();
+        }
+    });
+    rx
+}
+
+/// one --worker-serve line as (id, options); Err carries the id when the
+/// line got far enough to have one
+fn parse_serve_request(line: &str) -> Result<(u64, scan::ScanOptions), (Option<u64>, String)> {
+    #[derive(serde::Deserialize)]
+    struct IdOnly {
+        id: Option<u64>,
+    }
+
+    let request: ipc::ServeRequest = serde_json::from_str(line).map_err(|e| {
+        let id = serde_json::from_str::<IdOnly>(line).ok().and_then(|r| r.id);
+        (id, format!("invalid request: {}", e))
+    })?;
+    check_request_version(&request.request).map_err(|e| (Some(request.id), e))?;
+    Ok((request.id, request.request.options))
+}
@@ -1,136 +1,6787 @@
 // src/scan.rs
+use crate::category::{categorize, compound_extension, Category};
 use rayon::prelude::*;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::cell::{Cell, RefCell};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::mpsc::{Sender, SyncSender, sync_channel};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use walkdir::WalkDir;
 use humansize::{file_size_opts as options, FileSize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "FileEntryWire")]
 pub struct FileEntry {
-    pub path: String,
+    /// the real path, byte for byte (see os_path for the JSON form)
+    #[serde(with = "os_path")]
+    pub path: PathBuf,
+    /// path for display: lossy UTF-8, so two odd names may look the same
+    pub display: String,
     pub size: u64,
+    /// bytes actually allocated on disk (== size where block info is unavailable)
+    #[serde(default)]
+    pub allocated: u64,
+    /// last modification time, serialized as unix seconds (None if unreadable)
+    #[serde(default, with = "unix_secs")]
+    pub mtime: Option<SystemTime>,
+    /// hex SHA-256 of the content (hash_filtered only; None if it couldn't be read)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// path below the scan root it was found under, lossy like `display`
+    pub rel_path: String,
+}
+
+/// FileEntry as read from JSON: `display` is optional (results saved before
+/// it existed), and filled in from the path when missing
+#[derive(Deserialize)]
+struct FileEntryWire {
+    #[serde(with = "os_path")]
+    path: PathBuf,
+    #[serde(default)]
+    display: Option<String>,
+    size: u64,
+    #[serde(default)]
+    allocated: u64,
+    #[serde(default, with = "unix_secs")]
+    mtime: Option<SystemTime>,
+    #[serde(default)]
+    hash: Option<String>,
+    /// older results have none: the full path stands in for it
+    #[serde(default)]
+    rel_path: Option<String>,
+}
+
+impl From<FileEntryWire> for FileEntry {
+    fn from(w: FileEntryWire) -> Self {
+        let display = w.display.unwrap_or_else(|| w.path.to_string_lossy().into_owned());
+        FileEntry {
+            rel_path: w.rel_path.unwrap_or_else(|| display.clone()),
+            display,
+            path: w.path,
+            size: w.size,
+            allocated: w.allocated,
+            mtime: w.mtime,
+            hash: w.hash,
+        }
+    }
+}
+
+/// serde helpers: PathBuf <-> a JSON string when it is valid UTF-8, else
+/// {"bytes": [..]} with the raw OS bytes (Unix; elsewhere the lossy string)
+mod os_path {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::path::{Path, PathBuf};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Text(String),
+        Bytes { bytes: Vec<u8> },
+    }
+
+    pub fn serialize<S: Serializer>(path: &Path, s: S) -> Result<S::Ok, S::Error> {
+        repr(path).serialize(s)
+    }
+
+    fn repr(path: &Path) -> Repr {
+        match path.to_str() {
+            Some(text) => Repr::Text(text.to_string()),
+            None => raw(path),
+        }
+    }
+
+    #[cfg(unix)]
+    fn raw(path: &Path) -> Repr {
+        use std::os::unix::ffi::OsStrExt;
+        Repr::Bytes {
+            bytes: path.as_os_str().as_bytes().to_vec(),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn raw(path: &Path) -> Repr {
+        Repr::Text(path.to_string_lossy().into_owned())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<PathBuf, D::Error> {
+        from_repr(Repr::deserialize(d)?).map_err(serde::de::Error::custom)
+    }
+
+    fn from_repr(r: Repr) -> Result<PathBuf, String> {
+        match r {
+            Repr::Text(text) => Ok(PathBuf::from(text)),
+            Repr::Bytes { bytes } => from_bytes(bytes),
+        }
+    }
+
+    /// the same for Option<PathBuf>: null or one of the forms above
+    pub mod option {
+        use super::{Repr, from_repr, repr};
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+        use std::path::PathBuf;
+
+        pub fn serialize<S: Serializer>(path: &Option<PathBuf>, s: S) -> Result<S::Ok, S::Error> {
+            path.as_deref().map(repr).serialize(s)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<PathBuf>, D::Error> {
+            Option::<Repr>::deserialize(d)?
+                .map(from_repr)
+                .transpose()
+                .map_err(serde::de::Error::custom)
+        }
+    }
+
+    /// the same for Vec<PathBuf>: a list of the forms above
+    pub mod vec {
+        use super::{Repr, from_repr, repr};
+        use serde::{Deserialize, Deserializer, Serializer};
+        use std::path::PathBuf;
+
+        pub fn serialize<S: Serializer>(paths: &[PathBuf], s: S) -> Result<S::Ok, S::Error> {
+            s.collect_seq(paths.iter().map(|p| repr(p)))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<PathBuf>, D::Error> {
+            Vec::<Repr>::deserialize(d)?
+                .into_iter()
+                .map(from_repr)
+                .collect::<Result<_, _>>()
+                .map_err(serde::de::Error::custom)
+        }
+    }
+
+    #[cfg(unix)]
+    fn from_bytes(bytes: Vec<u8>) -> Result<PathBuf, String> {
+        use std::os::unix::ffi::OsStringExt;
+        Ok(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+    }
+
+    #[cfg(not(unix))]
+    fn from_bytes(bytes: Vec<u8>) -> Result<PathBuf, String> {
+        String::from_utf8(bytes)
+            .map(PathBuf::from)
+            .map_err(|_| "raw path bytes are only supported on Unix".to_string())
+    }
+}
+
+/// serde helpers: Option<SystemTime> <-> Option<i64> unix seconds
+mod unix_secs {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    pub fn serialize<S: Serializer>(t: &Option<SystemTime>, s: S) -> Result<S::Ok, S::Error> {
+        t.map(|t| match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        })
+        .serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<SystemTime>, D::Error> {
+        Ok(Option::<i64>::deserialize(d)?.map(|secs| super::system_time_from_unix(secs, 0)))
+    }
 }
 
+/// per-extension totals: how many files and how many bytes
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct FolderStats {
+#[serde(from = "ExtensionStatWire")]
+pub struct ExtensionStat {
+    pub extension: String,
+    pub count: usize,
     pub total_size: u64,
-    pub total_files: usize,
-    pub extension_count: Vec<(String, usize)>,
-    pub filtered_files: Vec<FileEntry>,
+    /// average size of the files with a readable size
+    #[serde(default)]
+    pub mean_size: u64,
+    /// median size of the files with a readable size (mean of the two middle
+    /// ones for an even count)
+    /// - exact up to MEDIAN_EXACT_LIMIT files, above that an estimate from
+    ///   log-scale buckets, within about 3% of the true value
+    #[serde(default)]
+    pub median_size: u64,
+    /// false when median_size is the estimate
+    #[serde(default = "default_true")]
+    pub median_exact: bool,
 }
 
-/// parsing filter text -> bytes
-pub fn parse_filter_option(opt: &str, custom_text: Option<&str>) -> u64 {
-    match opt {
-        "100 MB" => 100 * 1024 * 1024,
-        "500 MB" => 500 * 1024 * 1024,
-        "1 GB" => 1 * 1024 * 1024 * 1024,
-        "5 GB" => 5 * 1024 * 1024 * 1024,
-        "Custom" => {
-            if let Some(s) = custom_text {
-                parse_human_input_to_bytes(s).unwrap_or(0)
-            } else {
-                0
-            }
+fn default_true() -> bool {
+    true
+}
+
+/// ExtensionStat as read from JSON: results from before schema 1 (see
+/// FOLDER_STATS_SCHEMA) have a bare ["ext", count] pair, without sizes
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ExtensionStatWire {
+    Full {
+        extension: String,
+        count: usize,
+        total_size: u64,
+        #[serde(default)]
+        mean_size: u64,
+        #[serde(default)]
+        median_size: u64,
+        #[serde(default = "default_true")]
+        median_exact: bool,
+    },
+    Pair(String, usize),
+}
+
+impl From<ExtensionStatWire> for ExtensionStat {
+    fn from(w: ExtensionStatWire) -> Self {
+        match w {
+            ExtensionStatWire::Full {
+                extension,
+                count,
+                total_size,
+                mean_size,
+                median_size,
+                median_exact,
+            } => ExtensionStat {
+                extension,
+                count,
+                total_size,
+                mean_size,
+                median_size,
+                median_exact,
+            },
+            // sizes unknown: zero, and not claimed exact
+            ExtensionStatWire::Pair(extension, count) => ExtensionStat {
+                extension,
+                count,
+                total_size: 0,
+                mean_size: 0,
+                median_size: 0,
+                median_exact: false,
+            },
         }
-        _ => 0,
     }
 }
 
-pub fn parse_human_input_to_bytes(s: &str) -> Option<u64> {
-    let s = s.trim().to_uppercase();
-    if s.is_empty() {
-        return None;
+/// file size distribution over every file with a readable size
+/// - quantiles are nearest-rank ("p90 = the size 90% of files are at most")
+/// - exact up to MEDIAN_EXACT_LIMIT files; above that p50/p90/p99 come from
+///   the same log-scale buckets as ExtensionStat::median_size and are within
+///   1/32 (about 3%) of the true value; max is always exact
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeQuantiles {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+    /// false when p50/p90/p99 are the bucket estimates
+    #[serde(default = "default_true")]
+    pub exact: bool,
+}
+
+/// ordering of FolderStats::extension_count
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtensionSort {
+    Count,
+    #[default]
+    Size,
+    /// alphabetical by extension
+    Name,
+}
+
+impl std::str::FromStr for ExtensionSort {
+    type Err = String;
+
+    /// "size", "count" or "name"
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.trim().to_lowercase().as_str() {
+            "size" => Ok(ExtensionSort::Size),
+            "count" => Ok(ExtensionSort::Count),
+            "name" => Ok(ExtensionSort::Name),
+            other => Err(format!(
+                "unknown extension sort '{}' (expected size, count or name)",
+                other
+            )),
+        }
     }
-    let parts: Vec<&str> = s.split_whitespace().collect();
-    if parts.len() == 1 {
-        if let Ok(n) = parts[0].parse::<f64>() {
-            return Some((n * 1024.0 * 1024.0) as u64); // assume MB
-        }
-    } else if let Ok(value) = parts[0].parse::<f64>() {
-        let unit = parts[1];
-        return match unit {
-            "B" => Some(value as u64),
-            "KB" => Some((value * 1024.0) as u64),
-            "MB" => Some((value * 1024.0 * 1024.0) as u64),
-            "GB" => Some((value * 1024.0 * 1024.0 * 1024.0) as u64),
-            _ => None,
+}
+
+/// order extension_count entries by `sort`: count and size descending,
+/// name ascending; ties by extension name, so the order never depends on
+/// the walk
+pub fn sort_extensions(stats: &mut [ExtensionStat], sort: ExtensionSort) {
+    let by_name = |a: &ExtensionStat, b: &ExtensionStat| a.extension.cmp(&b.extension);
+    match sort {
+        ExtensionSort::Count => {
+            stats.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| by_name(a, b)))
+        }
+        ExtensionSort::Size => {
+            stats.sort_by(|a, b| b.total_size.cmp(&a.total_size).then_with(|| by_name(a, b)))
+        }
+        ExtensionSort::Name => stats.sort_by(by_name),
+    }
+}
+
+/// key FolderStats::filtered_files is ordered by
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileSortKey {
+    #[default]
+    Size,
+    /// file name only
+    Name,
+    /// full path
+    Path,
+    Mtime,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// ordering of filtered_files; default size descending (largest first)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileSort {
+    pub key: FileSortKey,
+    pub order: SortOrder,
+}
+
+impl std::str::FromStr for FileSort {
+    type Err = String;
+
+    /// "size", "name", "path" or "mtime", optionally ":asc" / ":desc"
+    /// - without an order, size/mtime sort descending and name/path ascending
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, order) = match s.split_once(':') {
+            Some((k, o)) => (k, Some(o)),
+            None => (s, None),
+        };
+        let key = match key.trim().to_lowercase().as_str() {
+            "size" => FileSortKey::Size,
+            "name" => FileSortKey::Name,
+            "path" => FileSortKey::Path,
+            "mtime" => FileSortKey::Mtime,
+            other => return Err(format!("unknown sort key: {}", other)),
+        };
+        let order = match order.map(|o| o.trim().to_lowercase()) {
+            Some(o) if o == "asc" => SortOrder::Asc,
+            Some(o) if o == "desc" => SortOrder::Desc,
+            Some(o) => return Err(format!("unknown sort order: {}", o)),
+            None => match key {
+                FileSortKey::Size | FileSortKey::Mtime => SortOrder::Desc,
+                FileSortKey::Name | FileSortKey::Path => SortOrder::Asc,
+            },
         };
+        Ok(FileSort { key, order })
     }
-    None
 }
 
-/// scan_folder: returns FolderStats
-/// - uses parallel iterators (rayon)
-/// - minimal mutable: local fold usage (safe)
-pub fn scan_folder(path: &PathBuf, min_size_bytes: u64) -> Result<FolderStats, String> {
-    let paths: Vec<PathBuf> = WalkDir::new(path)
+/// one directory with everything below it attributed to it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DirEntrySummary {
+    pub path: String,
+    pub size: u64,
+    pub file_count: usize,
+}
+
+/// hierarchical size tree rooted at the scanned path (for drill-down views)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DirNode {
+    pub name: String,
+    pub size: u64,
+    pub file_count: usize,
+    pub children: Vec<DirNode>,
+}
+
+/// files grouped by how long ago they were last modified
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AgeBucket {
+    pub label: String,
+    pub count: usize,
+    pub total_size: u64,
+}
+
+const DAY_SECS: u64 = 86_400;
+
+/// upper bounds (exclusive, seconds of age) and labels of the age histogram;
+/// the last bucket catches everything older, and AGE_UNKNOWN_LABEL files
+/// whose mtime could not be read
+const AGE_BUCKETS: [(u64, &str); 5] = [
+    (7 * DAY_SECS, "<1 week"),
+    (30 * DAY_SECS, "1 week–1 month"),
+    (182 * DAY_SECS, "1–6 months"),
+    (365 * DAY_SECS, "6–12 months"),
+    (3 * 365 * DAY_SECS, "1–3 years"),
+];
+const AGE_OLDEST_LABEL: &str = ">3 years";
+const AGE_UNKNOWN_LABEL: &str = "unknown";
+
+/// files grouped by size range: min <= size < max (max None = no upper bound)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SizeBucket {
+    pub label: String,
+    pub min: u64,
+    pub max: Option<u64>,
+    pub count: usize,
+    pub total_size: u64,
+}
+
+/// a path the scan could not read (directory listing or file metadata)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScanErrorEntry {
+    pub path: String,
+    /// true when a directory could not be listed (its contents are missing)
+    pub is_dir: bool,
+    /// short io error kind, e.g. "permission denied"
+    pub kind: String,
+}
+
+/// stage a ScanProgress was sent from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanPhase {
+    /// walking the tree and reading metadata
+    Walking,
+    /// walk done, building the stats in parallel
+    Analyzing,
+    /// reading the listed files for their hashes (ScanOptions::hash_filtered);
+    /// sent again as Analyzing once that's done
+    Hashing,
+}
+
+/// periodic snapshot of a running scan (see ScanControl::progress)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScanProgress {
+    pub phase: ScanPhase,
+    pub files_seen: usize,
+    pub bytes_seen: u64,
+    /// directory of the most recently seen file, or the directory just entered
+    pub current_dir: String,
+}
+
+/// send progress at most every this many files...
+const PROGRESS_EVERY_FILES: usize = 2_000;
+/// ...or this often, whichever comes first
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// why a scan stopped without producing stats
+/// - problems below the root are not errors: they end up in FolderStats::errors
+#[derive(Debug, thiserror::Error)]
+pub enum ScanError {
+    /// the CancellationToken was triggered
+    #[error("scan cancelled")]
+    Cancelled,
+    #[error("folder not found: {}", path.display())]
+    RootNotFound { path: PathBuf },
+    #[error("not a folder: {}", path.display())]
+    RootNotADirectory { path: PathBuf },
+    /// the root exists but can't be stat'ed or listed (permissions, I/O)
+    #[error("cannot read {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// options that can't work (bad glob or regex, inverted size range)
+    #[error("{0}")]
+    InvalidOptions(String),
+}
+
+impl From<String> for ScanError {
+    fn from(msg: String) -> Self {
+        ScanError::InvalidOptions(msg)
+    }
+}
+
+/// ScanError without its payload: what the worker's exit code and the ipc
+/// layer carry, so the GUI can tell "pick another folder" from "retry"
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanErrorKind {
+    Cancelled,
+    RootNotFound,
+    RootNotADirectory,
+    Io,
+    InvalidOptions,
+}
+
+impl ScanError {
+    pub fn kind(&self) -> ScanErrorKind {
+        match self {
+            ScanError::Cancelled => ScanErrorKind::Cancelled,
+            ScanError::RootNotFound { .. } => ScanErrorKind::RootNotFound,
+            ScanError::RootNotADirectory { .. } => ScanErrorKind::RootNotADirectory,
+            ScanError::Io { .. } => ScanErrorKind::Io,
+            ScanError::InvalidOptions(_) => ScanErrorKind::InvalidOptions,
+        }
+    }
+
+    /// the folder it is about, if any
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            ScanError::RootNotFound { path }
+            | ScanError::RootNotADirectory { path }
+            | ScanError::Io { path, .. } => Some(path),
+            ScanError::Cancelled | ScanError::InvalidOptions(_) => None,
+        }
+    }
+
+    /// the cause under the message: the OS error, or what is wrong with the
+    /// options
+    pub fn detail(&self) -> Option<String> {
+        match self {
+            ScanError::Io { source, .. } => Some(source.to_string()),
+            ScanError::InvalidOptions(reason) => Some(reason.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl ScanErrorKind {
+    /// worker exit code for this kind (see run_worker in main.rs)
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ScanErrorKind::InvalidOptions => 3,
+            ScanErrorKind::Cancelled => 4,
+            ScanErrorKind::RootNotFound => 5,
+            ScanErrorKind::RootNotADirectory => 6,
+            ScanErrorKind::Io => 7,
+        }
+    }
+
+    /// inverse of exit_code; None for codes that aren't scan errors
+    pub fn from_exit_code(code: i32) -> Option<ScanErrorKind> {
+        [
+            ScanErrorKind::InvalidOptions,
+            ScanErrorKind::Cancelled,
+            ScanErrorKind::RootNotFound,
+            ScanErrorKind::RootNotADirectory,
+            ScanErrorKind::Io,
+        ]
         .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-        .map(|e| e.into_path())
-        .collect();
+        .find(|k| k.exit_code() == code)
+    }
 
-    let total_size: u64 = paths
-        .par_iter()
-        .map(|p| fs::metadata(p).map(|m| m.len()).unwrap_or(0))
-        .sum();
+    /// name as serialized ("root_not_found")
+    pub fn name(self) -> &'static str {
+        match self {
+            ScanErrorKind::Cancelled => "cancelled",
+            ScanErrorKind::RootNotFound => "root_not_found",
+            ScanErrorKind::RootNotADirectory => "root_not_a_directory",
+            ScanErrorKind::Io => "io",
+            ScanErrorKind::InvalidOptions => "invalid_options",
+        }
+    }
+}
 
-    let total_files = paths.len();
+/// shared flag to stop a running scan; clones observe the same flag
+/// - the walk checks it per entry, the analysis per file in its parallel
+///   stages (until) and between them, so a scan returns
+///   ScanError::Cancelled well within a second
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
 
-    // count extensions via parallel fold + reduce
-    let ext_map: HashMap<String, usize> = paths
-        .par_iter()
-        .map(|p| {
-            p.extension()
-                .and_then(|e| e.to_str())
-                .map(|s| s.to_lowercase())
-                .unwrap_or_else(|| "unknown".to_string())
-        })
-        .fold(
-            || HashMap::new(),
-            |mut acc: HashMap<String, usize>, ext| {
-                *acc.entry(ext).or_insert(0) += 1;
-                acc
-            },
-        )
-        .reduce(
-            || HashMap::new(),
-            |mut a: HashMap<String, usize>, b: HashMap<String, usize>| {
-                for (k, v) in b {
-                    *a.entry(k).or_insert(0) += v;
-                }
-                a
-            },
-        );
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, AtomicOrdering::Relaxed);
+    }
 
-    let mut extension_count: Vec<(String, usize)> = ext_map.into_iter().collect();
-    extension_count.sort_by(|a, b| b.1.cmp(&a.1));
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(AtomicOrdering::Relaxed)
+    }
 
-    // filtered files -> FileEntry
-    let filtered_files: Vec<FileEntry> = paths
-        .par_iter()
-        .filter_map(|p| fs::metadata(p).ok().map(|m| (p.clone(), m.len())))
-        .filter(|(_, sz)| *sz >= min_size_bytes)
-        .map(|(p, sz)| FileEntry {
-            path: p.to_string_lossy().into_owned(),
-            size: sz,
-        })
-        .collect();
+    /// Err(Cancelled) once cancel() was called
+    fn check(&self) -> Result<(), ScanError> {
+        if self.is_cancelled() {
+            Err(ScanError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
 
-    Ok(FolderStats {
-        total_size,
-        total_files,
-        extension_count,
-        filtered_files,
-    })
+/// hooks into a running scan, separate from ScanOptions because they are
+/// live handles rather than serializable parameters
+#[derive(Clone, Default)]
+pub struct ScanControl {
+    progress: Option<Sender<ScanProgress>>,
+    cancel: CancellationToken,
+    keep_partial: bool,
 }
 
-/// helper format human readable
-pub fn format_bytes(bytes: u64) -> String {
-    bytes
-        .file_size(options::CONVENTIONAL)
-        .unwrap_or_else(|_| format!("{} B", bytes))
+impl ScanControl {
+    /// stop the scan when `token` is cancelled
+    pub fn cancel_token(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// receive throttled ScanProgress updates; a dropped receiver is ignored
+    pub fn progress(mut self, tx: Sender<ScanProgress>) -> Self {
+        self.progress = Some(tx);
+        self
+    }
+
+    /// a cancelled scan_folder_with returns what it found until then, with
+    /// FolderStats::partial set, instead of Err(Cancelled)
+    pub fn keep_partial(mut self) -> Self {
+        self.keep_partial = true;
+        self
+    }
+}
+
+/// throttles ScanProgress across all roots of one scan
+/// - without a sender, `file()` is a single branch and nothing else
+struct ProgressReporter<'a> {
+    tx: Option<&'a Sender<ScanProgress>>,
+    files: usize,
+    bytes: u64,
+    since_last: usize,
+    last_sent: Instant,
+}
+
+impl<'a> ProgressReporter<'a> {
+    fn new(tx: Option<&'a Sender<ScanProgress>>) -> Self {
+        ProgressReporter {
+            tx,
+            files: 0,
+            bytes: 0,
+            since_last: 0,
+            last_sent: Instant::now(),
+        }
+    }
+
+    /// count one file; sends when the file or time threshold is crossed
+    fn file(&mut self, path: &Path, size: u64) {
+        if self.tx.is_none() {
+            return;
+        }
+        self.files += 1;
+        self.bytes += size;
+        self.since_last += 1;
+        // checking the clock every file would cost more than the send itself
+        if self.since_last >= PROGRESS_EVERY_FILES
+            || (self.since_last.is_multiple_of(64) && self.last_sent.elapsed() >= PROGRESS_INTERVAL)
+        {
+            let dir = path.parent().unwrap_or(path);
+            self.send(ScanPhase::Walking, dir);
+        }
+    }
+
+    /// note a directory being entered; sends on the time threshold only, so a
+    /// walk through empty directories still reports where it is
+    fn dir(&mut self, path: &Path) {
+        if self.tx.is_some() && self.last_sent.elapsed() >= PROGRESS_INTERVAL {
+            self.send(ScanPhase::Walking, path);
+        }
+    }
+
+    /// note a file being hashed, like dir: on the time threshold only, with
+    /// the walk's totals
+    fn hashing(&mut self, path: &Path) {
+        if self.tx.is_some() && self.last_sent.elapsed() >= PROGRESS_INTERVAL {
+            self.send(ScanPhase::Hashing, path.parent().unwrap_or(path));
+        }
+    }
+
+    fn send(&mut self, phase: ScanPhase, dir: &Path) {
+        if let Some(tx) = self.tx {
+            let _ = tx.send(ScanProgress {
+                phase,
+                files_seen: self.files,
+                bytes_seen: self.bytes,
+                current_dir: dir.to_string_lossy().into_owned(),
+            });
+            self.since_last = 0;
+            self.last_sent = Instant::now();
+        }
+    }
+}
+
+/// how the scan went: timing, throughput and the limits it ran under
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ScanMeta {
+    /// wall-clock time of the whole scan_folder call
+    pub duration_ms: u64,
+    /// time spent walking the tree and reading metadata
+    pub walk_ms: u64,
+    /// time spent in the parallel aggregation after the walk
+    pub analysis_ms: u64,
+    /// directories entered, the scan root included
+    pub dirs_visited: usize,
+    /// of those, directories whose files' metadata came from the scan cache
+    pub dirs_from_cache: usize,
+    /// files stat'ed per second of walk time
+    pub files_per_sec: f64,
+    /// a max_depth was set, so deeper content was not visited
+    pub depth_limited: bool,
+    /// exclude/include/hidden/gitignore filters were active
+    pub filtered: bool,
+    /// size range filtered_files was selected with
+    pub min_size: u64,
+    pub max_size: Option<u64>,
+    /// name_contains query filtered_files was selected with, as typed
+    #[serde(default)]
+    pub name_contains: Option<String>,
+    /// rayon threads the scan ran on (GENTLE_THREADS or fewer with
+    /// low_priority)
+    #[serde(default)]
+    pub threads: usize,
+    /// ScanOptions::ext_stats_min_size the breakdown was built with
+    #[serde(default)]
+    pub ext_stats_min_size: Option<u64>,
+    /// files left out of the breakdown by that floor
+    #[serde(default)]
+    pub ext_stats_below_floor: usize,
+    /// ran inside a Flatpak sandbox (in_flatpak_sandbox), which shows only
+    /// the parts of the host filesystem shared with it
+    #[serde(default)]
+    pub sandboxed: bool,
+}
+
+/// disk usage of one file owner (ScanOptions::group_by_owner)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OwnerUsage {
+    pub uid: u32,
+    /// login name from /etc/passwd, or the numeric uid when it has no entry
+    pub name: String,
+    pub total_size: u64,
+    pub file_count: usize,
+}
+
+/// why a file showed up in FolderStats::permission_findings
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionKind {
+    /// writable by every user (o+w)
+    WorldWritable,
+    Setuid,
+    Setgid,
+    /// owned by another user than the one running the scan
+    ForeignOwner,
+}
+
+impl PermissionKind {
+    /// short label for the GUI list
+    pub fn label(self) -> &'static str {
+        match self {
+            PermissionKind::WorldWritable => "world-writable",
+            PermissionKind::Setuid => "setuid",
+            PermissionKind::Setgid => "setgid",
+            PermissionKind::ForeignOwner => "foreign owner",
+        }
+    }
+}
+
+/// one risky-permission finding (ScanOptions::check_permissions); a file
+/// with several problems has one finding per kind
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PermissionFinding {
+    pub path: String,
+    /// permission bits, e.g. 0o4755
+    pub mode: u32,
+    pub kind: PermissionKind,
+}
+
+/// totals for one coarse content category (see category.rs for the mapping)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CategoryStat {
+    pub category: Category,
+    pub count: usize,
+    pub total_size: u64,
+}
+
+/// upper bounds (exclusive) of the size histogram; one more bucket above the last
+const SIZE_BUCKET_BOUNDS: [u64; 4] = [4 * 1024, 1024 * 1024, 100 * 1024 * 1024, 1024 * 1024 * 1024];
+const SIZE_BUCKET_LABELS: [&str; 5] = ["0–4 KiB", "4 KiB–1 MiB", "1–100 MiB", "100 MiB–1 GiB", ">1 GiB"];
+
+/// version of the FolderStats shape, written into every result as
+/// schema_version
+/// - bump it whenever a field is added or changes type, and keep older
+///   shapes readable (ExtensionStatWire, FileEntryWire)
+/// - 0 = written before the field existed
+pub const FOLDER_STATS_SCHEMA: u32 = 3;
+
+/// why a scan stopped before it was done (PartialScan::reason)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// its CancellationToken (a worker: SIGTERM)
+    Cancelled,
+    /// cancelled by a caller whose time limit ran out (ipc's timeout)
+    TimedOut,
+}
+
+/// FolderStats of a scan stopped half-way (ScanControl::keep_partial): what
+/// the walk had reached by then
+/// - every count and size is a lower bound: totals, per extension,
+///   category, directory and owner, histogram buckets, matched_*, the
+///   skipped/excluded/error counters
+/// - ranked lists (filtered_files, largest_dirs, extension_largest,
+///   oldest/newest/recent files, ...) are the top of the files seen; the
+///   tree's real top may sit in the part never read
+/// - size_quantiles, mean_file_depth and max_depth_seen describe the files
+///   seen, they bound nothing
+/// - empty_dirs is left empty (unread directories would look empty) and
+///   the scan cache is not updated, as with truncated
+/// - hashes (hash_filtered) are only there for files hashed before the
+///   stop; the others count in hash_errors
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartialScan {
+    pub reason: StopReason,
+    /// only total_files and total_size are known, from the last progress
+    /// update of a worker that was gone before it sent its partial result;
+    /// everything else is empty
+    #[serde(default)]
+    pub totals_only: bool,
+}
+
+/// result of scan_folder
+/// - every list has a total order (ties broken by name or path), so two scans
+///   of an unchanged tree serialize identically apart from scan_meta
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FolderStats {
+    /// FOLDER_STATS_SCHEMA of the build that wrote it
+    #[serde(default)]
+    pub schema_version: u32,
+    pub total_size: u64,
+    /// on-disk usage (allocated blocks); differs from total_size for sparse
+    /// files and small-file block overhead
+    #[serde(default)]
+    pub total_allocated: u64,
+    pub total_files: usize,
+    pub extension_count: Vec<ExtensionStat>,
+    /// (extension, its biggest file) for every extension in extension_count
+    /// with a readable file, biggest first
+    #[serde(default)]
+    pub extension_largest: Vec<(String, FileEntry)>,
+    /// count/size per content category (images, video, ...), largest first;
+    /// categories without files are left out
+    #[serde(default)]
+    pub category_summary: Vec<CategoryStat>,
+    pub filtered_files: Vec<FileEntry>,
+    /// files that passed the filters; more than filtered_files.len() when
+    /// ScanOptions::max_results cut the list
+    #[serde(default)]
+    pub matched_total: usize,
+    /// combined size of all matched files, listed or not
+    #[serde(default)]
+    pub matched_size: u64,
+    /// top N subdirectories by cumulative size (scan root excluded)
+    #[serde(default)]
+    pub largest_dirs: Vec<DirEntrySummary>,
+    /// (name, size, file count) per immediate child of the root, like
+    /// `du -d1`, biggest first (ties by name); files directly in the root
+    /// are under TOP_LEVEL_ROOT_FILES, extra roots' children merge by name
+    #[serde(default)]
+    pub top_level_summary: Vec<(String, u64, usize)>,
+    /// top N directories by direct-child file count (root included), for
+    /// finding inode-heavy folders; same N as largest_dirs
+    #[serde(default)]
+    pub dirs_by_file_count: Vec<(String, usize)>,
+    /// depth limit the scan ran with; Some(_) means the totals are not a full scan
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    /// directories pruned by exclude patterns (their contents never visited)
+    #[serde(default)]
+    pub excluded_dirs: usize,
+    /// individual files skipped by exclude patterns
+    #[serde(default)]
+    pub excluded_files: usize,
+    /// symlinks that could not be followed (broken target or loop)
+    #[serde(default)]
+    pub skipped_links: usize,
+    /// symlinks met during the walk, followed or not; they add to total_size
+    /// only through their targets, and only with follow_symlinks
+    #[serde(default)]
+    pub symlink_count: usize,
+    /// symlinks among symlink_count whose target does not exist
+    #[serde(default)]
+    pub symlink_targets_missing: usize,
+    /// those links, sorted, at most BROKEN_SYMLINK_LIMIT of them
+    #[serde(default)]
+    pub broken_symlinks: Vec<String>,
+    /// broken symlinks beyond BROKEN_SYMLINK_LIMIT that were not listed
+    #[serde(default)]
+    pub broken_symlinks_overflow: usize,
+    /// paths skipped because they were the same physical file as one already
+    /// counted (reached via a followed symlink, or a collapsed hardlink)
+    #[serde(default)]
+    pub collapsed_duplicates: usize,
+    /// hidden entries (files + directories) pruned by skip_hidden
+    #[serde(default)]
+    pub skipped_hidden: usize,
+    /// entries left out by .gitignore rules (plus .git directories)
+    #[serde(default)]
+    pub gitignored: usize,
+    /// directories pruned by ScanOptions::skip_presets
+    #[serde(default)]
+    pub preset_skipped_dirs: usize,
+    /// virtual filesystem mounts (/proc, /sys, ...) left out, sorted; see
+    /// ScanOptions::include_virtual
+    #[serde(default)]
+    pub virtual_mounts_skipped: Vec<String>,
+    /// the walk stopped at ScanOptions::file_limit: every total covers only
+    /// the files counted before that (and empty_dirs is left empty)
+    #[serde(default)]
+    pub truncated: bool,
+    /// files the walk had already found past the limit and left out; a lower
+    /// bound, the part of the tree it never read is not guessed at
+    /// - both walks count the rest of the directory being read when the
+    ///   limit hit; the parallel one also the other listings it had read
+    #[serde(default)]
+    pub files_skipped_estimate: usize,
+    /// Some = the scan was stopped before it was done, see PartialScan
+    #[serde(default)]
+    pub partial: Option<PartialScan>,
+    /// file count/size by modification age, relative to the scan time
+    #[serde(default)]
+    pub age_histogram: Vec<AgeBucket>,
+    /// file count/size by size range (files with unreadable size left out)
+    #[serde(default)]
+    pub size_histogram: Vec<SizeBucket>,
+    /// least recently modified file (files with unreadable mtime skipped)
+    #[serde(default)]
+    pub oldest_file: Option<FileEntry>,
+    /// most recently modified file
+    #[serde(default)]
+    pub newest_file: Option<FileEntry>,
+    /// the ScanOptions::recent_files_limit most recently modified files,
+    /// newest first (equal mtimes by path; unreadable mtimes left out)
+    #[serde(default)]
+    pub recent_files: Vec<FileEntry>,
+    /// largest files of ScanOptions::detail_extension, whatever the size
+    /// filter (empty without it)
+    #[serde(default)]
+    pub extension_detail: Vec<FileEntry>,
+    /// zero-byte files (only with ScanOptions::find_empty_files), sorted by path
+    #[serde(default)]
+    pub empty_files: Vec<String>,
+    /// zero-byte files beyond empty_files_limit that were not listed
+    #[serde(default)]
+    pub empty_files_overflow: usize,
+    /// files with far fewer bytes allocated than their size (VM images, core
+    /// dumps, preallocated downloads), most unallocated bytes first; always
+    /// empty on non-Unix builds
+    #[serde(default)]
+    pub sparse_files: Vec<FileEntry>,
+    /// sparse files beyond SPARSE_LIST_LIMIT that were not listed
+    #[serde(default)]
+    pub sparse_files_overflow: usize,
+    /// size - allocated summed over all sparse files, listed or not
+    #[serde(default)]
+    pub sparse_unallocated: u64,
+    /// allocated - size summed over files where allocation exceeds the size
+    /// (the last block's unused tail); None on non-Unix builds
+    #[serde(default)]
+    pub slack_bytes: Option<u64>,
+    /// files smaller than one block of the root's filesystem (None as above)
+    #[serde(default)]
+    pub sub_block_files: Option<usize>,
+    /// world-writable, setuid/setgid and foreign-owned files, sorted by path
+    /// (only with ScanOptions::check_permissions; always empty on non-Unix)
+    #[serde(default)]
+    pub permission_findings: Vec<PermissionFinding>,
+    /// findings beyond PERMISSION_LIST_LIMIT that were not listed
+    #[serde(default)]
+    pub permission_findings_overflow: usize,
+    /// directories with nothing in them at any depth (only with
+    /// ScanOptions::find_empty_dirs); only the topmost of a nested empty
+    /// chain is listed, sorted by path
+    #[serde(default)]
+    pub empty_dirs: Vec<String>,
+    /// empty directories beyond empty_dirs_limit that were not listed
+    #[serde(default)]
+    pub empty_dirs_overflow: usize,
+    /// usage per owner, largest first (only with ScanOptions::group_by_owner)
+    #[serde(default)]
+    pub owner_summary: Vec<OwnerUsage>,
+    /// unreadable paths sorted by path (the first ScanOptions::error_limit
+    /// of them); totals above are partial when this is non-empty
+    #[serde(default)]
+    pub errors: Vec<ScanErrorEntry>,
+    /// all read errors, including those beyond the errors cap
+    #[serde(default)]
+    pub error_count: usize,
+    /// files counted in total_files whose metadata could not be read (even
+    /// after a retry): they add 0 bytes, so total_size is a lower bound and
+    /// they can't match a size filter
+    #[serde(default)]
+    pub metadata_errors: usize,
+    /// files listed by the walk but gone before their metadata was read;
+    /// they count nowhere else (not in total_files, errors or any list)
+    #[serde(default)]
+    pub vanished_files: usize,
+    /// the first METADATA_ERROR_SAMPLE of those by path
+    #[serde(default)]
+    pub metadata_error_paths: Vec<String>,
+    /// filtered_files whose content could not be hashed (hash_filtered only)
+    #[serde(default)]
+    pub hash_errors: usize,
+    /// levels below the root of the deepest entry walked (file or directory;
+    /// 0 for an empty root)
+    #[serde(default)]
+    pub max_depth_seen: usize,
+    /// that entry; on equal depth the smallest path
+    #[serde(default, with = "os_path::option")]
+    pub deepest_path: Option<PathBuf>,
+    /// average levels below the root of the counted files (1 = in the root)
+    #[serde(default)]
+    pub mean_file_depth: f64,
+    /// how file sizes are spread: a few huge files or very many small ones
+    #[serde(default)]
+    pub size_quantiles: SizeQuantiles,
+    /// timing and throughput of this scan
+    #[serde(default)]
+    pub scan_meta: ScanMeta,
+}
+
+/// one line of the streaming output (worker --format ndjson)
+/// - `"type"` is "file", "error" or "summary"; the other keys are those of
+///   the wrapped struct, so a file line is a FileEntry plus "type"
+/// - file and error lines come in walk order, one summary line last
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum StreamRecord {
+    File(FileEntry),
+    Error(ScanErrorEntry),
+    Summary(StreamSummary),
+}
+
+/// totals closing a scan_folder_stream run
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StreamSummary {
+    pub total_size: u64,
+    pub total_allocated: u64,
+    pub total_files: usize,
+    /// files that passed the filters, i.e. "file" lines emitted
+    pub matched_total: usize,
+    pub matched_size: u64,
+    pub error_count: usize,
+    /// see FolderStats::metadata_errors
+    #[serde(default)]
+    pub metadata_errors: usize,
+    /// see FolderStats::vanished_files
+    #[serde(default)]
+    pub vanished_files: usize,
+    /// file lines whose content could not be hashed (hash_filtered only)
+    pub hash_errors: usize,
+    pub extension_count: Vec<ExtensionStat>,
+    pub duration_ms: u64,
+    /// see FolderStats::truncated
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+/// one CSV field: quoted (quotes doubled) when it holds a comma, quote or newline
+fn csv_field(s: &str) -> std::borrow::Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\"")).into()
+    } else {
+        s.into()
+    }
+}
+
+impl FolderStats {
+    /// stand-in for a stopped scan known only from its progress updates
+    /// (PartialScan::totals_only): `files` and `bytes` seen so far
+    /// - total_allocated is unknown and given as total_size, so it doesn't
+    ///   read as sparse or slack space
+    pub fn partial_totals(files: usize, bytes: u64, reason: StopReason) -> FolderStats {
+        FolderStats {
+            schema_version: FOLDER_STATS_SCHEMA,
+            total_size: bytes,
+            total_allocated: bytes,
+            total_files: files,
+            partial: Some(PartialScan {
+                reason,
+                totals_only: true,
+            }),
+            ..FolderStats::default()
+        }
+    }
+
+    /// every path in it replaced by what `map` makes of it (None = kept),
+    /// display strings too; for a result scanned where its roots have
+    /// other paths (ipc's host-side Flatpak worker)
+    pub fn map_paths(&mut self, map: &dyn Fn(&Path) -> Option<PathBuf>) {
+        let text = |s: &mut String| {
+            if let Some(new) = map(Path::new(s.as_str())) {
+                *s = new.to_string_lossy().into_owned();
+            }
+        };
+        let entry = |e: &mut FileEntry| {
+            if let Some(new) = map(&e.path) {
+                e.display = new.to_string_lossy().into_owned();
+                e.path = new;
+            }
+        };
+        self.extension_largest.iter_mut().for_each(|(_, e)| entry(e));
+        self.filtered_files.iter_mut().for_each(entry);
+        self.oldest_file.iter_mut().for_each(entry);
+        self.newest_file.iter_mut().for_each(entry);
+        self.recent_files.iter_mut().for_each(entry);
+        self.extension_detail.iter_mut().for_each(entry);
+        self.sparse_files.iter_mut().for_each(entry);
+        self.largest_dirs.iter_mut().for_each(|d| text(&mut d.path));
+        self.dirs_by_file_count.iter_mut().for_each(|(path, _)| text(path));
+        self.broken_symlinks.iter_mut().for_each(text);
+        self.empty_files.iter_mut().for_each(text);
+        self.empty_dirs.iter_mut().for_each(text);
+        self.permission_findings.iter_mut().for_each(|f| text(&mut f.path));
+        self.errors.iter_mut().for_each(|e| text(&mut e.path));
+        self.metadata_error_paths.iter_mut().for_each(text);
+        if let Some(deepest) = &mut self.deepest_path
+            && let Some(new) = map(deepest)
+        {
+            *deepest = new;
+        }
+    }
+
+    /// extension_count as CSV: extension,count,size_bytes,size_human
+    pub fn extensions_to_csv(&self, mut w: impl Write) -> io::Result<()> {
+        writeln!(w, "extension,count,size_bytes,size_human")?;
+        for e in &self.extension_count {
+            writeln!(
+                w,
+                "{},{},{},{}",
+                csv_field(&e.extension),
+                e.count,
+                e.total_size,
+                format_bytes(e.total_size)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// filtered_files as CSV: path,size_bytes,size_human
+    /// - paths are FileEntry::display, so a name that isn't valid UTF-8 is
+    ///   written lossily
+    pub fn files_to_csv(&self, mut w: impl Write) -> io::Result<()> {
+        writeln!(w, "path,size_bytes,size_human")?;
+        for f in &self.filtered_files {
+            writeln!(w, "{},{},{}", csv_field(&f.display), f.size, format_bytes(f.size))?;
+        }
+        Ok(())
+    }
+
+    /// both CSV sections, extensions first, separated by one empty line
+    pub fn to_csv(&self, mut w: impl Write) -> io::Result<()> {
+        self.extensions_to_csv(&mut w)?;
+        writeln!(w)?;
+        self.files_to_csv(&mut w)
+    }
+
+    /// what changed from this scan (before) to `after`
+    /// - files are matched by exact path (case-sensitive); only the listed
+    ///   filtered_files of each side are compared
+    /// - a moved file shows up as removed + added, never as a rename
+    /// - lists are sorted by path, extensions by size change (largest first)
+    pub fn diff(&self, after: &FolderStats) -> StatsDiff {
+        let before_files: HashMap<&Path, &FileEntry> =
+            self.filtered_files.iter().map(|f| (f.path.as_path(), f)).collect();
+        let after_files: HashMap<&Path, &FileEntry> =
+            after.filtered_files.iter().map(|f| (f.path.as_path(), f)).collect();
+
+        let mut added: Vec<FileEntry> = after_files
+            .iter()
+            .filter(|(p, _)| !before_files.contains_key(*p))
+            .map(|(_, f)| (*f).clone())
+            .collect();
+        let mut removed: Vec<FileEntry> = before_files
+            .iter()
+            .filter(|(p, _)| !after_files.contains_key(*p))
+            .map(|(_, f)| (*f).clone())
+            .collect();
+        let mut resized: Vec<SizeChange> = before_files
+            .iter()
+            .filter_map(|(p, old)| {
+                let new = after_files.get(p)?;
+                (old.size != new.size).then(|| SizeChange {
+                    path: old.display.clone(),
+                    old_size: old.size,
+                    new_size: new.size,
+                })
+            })
+            .collect();
+        added.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+        removed.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+        resized.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+
+        // (count, size) per extension on each side; missing = (0, 0)
+        type Side = (usize, u64);
+        let mut ext: HashMap<&str, (Side, Side)> = HashMap::new();
+        for e in &self.extension_count {
+            ext.entry(e.extension.as_str()).or_default().0 = (e.count, e.total_size);
+        }
+        for e in &after.extension_count {
+            ext.entry(e.extension.as_str()).or_default().1 = (e.count, e.total_size);
+        }
+        let mut extensions: Vec<ExtensionDelta> = ext
+            .into_iter()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(extension, (old, new))| ExtensionDelta {
+                extension: extension.to_string(),
+                count_delta: new.0 as i64 - old.0 as i64,
+                size_delta: new.1 as i64 - old.1 as i64,
+            })
+            .collect();
+        extensions.sort_unstable_by(|a, b| {
+            b.size_delta
+                .unsigned_abs()
+                .cmp(&a.size_delta.unsigned_abs())
+                .then_with(|| a.extension.cmp(&b.extension))
+        });
+
+        StatsDiff {
+            total_size_delta: after.total_size as i64 - self.total_size as i64,
+            total_files_delta: after.total_files as i64 - self.total_files as i64,
+            extensions,
+            added,
+            removed,
+            resized,
+        }
+    }
+}
+
+/// FolderStats::diff result; deltas are after - before
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StatsDiff {
+    pub total_size_delta: i64,
+    pub total_files_delta: i64,
+    /// extensions whose count or size changed
+    pub extensions: Vec<ExtensionDelta>,
+    /// listed files only in the later scan
+    pub added: Vec<FileEntry>,
+    /// listed files only in the earlier scan
+    pub removed: Vec<FileEntry>,
+    /// listed files in both scans with a different size
+    pub resized: Vec<SizeChange>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExtensionDelta {
+    pub extension: String,
+    pub count_delta: i64,
+    pub size_delta: i64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SizeChange {
+    pub path: String,
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+/// named lists of directories that are rarely worth scanning
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// dependency trees, build output, VCS metadata and virtualenvs
+    Development,
+    /// application and tool caches
+    Caches,
+}
+
+impl Preset {
+    /// directory names this preset prunes, matched exactly against each
+    /// directory's own name at any depth
+    pub fn dir_names(self) -> &'static [&'static str] {
+        match self {
+            Preset::Development => &["node_modules", ".git", "target", "__pycache__", ".venv"],
+            Preset::Caches => &[
+                ".cache",
+                "Cache",
+                "Caches",
+                ".ccache",
+                ".pytest_cache",
+                ".mypy_cache",
+                ".sass-cache",
+            ],
+        }
+    }
+}
+
+impl std::str::FromStr for Preset {
+    type Err = String;
+
+    /// "dev"/"development" or "caches"/"cache"
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.trim().to_lowercase().as_str() {
+            "dev" | "development" => Ok(Preset::Development),
+            "cache" | "caches" => Ok(Preset::Caches),
+            other => Err(format!("unknown preset '{}' (expected dev or caches)", other)),
+        }
+    }
+}
+
+/// whether one of `presets` prunes a directory called `name`
+fn preset_skips(presets: &[Preset], name: &std::ffi::OsStr) -> bool {
+    presets.iter().any(|p| p.dir_names().iter().any(|n| name == *n))
+}
+
+/// filesystem types whose files describe the running system, not data on a
+/// disk: sizes like /proc/kcore's are meaningless and some files block
+const VIRTUAL_FS_TYPES: &[&str] = &[
+    "proc",
+    "sysfs",
+    "devtmpfs",
+    "devpts",
+    "cgroup",
+    "cgroup2",
+    "securityfs",
+    "debugfs",
+    "tracefs",
+    "pstore",
+    "bpf",
+    "configfs",
+    "fusectl",
+    "mqueue",
+];
+
+/// virtual mount points (see VIRTUAL_FS_TYPES) strictly below `root`, as
+/// paths the walk of `root` meets them at; empty with include_virtual
+/// - from /proc/mounts, so Linux only; a root that is itself a virtual
+///   mount is scanned, since it was asked for
+fn virtual_mounts_below(opts: &ScanOptions, root: &Path) -> Vec<PathBuf> {
+    if opts.include_virtual {
+        return Vec::new();
+    }
+    let Ok(real_root) = fs::canonicalize(root) else {
+        return Vec::new();
+    };
+    virtual_mounts()
+        .into_iter()
+        .filter_map(|m| {
+            let rel = m.strip_prefix(&real_root).ok()?;
+            (!rel.as_os_str().is_empty()).then(|| root.join(rel))
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn virtual_mounts() -> Vec<PathBuf> {
+    let Ok(mounts) = fs::read_to_string("/proc/mounts") else {
+        return Vec::new();
+    };
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let (_, point, fs_type) = (fields.next()?, fields.next()?, fields.next()?);
+            VIRTUAL_FS_TYPES
+                .contains(&fs_type)
+                .then(|| unescape_mount_point(point))
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn virtual_mounts() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+/// file Flatpak puts at the root of every sandbox, describing it
+pub const FLATPAK_INFO: &str = "/.flatpak-info";
+
+/// whether this process runs inside a Flatpak sandbox
+pub fn in_flatpak_sandbox() -> bool {
+    Path::new(FLATPAK_INFO).exists()
+}
+
+/// /proc/mounts writes space, tab, newline and backslash as \ooo octal
+#[cfg(target_os = "linux")]
+fn unescape_mount_point(field: &str) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|d| {
+            let d = std::str::from_utf8(d).ok()?;
+            u8::from_str_radix(d, 8).ok()
+        });
+        match octal {
+            Some(b) if bytes[i] == b'\\' => {
+                out.push(b);
+                i += 4;
+            }
+            _ => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(std::ffi::OsString::from_vec(out))
+}
+
+/// scan parameters, passed as one value through the GUI, ipc and worker
+/// - builder style: ScanOptions::new(path).min_size(..).max_depth(..)
+/// - deserializable from JSON so the worker can receive richer requests
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    #[serde(with = "os_path")]
+    pub path: PathBuf,
+    /// more folders scanned together with `path` into one FolderStats
+    /// (scan_folder only; roots nested in another root are dropped)
+    #[serde(with = "os_path::vec")]
+    pub extra_roots: Vec<PathBuf>,
+    pub min_size: u64,
+    /// largest size (bytes, inclusive) for filtered_files; None = no upper bound
+    pub max_size: Option<u64>,
+    pub max_depth: Option<usize>,
+    pub ext_sort: ExtensionSort,
+    /// ordering of filtered_files (default size descending)
+    pub sort_by: FileSort,
+    /// keep only the first N matches (in sort_by order) in filtered_files (None = all)
+    pub max_results: Option<usize>,
+    /// stop the walk once this many files are counted (FolderStats::truncated)
+    pub file_limit: Option<usize>,
+    pub top_dirs: usize,
+    /// how many entries FolderStats::recent_files keeps
+    pub recent_files_limit: usize,
+    /// extension_key (as listed in extension_count, leading dot optional)
+    /// whose largest files go to FolderStats::extension_detail
+    pub detail_extension: Option<String>,
+    /// how many entries FolderStats::extension_detail keeps
+    pub detail_limit: usize,
+    pub tree_depth: usize,
+    /// glob patterns for directories/files to leave out of the scan
+    pub exclude: Vec<String>,
+    /// when non-empty, only files matching one of these globs are counted
+    /// (directories are always descended; exclude still wins)
+    pub include: Vec<String>,
+    /// only count files with one of these extensions (case-insensitive, dot
+    /// optional); see extension_allowed. None or empty = every file
+    pub only_extensions: Option<Vec<String>>,
+    /// group extension_count case-insensitively (default); false keeps
+    /// "JPG" and "jpg" apart, exactly as the names have them
+    pub case_insensitive_extensions: bool,
+    /// leave files under this size (bytes) out of extension_count,
+    /// extension_largest and category_summary; totals still count them
+    pub ext_stats_min_size: Option<u64>,
+    /// regex that file names must match to appear in filtered_files
+    pub name_regex: Option<String>,
+    /// apply name_regex to the whole path instead of just the file name
+    pub match_full_path: bool,
+    /// text file names must contain (case-insensitive) to appear in filtered_files
+    pub name_contains: Option<String>,
+    /// descend into symlinked directories and count symlinked files
+    pub follow_symlinks: bool,
+    /// count each hardlinked physical file once (Unix only; no-op elsewhere)
+    pub dedupe_hardlinks: bool,
+    /// prune directories named in these presets (see Preset::dir_names)
+    pub skip_presets: Vec<Preset>,
+    /// descend into proc/sysfs/devtmpfs/cgroup mounts below the root
+    /// (default: pruned, see virtual_mounts_below)
+    pub include_virtual: bool,
+    /// prune entries whose name starts with '.' (dot-directories not descended)
+    pub skip_hidden: bool,
+    /// honor .gitignore / .git/info/exclude files found in the tree, and skip .git
+    pub respect_gitignore: bool,
+    /// only list files last modified strictly before this time
+    pub modified_before: Option<SystemTime>,
+    /// only list files last modified at or after this time
+    pub modified_after: Option<SystemTime>,
+    /// collect zero-byte files into FolderStats::empty_files
+    pub find_empty_files: bool,
+    /// max entries kept in empty_files (the rest only counted)
+    pub empty_files_limit: usize,
+    /// collect recursively empty directories into FolderStats::empty_dirs
+    pub find_empty_dirs: bool,
+    /// max entries kept in empty_dirs (the rest only counted)
+    pub empty_dirs_limit: usize,
+    /// aggregate usage per file owner into FolderStats::owner_summary (Unix only)
+    pub group_by_owner: bool,
+    /// max entries kept in FolderStats::errors (the rest only counted)
+    pub error_limit: usize,
+    /// reuse/refresh the per-root metadata cache under ~/.cache/fscan (see
+    /// SCAN CACHE: files changed in place can keep stale metadata)
+    pub use_cache: bool,
+    /// SHA-256 every file in filtered_files into FileEntry::hash
+    pub hash_filtered: bool,
+    /// collect FolderStats::permission_findings (Unix only)
+    pub check_permissions: bool,
+    /// read directories in parallel on the rayon pool (false = one thread)
+    pub parallel_walk: bool,
+    /// scan gently: GENTLE_THREADS threads (fewer under limit_threads) and
+    /// a short pause every GENTLE_BATCH directories; same result, only slower
+    pub low_priority: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            path: PathBuf::new(),
+            extra_roots: Vec::new(),
+            min_size: 0,
+            max_size: None,
+            max_depth: None,
+            ext_sort: ExtensionSort::default(),
+            sort_by: FileSort::default(),
+            max_results: None,
+            file_limit: None,
+            top_dirs: 20,
+            recent_files_limit: 20,
+            detail_extension: None,
+            detail_limit: 100,
+            tree_depth: 4,
+            exclude: Vec::new(),
+            include: Vec::new(),
+            name_regex: None,
+            match_full_path: false,
+            name_contains: None,
+            only_extensions: None,
+            case_insensitive_extensions: true,
+            ext_stats_min_size: None,
+            follow_symlinks: false,
+            dedupe_hardlinks: false,
+            skip_presets: Vec::new(),
+            include_virtual: false,
+            skip_hidden: false,
+            respect_gitignore: false,
+            modified_before: None,
+            modified_after: None,
+            find_empty_files: false,
+            empty_files_limit: 1000,
+            find_empty_dirs: false,
+            empty_dirs_limit: 1000,
+            group_by_owner: false,
+            error_limit: 500,
+            use_cache: false,
+            hash_filtered: false,
+            check_permissions: false,
+            parallel_walk: true,
+            low_priority: false,
+        }
+    }
+}
+
+impl ScanOptions {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        ScanOptions {
+            path: path.into(),
+            ..Default::default()
+        }
+    }
+
+    /// add another folder to scan alongside `path`
+    pub fn extra_root(mut self, path: impl Into<PathBuf>) -> Self {
+        self.extra_roots.push(path.into());
+        self
+    }
+
+    /// minimum size (bytes) for a file to appear in filtered_files
+    pub fn min_size(mut self, bytes: u64) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    /// maximum size (bytes, inclusive) for a file to appear in filtered_files
+    /// - must not be below min_size, scan_folder rejects that
+    pub fn max_size(mut self, bytes: Option<u64>) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// max recursion depth, None = unlimited
+    /// - 1 = only files directly inside the chosen folder
+    pub fn max_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// ordering of the extension list (default: total size descending)
+    pub fn ext_sort(mut self, sort: ExtensionSort) -> Self {
+        self.ext_sort = sort;
+        self
+    }
+
+    /// order filtered_files by this key/direction
+    pub fn sort_by(mut self, sort: FileSort) -> Self {
+        self.sort_by = sort;
+        self
+    }
+
+    /// cap filtered_files at the first N matches in sort_by order,
+    /// i.e. the N largest by default (default: unlimited)
+    pub fn max_results(mut self, n: Option<usize>) -> Self {
+        self.max_results = n;
+        self
+    }
+
+    /// count at most N files, then stop descending and report what was found
+    pub fn file_limit(mut self, n: Option<usize>) -> Self {
+        self.file_limit = n;
+        self
+    }
+
+    /// how many entries to keep in largest_dirs and dirs_by_file_count (default 20)
+    pub fn top_dirs(mut self, n: usize) -> Self {
+        self.top_dirs = n;
+        self
+    }
+
+    /// levels kept by scan_folder_tree; deeper content is folded into its
+    /// ancestor at this depth (default 4)
+    pub fn tree_depth(mut self, depth: usize) -> Self {
+        self.tree_depth = depth;
+        self
+    }
+
+    /// add a glob exclude pattern (see GlobPattern for the matching rules)
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// add a glob include pattern; once any is set, only matching files count
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// restrict filtered_files to names matching this regex
+    pub fn name_regex(mut self, re: Option<String>) -> Self {
+        self.name_regex = re;
+        self
+    }
+
+    /// match name_regex against the full path rather than the file name
+    pub fn match_full_path(mut self, yes: bool) -> Self {
+        self.match_full_path = yes;
+        self
+    }
+
+    /// prune the directories of a preset; repeatable
+    pub fn skip_preset(mut self, preset: Preset) -> Self {
+        if !self.skip_presets.contains(&preset) {
+            self.skip_presets.push(preset);
+        }
+        self
+    }
+
+    /// walk virtual filesystem mounts too (/proc, /sys, ...)
+    pub fn include_virtual(mut self, yes: bool) -> Self {
+        self.include_virtual = yes;
+        self
+    }
+
+    /// count only files with these extensions, everywhere (totals too)
+    pub fn only_extensions(mut self, exts: Option<Vec<String>>) -> Self {
+        self.only_extensions = exts;
+        self
+    }
+
+    /// fold extension case in extension_count (default true)
+    pub fn case_insensitive_extensions(mut self, yes: bool) -> Self {
+        self.case_insensitive_extensions = yes;
+        self
+    }
+
+    /// size floor for the extension and category breakdown
+    pub fn ext_stats_min_size(mut self, floor: Option<u64>) -> Self {
+        self.ext_stats_min_size = floor;
+        self
+    }
+
+    /// restrict filtered_files to names containing this text, ignoring case
+    pub fn name_contains(mut self, text: Option<String>) -> Self {
+        self.name_contains = text;
+        self
+    }
+
+    /// follow symlinks (loop-safe, each real file counted once)
+    pub fn follow_symlinks(mut self, yes: bool) -> Self {
+        self.follow_symlinks = yes;
+        self
+    }
+
+    /// collapse hardlinks so total_size matches physical usage
+    pub fn dedupe_hardlinks(mut self, yes: bool) -> Self {
+        self.dedupe_hardlinks = yes;
+        self
+    }
+
+    /// skip hidden files and don't descend into dot-directories
+    pub fn skip_hidden(mut self, yes: bool) -> Self {
+        self.skip_hidden = yes;
+        self
+    }
+
+    /// leave out what git would ignore (nested .gitignore files honored)
+    pub fn respect_gitignore(mut self, yes: bool) -> Self {
+        self.respect_gitignore = yes;
+        self
+    }
+
+    /// filtered_files only: modified before `t` (e.g. "not touched in 2 years")
+    pub fn modified_before(mut self, t: Option<SystemTime>) -> Self {
+        self.modified_before = t;
+        self
+    }
+
+    /// filtered_files only: modified at or after `t`
+    pub fn modified_after(mut self, t: Option<SystemTime>) -> Self {
+        self.modified_after = t;
+        self
+    }
+
+    /// list zero-byte files (off by default, so nobody pays for it unasked)
+    pub fn find_empty_files(mut self, yes: bool) -> Self {
+        self.find_empty_files = yes;
+        self
+    }
+
+    /// how many recently modified files to list (default 20)
+    pub fn recent_files_limit(mut self, n: usize) -> Self {
+        self.recent_files_limit = n;
+        self
+    }
+
+    /// list the largest files of one extension in extension_detail
+    pub fn detail_extension(mut self, ext: Option<String>) -> Self {
+        self.detail_extension = ext;
+        self
+    }
+
+    /// number of files kept in extension_detail (default 100)
+    pub fn detail_limit(mut self, n: usize) -> Self {
+        self.detail_limit = n;
+        self
+    }
+
+    /// cap for the empty_files list (default 1000)
+    pub fn empty_files_limit(mut self, n: usize) -> Self {
+        self.empty_files_limit = n;
+        self
+    }
+
+    /// list directories that contain nothing, not even in subdirectories
+    pub fn find_empty_dirs(mut self, yes: bool) -> Self {
+        self.find_empty_dirs = yes;
+        self
+    }
+
+    /// cap for the empty_dirs list (default 1000)
+    pub fn empty_dirs_limit(mut self, n: usize) -> Self {
+        self.empty_dirs_limit = n;
+        self
+    }
+
+    /// report per-owner totals (empty list on non-Unix builds)
+    pub fn group_by_owner(mut self, yes: bool) -> Self {
+        self.group_by_owner = yes;
+        self
+    }
+
+    /// cap for the errors list (default 500)
+    pub fn error_limit(mut self, n: usize) -> Self {
+        self.error_limit = n;
+        self
+    }
+
+    /// turn the incremental-rescan cache on/off (default off)
+    pub fn use_cache(mut self, yes: bool) -> Self {
+        self.use_cache = yes;
+        self
+    }
+
+    /// hash the content of every listed file (reads each one in full)
+    pub fn hash_filtered(mut self, yes: bool) -> Self {
+        self.hash_filtered = yes;
+        self
+    }
+
+    /// report files with risky permissions (no-op on non-Unix builds)
+    pub fn check_permissions(mut self, yes: bool) -> Self {
+        self.check_permissions = yes;
+        self
+    }
+
+    /// walk with one directory task per directory (default) or single-threaded
+    pub fn parallel_walk(mut self, yes: bool) -> Self {
+        self.parallel_walk = yes;
+        self
+    }
+
+    /// fewer threads and short pauses, to keep the desktop responsive
+    pub fn low_priority(mut self, yes: bool) -> Self {
+        self.low_priority = yes;
+        self
+    }
+}
+
+pub const KIB: u64 = 1024;
+pub const MIB: u64 = 1024 * KIB;
+pub const GIB: u64 = 1024 * MIB;
+pub const TIB: u64 = 1024 * GIB;
+pub const PIB: u64 = 1024 * TIB;
+
+pub const KB: u64 = 1000;
+pub const MB: u64 = 1000 * KB;
+pub const GB: u64 = 1000 * MB;
+pub const TB: u64 = 1000 * GB;
+pub const PB: u64 = 1000 * TB;
+
+/// how the plain units (KB, MB, GB, TB, PB) are read and shown; KiB/MiB/...
+/// are always binary
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitStyle {
+    /// KB/MB/... are powers of 1024, as this app always read them
+    #[default]
+    Binary,
+    /// KB/MB/... are SI powers of 1000, like drive vendors use
+    Decimal,
+}
+
+impl UnitStyle {
+    /// bytes per plain unit ("KB".."PB"), or None for anything else
+    fn plain_unit(self, unit: &str) -> Option<u64> {
+        let (binary, decimal) = match unit {
+            "KB" => (KIB, KB),
+            "MB" => (MIB, MB),
+            "GB" => (GIB, GB),
+            "TB" => (TIB, TB),
+            "PB" => (PIB, PB),
+            _ => return None,
+        };
+        Some(match self {
+            UnitStyle::Binary => binary,
+            UnitStyle::Decimal => decimal,
+        })
+    }
+}
+
+/// parsing filter text -> bytes
+/// - the presets are always binary sizes; `style` only applies to Custom text
+pub fn parse_filter_option(opt: &str, custom_text: Option<&str>, style: UnitStyle) -> u64 {
+    match opt {
+        "100 MB" => 100 * MIB,
+        "500 MB" => 500 * MIB,
+        "1 GB" => GIB,
+        "5 GB" => 5 * GIB,
+        "Custom" => {
+            if let Some(s) = custom_text {
+                parse_human_input_to_bytes_with(s, style).unwrap_or(0)
+            } else {
+                0
+            }
+        }
+        _ => 0,
+    }
+}
+
+/// human size text -> bytes with the default unit style (KB = 1024)
+pub fn parse_human_input_to_bytes(s: &str) -> Option<u64> {
+    parse_human_input_to_bytes_with(s, UnitStyle::default())
+}
+
+/// human size text -> bytes: "1.5 GB", "300mb", "2TiB", "512 B", "750"
+/// - the unit may follow the number with or without a space, any case
+/// - KiB, MiB, GiB, TiB, PiB are powers of 1024
+/// - KB, MB, GB, TB, PB depend on `style`
+/// - a bare number is MB (so MiB in the binary style)
+/// - the number may have an exponent: "1e3" is 1000 MB, "1.5e3 KB"
+/// - negative, non-numeric or unknown units -> None
+pub fn parse_human_input_to_bytes_with(s: &str, style: UnitStyle) -> Option<u64> {
+    let s = s.trim().to_uppercase();
+    let bytes = s.as_bytes();
+    let split = (0..bytes.len())
+        .find(|&i| bytes[i].is_ascii_alphabetic() && !is_exponent(bytes, i))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let (number, unit) = (number.trim(), unit.trim());
+    if number.is_empty() {
+        return None;
+    }
+    let value = number.parse::<f64>().ok().filter(|v| v.is_finite() && *v >= 0.0)?;
+
+    let multiplier: u64 = match unit {
+        "" => style.plain_unit("MB")?, // bare number: assume MB
+        "B" => 1,
+        "KIB" => KIB,
+        "MIB" => MIB,
+        "GIB" => GIB,
+        "TIB" => TIB,
+        "PIB" => PIB,
+        _ => style.plain_unit(unit)?,
+    };
+    Some((value * multiplier as f64) as u64)
+}
+
+/// whether the letter at `i` of uppercase size text is an exponent's 'E': it
+/// follows a digit or '.' and digits (optionally signed) follow it
+fn is_exponent(text: &[u8], i: usize) -> bool {
+    let digits = match text.get(i + 1) {
+        Some(b'+' | b'-') => i + 2,
+        _ => i + 1,
+    };
+    text[i] == b'E'
+        && i > 0
+        && (text[i - 1].is_ascii_digit() || text[i - 1] == b'.')
+        && text.get(digits).is_some_and(u8::is_ascii_digit)
+}
+
+/// days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        _ if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        _ => 28,
+    }
+}
+
+/// seconds offset from the epoch -> SystemTime (negative = before 1970)
+fn system_time_from_unix(secs: i64, nanos: u32) -> SystemTime {
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::new(secs as u64, nanos)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()) + Duration::from_nanos(nanos as u64)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str, what: &str) -> Result<T, String> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("invalid {}: '{}'", what, s));
+    }
+    s.parse::<T>().map_err(|_| format!("invalid {}: '{}'", what, s))
+}
+
+/// RFC3339 timestamp ("2023-05-01T12:00:00Z", "2023-05-01T12:00:00.5+07:00")
+/// or a bare date ("2023-05-01", midnight UTC)
+/// - leap second 60 is accepted and treated as :59
+/// - fractional digits past nanoseconds are checked, then dropped
+pub fn parse_rfc3339(s: &str) -> Result<SystemTime, String> {
+    let s = s.trim();
+    let (date, time) = match s.find(['T', 't', ' ']) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+
+    let dparts: Vec<&str> = date.split('-').collect();
+    if dparts.len() != 3 || dparts[0].len() != 4 || dparts[1].len() != 2 || dparts[2].len() != 2 {
+        return Err(format!("invalid date '{}', expected YYYY-MM-DD", date));
+    }
+    let year: i64 = parse_number(dparts[0], "year")?;
+    let month: u32 = parse_number(dparts[1], "month")?;
+    let day: u32 = parse_number(dparts[2], "day")?;
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return Err(format!("date out of range: '{}'", date));
+    }
+
+    let mut secs = days_from_civil(year, month, day) * 86_400;
+    let mut nanos = 0u32;
+
+    if let Some(time) = time {
+        // split off the zone designator: Z, +HH:MM or -HH:MM
+        let (clock, offset_secs) = if let Some(c) = time.strip_suffix(['Z', 'z']) {
+            (c, 0i64)
+        } else if let Some(i) = time.rfind(['+', '-']) {
+            let (c, zone) = time.split_at(i);
+            let sign = if zone.starts_with('-') { -1 } else { 1 };
+            let zparts: Vec<&str> = zone[1..].split(':').collect();
+            if zparts.len() != 2 || zparts[0].len() != 2 || zparts[1].len() != 2 {
+                return Err(format!("invalid UTC offset '{}'", zone));
+            }
+            let zh: i64 = parse_number(zparts[0], "offset hours")?;
+            let zm: i64 = parse_number(zparts[1], "offset minutes")?;
+            if zh > 23 || zm > 59 {
+                return Err(format!("invalid UTC offset '{}'", zone));
+            }
+            (c, sign * (zh * 3600 + zm * 60))
+        } else {
+            return Err(format!("missing UTC offset in '{}'", time));
+        };
+
+        let (hms, frac) = match clock.split_once('.') {
+            Some((hms, frac)) => (hms, Some(frac)),
+            None => (clock, None),
+        };
+        let tparts: Vec<&str> = hms.split(':').collect();
+        if tparts.len() != 3 || tparts.iter().any(|p| p.len() != 2) {
+            return Err(format!("invalid time '{}', expected HH:MM:SS", clock));
+        }
+        let hour: i64 = parse_number(tparts[0], "hour")?;
+        let minute: i64 = parse_number(tparts[1], "minute")?;
+        let second: i64 = parse_number(tparts[2], "second")?;
+        if hour > 23 || minute > 59 || second > 60 {
+            return Err(format!("time out of range: '{}'", clock));
+        }
+
+        if let Some(frac) = frac {
+            if frac.is_empty() || !frac.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(format!("invalid fraction: '{}'", frac));
+            }
+            let digits = &frac[..frac.len().min(9)];
+            let n: u32 = parse_number(digits, "fraction")?;
+            nanos = n * 10u32.pow(9 - digits.len() as u32);
+        }
+
+        secs += hour * 3600 + minute * 60 + second.min(59) - offset_secs;
+    }
+
+    Ok(system_time_from_unix(secs, nanos))
+}
+
+/// relative age like "90d" or "2y", counted back from `now`
+/// - units: s, m (minutes), h, d, w, mo (30 days), y (365 days)
+pub fn parse_relative_age(s: &str, now: SystemTime) -> Result<SystemTime, String> {
+    let s = s.trim().to_lowercase();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let n: u64 = parse_number(num, "relative age")?;
+
+    let unit_secs: u64 = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        "w" => 7 * 86_400,
+        "mo" => 30 * 86_400,
+        "y" => 365 * 86_400,
+        _ => return Err(format!("unknown age unit '{}' in '{}'", unit, s)),
+    };
+
+    let age = n
+        .checked_mul(unit_secs)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("relative age too large: '{}'", s))?;
+    now.checked_sub(age)
+        .ok_or_else(|| format!("relative age too large: '{}'", s))
+}
+
+/// point in time from either an RFC3339 string or a relative age ("2y", "90d")
+pub fn parse_time_spec(s: &str) -> Result<SystemTime, String> {
+    if s.contains('-') || s.contains(':') {
+        parse_rfc3339(s)
+    } else {
+        parse_relative_age(s, SystemTime::now())
+    }
+}
+
+/// one file seen during the walk, with its metadata read exactly once
+/// - size/mtime are None when metadata could not be read
+#[derive(Clone, Debug)]
+pub struct ScannedFile {
+    pub path: PathBuf,
+    /// leading components of `path` that are the scan root it was found under
+    pub root_components: usize,
+    pub size: Option<u64>,
+    pub allocated: Option<u64>,
+    pub mtime: Option<SystemTime>,
+    /// owner uid (Unix only)
+    pub uid: Option<u32>,
+    /// permission bits incl. setuid/setgid (Unix only)
+    pub mode: Option<u32>,
+}
+
+impl ScannedFile {
+    /// `path` below its scan root ("sub/x.txt" for root/sub/x.txt)
+    pub fn relative_path(&self) -> PathBuf {
+        self.path.components().skip(self.root_components).collect()
+    }
+}
+
+/// one item of the file stream: a counted file, or a path that couldn't be read
+pub type ScanItem = Result<ScannedFile, ScanErrorEntry>;
+
+/// one token of a compiled glob pattern
+#[derive(Clone, Debug)]
+enum GlobToken {
+    Literal(char),
+    /// `?`: exactly one character, not '/'
+    AnyChar,
+    /// `*`: any run of characters within one path component
+    Star,
+    /// `**`: any run of characters, across components
+    DoubleStar,
+    /// `**/`: zero or more whole components
+    DoubleStarSlash,
+    /// `[abc]`, `[a-z]`, `[!abc]`
+    Class { ranges: Vec<(char, char)>, negated: bool },
+}
+
+/// glob pattern used by exclude/include filters
+/// - no '/' in the pattern: matched against the entry name at any depth (`*.iso`)
+/// - with '/': matched against the root-relative path, at any depth unless it
+///   starts with '/' (`node_modules/**`, `/build`)
+/// - trailing '/': only matches directories (`target/`)
+/// - `dir/**` also matches `dir` itself, so the walk can prune it
+#[derive(Clone, Debug)]
+pub struct GlobPattern {
+    tokens: Vec<GlobToken>,
+    dir_only: bool,
+    match_path: bool,
+}
+
+impl GlobPattern {
+    pub fn new(pattern: &str) -> Result<GlobPattern, String> {
+        let mut raw = pattern.trim();
+        if raw.is_empty() {
+            return Err("empty glob pattern".to_string());
+        }
+
+        let dir_only = raw.len() > 1 && raw.ends_with('/');
+        if dir_only {
+            raw = &raw[..raw.len() - 1];
+        }
+
+        // `dir/**` matches the directory itself as well as everything below it
+        let raw = raw.strip_suffix("/**").unwrap_or(raw);
+
+        let match_path = raw.contains('/');
+        let anchored = raw.starts_with('/');
+        let body = raw.trim_start_matches('/');
+
+        let mut tokens = Vec::new();
+        if match_path && !anchored && !body.starts_with("**/") {
+            tokens.push(GlobToken::DoubleStarSlash);
+        }
+        tokens.extend(Self::tokenize(body).map_err(|e| format!("{}: {}", pattern, e))?);
+
+        Ok(GlobPattern {
+            tokens,
+            dir_only,
+            match_path,
+        })
+    }
+
+    fn tokenize(body: &str) -> Result<Vec<GlobToken>, String> {
+        let chars: Vec<char> = body.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            match chars[i] {
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    if chars.get(i + 2) == Some(&'/') {
+                        tokens.push(GlobToken::DoubleStarSlash);
+                        i += 3;
+                    } else {
+                        tokens.push(GlobToken::DoubleStar);
+                        i += 2;
+                    }
+                }
+                '*' => {
+                    tokens.push(GlobToken::Star);
+                    i += 1;
+                }
+                '?' => {
+                    tokens.push(GlobToken::AnyChar);
+                    i += 1;
+                }
+                '[' => {
+                    let mut j = i + 1;
+                    let negated = matches!(chars.get(j), Some('!') | Some('^'));
+                    if negated {
+                        j += 1;
+                    }
+
+                    let mut ranges = Vec::new();
+                    let mut closed = false;
+                    while j < chars.len() {
+                        // a ']' right after '[' or '[!' is a literal member
+                        if chars[j] == ']' && !ranges.is_empty() {
+                            closed = true;
+                            break;
+                        }
+                        let lo = chars[j];
+                        if chars.get(j + 1) == Some(&'-') && chars.get(j + 2).is_some_and(|c| *c != ']') {
+                            ranges.push((lo, chars[j + 2]));
+                            j += 3;
+                        } else {
+                            ranges.push((lo, lo));
+                            j += 1;
+                        }
+                    }
+
+                    if !closed {
+                        return Err("unclosed '['".to_string());
+                    }
+                    tokens.push(GlobToken::Class { ranges, negated });
+                    i = j + 1;
+                }
+                '\\' if i + 1 < chars.len() => {
+                    tokens.push(GlobToken::Literal(chars[i + 1]));
+                    i += 2;
+                }
+                c => {
+                    tokens.push(GlobToken::Literal(c));
+                    i += 1;
+                }
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    /// rel_path: '/'-separated path relative to the scan root
+    pub fn matches(&self, rel_path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        let subject = if self.match_path {
+            rel_path
+        } else {
+            rel_path.rsplit('/').next().unwrap_or(rel_path)
+        };
+        let text: Vec<char> = subject.chars().collect();
+        glob_match(&self.tokens, &text)
+    }
+}
+
+/// iterative backtracking over (token, character) positions: every star
+/// leaves its alternatives on a stack, and a position that failed once is
+/// never tried again, so `*a*a*a*b` against a long name costs at most
+/// tokens × characters steps instead of exponential time
+fn glob_match(tokens: &[GlobToken], text: &[char]) -> bool {
+    let width = text.len() + 1;
+    let mut tried = vec![0u64; ((tokens.len() + 1) * width).div_ceil(64)];
+    let mut pending = vec![(0, 0)];
+    while let Some((t, x)) = pending.pop() {
+        let bit = t * width + x;
+        if tried[bit / 64] & (1 << (bit % 64)) != 0 {
+            continue;
+        }
+        tried[bit / 64] |= 1 << (bit % 64);
+
+        let Some(tok) = tokens.get(t) else {
+            if x == text.len() {
+                return true;
+            }
+            continue;
+        };
+        let c = text.get(x);
+        let in_component = c.is_some_and(|c| *c != '/');
+        match tok {
+            GlobToken::Literal(l) => {
+                if c == Some(l) {
+                    pending.push((t + 1, x + 1));
+                }
+            }
+            GlobToken::AnyChar => {
+                if in_component {
+                    pending.push((t + 1, x + 1));
+                }
+            }
+            GlobToken::Class { ranges, negated } => {
+                if let Some(c) = c.filter(|_| in_component)
+                    && ranges.iter().any(|(lo, hi)| lo <= c && c <= hi) != *negated
+                {
+                    pending.push((t + 1, x + 1));
+                }
+            }
+            // stop here, or take one more character and stay on the star
+            GlobToken::Star => {
+                pending.push((t + 1, x));
+                if in_component {
+                    pending.push((t, x + 1));
+                }
+            }
+            GlobToken::DoubleStar => {
+                pending.push((t + 1, x));
+                if c.is_some() {
+                    pending.push((t, x + 1));
+                }
+            }
+            // stop here, or take the rest of this component and its '/'
+            GlobToken::DoubleStarSlash => {
+                pending.push((t + 1, x));
+                if let Some(slash) = text[x..].iter().position(|c| *c == '/') {
+                    pending.push((t, x + slash + 1));
+                }
+            }
+        }
+    }
+    false
+}
+
+/// compile every pattern up front so a typo fails the scan instead of matching nothing
+fn compile_globs(patterns: &[String]) -> Result<Vec<GlobPattern>, String> {
+    patterns
+        .iter()
+        .map(|p| GlobPattern::new(p).map_err(|e| format!("invalid glob pattern {}", e)))
+        .collect()
+}
+
+/// root-relative path with '/' separators, used for glob matching
+fn relative_path_string(path: &Path, root: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// one line of a .gitignore / .git/info/exclude file
+struct IgnoreRule {
+    glob: GlobPattern,
+    negated: bool,
+}
+
+/// rules loaded from one directory, matched relative to that directory
+struct IgnoreFrame {
+    depth: usize,
+    base: PathBuf,
+    /// for a directory above the scan root: `base` is the root, and this is
+    /// the root's path below the directory the rules came from ("sub/dir")
+    prefix: String,
+    rules: Vec<IgnoreRule>,
+}
+
+impl AsRef<IgnoreFrame> for IgnoreFrame {
+    fn as_ref(&self) -> &IgnoreFrame {
+        self
+    }
+}
+
+/// parse gitignore syntax into rules
+/// - patterns with an inner '/' are anchored to the file's directory, others
+///   match the entry name at any depth below it (git semantics)
+/// - trailing spaces are dropped unless escaped ("name\ "); "\#" and "\!"
+///   start a pattern with a literal '#' / '!' (the glob reads the escape)
+/// - unparsable lines are skipped, the same way git ignores them
+fn parse_ignore_rules(text: &str) -> Vec<IgnoreRule> {
+    text.lines()
+        .filter_map(|line| {
+            let line = trim_ignore_line(line);
+            let line = line.as_str();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+
+            let (negated, pat) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+
+            let inner = pat.trim_end_matches('/');
+            let anchored = inner.contains('/') && !inner.starts_with('/') && !inner.starts_with("**/");
+            let pat = if anchored { format!("/{}", pat) } else { pat.to_string() };
+
+            GlobPattern::new(&pat)
+                .ok()
+                .map(|glob| IgnoreRule { glob, negated })
+        })
+        .collect()
+}
+
+/// a gitignore line without its trailing spaces (and '\r'), but with an
+/// escaped last one ("a\ ") kept as "[ ]", which GlobPattern::new won't trim
+fn trim_ignore_line(line: &str) -> String {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let trimmed = line.trim_end_matches(' ');
+    let backslashes = trimmed.len() - trimmed.trim_end_matches('\\').len();
+    if backslashes % 2 == 1 && trimmed.len() < line.len() {
+        format!("{}[ ]", &trimmed[..trimmed.len() - 1])
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// load .gitignore (and .git/info/exclude for repository roots) in `dir`
+fn load_ignore_frame(dir: &Path, depth: usize) -> Option<IgnoreFrame> {
+    let mut rules = Vec::new();
+    for file in [dir.join(".git").join("info").join("exclude"), dir.join(".gitignore")] {
+        if let Ok(text) = fs::read_to_string(&file) {
+            rules.extend(parse_ignore_rules(&text));
+        }
+    }
+
+    if rules.is_empty() {
+        return None;
+    }
+    Some(IgnoreFrame {
+        depth,
+        base: dir.to_path_buf(),
+        prefix: String::new(),
+        rules,
+    })
+}
+
+/// the frames that apply to everything below the scan root, outermost first
+/// - the root's own, and when the root is inside a git work tree (not its
+///   top), those of every directory above it up to the top, whose
+///   .git/info/exclude counts too, the way git reads them
+/// - outside a work tree only the root's own, as git would read none
+fn root_ignore_frames(root: &Path) -> Vec<IgnoreFrame> {
+    let own = load_ignore_frame(root, 0);
+    let Ok(real) = fs::canonicalize(root) else {
+        return own.into_iter().collect();
+    };
+    let mut frames = Vec::new();
+    let mut below = Vec::new();
+    let mut dir = real.as_path();
+    while !dir.join(".git").exists() {
+        let (Some(parent), Some(name)) = (dir.parent(), dir.file_name()) else {
+            return own.into_iter().collect();
+        };
+        below.insert(0, name.to_string_lossy().into_owned());
+        if let Some(mut frame) = load_ignore_frame(parent, 0) {
+            frame.base = root.to_path_buf();
+            frame.prefix = below.join("/");
+            frames.push(frame);
+        }
+        dir = parent;
+    }
+    frames.reverse();
+    frames.extend(own);
+    frames
+}
+
+/// gitignore decision for `path`: the last matching rule wins, and rules from
+/// deeper directories come later in the stack
+fn is_gitignored<F: AsRef<IgnoreFrame>>(stack: &[F], path: &Path, is_dir: bool) -> bool {
+    let mut ignored = false;
+    for frame in stack.iter().map(AsRef::as_ref) {
+        let mut rel = relative_path_string(path, &frame.base);
+        if !frame.prefix.is_empty() {
+            rel = format!("{}/{}", frame.prefix, rel);
+        }
+        for rule in &frame.rules {
+            if rule.glob.matches(&rel, is_dir) {
+                ignored = !rule.negated;
+            }
+        }
+    }
+    ignored
+}
+
+/// output of the walk besides the streamed files: counters about what was left out
+struct WalkOutput {
+    excluded_dirs: usize,
+    excluded_files: usize,
+    skipped_links: usize,
+    symlink_count: usize,
+    symlink_targets_missing: usize,
+    /// the links counted in symlink_targets_missing
+    broken_symlinks: Vec<PathBuf>,
+    collapsed_duplicates: usize,
+    skipped_hidden: usize,
+    gitignored: usize,
+    preset_skipped_dirs: usize,
+    /// virtual mounts pruned (see virtual_mounts_below)
+    virtual_skipped: Vec<PathBuf>,
+    /// stopped at file_limit
+    truncated: bool,
+    /// stopped by the CancellationToken, like truncated otherwise
+    cancelled: bool,
+    /// files found past file_limit and not sent to the sink
+    files_skipped: usize,
+    /// files whose stat found them deleted, see stat_vanished
+    vanished_files: usize,
+    /// (depth, path) of the deepest entry walked, see note_depth
+    deepest: Option<(usize, PathBuf)>,
+    /// topmost recursively empty directories, sorted (find_empty_dirs only)
+    empty_dirs: Vec<String>,
+    errors: ErrorLog,
+    dirs_visited: usize,
+    /// directories whose file metadata came from the scan cache
+    dirs_from_cache: usize,
+}
+
+impl WalkOutput {
+    /// fold another root's walk into this one
+    fn merge(&mut self, other: WalkOutput) {
+        self.excluded_dirs += other.excluded_dirs;
+        self.excluded_files += other.excluded_files;
+        self.skipped_links += other.skipped_links;
+        self.symlink_count += other.symlink_count;
+        self.symlink_targets_missing += other.symlink_targets_missing;
+        self.broken_symlinks.extend(other.broken_symlinks);
+        self.collapsed_duplicates += other.collapsed_duplicates;
+        self.skipped_hidden += other.skipped_hidden;
+        self.gitignored += other.gitignored;
+        self.preset_skipped_dirs += other.preset_skipped_dirs;
+        self.virtual_skipped.extend(other.virtual_skipped);
+        self.truncated |= other.truncated;
+        self.cancelled |= other.cancelled;
+        self.files_skipped += other.files_skipped;
+        self.vanished_files += other.vanished_files;
+        if let Some((depth, path)) = other.deepest {
+            note_depth(&mut self.deepest, depth, &path);
+        }
+        self.empty_dirs.extend(other.empty_dirs);
+        self.empty_dirs.sort_unstable();
+        self.errors.merge(other.errors);
+        self.dirs_visited += other.dirs_visited;
+        self.dirs_from_cache += other.dirs_from_cache;
+    }
+}
+
+/// keep (depth, path) in `deepest` if it is deeper than what is there, or as
+/// deep with a smaller path (so the pick doesn't depend on walk order)
+fn note_depth(deepest: &mut Option<(usize, PathBuf)>, depth: usize, path: &Path) {
+    let replace = match deepest {
+        None => true,
+        Some((d, p)) => depth > *d || (depth == *d && path < p.as_path()),
+    };
+    if replace {
+        *deepest = Some((depth, path.to_path_buf()));
+    }
+}
+
+/// read errors met during the walk: all counted, the `limit` smallest by
+/// path kept (not the first found, which depends on the parallel walk)
+struct ErrorLog {
+    entries: Vec<ScanErrorEntry>,
+    count: usize,
+    limit: usize,
+}
+
+impl ErrorLog {
+    fn new(limit: usize) -> Self {
+        ErrorLog {
+            entries: Vec::new(),
+            count: 0,
+            limit,
+        }
+    }
+
+    fn merge(&mut self, other: ErrorLog) {
+        self.count += other.count;
+        self.entries.extend(other.entries);
+        self.compact();
+    }
+
+    fn record(&mut self, entry: &ScanErrorEntry) {
+        self.count += 1;
+        self.entries.push(entry.clone());
+        self.compact();
+    }
+
+    /// drop all but the smallest `limit` once twice that many are held, so
+    /// sorting stays amortized
+    fn compact(&mut self) {
+        if self.entries.len() > self.limit.saturating_mul(2) {
+            self.sort_and_truncate();
+        }
+    }
+
+    fn sort_and_truncate(&mut self) {
+        self.entries.sort_unstable_by(|a, b| {
+            a.path
+                .cmp(&b.path)
+                .then_with(|| a.kind.cmp(&b.kind))
+                .then(a.is_dir.cmp(&b.is_dir))
+        });
+        self.entries.truncate(self.limit);
+    }
+
+    fn into_sorted(mut self) -> Vec<ScanErrorEntry> {
+        self.sort_and_truncate();
+        self.entries
+    }
+}
+
+/// mark `dir` and its ancestors below `root` as having content
+/// - stops at the first directory already marked: its ancestors are too
+fn mark_occupied(occupied: &mut HashSet<PathBuf>, dir: Option<&Path>, root: &Path) {
+    let mut dir = dir;
+    while let Some(d) = dir {
+        if !d.starts_with(root) || !occupied.insert(d.to_path_buf()) {
+            break;
+        }
+        dir = d.parent();
+    }
+}
+
+/// (device, inode) of a file, used to recognise the same physical file
+/// reached through different paths; None where the platform has no such id
+#[cfg(unix)]
+fn file_identity(meta: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_identity(_meta: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// bytes allocated on disk: 512-byte blocks on Unix, apparent size elsewhere
+#[cfg(unix)]
+fn allocated_size(meta: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn allocated_size(meta: &fs::Metadata) -> u64 {
+    meta.len()
+}
+
+/// number of hardlinks pointing at this file (1 where unknown)
+#[cfg(unix)]
+fn hardlink_count(meta: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.nlink()
+}
+
+#[cfg(not(unix))]
+fn hardlink_count(_meta: &fs::Metadata) -> u64 {
+    1
+}
+
+/// owning user id; None where the platform has no uids
+#[cfg(unix)]
+fn file_owner(meta: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.uid())
+}
+
+#[cfg(not(unix))]
+fn file_owner(_meta: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// mode bits (permissions, setuid/setgid/sticky); None where there are none
+#[cfg(unix)]
+fn file_mode(meta: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(meta.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_meta: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// effective uid of this process, the "scan invoker"
+#[cfg(unix)]
+fn current_uid() -> Option<u32> {
+    Some(unsafe { libc::geteuid() })
+}
+
+#[cfg(not(unix))]
+fn current_uid() -> Option<u32> {
+    None
+}
+
+/// short description of a walk error: the io kind when there is one
+fn error_kind(err: &walkdir::Error) -> String {
+    match err.io_error() {
+        Some(io) => io.kind().to_string(),
+        None => err.to_string(),
+    }
+}
+
+/// walk error caused by a symlink: a loop back to an ancestor, or a dangling target
+fn is_symlink_error(err: &walkdir::Error) -> bool {
+    err.loop_ancestor().is_some()
+        || err
+            .path()
+            .and_then(|p| fs::symlink_metadata(p).ok())
+            .is_some_and(|m| m.file_type().is_symlink())
+}
+
+/// io errors that may well be gone a moment later
+fn is_transient(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::ResourceBusy
+    )
+}
+
+/// a file's stat failed because it was deleted after its directory was
+/// listed (busy caches, build outputs): the file is left out of the scan
+/// instead of counting as 0 bytes
+fn stat_vanished(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::NotFound
+}
+
+/// FileMeta from `stat`, called a second time after a transient error
+/// - without the retry a file that was briefly busy would count as 0 bytes
+fn read_file_meta(stat: impl Fn() -> io::Result<fs::Metadata>) -> io::Result<FileMeta> {
+    let m = match stat() {
+        Err(err) if is_transient(&err) => stat(),
+        other => other,
+    }?;
+    Ok(FileMeta::from_metadata(&m))
+}
+
+/// the per-file facts the scan needs, from a stat or from the scan cache
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct FileMeta {
+    size: u64,
+    allocated: u64,
+    mtime: Option<SystemTime>,
+    uid: Option<u32>,
+    mode: Option<u32>,
+    identity: Option<(u64, u64)>,
+    nlink: u64,
+}
+
+impl FileMeta {
+    fn from_metadata(m: &fs::Metadata) -> Self {
+        FileMeta {
+            size: m.len(),
+            allocated: allocated_size(m),
+            mtime: m.modified().ok(),
+            uid: file_owner(m),
+            mode: file_mode(m),
+            identity: file_identity(m),
+            nlink: hardlink_count(m),
+        }
+    }
+}
+
+// ============ SCAN CACHE ============
+// One JSON file per scan root under ~/.cache/fscan/ (or $XDG_CACHE_HOME).
+// For each directory it records the directory's mtime and the metadata of
+// the files directly inside it. On the next scan, a directory whose mtime
+// is unchanged reuses those entries instead of stat'ing every file; the
+// listing itself is still read, so added/removed entries are always seen.
+// Off unless asked for (ScanOptions::use_cache, --cache), because:
+// - a file rewritten in place, appended to or chmod'ed doesn't touch its
+//   directory's mtime, so its size/mode stays stale until something else
+//   changes there; a growing log would keep its first size
+// - mtimes have coarse granularity on some filesystems: a directory whose
+//   mtime is within CACHE_MTIME_SLACK of the previous scan's start is re-read
+// - a renamed directory is a new path, so it simply misses the cache; entries
+//   of directories not visited again are dropped on the next save
+// - names that aren't UTF-8 are never cached: the JSON keys are strings, and
+//   two such names could share one lossy key
+
+/// bump when the cache layout changes; files with another version are ignored
+const CACHE_VERSION: u32 = 2;
+const CACHE_MTIME_SLACK: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize)]
+struct ScanCache {
+    version: u32,
+    root: PathBuf,
+    follow_symlinks: bool,
+    /// when the scan that wrote this cache started
+    started_at: SystemTime,
+    dirs: HashMap<PathBuf, CachedDir>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct CachedDir {
+    mtime: Option<SystemTime>,
+    /// file name -> metadata, for files directly inside the directory
+    files: HashMap<String, FileMeta>,
+}
+
+impl ScanCache {
+    /// cached entry for `dir`, only when it can be trusted for `mtime`
+    fn fresh_dir(&self, dir: &Path, mtime: Option<SystemTime>) -> Option<&CachedDir> {
+        let cached = self.dirs.get(dir)?;
+        let m = cached.mtime?;
+        (mtime == Some(m) && m + CACHE_MTIME_SLACK < self.started_at).then_some(cached)
+    }
+}
+
+/// ~/.cache/fscan, honoring XDG_CACHE_HOME
+fn cache_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))?;
+    Some(base.join("fscan"))
+}
+
+/// FNV-1a of a path plus some tag bytes; keeps file names stable across builds
+fn path_hash(path: &Path, tag: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in path.to_string_lossy().as_bytes().iter().chain(tag) {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// cache file for a root
+fn cache_file(root: &Path, follow_symlinks: bool) -> Option<PathBuf> {
+    let hash = path_hash(root, &[follow_symlinks as u8]);
+    Some(cache_dir()?.join(format!("{:016x}.json", hash)))
+}
+
+/// write to a temp file first and rename, so a crash never leaves a torn file
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+/// previous cache for this root, if any (unreadable or mismatched = none)
+fn load_cache(root: &Path, follow_symlinks: bool) -> Option<ScanCache> {
+    let text = fs::read_to_string(cache_file(root, follow_symlinks)?).ok()?;
+    let cache: ScanCache = serde_json::from_str(&text).ok()?;
+    let matches = cache.version == CACHE_VERSION
+        && cache.root == root
+        && cache.follow_symlinks == follow_symlinks;
+    matches.then_some(cache)
+}
+
+/// best effort: a cache that can't be written just means a slower next scan
+fn save_cache(cache: &ScanCache) {
+    let Some(path) = cache_file(&cache.root, cache.follow_symlinks) else {
+        return;
+    };
+    if let Ok(json) = serde_json::to_string(cache) {
+        let _ = write_atomic(&path, json.as_bytes());
+    }
+}
+
+// ============ RESULT CACHE ============
+// The last FolderStats of each scanned folder, under ~/.cache/fscan/results/,
+// so the GUI can show it (marked stale) while a fresh scan runs.
+// index.json maps folder -> result file + when it was saved. Anything
+// unreadable or corrupt is treated as "no cached result".
+
+#[derive(Default, Serialize, Deserialize)]
+struct ResultIndex {
+    entries: HashMap<PathBuf, ResultIndexEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ResultIndexEntry {
+    /// file name inside the results directory
+    file: String,
+    #[serde(with = "unix_secs")]
+    saved_at: Option<SystemTime>,
+}
+
+fn results_dir() -> Option<PathBuf> {
+    Some(cache_dir()?.join("results"))
+}
+
+fn load_result_index(dir: &Path) -> ResultIndex {
+    fs::read_to_string(dir.join("index.json"))
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+impl FolderStats {
+    /// write as JSON, atomically (temp file + rename)
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string(self).map_err(io::Error::other)?;
+        write_atomic(path, json.as_bytes())
+    }
+
+    /// read a file written by save_to; a corrupt file is InvalidData, and so
+    /// is one saved by a newer build (schema_version above ours)
+    /// - an older one is migrated as it's read; what it lacks stays empty
+    pub fn load_from(path: &Path) -> io::Result<FolderStats> {
+        let text = fs::read_to_string(path)?;
+        let stats: FolderStats = serde_json::from_str(&text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if stats.schema_version > FOLDER_STATS_SCHEMA {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "saved with FolderStats schema {}, this build reads up to {}",
+                    stats.schema_version, FOLDER_STATS_SCHEMA
+                ),
+            ));
+        }
+        Ok(stats)
+    }
+}
+
+/// remember `stats` as the latest result for `folder`
+pub fn save_last_result(folder: &Path, stats: &FolderStats) -> io::Result<()> {
+    let dir = results_dir().ok_or_else(|| io::Error::other("no cache directory"))?;
+    let folder = std::path::absolute(folder)?;
+    let file = format!("{:016x}.json", path_hash(&folder, b"result"));
+    stats.save_to(&dir.join(&file))?;
+
+    let mut index = load_result_index(&dir);
+    index.entries.insert(
+        folder,
+        ResultIndexEntry {
+            file,
+            saved_at: Some(SystemTime::now()),
+        },
+    );
+    let json = serde_json::to_string(&index).map_err(io::Error::other)?;
+    write_atomic(&dir.join("index.json"), json.as_bytes())
+}
+
+/// latest saved result for `folder` and when it was saved, if any is readable
+pub fn load_last_result(folder: &Path) -> Option<(FolderStats, SystemTime)> {
+    let dir = results_dir()?;
+    let folder = std::path::absolute(folder).ok()?;
+    let index = load_result_index(&dir);
+    let entry = index.entries.get(&folder)?;
+    let stats = FolderStats::load_from(&dir.join(&entry.file)).ok()?;
+    Some((stats, entry.saved_at?))
+}
+
+/// one directory on the walk's current path, for cache lookups and recording
+struct DirFrame<'a> {
+    depth: usize,
+    path: PathBuf,
+    old: Option<&'a CachedDir>,
+    new: CachedDir,
+}
+
+/// the scan cache side of visit_file, for the directory a file is in
+struct DirCache<'a> {
+    /// the directory's old entry, when it can be trusted (fresh_dir)
+    old: Option<&'a CachedDir>,
+    /// the entry being built for the new cache
+    new: &'a mut CachedDir,
+    /// set when a file's metadata wasn't in the old entry
+    dirty: &'a mut bool,
+}
+
+/// what visit_file made of one file
+enum FileVisit {
+    /// left out by the include patterns or only_extensions
+    Skipped,
+    /// gone between the listing and the stat (vanished_files)
+    Vanished,
+    Counted(CountedFile),
+}
+
+struct CountedFile {
+    file: ScannedFile,
+    /// the stat failed: reported as a read error, the file has no size
+    error: Option<ScanErrorEntry>,
+    /// Some when the file may be reached twice and needs the dedupe
+    identity: Option<(u64, u64)>,
+}
+
+/// per-file handling shared by walk_files and the parallel walk, for a file
+/// the entry filters kept: include/extension filters, then its metadata from
+/// the scan cache or `stat`, recorded in the new cache
+fn visit_file(
+    opts: &ScanOptions,
+    includes: &[GlobPattern],
+    root_components: usize,
+    path: PathBuf,
+    name: &std::ffi::OsStr,
+    mut cache: Option<DirCache>,
+    stat: impl Fn(&Path) -> io::Result<fs::Metadata>,
+) -> FileVisit {
+    if !includes.is_empty() {
+        let rel = relative_path_string(&path, &opts.path);
+        if !includes.iter().any(|g| g.matches(&rel, false)) {
+            return FileVisit::Skipped;
+        }
+    }
+    if !extension_allowed(&path, opts.only_extensions.as_deref()) {
+        return FileVisit::Skipped;
+    }
+
+    // a lossy key could hand one non-UTF-8 name another's metadata
+    let name = name.to_str().map(str::to_string);
+    let cached = match (&cache, &name) {
+        (Some(c), Some(name)) => c.old.and_then(|old| old.files.get(name)).cloned(),
+        _ => None,
+    };
+    let mut error = None;
+    let meta = match cached {
+        Some(m) => Some(m),
+        None => {
+            if let Some(c) = &mut cache {
+                *c.dirty = true;
+            }
+            match read_file_meta(|| stat(&path)) {
+                Ok(m) => Some(m),
+                Err(err) if stat_vanished(&err) => return FileVisit::Vanished,
+                Err(err) => {
+                    error = Some(ScanErrorEntry {
+                        path: path.to_string_lossy().into_owned(),
+                        is_dir: false,
+                        kind: err.kind().to_string(),
+                    });
+                    None
+                }
+            }
+        }
+    };
+    if let (Some(c), Some(name), Some(m)) = (cache, name, &meta) {
+        c.new.files.insert(name, m.clone());
+    }
+
+    // following links can reach the same real file twice; with only
+    // hardlink dedupe, files with a single link can't be duplicates
+    let identity = meta
+        .as_ref()
+        .filter(|m| opts.follow_symlinks || (opts.dedupe_hardlinks && m.nlink > 1))
+        .and_then(|m| m.identity);
+    FileVisit::Counted(CountedFile {
+        file: ScannedFile {
+            path,
+            root_components,
+            size: meta.as_ref().map(|m| m.size),
+            allocated: meta.as_ref().map(|m| m.allocated),
+            mtime: meta.as_ref().and_then(|m| m.mtime),
+            uid: meta.as_ref().and_then(|m| m.uid),
+            mode: meta.as_ref().and_then(|m| m.mode),
+        },
+        error,
+        identity,
+    })
+}
+
+/// walk the tree once, capturing each file's size from the walk's own metadata
+/// - every kept file and every read error is handed to `sink` as it is met
+/// - excluded directories are pruned, so their contents are never visited
+/// - include patterns only filter files, never directories
+/// - with follow_symlinks, each real file is counted once and loops/broken
+///   links are skipped (counted in skipped_links) instead of aborting
+/// - with dedupe_hardlinks, files with several links are counted once
+/// - with skip_hidden, dot-entries are pruned before any other filter
+/// - with respect_gitignore, ignored entries are pruned like excludes
+/// - with find_empty_dirs, every non-directory entry (files, unfollowed
+///   symlinks, and anything pruned by a filter) marks its ancestors as
+///   occupied; directories left unmarked are empty all the way down.
+///   Directories at max_depth or that failed to read are never reported.
+/// - with use_cache, unchanged directories reuse cached file metadata
+///   (see SCAN CACHE above)
+fn walk_files(
+    opts: &ScanOptions,
+    progress: &mut ProgressReporter,
+    cancel: &CancellationToken,
+    sink: &mut dyn FnMut(ScanItem),
+) -> Result<WalkOutput, ScanError> {
+    let excludes = compile_globs(&opts.exclude)?;
+    let includes = compile_globs(&opts.include)?;
+
+    let mut walker = WalkDir::new(&opts.path).follow_links(opts.follow_symlinks);
+    if let Some(depth) = opts.max_depth {
+        walker = walker.max_depth(depth);
+    }
+
+    let excluded_dirs = Cell::new(0usize);
+    let excluded_files = Cell::new(0usize);
+    let skipped_hidden = Cell::new(0usize);
+    let gitignored = Cell::new(0usize);
+    let preset_skipped_dirs = Cell::new(0usize);
+    let virtual_mounts = virtual_mounts_below(opts, &opts.path);
+    let virtual_skipped: RefCell<Vec<PathBuf>> = RefCell::new(Vec::new());
+    let mut skipped_links = 0usize;
+    let mut symlink_count = 0usize;
+    let mut symlink_targets_missing = 0usize;
+    let mut broken_symlinks: Vec<PathBuf> = Vec::new();
+    let mut vanished_files = 0usize;
+    let mut collapsed_duplicates = 0usize;
+    let mut seen: HashSet<(u64, u64)> = HashSet::new();
+    let occupied: RefCell<HashSet<PathBuf>> = RefCell::new(HashSet::new());
+    let mut visited_dirs: Vec<PathBuf> = Vec::new();
+    let mut errors = ErrorLog::new(opts.error_limit);
+    let mut dirs_visited = 0usize;
+    let throttle = Throttle::new(opts);
+    let limit = opts.file_limit.unwrap_or(usize::MAX);
+    let mut sent = 0usize;
+    let mut truncated = false;
+    let mut cancelled = false;
+    let root_components = opts.path.components().count();
+    let mut deepest: Option<(usize, PathBuf)> = None;
+
+    let started_at = SystemTime::now();
+    let cache_root = std::path::absolute(&opts.path).unwrap_or_else(|_| opts.path.clone());
+    let old_cache = if opts.use_cache {
+        load_cache(&cache_root, opts.follow_symlinks)
+    } else {
+        None
+    };
+    let mut new_dirs: HashMap<PathBuf, CachedDir> = HashMap::new();
+    let mut frames: Vec<DirFrame> = Vec::new();
+    let mut dirs_from_cache = 0usize;
+    // anything the old cache didn't already say; unchanged caches aren't rewritten
+    let mut cache_dirty = old_cache.is_none();
+
+    // .gitignore rules of the directories above the current entry; the walk
+    // is depth-first, so frames deeper than the entry's parent can be dropped
+    let mut ignore_stack: Vec<IgnoreFrame> = Vec::new();
+    if opts.respect_gitignore {
+        ignore_stack.extend(root_ignore_frames(&opts.path));
+    }
+
+    // the filters for every entry below the root; false prunes it
+    let mut keep_entry = |e: &walkdir::DirEntry| {
+        if e.file_type().is_dir() && virtual_mounts.iter().any(|m| m == e.path()) {
+            virtual_skipped.borrow_mut().push(e.path().to_path_buf());
+            return false;
+        }
+        if opts.skip_hidden && e.file_name().to_string_lossy().starts_with('.') {
+            skipped_hidden.set(skipped_hidden.get() + 1);
+            return false;
+        }
+        if e.file_type().is_dir() && preset_skips(&opts.skip_presets, e.file_name()) {
+            preset_skipped_dirs.set(preset_skipped_dirs.get() + 1);
+            return false;
+        }
+        if opts.respect_gitignore {
+            let is_dir = e.file_type().is_dir();
+            while ignore_stack.last().is_some_and(|f| f.depth >= e.depth()) {
+                ignore_stack.pop();
+            }
+            if e.file_name() == ".git" || is_gitignored(&ignore_stack, e.path(), is_dir) {
+                gitignored.set(gitignored.get() + 1);
+                return false;
+            }
+            if is_dir {
+                ignore_stack.extend(load_ignore_frame(e.path(), e.depth()));
+            }
+        }
+        if excludes.is_empty() {
+            return true;
+        }
+        let is_dir = e.file_type().is_dir();
+        let rel = relative_path_string(e.path(), &opts.path);
+        if excludes.iter().any(|g| g.matches(&rel, is_dir)) {
+            let counter = if is_dir { &excluded_dirs } else { &excluded_files };
+            counter.set(counter.get() + 1);
+            return false;
+        }
+        true
+    };
+
+    let mut entries = walker.into_iter().filter_entry(|e| {
+        if e.depth() == 0 {
+            return true;
+        }
+        let keep = keep_entry(e);
+        // a pruned directory's content is unknown, so it counts as content too
+        if opts.find_empty_dirs && (!keep || !e.file_type().is_dir()) {
+            mark_occupied(&mut occupied.borrow_mut(), e.path().parent(), &opts.path);
+        }
+        keep
+    });
+
+    // depth of the file the limit stopped the walk at
+    let mut truncated_at = None;
+    for entry in entries.by_ref() {
+        if cancel.is_cancelled() {
+            cancelled = true;
+            break;
+        }
+        let e = match entry {
+            Ok(e) => e,
+            Err(err) => {
+                // symlink problems are a policy outcome (skipped_links), not a read failure
+                if is_symlink_error(&err) {
+                    skipped_links += 1;
+                    symlink_count += 1;
+                    if err.loop_ancestor().is_none() {
+                        symlink_targets_missing += 1;
+                        broken_symlinks.extend(err.path().map(Path::to_path_buf));
+                    }
+                } else if let Some(path) = err.path() {
+                    let entry = ScanErrorEntry {
+                        path: path.to_string_lossy().into_owned(),
+                        is_dir: fs::symlink_metadata(path).is_ok_and(|m| m.is_dir()),
+                        kind: error_kind(&err),
+                    };
+                    errors.record(&entry);
+                    sink(Err(entry));
+                }
+                if opts.find_empty_dirs {
+                    mark_occupied(&mut occupied.borrow_mut(), err.path(), &opts.path);
+                }
+                continue;
+            }
+        };
+
+        if e.file_type().is_dir() {
+            dirs_visited += 1;
+            throttle.dir();
+            progress.dir(e.path());
+        }
+        if e.depth() > 0 {
+            note_depth(&mut deepest, e.depth(), e.path());
+        }
+
+        // a followed link that resolved has a target; an unfollowed one needs a stat
+        if e.depth() > 0 && e.path_is_symlink() {
+            symlink_count += 1;
+            // existence only: the size stays the link's own, which isn't counted
+            if !opts.follow_symlinks && fs::metadata(e.path()).is_err() {
+                symlink_targets_missing += 1;
+                broken_symlinks.push(e.path().to_path_buf());
+            }
+        }
+
+        // pre-order walk: frames at this depth or deeper are finished
+        if opts.use_cache {
+            while frames.last().is_some_and(|f| f.depth >= e.depth()) {
+                let f = frames.pop().expect("checked by last()");
+                new_dirs.insert(f.path, f.new);
+            }
+            if e.file_type().is_dir() {
+                let mtime = e.metadata().ok().and_then(|m| m.modified().ok());
+                let old = old_cache.as_ref().and_then(|c| c.fresh_dir(e.path(), mtime));
+                match old {
+                    Some(_) => dirs_from_cache += 1,
+                    None => cache_dirty = true,
+                }
+                frames.push(DirFrame {
+                    depth: e.depth(),
+                    path: e.path().to_path_buf(),
+                    old,
+                    new: CachedDir {
+                        mtime,
+                        files: HashMap::new(),
+                    },
+                });
+            }
+        }
+
+        if opts.find_empty_dirs
+            && e.file_type().is_dir()
+            && e.depth() > 0
+            && opts.max_depth.is_none_or(|m| e.depth() < m)
+        {
+            visited_dirs.push(e.path().to_path_buf());
+        }
+
+        if !e.file_type().is_file() {
+            continue;
+        }
+
+        // the parent's frame is on top when the cache is in use
+        let cache = frames
+            .last_mut()
+            .filter(|f| f.depth + 1 == e.depth())
+            .map(|f| DirCache {
+                old: f.old,
+                new: &mut f.new,
+                dirty: &mut cache_dirty,
+            });
+        let stat = |_: &Path| e.metadata().map_err(io::Error::from);
+        let (path, name) = (e.path().to_path_buf(), e.file_name());
+        let counted = match visit_file(opts, &includes, root_components, path, name, cache, stat) {
+            FileVisit::Skipped => continue,
+            FileVisit::Vanished => {
+                vanished_files += 1;
+                continue;
+            }
+            FileVisit::Counted(counted) => counted,
+        };
+        if let Some(entry) = counted.error {
+            errors.record(&entry);
+            sink(Err(entry));
+        }
+        if let Some(id) = counted.identity
+            && !seen.insert(id)
+        {
+            collapsed_duplicates += 1;
+            continue;
+        }
+
+        if sent == limit {
+            truncated = true;
+            truncated_at = Some(e.depth());
+            break;
+        }
+        sent += 1;
+        progress.file(&counted.file.path, counted.file.size.unwrap_or(0));
+        sink(Ok(counted.file));
+    }
+
+    // the rest of that file's directory, as the parallel walk would have
+    // listed it; its subdirectories stay unread
+    let mut files_skipped = truncated as usize;
+    if let Some(depth) = truncated_at {
+        while let Some(entry) = entries.next() {
+            let Ok(e) = entry else { continue };
+            if e.depth() < depth {
+                break;
+            }
+            if e.file_type().is_dir() {
+                entries.skip_current_dir();
+                continue;
+            }
+            if !e.file_type().is_file() {
+                continue;
+            }
+            let stat = |_: &Path| e.metadata().map_err(io::Error::from);
+            let (path, name) = (e.path().to_path_buf(), e.file_name());
+            let visit = visit_file(opts, &includes, root_components, path, name, None, stat);
+            files_skipped += matches!(visit, FileVisit::Counted(_)) as usize;
+        }
+    }
+
+    // a partial walk would leave the unread directories out of the cache
+    if opts.use_cache && !truncated && !cancelled {
+        for f in frames.drain(..) {
+            new_dirs.insert(f.path, f.new);
+        }
+        let cache = ScanCache {
+            version: CACHE_VERSION,
+            root: cache_root,
+            follow_symlinks: opts.follow_symlinks,
+            started_at,
+            dirs: new_dirs,
+        };
+        finish_cache(cache, old_cache.as_ref(), cache_dirty);
+    }
+
+    // unread directories would look empty
+    let empty_dirs = if truncated || cancelled {
+        Vec::new()
+    } else {
+        topmost_empty_dirs(visited_dirs, &occupied.into_inner(), &opts.path)
+    };
+
+    Ok(WalkOutput {
+        excluded_dirs: excluded_dirs.get(),
+        excluded_files: excluded_files.get(),
+        skipped_links,
+        symlink_count,
+        symlink_targets_missing,
+        broken_symlinks,
+        collapsed_duplicates,
+        skipped_hidden: skipped_hidden.get(),
+        gitignored: gitignored.get(),
+        preset_skipped_dirs: preset_skipped_dirs.get(),
+        virtual_skipped: virtual_skipped.into_inner(),
+        truncated,
+        cancelled,
+        files_skipped,
+        vanished_files,
+        deepest,
+        empty_dirs,
+        errors,
+        dirs_visited,
+        dirs_from_cache,
+    })
+}
+
+/// save the walk's new cache if anything changed since `old`
+fn finish_cache(cache: ScanCache, old: Option<&ScanCache>, dirty: bool) {
+    // directories that disappeared (or are no longer visited) also count
+    if dirty || old.is_some_and(|c| c.dirs.len() != cache.dirs.len()) {
+        save_cache(&cache);
+    }
+}
+
+/// report an empty directory only if its parent has content (or is the
+/// root), so a chain of nested empty directories shows up once
+fn topmost_empty_dirs(
+    visited_dirs: Vec<PathBuf>,
+    occupied: &HashSet<PathBuf>,
+    root: &Path,
+) -> Vec<String> {
+    let mut empty_dirs: Vec<String> = visited_dirs
+        .into_iter()
+        .filter(|d| !occupied.contains(d))
+        .filter(|d| d.parent().is_none_or(|p| p == root || occupied.contains(p)))
+        .map(|d| d.to_string_lossy().into_owned())
+        .collect();
+    empty_dirs.sort_unstable();
+    empty_dirs
+}
+
+// ============ LOW PRIORITY ============
+// ScanOptions::low_priority runs the walk and the aggregation on a small
+// pool of its own instead of the global one, and pauses the walk now and
+// then so a slow disk gets a breather. Nothing else changes.
+
+/// threads of the low_priority pool
+pub const GENTLE_THREADS: usize = 2;
+/// directories read between two pauses
+const GENTLE_BATCH: usize = 64;
+/// length of one pause
+const GENTLE_PAUSE: Duration = Duration::from_millis(5);
+
+/// most threads a scan of this process may use (limit_threads)
+static THREAD_LIMIT: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+
+/// run every scan of this process on at most `threads` threads: rayon's
+/// global pool and the low_priority one; must happen before the first scan
+pub fn limit_threads(threads: usize) {
+    let _ = THREAD_LIMIT.set(threads);
+    let _ = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global();
+}
+
+/// threads of the low_priority pool under a limit_threads `limit`
+fn gentle_threads(limit: Option<usize>) -> usize {
+    limit.map_or(GENTLE_THREADS, |limit| GENTLE_THREADS.min(limit.max(1)))
+}
+
+/// the pool a scan with these options runs on; None = the global pool
+/// - built once, on the first low_priority scan
+fn scan_pool(opts: &ScanOptions) -> Option<&'static rayon::ThreadPool> {
+    static GENTLE_POOL: std::sync::OnceLock<Option<rayon::ThreadPool>> =
+        std::sync::OnceLock::new();
+    if !opts.low_priority {
+        return None;
+    }
+    GENTLE_POOL
+        .get_or_init(|| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(gentle_threads(THREAD_LIMIT.get().copied()))
+                .thread_name(|i| format!("fscan-gentle-{}", i))
+                .build()
+                .ok()
+        })
+        .as_ref()
+}
+
+/// run `f` on the pool scan_pool picks, so its parallel iterators use it
+fn in_scan_pool<R: Send>(opts: &ScanOptions, f: impl FnOnce() -> R + Send) -> R {
+    match scan_pool(opts) {
+        Some(pool) => pool.install(f),
+        None => f(),
+    }
+}
+
+/// counts directories read and pauses every GENTLE_BATCH (low_priority only)
+struct Throttle {
+    enabled: bool,
+    dirs: AtomicUsize,
+}
+
+impl Throttle {
+    fn new(opts: &ScanOptions) -> Self {
+        Throttle {
+            enabled: opts.low_priority,
+            dirs: AtomicUsize::new(0),
+        }
+    }
+
+    /// call once per directory, from any thread
+    fn dir(&self) {
+        if !self.enabled {
+            return;
+        }
+        if self.dirs.fetch_add(1, AtomicOrdering::Relaxed) % GENTLE_BATCH == GENTLE_BATCH - 1 {
+            std::thread::sleep(GENTLE_PAUSE);
+        }
+    }
+}
+
+// ============ PARALLEL WALK ============
+// walk_files_parallel: same output as walk_files, but every directory is
+// read by its own task on the rayon pool. A task lists its directory, stats
+// the files in it, spawns one task per kept subdirectory and sends the
+// whole listing to the walking thread, which does everything that needs
+// one owner: the sink, progress, the error log, hardlink/symlink dedupe,
+// the new scan cache and the empty-directory bookkeeping.
+// Differences from the WalkDir walk:
+// - files arrive grouped by directory, directories in no fixed order
+// - files that may be duplicates (CountedFile::identity) are held back
+//   until the walk ends, then deduplicated in path order, so the copy that
+//   is kept no longer depends on the walk order
+// - with more than error_limit errors, which ones are kept depends on timing
+
+/// directory listings in flight to the walking thread
+const LISTING_BUFFER: usize = 256;
+
+/// everything a directory task needs besides its own directory
+struct WalkContext<'a> {
+    opts: &'a ScanOptions,
+    excludes: &'a [GlobPattern],
+    includes: &'a [GlobPattern],
+    old_cache: Option<&'a ScanCache>,
+    cancel: &'a CancellationToken,
+    /// see ScannedFile::root_components
+    root_components: usize,
+    /// see virtual_mounts_below
+    virtual_mounts: Vec<PathBuf>,
+    /// set once a file past file_limit arrived: no new directories are read
+    limit_hit: &'a AtomicBool,
+    throttle: Throttle,
+    tx: SyncSender<DirListing>,
+}
+
+/// one directory to read
+struct DirTask {
+    path: PathBuf,
+    depth: usize,
+    /// .gitignore frames of the ancestors, root first
+    ignore: Vec<Arc<IgnoreFrame>>,
+    /// (device, inode) of the directories from the root down to this one,
+    /// for symlink loop detection (follow_symlinks only)
+    ancestors: Vec<(u64, u64)>,
+}
+
+/// what one directory task found
+#[derive(Default)]
+struct DirListing {
+    /// the directory that was read
+    dir: PathBuf,
+    /// counted files and read errors, in read_dir order
+    items: Vec<ScanItem>,
+    /// files that may be reached twice, with their identity
+    tracked: Vec<(ScannedFile, (u64, u64))>,
+    excluded_dirs: usize,
+    excluded_files: usize,
+    skipped_hidden: usize,
+    gitignored: usize,
+    preset_skipped_dirs: usize,
+    virtual_skipped: Vec<PathBuf>,
+    skipped_links: usize,
+    symlink_count: usize,
+    symlink_targets_missing: usize,
+    broken_symlinks: Vec<PathBuf>,
+    vanished_files: usize,
+    /// (depth, path) of one kept entry, see note_depth
+    deepest: Option<(usize, PathBuf)>,
+    /// this directory, when it is an empty-directory candidate
+    visited: Option<PathBuf>,
+    /// paths to mark_occupied (find_empty_dirs only)
+    occupied: Vec<PathBuf>,
+    /// this directory's new cache entry (use_cache only)
+    cache: Option<(PathBuf, CachedDir)>,
+    from_cache: bool,
+    cache_dirty: bool,
+}
+
+/// walk_files on the rayon pool; see PARALLEL WALK above
+/// - `opts.path` must be a directory (see check_roots)
+fn walk_files_parallel(
+    opts: &ScanOptions,
+    progress: &mut ProgressReporter,
+    cancel: &CancellationToken,
+    sink: &mut dyn FnMut(ScanItem),
+) -> Result<WalkOutput, ScanError> {
+    let excludes = compile_globs(&opts.exclude)?;
+    let includes = compile_globs(&opts.include)?;
+
+    let started_at = SystemTime::now();
+    let cache_root = std::path::absolute(&opts.path).unwrap_or_else(|_| opts.path.clone());
+    let old_cache = if opts.use_cache {
+        load_cache(&cache_root, opts.follow_symlinks)
+    } else {
+        None
+    };
+
+    let root = DirTask {
+        path: opts.path.clone(),
+        depth: 0,
+        ignore: if opts.respect_gitignore {
+            root_ignore_frames(&opts.path).into_iter().map(Arc::new).collect()
+        } else {
+            Vec::new()
+        },
+        ancestors: if opts.follow_symlinks {
+            fs::metadata(&opts.path).ok().and_then(|m| file_identity(&m)).into_iter().collect()
+        } else {
+            Vec::new()
+        },
+    };
+
+    let mut out = WalkOutput {
+        excluded_dirs: 0,
+        excluded_files: 0,
+        skipped_links: 0,
+        symlink_count: 0,
+        symlink_targets_missing: 0,
+        broken_symlinks: Vec::new(),
+        collapsed_duplicates: 0,
+        skipped_hidden: 0,
+        gitignored: 0,
+        preset_skipped_dirs: 0,
+        virtual_skipped: Vec::new(),
+        truncated: false,
+        cancelled: false,
+        files_skipped: 0,
+        vanished_files: 0,
+        deepest: None,
+        empty_dirs: Vec::new(),
+        errors: ErrorLog::new(opts.error_limit),
+        dirs_visited: 0,
+        dirs_from_cache: 0,
+    };
+    let mut tracked: Vec<(ScannedFile, (u64, u64))> = Vec::new();
+    let mut visited_dirs: Vec<PathBuf> = Vec::new();
+    let mut occupied: HashSet<PathBuf> = HashSet::new();
+    let mut new_dirs: HashMap<PathBuf, CachedDir> = HashMap::new();
+    let mut cache_dirty = old_cache.is_none();
+    let limit = opts.file_limit.unwrap_or(usize::MAX);
+    let limit_hit = AtomicBool::new(false);
+    let mut sent = 0usize;
+    // distinct files among `tracked`, i.e. how many the dedupe will keep
+    let mut tracked_ids: HashSet<(u64, u64)> = HashSet::new();
+
+    let (tx, rx) = sync_channel::<DirListing>(LISTING_BUFFER);
+    std::thread::scope(|threads| {
+        let ctx = WalkContext {
+            opts,
+            excludes: &excludes,
+            includes: &includes,
+            old_cache: old_cache.as_ref(),
+            cancel,
+            root_components: opts.path.components().count(),
+            virtual_mounts: virtual_mounts_below(opts, &opts.path),
+            limit_hit: &limit_hit,
+            throttle: Throttle::new(opts),
+            tx,
+        };
+        // the context (and with it the sender) is dropped when every task is
+        // done, which ends the loop below
+        threads.spawn(move || match scan_pool(opts) {
+            Some(pool) => pool.scope(|scope| visit_dir(scope, &ctx, root)),
+            None => rayon::scope(|scope| visit_dir(scope, &ctx, root)),
+        });
+
+        // keep draining after a cancel: a task blocked on a full channel
+        // would never finish otherwise
+        for listing in rx {
+            if cancel.is_cancelled() {
+                continue;
+            }
+            out.dirs_visited += 1;
+            progress.dir(&listing.dir);
+            out.excluded_dirs += listing.excluded_dirs;
+            out.excluded_files += listing.excluded_files;
+            out.skipped_hidden += listing.skipped_hidden;
+            out.gitignored += listing.gitignored;
+            out.preset_skipped_dirs += listing.preset_skipped_dirs;
+            out.virtual_skipped.extend(listing.virtual_skipped);
+            out.skipped_links += listing.skipped_links;
+            out.symlink_count += listing.symlink_count;
+            out.symlink_targets_missing += listing.symlink_targets_missing;
+            out.broken_symlinks.extend(listing.broken_symlinks);
+            out.dirs_from_cache += listing.from_cache as usize;
+            out.vanished_files += listing.vanished_files;
+            if let Some((depth, path)) = &listing.deepest {
+                note_depth(&mut out.deepest, *depth, path);
+            }
+            cache_dirty |= listing.cache_dirty;
+            visited_dirs.extend(listing.visited);
+            for path in &listing.occupied {
+                mark_occupied(&mut occupied, Some(path), &opts.path);
+            }
+            if let Some((path, dir)) = listing.cache {
+                new_dirs.insert(path, dir);
+            }
+            // held-back files count towards the limit too, or a walk that
+            // tracks every file would never stop
+            tracked_ids.extend(listing.tracked.iter().map(|(_, id)| *id));
+            tracked.extend(listing.tracked);
+            for item in listing.items {
+                match &item {
+                    Ok(_) if sent + tracked_ids.len() >= limit => {
+                        out.files_skipped += 1;
+                        limit_hit.store(true, AtomicOrdering::Relaxed);
+                        continue;
+                    }
+                    Ok(f) => {
+                        sent += 1;
+                        progress.file(&f.path, f.size.unwrap_or(0));
+                    }
+                    Err(entry) => out.errors.record(entry),
+                }
+                sink(item);
+            }
+            if sent + tracked_ids.len() > limit {
+                limit_hit.store(true, AtomicOrdering::Relaxed);
+            }
+        }
+    });
+    out.cancelled = cancel.is_cancelled();
+    out.truncated = limit_hit.into_inner();
+
+    tracked.sort_by(|a, b| a.0.path.cmp(&b.0.path));
+    let mut seen: HashSet<(u64, u64)> = HashSet::new();
+    for (f, id) in tracked {
+        if !seen.insert(id) {
+            out.collapsed_duplicates += 1;
+            continue;
+        }
+        if sent == limit {
+            out.files_skipped += 1;
+            continue;
+        }
+        sent += 1;
+        progress.file(&f.path, f.size.unwrap_or(0));
+        sink(Ok(f));
+    }
+
+    if opts.use_cache && !out.truncated && !out.cancelled {
+        let cache = ScanCache {
+            version: CACHE_VERSION,
+            root: cache_root,
+            follow_symlinks: opts.follow_symlinks,
+            started_at,
+            dirs: new_dirs,
+        };
+        finish_cache(cache, old_cache.as_ref(), cache_dirty);
+    }
+
+    if !out.truncated && !out.cancelled {
+        out.empty_dirs = topmost_empty_dirs(visited_dirs, &occupied, &opts.path);
+    }
+    Ok(out)
+}
+
+/// read one directory, send its listing and spawn its kept subdirectories
+/// - mirrors the per-entry logic of walk_files (files go through the same
+///   visit_file); a directory at max_depth is visited but not read, like
+///   WalkDir does
+fn visit_dir<'s>(scope: &rayon::Scope<'s>, ctx: &'s WalkContext<'s>, task: DirTask) {
+    if ctx.cancel.is_cancelled() || ctx.limit_hit.load(AtomicOrdering::Relaxed) {
+        return;
+    }
+    let opts = ctx.opts;
+    let mut listing = DirListing {
+        dir: task.path.clone(),
+        ..DirListing::default()
+    };
+    ctx.throttle.dir();
+
+    if opts.find_empty_dirs && task.depth > 0 && opts.max_depth.is_none_or(|m| task.depth < m) {
+        listing.visited = Some(task.path.clone());
+    }
+
+    let mut old: Option<&CachedDir> = None;
+    let mut new = CachedDir::default();
+    if opts.use_cache {
+        new.mtime = fs::metadata(&task.path).ok().and_then(|m| m.modified().ok());
+        old = ctx.old_cache.and_then(|c| c.fresh_dir(&task.path, new.mtime));
+        listing.from_cache = old.is_some();
+        listing.cache_dirty = old.is_none();
+    }
+
+    if opts.max_depth.is_none_or(|m| task.depth < m) {
+        read_listing(scope, ctx, &task, old, &mut new, &mut listing);
+    }
+
+    if opts.use_cache {
+        listing.cache = Some((task.path, new));
+    }
+    // the walking thread only goes away once every task is done
+    let _ = ctx.tx.send(listing);
+}
+
+/// the read_dir part of visit_dir
+fn read_listing<'s>(
+    scope: &rayon::Scope<'s>,
+    ctx: &'s WalkContext<'s>,
+    task: &DirTask,
+    old: Option<&CachedDir>,
+    new: &mut CachedDir,
+    listing: &mut DirListing,
+) {
+    let opts = ctx.opts;
+    let read_error = |listing: &mut DirListing, err: &io::Error| {
+        listing.items.push(Err(ScanErrorEntry {
+            path: task.path.to_string_lossy().into_owned(),
+            is_dir: fs::symlink_metadata(&task.path).is_ok_and(|m| m.is_dir()),
+            kind: err.kind().to_string(),
+        }));
+        if opts.find_empty_dirs {
+            listing.occupied.push(task.path.clone());
+        }
+    };
+    let entries = match fs::read_dir(&task.path) {
+        Ok(entries) => entries,
+        Err(err) => return read_error(listing, &err),
+    };
+
+    // .gitignore rules of this directory apply to everything below it
+    let mut ignore = task.ignore.clone();
+    if opts.respect_gitignore
+        && task.depth > 0
+        && let Some(frame) = load_ignore_frame(&task.path, task.depth)
+    {
+        ignore.push(Arc::new(frame));
+    }
+
+    let depth = task.depth + 1;
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                read_error(listing, &err);
+                continue;
+            }
+        };
+        let path = entry.path();
+        let Ok(mut file_type) = entry.file_type() else {
+            continue;
+        };
+        let is_link = file_type.is_symlink();
+
+        // a followed link takes its target's type; broken links and loops
+        // are counted like WalkDir's symlink errors in walk_files
+        let mut target_id = None;
+        if is_link && opts.follow_symlinks {
+            let target = match fs::metadata(&path) {
+                Ok(m) => m,
+                Err(_) => {
+                    listing.skipped_links += 1;
+                    listing.symlink_count += 1;
+                    listing.symlink_targets_missing += 1;
+                    listing.broken_symlinks.push(path.clone());
+                    if opts.find_empty_dirs {
+                        listing.occupied.push(path);
+                    }
+                    continue;
+                }
+            };
+            file_type = target.file_type();
+            target_id = file_identity(&target);
+            if file_type.is_dir() && target_id.is_some_and(|id| task.ancestors.contains(&id)) {
+                listing.skipped_links += 1;
+                listing.symlink_count += 1;
+                if opts.find_empty_dirs {
+                    listing.occupied.push(path);
+                }
+                continue;
+            }
+        }
+        let is_dir = file_type.is_dir();
+
+        let keep = keep_parallel_entry(ctx, &ignore, &path, &entry.file_name(), is_dir, listing);
+        if opts.find_empty_dirs && (!keep || !is_dir) && listing.occupied.last() != Some(&task.path)
+        {
+            listing.occupied.push(task.path.clone());
+        }
+        if !keep {
+            continue;
+        }
+
+        if is_link {
+            listing.symlink_count += 1;
+            if !opts.follow_symlinks && fs::metadata(&path).is_err() {
+                listing.symlink_targets_missing += 1;
+                listing.broken_symlinks.push(path.clone());
+            }
+        }
+        note_depth(&mut listing.deepest, depth, &path);
+
+        if is_dir {
+            let mut ancestors = Vec::new();
+            if opts.follow_symlinks {
+                let id = match target_id {
+                    Some(id) => Some(id),
+                    None => fs::metadata(&path).ok().and_then(|m| file_identity(&m)),
+                };
+                ancestors = task.ancestors.clone();
+                ancestors.extend(id);
+            }
+            let child = DirTask {
+                path,
+                depth,
+                ignore: ignore.clone(),
+                ancestors,
+            };
+            scope.spawn(move |scope| visit_dir(scope, ctx, child));
+            continue;
+        }
+        if !file_type.is_file() {
+            continue;
+        }
+
+        let cache = opts.use_cache.then_some(DirCache {
+            old,
+            new: &mut *new,
+            dirty: &mut listing.cache_dirty,
+        });
+        let stat = |path: &Path| {
+            if opts.follow_symlinks {
+                fs::metadata(path)
+            } else {
+                entry.metadata()
+            }
+        };
+        let name = entry.file_name();
+        match visit_file(opts, ctx.includes, ctx.root_components, path, &name, cache, stat) {
+            FileVisit::Skipped => {}
+            FileVisit::Vanished => listing.vanished_files += 1,
+            FileVisit::Counted(counted) => {
+                listing.items.extend(counted.error.map(Err));
+                match counted.identity {
+                    Some(id) => listing.tracked.push((counted.file, id)),
+                    None => listing.items.push(Ok(counted.file)),
+                }
+            }
+        }
+    }
+}
+
+/// walk_files' keep_entry for one entry of a parallel listing
+fn keep_parallel_entry(
+    ctx: &WalkContext,
+    ignore: &[Arc<IgnoreFrame>],
+    path: &Path,
+    name: &std::ffi::OsStr,
+    is_dir: bool,
+    listing: &mut DirListing,
+) -> bool {
+    let opts = ctx.opts;
+    if is_dir && ctx.virtual_mounts.iter().any(|m| m == path) {
+        listing.virtual_skipped.push(path.to_path_buf());
+        return false;
+    }
+    if opts.skip_hidden && name.to_string_lossy().starts_with('.') {
+        listing.skipped_hidden += 1;
+        return false;
+    }
+    if is_dir && preset_skips(&opts.skip_presets, name) {
+        listing.preset_skipped_dirs += 1;
+        return false;
+    }
+    if opts.respect_gitignore && (name == ".git" || is_gitignored(ignore, path, is_dir)) {
+        listing.gitignored += 1;
+        return false;
+    }
+    if ctx.excludes.is_empty() {
+        return true;
+    }
+    let rel = relative_path_string(path, &opts.path);
+    if ctx.excludes.iter().any(|g| g.matches(&rel, is_dir)) {
+        if is_dir {
+            listing.excluded_dirs += 1;
+        } else {
+            listing.excluded_files += 1;
+        }
+        return false;
+    }
+    true
+}
+
+/// extension_count bucket for names without a dot ("Makefile", "README") or
+/// with nothing after the last one ("notes.")
+pub const EXT_NONE_LABEL: &str = "(no extension)";
+/// extension_count bucket for dotfiles with no further extension (".bashrc")
+pub const EXT_DOTFILE_LABEL: &str = "(dotfile)";
+/// extension_count bucket for extensions that aren't valid UTF-8
+pub const EXT_UNKNOWN_LABEL: &str = "unknown";
+
+/// extension_count key of a file: its lowercase extension, or one of the
+/// EXT_*_LABEL buckets
+/// - compound extensions stay whole ("x.tar.gz" -> "tar.gz", not "gz")
+pub fn extension_key(path: &Path) -> String {
+    extension_key_with(path, true)
+}
+
+/// extension_key, optionally keeping the extension's case as written
+/// ("IMG.JPG" -> "JPG", "x.TAR.gz" -> "TAR.gz")
+pub fn extension_key_with(path: &Path, case_insensitive: bool) -> String {
+    if let Some(ext) = compound_extension(path) {
+        // the suffix as the name spells it; same length, the suffixes are ASCII
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        return match name.get(name.len().saturating_sub(ext.len())..) {
+            Some(raw) if !case_insensitive => raw.to_string(),
+            _ => ext.to_string(),
+        };
+    }
+    // a trailing dot ("notes.") leaves an empty extension: none at all
+    match path.extension().filter(|e| !e.is_empty()) {
+        Some(ext) => match ext.to_str() {
+            Some(e) if case_insensitive => e.to_lowercase(),
+            Some(e) => e.to_string(),
+            None => EXT_UNKNOWN_LABEL.to_string(),
+        },
+        // Path::extension treats a leading dot as part of the stem
+        None if path.file_name().is_some_and(|n| n.as_encoded_bytes().starts_with(b".")) => {
+            EXT_DOTFILE_LABEL.to_string()
+        }
+        None => EXT_NONE_LABEL.to_string(),
+    }
+}
+
+/// ScanOptions::only_extensions check for one file
+/// - a listed entry matches the file's extension_key or its last extension,
+///   so "gz" takes "x.tar.gz" too while "tar.gz" takes only the compound one
+/// - entries are compared trimmed, without a leading dot, lowercase
+fn extension_allowed(path: &Path, only: Option<&[String]>) -> bool {
+    let Some(only) = only.filter(|o| !o.is_empty()) else {
+        return true;
+    };
+    let key = extension_key(path);
+    let last = path.extension().and_then(|e| e.to_str()).map(str::to_lowercase);
+    only.iter().any(|want| {
+        let want = want.trim().trim_start_matches('.').to_lowercase();
+        want == key || last.as_deref() == Some(want.as_str())
+    })
+}
+
+/// running totals over the file stream, one `add` per file
+/// - what scan_folder reports as totals and extension_count
+#[derive(Clone, Debug, Default)]
+pub struct ScanTotals {
+    pub total_size: u64,
+    pub total_allocated: u64,
+    pub total_files: usize,
+    /// files among total_files whose size could not be read
+    pub metadata_errors: usize,
+    /// files among total_files under ScanOptions::ext_stats_min_size (or of
+    /// unknown size while it is set), in no extension
+    pub below_ext_floor: usize,
+    /// keep extension case apart (ScanOptions::case_insensitive_extensions off)
+    case_sensitive: bool,
+    ext_floor: Option<u64>,
+    extensions: HashMap<String, ExtTotals>,
+    /// every readable size, for size_quantiles
+    sizes: SizeSamples,
+    max_size: u64,
+}
+
+/// whether `f` belongs in the extension/category breakdown under
+/// ScanOptions::ext_stats_min_size (files of unknown size don't, with a floor)
+fn above_ext_floor(f: &ScannedFile, floor: Option<u64>) -> bool {
+    floor.is_none_or(|floor| f.size.is_some_and(|size| size >= floor))
+}
+
+/// per extension, sizes are kept exactly up to this many files; beyond it
+/// they move into a SizeSketch (a few KB at most, whatever the count)
+pub const MEDIAN_EXACT_LIMIT: usize = 100_000;
+
+/// sub-buckets per power of two in SizeSketch
+const SKETCH_SUB_BITS: u32 = 4;
+
+/// size distribution in log-scale buckets: each power of two split into 16,
+/// so a bucket spans at most 1/16 of its lower bound
+#[derive(Clone, Debug, Default)]
+struct SizeSketch {
+    buckets: BTreeMap<u32, u64>,
+    count: u64,
+}
+
+impl SizeSketch {
+    fn bucket(size: u64) -> u32 {
+        if size < 1 << SKETCH_SUB_BITS {
+            return size as u32;
+        }
+        let exp = 63 - size.leading_zeros();
+        let sub = (size >> (exp - SKETCH_SUB_BITS)) as u32 & ((1 << SKETCH_SUB_BITS) - 1);
+        ((exp - SKETCH_SUB_BITS + 1) << SKETCH_SUB_BITS) | sub
+    }
+
+    /// [low, high) sizes falling into `bucket`
+    fn bounds(bucket: u32) -> (u64, u64) {
+        let exp = bucket >> SKETCH_SUB_BITS;
+        if exp == 0 {
+            return (bucket as u64, bucket as u64 + 1);
+        }
+        let shift = exp - 1;
+        let low = ((1u64 << SKETCH_SUB_BITS) | (bucket & ((1 << SKETCH_SUB_BITS) - 1)) as u64)
+            << shift;
+        (low, low.saturating_add(1 << shift))
+    }
+
+    fn add(&mut self, size: u64) {
+        *self.buckets.entry(Self::bucket(size)).or_insert(0) += 1;
+        self.count += 1;
+    }
+
+    /// midpoint of the bucket holding the median
+    fn median(&self) -> u64 {
+        self.rank(self.count.div_ceil(2))
+    }
+
+    /// midpoint of the bucket holding the `target`-th smallest size (1-based)
+    /// - the true size is in [low, high) and high - low <= low/16, so the
+    ///   midpoint is off by at most 1/32 of it
+    fn rank(&self, target: u64) -> u64 {
+        let mut seen = 0;
+        for (&bucket, &n) in &self.buckets {
+            seen += n;
+            if seen >= target {
+                let (low, high) = Self::bounds(bucket);
+                return low + (high - low) / 2;
+            }
+        }
+        0
+    }
+}
+
+/// 1-based nearest rank of quantile `q` among `n` values
+fn nearest_rank(q: f64, n: u64) -> u64 {
+    ((q * n as f64).ceil() as u64).clamp(1, n.max(1))
+}
+
+/// sizes of one extension, for its median
+#[derive(Clone, Debug)]
+enum SizeSamples {
+    Exact(Vec<u64>),
+    Sketch(SizeSketch),
+}
+
+impl Default for SizeSamples {
+    fn default() -> Self {
+        SizeSamples::Exact(Vec::new())
+    }
+}
+
+impl SizeSamples {
+    fn add(&mut self, size: u64) {
+        match self {
+            SizeSamples::Exact(sizes) if sizes.len() < MEDIAN_EXACT_LIMIT => sizes.push(size),
+            SizeSamples::Exact(sizes) => {
+                let mut sketch = SizeSketch::default();
+                for &s in sizes.iter() {
+                    sketch.add(s);
+                }
+                sketch.add(size);
+                *self = SizeSamples::Sketch(sketch);
+            }
+            SizeSamples::Sketch(sketch) => sketch.add(size),
+        }
+    }
+
+    /// (median, exact?); 0 with no samples
+    fn median(&self) -> (u64, bool) {
+        match self {
+            SizeSamples::Exact(sizes) if sizes.is_empty() => (0, true),
+            SizeSamples::Exact(sizes) => {
+                let mut sorted = sizes.clone();
+                let mid = sorted.len() / 2;
+                let (_, &mut upper, _) = sorted.select_nth_unstable(mid);
+                if sizes.len() % 2 == 1 {
+                    return (upper, true);
+                }
+                let lower = *sorted[..mid].iter().max().expect("even count >= 2");
+                (lower + (upper - lower) / 2, true)
+            }
+            SizeSamples::Sketch(sketch) => (sketch.median(), false),
+        }
+    }
+
+    /// nearest-rank quantiles `qs` (each in 0..=1), and exact?; 0s with no samples
+    fn quantiles(&self, qs: &[f64]) -> (Vec<u64>, bool) {
+        match self {
+            SizeSamples::Exact(sizes) if sizes.is_empty() => (vec![0; qs.len()], true),
+            SizeSamples::Exact(sizes) => {
+                let mut sorted = sizes.clone();
+                sorted.sort_unstable();
+                let n = sorted.len() as u64;
+                let values = qs.iter().map(|&q| sorted[nearest_rank(q, n) as usize - 1]);
+                (values.collect(), true)
+            }
+            SizeSamples::Sketch(sketch) => {
+                let values = qs.iter().map(|&q| sketch.rank(nearest_rank(q, sketch.count)));
+                (values.collect(), false)
+            }
+        }
+    }
+}
+
+/// running totals of one extension
+#[derive(Clone, Debug, Default)]
+struct ExtTotals {
+    count: usize,
+    total_size: u64,
+    /// biggest file with a readable size; equal sizes keep the smaller path
+    largest: Option<FileEntry>,
+    /// readable sizes, for mean and median
+    sized: usize,
+    samples: SizeSamples,
+}
+
+impl ScanTotals {
+    /// empty totals grouping extensions as `opts` asks; Default folds case
+    pub fn new(opts: &ScanOptions) -> Self {
+        ScanTotals {
+            case_sensitive: !opts.case_insensitive_extensions,
+            ext_floor: opts.ext_stats_min_size,
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&mut self, f: &ScannedFile) {
+        let size = f.size.unwrap_or(0);
+        self.total_size += size;
+        self.total_allocated += f.allocated.unwrap_or(0);
+        self.total_files += 1;
+        self.metadata_errors += f.size.is_none() as usize;
+        if let Some(size) = f.size {
+            self.sizes.add(size);
+            self.max_size = self.max_size.max(size);
+        }
+        if !above_ext_floor(f, self.ext_floor) {
+            self.below_ext_floor += 1;
+            return;
+        }
+
+        let key = extension_key_with(&f.path, !self.case_sensitive);
+        let slot = self.extensions.entry(key).or_default();
+        slot.count += 1;
+        slot.total_size += size;
+        if let Some(size) = f.size {
+            slot.sized += 1;
+            slot.samples.add(size);
+            let bigger = match &slot.largest {
+                None => true,
+                Some(cur) => {
+                    size > cur.size || (size == cur.size && f.path < cur.path)
+                }
+            };
+            if bigger {
+                slot.largest = Some(file_entry(f, size));
+            }
+        }
+    }
+
+    /// per-extension stats, largest first by `sort`
+    pub fn extension_count(&self, sort: ExtensionSort) -> Vec<ExtensionStat> {
+        let mut extension_count: Vec<ExtensionStat> = self
+            .extensions
+            .iter()
+            .map(|(extension, t)| {
+                let (median_size, median_exact) = t.samples.median();
+                ExtensionStat {
+                    extension: extension.clone(),
+                    count: t.count,
+                    total_size: t.total_size,
+                    mean_size: t.total_size.checked_div(t.sized as u64).unwrap_or(0),
+                    median_size,
+                    median_exact,
+                }
+            })
+            .collect();
+
+        sort_extensions(&mut extension_count, sort);
+        extension_count
+    }
+
+    /// p50/p90/p99/max over every file with a readable size
+    pub fn size_quantiles(&self) -> SizeQuantiles {
+        let (q, exact) = self.sizes.quantiles(&[0.5, 0.9, 0.99]);
+        SizeQuantiles {
+            p50: q[0],
+            p90: q[1],
+            p99: q[2],
+            max: self.max_size,
+            exact,
+        }
+    }
+
+    /// biggest file of each extension, biggest first (ties by extension name)
+    pub fn extension_largest(&self) -> Vec<(String, FileEntry)> {
+        let mut largest: Vec<(String, FileEntry)> = self
+            .extensions
+            .iter()
+            .filter_map(|(ext, t)| t.largest.clone().map(|f| (ext.clone(), f)))
+            .collect();
+        largest.sort_by(|a, b| b.1.size.cmp(&a.1.size).then_with(|| a.0.cmp(&b.0)));
+        largest
+    }
+}
+
+/// FileEntry for a scanned file whose size is known
+fn file_entry(f: &ScannedFile, size: u64) -> FileEntry {
+    FileEntry {
+        path: f.path.clone(),
+        display: f.path.to_string_lossy().into_owned(),
+        size,
+        allocated: f.allocated.unwrap_or(size),
+        mtime: f.mtime,
+        hash: None,
+        rel_path: f.relative_path().to_string_lossy().into_owned(),
+    }
+}
+
+/// read size when hashing file content
+const HASH_CHUNK: usize = 1024 * 1024;
+
+/// hex SHA-256 of a file's content, read HASH_CHUNK at a time
+/// - a cancelled scan stops mid-file with ErrorKind::Interrupted
+fn hash_file(path: &Path, cancel: &CancellationToken) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; HASH_CHUNK];
+    loop {
+        if cancel.is_cancelled() {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "scan cancelled"));
+        }
+        match file.read(&mut buf) {
+            Ok(0) => return Ok(format!("{:x}", hasher.finalize())),
+            Ok(n) => hasher.update(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// fill in FileEntry::hash on the rayon pool -> how many could not be read
+/// - files are reopened by their raw FileEntry::path, so names that aren't
+///   valid UTF-8 hash like any other
+/// - `on_file` hears of each file before it's read
+fn hash_entries(
+    entries: &mut [FileEntry],
+    cancel: &CancellationToken,
+    on_file: &(dyn Fn(&Path) + Sync),
+) -> usize {
+    entries
+        .par_iter_mut()
+        .map(|e| {
+            on_file(&e.path);
+            match hash_file(&e.path, cancel) {
+                Ok(h) => {
+                    e.hash = Some(h);
+                    0
+                }
+                Err(_) => 1,
+            }
+        })
+        .sum()
+}
+
+/// the file-name filters of ScanOptions, prepared once per scan
+struct NameFilter {
+    regex: Option<Regex>,
+    match_full_path: bool,
+    /// name_contains, lowercased
+    contains: Option<String>,
+}
+
+impl NameFilter {
+    /// compile once up front: an invalid regex is an error, not "nothing matched"
+    fn new(opts: &ScanOptions) -> Result<Self, ScanError> {
+        let regex = match &opts.name_regex {
+            Some(re) => Some(Regex::new(re).map_err(|e| format!("invalid name regex: {}", e))?),
+            None => None,
+        };
+        Ok(NameFilter {
+            regex,
+            match_full_path: opts.match_full_path,
+            contains: opts
+                .name_contains
+                .as_ref()
+                .filter(|t| !t.is_empty())
+                .map(|t| t.to_lowercase()),
+        })
+    }
+
+    /// both filters, when given, must match
+    /// - name_regex: file name, or full path with match_full_path
+    /// - name_contains: always the file name
+    fn matches(&self, f: &ScannedFile) -> bool {
+        let name = f.path.file_name().map(|n| n.to_string_lossy());
+        let regex_ok = match &self.regex {
+            Some(re) if self.match_full_path => re.is_match(&f.path.to_string_lossy()),
+            Some(re) => name.as_ref().is_some_and(|n| re.is_match(n)),
+            None => true,
+        };
+        let contains_ok = match &self.contains {
+            Some(text) => name.is_some_and(|n| n.to_lowercase().contains(text.as_str())),
+            None => true,
+        };
+        regex_ok && contains_ok
+    }
+}
+
+/// whether a file (with readable size) belongs in filtered_files
+/// - the name filters, when given, must also match
+/// - modified_before/after need a readable mtime
+fn passes_filter(f: &ScannedFile, size: u64, opts: &ScanOptions, names: &NameFilter) -> bool {
+    if size < opts.min_size || opts.max_size.is_some_and(|max| size > max) {
+        return false;
+    }
+    names.matches(f)
+        && match (opts.modified_before, opts.modified_after) {
+            (None, None) => true,
+            (before, after) => f.mtime.is_some_and(|t| {
+                before.is_none_or(|b| t < b) && after.is_none_or(|a| t >= a)
+            }),
+        }
+}
+
+/// output order of two matched files under `sort` (Less = listed first)
+/// - ties on the key fall back to path ascending, so the order never
+///   depends on walk order
+/// - an unreadable mtime compares as older than any real one
+fn file_order(sort: FileSort, a: (&ScannedFile, u64), b: (&ScannedFile, u64)) -> Ordering {
+    let by_key = match sort.key {
+        FileSortKey::Size => a.1.cmp(&b.1),
+        FileSortKey::Name => a.0.path.file_name().cmp(&b.0.path.file_name()),
+        FileSortKey::Path => a.0.path.cmp(&b.0.path),
+        FileSortKey::Mtime => a.0.mtime.cmp(&b.0.mtime),
+    };
+    let by_key = match sort.order {
+        SortOrder::Asc => by_key,
+        SortOrder::Desc => by_key.reverse(),
+    };
+    by_key.then_with(|| a.0.path.cmp(&b.0.path))
+}
+
+/// a matched file ranked for the top-N heap: greater = listed earlier
+struct Ranked<'a> {
+    size: u64,
+    file: &'a ScannedFile,
+    sort: FileSort,
+}
+
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked<'_> {}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        file_order(self.sort, (self.file, self.size), (other.file, other.size)).reverse()
+    }
+}
+
+/// the `n` highest-ranked files seen so far, as a min-heap whose root is
+/// the entry to evict next
+struct TopFiles<'a> {
+    heap: BinaryHeap<Reverse<Ranked<'a>>>,
+    n: usize,
+}
+
+impl<'a> TopFiles<'a> {
+    fn new(n: usize) -> Self {
+        TopFiles {
+            heap: BinaryHeap::new(),
+            n,
+        }
+    }
+
+    fn push(&mut self, item: Ranked<'a>) {
+        if self.heap.len() < self.n {
+            self.heap.push(Reverse(item));
+        } else if self.heap.peek().is_some_and(|Reverse(min)| item > *min) {
+            self.heap.pop();
+            self.heap.push(Reverse(item));
+        }
+    }
+
+    fn merge(mut self, other: TopFiles<'a>) -> Self {
+        for Reverse(item) in other.heap {
+            self.push(item);
+        }
+        self
+    }
+}
+
+/// for take_any_while in an analysis stage: it runs dry once `stop` is
+/// cancelled, and what it returns then is thrown away by the check() after
+/// it (analyse)
+fn until<T>(stop: &CancellationToken) -> impl Fn(&T) -> bool + Sync + Send + '_ {
+    move |_| !stop.is_cancelled()
+}
+
+/// files passing the filters -> (entries, matched_total, matched_size)
+/// - files with unreadable metadata never pass
+/// - entries are ordered by opts.sort_by
+/// - with max_results, only the first N in that order are kept (bounded
+///   heap per rayon job, merged); totals still cover every match
+fn filter_files(
+    files: &[ScannedFile],
+    opts: &ScanOptions,
+    names: &NameFilter,
+    stop: &CancellationToken,
+) -> (Vec<FileEntry>, usize, u64) {
+    let sort = opts.sort_by;
+    let matches = files
+        .par_iter()
+        .take_any_while(until(stop))
+        .filter_map(|f| f.size.map(|sz| (f, sz)))
+        .filter(|(f, sz)| passes_filter(f, *sz, opts, names));
+
+    let Some(n) = opts.max_results else {
+        let mut matched: Vec<(&ScannedFile, u64)> = matches.collect();
+        matched.par_sort_unstable_by(|a, b| file_order(sort, *a, *b));
+        let matched_size = matched.iter().map(|(_, sz)| sz).sum();
+        let entries: Vec<FileEntry> = matched.iter().map(|(f, sz)| file_entry(f, *sz)).collect();
+        return (entries, matched.len(), matched_size);
+    };
+
+    let (top, matched_total, matched_size) = matches
+        .fold(
+            || (TopFiles::new(n), 0usize, 0u64),
+            |(mut top, count, total), (file, size)| {
+                top.push(Ranked { size, file, sort });
+                (top, count + 1, total + size)
+            },
+        )
+        .reduce(
+            || (TopFiles::new(n), 0usize, 0u64),
+            |(a, ca, sa), (b, cb, sb)| (a.merge(b), ca + cb, sa + sb),
+        );
+
+    // ascending order of Reverse(rank) is descending rank: listing order
+    let entries = top
+        .heap
+        .into_sorted_vec()
+        .into_iter()
+        .map(|Reverse(r)| file_entry(r.file, r.size))
+        .collect();
+    (entries, matched_total, matched_size)
+}
+
+/// age histogram slot for a file: AGE_BUCKETS index, then "oldest", then "unknown"
+fn age_bucket_index(mtime: Option<SystemTime>, now: SystemTime) -> usize {
+    let Some(t) = mtime else {
+        return AGE_BUCKETS.len() + 1;
+    };
+    // mtimes in the future (clock skew) count as brand new
+    let age = now.duration_since(t).map(|d| d.as_secs()).unwrap_or(0);
+    AGE_BUCKETS
+        .iter()
+        .position(|(limit, _)| age < *limit)
+        .unwrap_or(AGE_BUCKETS.len())
+}
+
+/// count and total size per age bucket, in AGE_BUCKETS order
+fn age_histogram(
+    files: &[ScannedFile],
+    now: SystemTime,
+    stop: &CancellationToken,
+) -> Vec<AgeBucket> {
+    let slots = AGE_BUCKETS.len() + 2;
+    let acc: Vec<(usize, u64)> = files
+        .par_iter()
+        .take_any_while(until(stop))
+        .fold(
+            || vec![(0usize, 0u64); slots],
+            |mut acc, f| {
+                let slot = &mut acc[age_bucket_index(f.mtime, now)];
+                slot.0 += 1;
+                slot.1 += f.size.unwrap_or(0);
+                acc
+            },
+        )
+        .reduce(
+            || vec![(0usize, 0u64); slots],
+            |mut a, b| {
+                for (x, y) in a.iter_mut().zip(b) {
+                    x.0 += y.0;
+                    x.1 += y.1;
+                }
+                a
+            },
+        );
+
+    let labels = AGE_BUCKETS
+        .iter()
+        .map(|(_, label)| *label)
+        .chain([AGE_OLDEST_LABEL, AGE_UNKNOWN_LABEL]);
+    labels
+        .zip(acc)
+        .map(|(label, (count, total_size))| AgeBucket {
+            label: label.to_string(),
+            count,
+            total_size,
+        })
+        .collect()
+}
+
+/// count and total size per SIZE_BUCKET_BOUNDS range
+fn size_histogram(files: &[ScannedFile], stop: &CancellationToken) -> Vec<SizeBucket> {
+    let slots = SIZE_BUCKET_LABELS.len();
+    let acc: Vec<(usize, u64)> = files
+        .par_iter()
+        .take_any_while(until(stop))
+        .filter_map(|f| f.size)
+        .fold(
+            || vec![(0usize, 0u64); slots],
+            |mut acc, size| {
+                let idx = SIZE_BUCKET_BOUNDS
+                    .iter()
+                    .position(|bound| size < *bound)
+                    .unwrap_or(SIZE_BUCKET_BOUNDS.len());
+                acc[idx].0 += 1;
+                acc[idx].1 += size;
+                acc
+            },
+        )
+        .reduce(
+            || vec![(0usize, 0u64); slots],
+            |mut a, b| {
+                for (x, y) in a.iter_mut().zip(b) {
+                    x.0 += y.0;
+                    x.1 += y.1;
+                }
+                a
+            },
+        );
+
+    acc.into_iter()
+        .enumerate()
+        .map(|(i, (count, total_size))| SizeBucket {
+            label: SIZE_BUCKET_LABELS[i].to_string(),
+            min: if i == 0 { 0 } else { SIZE_BUCKET_BOUNDS[i - 1] },
+            max: SIZE_BUCKET_BOUNDS.get(i).copied(),
+            count,
+            total_size,
+        })
+        .collect()
+}
+
+/// count and total size per content category, sorted by size descending
+fn category_summary(
+    files: &[ScannedFile],
+    floor: Option<u64>,
+    stop: &CancellationToken,
+) -> Vec<CategoryStat> {
+    let map: HashMap<Category, (usize, u64)> = files
+        .par_iter()
+        .take_any_while(until(stop))
+        .filter(|f| above_ext_floor(f, floor))
+        .fold(
+            HashMap::new,
+            |mut acc: HashMap<Category, (usize, u64)>, f| {
+                let slot = acc.entry(categorize(&f.path)).or_insert((0, 0));
+                slot.0 += 1;
+                slot.1 += f.size.unwrap_or(0);
+                acc
+            },
+        )
+        .reduce(
+            HashMap::new,
+            |mut a: HashMap<Category, (usize, u64)>, b: HashMap<Category, (usize, u64)>| {
+                for (k, (cnt, size)) in b {
+                    let slot = a.entry(k).or_insert((0, 0));
+                    slot.0 += cnt;
+                    slot.1 += size;
+                }
+                a
+            },
+        );
+
+    let mut summary: Vec<CategoryStat> = map
+        .into_iter()
+        .map(|(category, (count, total_size))| CategoryStat {
+            category,
+            count,
+            total_size,
+        })
+        .collect();
+    // category as tie-break keeps equal-sized entries in a stable order
+    summary.sort_by_key(|c| (std::cmp::Reverse(c.total_size), c.category));
+    summary
+}
+
+/// uid -> login name for the given uids, read from /etc/passwd once
+/// - a missing or unreadable passwd file just leaves every uid unnamed
+fn owner_names(uids: &HashSet<u32>) -> HashMap<u32, String> {
+    let passwd = fs::read_to_string("/etc/passwd").unwrap_or_default();
+    passwd
+        .lines()
+        .filter_map(|line| {
+            // name:password:uid:gid:gecos:home:shell
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let uid = fields.nth(1)?.parse::<u32>().ok()?;
+            uids.contains(&uid).then(|| (uid, name.to_string()))
+        })
+        .collect()
+}
+
+/// total size and file count per owner uid, sorted by size descending
+fn owner_summary(files: &[ScannedFile], stop: &CancellationToken) -> Vec<OwnerUsage> {
+    let map: HashMap<u32, (u64, usize)> = files
+        .par_iter()
+        .take_any_while(until(stop))
+        .filter_map(|f| f.uid.map(|uid| (uid, f.size.unwrap_or(0))))
+        .fold(
+            HashMap::new,
+            |mut acc: HashMap<u32, (u64, usize)>, (uid, size)| {
+                let slot = acc.entry(uid).or_insert((0, 0));
+                slot.0 += size;
+                slot.1 += 1;
+                acc
+            },
+        )
+        .reduce(
+            HashMap::new,
+            |mut a: HashMap<u32, (u64, usize)>, b: HashMap<u32, (u64, usize)>| {
+                for (k, (size, cnt)) in b {
+                    let slot = a.entry(k).or_insert((0, 0));
+                    slot.0 += size;
+                    slot.1 += cnt;
+                }
+                a
+            },
+        );
+
+    let names = owner_names(&map.keys().copied().collect());
+    let mut summary: Vec<OwnerUsage> = map
+        .into_iter()
+        .map(|(uid, (total_size, file_count))| OwnerUsage {
+            uid,
+            name: names.get(&uid).cloned().unwrap_or_else(|| uid.to_string()),
+            total_size,
+            file_count,
+        })
+        .collect();
+    summary.sort_by_key(|o| (std::cmp::Reverse(o.total_size), o.uid));
+    summary
+}
+
+/// (oldest, newest) file by mtime in one parallel reduce; on equal mtimes
+/// the smaller path wins so the result doesn't depend on walk order
+fn oldest_and_newest(
+    files: &[ScannedFile],
+    stop: &CancellationToken,
+) -> (Option<FileEntry>, Option<FileEntry>) {
+    let extremes = files
+        .par_iter()
+        .take_any_while(until(stop))
+        .filter(|f| f.mtime.is_some())
+        .map(|f| (f, f))
+        .reduce_with(|(old_a, new_a), (old_b, new_b)| {
+            let oldest = if (old_b.mtime, &old_b.path) < (old_a.mtime, &old_a.path) {
+                old_b
+            } else {
+                old_a
+            };
+            let key_b = (new_b.mtime, std::cmp::Reverse(&new_b.path));
+            let newest = if key_b > (new_a.mtime, std::cmp::Reverse(&new_a.path)) {
+                new_b
+            } else {
+                new_a
+            };
+            (oldest, newest)
+        });
+
+    let entry = |f: &ScannedFile| file_entry(f, f.size.unwrap_or(0));
+    match extremes {
+        Some((oldest, newest)) => (Some(entry(oldest)), Some(entry(newest))),
+        None => (None, None),
+    }
+}
+
+/// the `n` newest files by mtime, newest first
+fn recent_files(files: &[ScannedFile], n: usize, stop: &CancellationToken) -> Vec<FileEntry> {
+    let sort = FileSort {
+        key: FileSortKey::Mtime,
+        order: SortOrder::Desc,
+    };
+    let dated = files.par_iter().take_any_while(until(stop)).filter(|f| f.mtime.is_some());
+    top_files_by(dated, n, sort)
+}
+
+/// the opts.detail_limit largest files whose extension_key is `ext`
+/// - matched like the extension_count keys, so any listed row can be asked
+///   for; min_size/max_size and the name filters don't apply
+fn extension_detail(
+    files: &[ScannedFile],
+    ext: &str,
+    opts: &ScanOptions,
+    stop: &CancellationToken,
+) -> Vec<FileEntry> {
+    let want = ext.trim();
+    let want = want.strip_prefix('.').unwrap_or(want);
+    let case_insensitive = opts.case_insensitive_extensions;
+    let matches = files.par_iter().take_any_while(until(stop)).filter(|f| {
+        let key = extension_key_with(&f.path, case_insensitive);
+        f.size.is_some() && (key == want || (case_insensitive && key.eq_ignore_ascii_case(want)))
+    });
+    top_files_by(matches, opts.detail_limit, FileSort::default())
+}
+
+/// the first `n` of `files` in `sort` order
+/// - bounded heap per rayon job, merged, as in filter_files
+fn top_files_by<'a>(
+    files: impl ParallelIterator<Item = &'a ScannedFile>,
+    n: usize,
+    sort: FileSort,
+) -> Vec<FileEntry> {
+    let top = files
+        .fold(
+            || TopFiles::new(n),
+            |mut top, file| {
+                top.push(Ranked {
+                    size: file.size.unwrap_or(0),
+                    file,
+                    sort,
+                });
+                top
+            },
+        )
+        .reduce(|| TopFiles::new(n), TopFiles::merge);
+
+    top.heap
+        .into_sorted_vec()
+        .into_iter()
+        .map(|Reverse(r)| file_entry(r.file, r.size))
+        .collect()
+}
+
+/// zero-byte files sorted by path, capped at `limit`; returns (listed, overflow)
+fn empty_files(
+    files: &[ScannedFile],
+    limit: usize,
+    stop: &CancellationToken,
+) -> (Vec<String>, usize) {
+    let mut empty: Vec<String> = files
+        .par_iter()
+        .take_any_while(until(stop))
+        .filter(|f| f.size == Some(0))
+        .map(|f| f.path.to_string_lossy().into_owned())
+        .collect();
+    empty.par_sort_unstable();
+
+    let overflow = truncate_counting(&mut empty, limit);
+    (empty, overflow)
+}
+
+/// max entries kept in FolderStats::broken_symlinks (the rest only counted)
+const BROKEN_SYMLINK_LIMIT: usize = 1000;
+
+/// broken symlink paths -> (sorted and listed, overflow)
+fn broken_symlinks(links: &[PathBuf]) -> (Vec<String>, usize) {
+    let mut listed: Vec<String> = links
+        .iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect();
+    listed.sort();
+    let overflow = truncate_counting(&mut listed, BROKEN_SYMLINK_LIMIT);
+    (listed, overflow)
+}
+
+/// a file is sparse when less than 9/10 of its size is allocated
+/// - small files are left out: filesystems that store them inline or
+///   compressed report few blocks without the file being sparse
+const SPARSE_MIN_SIZE: u64 = 1024 * 1024;
+/// max entries kept in FolderStats::sparse_files (the rest only counted)
+const SPARSE_LIST_LIMIT: usize = 1000;
+
+fn is_sparse(size: u64, allocated: u64) -> bool {
+    size >= SPARSE_MIN_SIZE && (allocated as u128) * 10 < (size as u128) * 9
+}
+
+/// sparse files -> (listed, overflow, unallocated bytes over all of them)
+fn sparse_files(
+    files: &[ScannedFile],
+    limit: usize,
+    stop: &CancellationToken,
+) -> (Vec<FileEntry>, usize, u64) {
+    let mut sparse: Vec<FileEntry> = files
+        .par_iter()
+        .take_any_while(until(stop))
+        .filter_map(|f| match (f.size, f.allocated) {
+            (Some(size), Some(allocated)) if is_sparse(size, allocated) => {
+                Some(file_entry(f, size))
+            }
+            _ => None,
+        })
+        .collect();
+    let unallocated = sparse.iter().map(|f| f.size - f.allocated).sum();
+    sparse.par_sort_unstable_by(|a, b| {
+        (b.size - b.allocated)
+            .cmp(&(a.size - a.allocated))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+
+    let overflow = truncate_counting(&mut sparse, limit);
+    (sparse, overflow, unallocated)
+}
+
+/// preferred I/O block size of the filesystem `path` is on (Unix only)
+#[cfg(unix)]
+fn fs_block_size(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|m| m.blksize()).filter(|&b| b > 0)
+}
+
+#[cfg(not(unix))]
+fn fs_block_size(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// (bytes allocated beyond each file's size, files smaller than `block`)
+/// - sparse files allocate less than their size; they add nothing here
+fn block_slack(files: &[ScannedFile], block: u64, stop: &CancellationToken) -> (u64, usize) {
+    files
+        .par_iter()
+        .take_any_while(until(stop))
+        .filter_map(|f| f.size.zip(f.allocated))
+        .map(|(size, allocated)| (allocated.saturating_sub(size), (size < block) as usize))
+        .reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1))
+}
+
+/// average levels below their root of `files` (0 with none)
+fn mean_file_depth(files: &[ScannedFile], stop: &CancellationToken) -> f64 {
+    if files.is_empty() {
+        return 0.0;
+    }
+    let levels: usize = files
+        .par_iter()
+        .take_any_while(until(stop))
+        .map(|f| f.path.components().count().saturating_sub(f.root_components))
+        .sum();
+    levels as f64 / files.len() as f64
+}
+
+/// max paths kept in FolderStats::metadata_error_paths
+const METADATA_ERROR_SAMPLE: usize = 20;
+
+/// the first `limit` paths, sorted, of files whose size could not be read
+fn unsized_paths(files: &[ScannedFile], limit: usize) -> Vec<String> {
+    let mut paths: Vec<&Path> = files
+        .iter()
+        .filter(|f| f.size.is_none())
+        .map(|f| f.path.as_path())
+        .collect();
+    paths.sort_unstable();
+    paths.truncate(limit);
+    paths.iter().map(|p| p.to_string_lossy().into_owned()).collect()
+}
+
+/// max entries kept in FolderStats::permission_findings (the rest only counted)
+const PERMISSION_LIST_LIMIT: usize = 1000;
+
+/// risky permissions of every file with a known mode -> (listed, overflow)
+/// - the owner check needs the invoker's uid, so it is skipped without one
+fn permission_findings(
+    files: &[ScannedFile],
+    limit: usize,
+    stop: &CancellationToken,
+) -> (Vec<PermissionFinding>, usize) {
+    let me = current_uid();
+    let mut findings: Vec<PermissionFinding> = files
+        .par_iter()
+        .take_any_while(until(stop))
+        .filter_map(|f| f.mode.map(|mode| (f, mode)))
+        .flat_map_iter(|(f, mode)| {
+            let kinds = [
+                (mode & 0o002 != 0, PermissionKind::WorldWritable),
+                (mode & 0o4000 != 0, PermissionKind::Setuid),
+                (mode & 0o2000 != 0, PermissionKind::Setgid),
+                (me.is_some() && f.uid.is_some() && f.uid != me, PermissionKind::ForeignOwner),
+            ];
+            kinds
+                .into_iter()
+                .filter(|(hit, _)| *hit)
+                .map(move |(_, kind)| PermissionFinding {
+                    path: f.path.to_string_lossy().into_owned(),
+                    mode,
+                    kind,
+                })
+        })
+        .collect();
+    findings.par_sort_unstable_by(|a, b| a.path.cmp(&b.path).then(a.kind.cmp(&b.kind)));
+
+    let overflow = truncate_counting(&mut findings, limit);
+    (findings, overflow)
+}
+
+/// keep the first `limit` entries, returning how many were dropped
+fn truncate_counting<T>(list: &mut Vec<T>, limit: usize) -> usize {
+    let overflow = list.len().saturating_sub(limit);
+    list.truncate(limit);
+    overflow
+}
+
+/// attribute each file's size to every ancestor directory below its root,
+/// then keep the `top_n` largest (ties by path)
+fn largest_dirs(
+    files: &[ScannedFile],
+    roots: &[PathBuf],
+    top_n: usize,
+    stop: &CancellationToken,
+) -> Vec<DirEntrySummary> {
+    if top_n == 0 {
+        return Vec::new();
+    }
+
+    let dir_map: HashMap<PathBuf, (u64, usize)> = files
+        .par_iter()
+        .take_any_while(until(stop))
+        .fold(
+            HashMap::new,
+            |mut acc: HashMap<PathBuf, (u64, usize)>, f| {
+                let size = f.size.unwrap_or(0);
+                let mut dir = f.path.parent();
+                while let Some(d) = dir {
+                    // stop at the root itself: it is not its own subdirectory
+                    if roots.iter().any(|r| d == r) || !roots.iter().any(|r| d.starts_with(r)) {
+                        break;
+                    }
+                    let slot = acc.entry(d.to_path_buf()).or_insert((0, 0));
+                    slot.0 += size;
+                    slot.1 += 1;
+                    dir = d.parent();
+                }
+                acc
+            },
+        )
+        .reduce(
+            HashMap::new,
+            |mut a: HashMap<PathBuf, (u64, usize)>, b: HashMap<PathBuf, (u64, usize)>| {
+                for (k, (size, cnt)) in b {
+                    let slot = a.entry(k).or_insert((0, 0));
+                    slot.0 += size;
+                    slot.1 += cnt;
+                }
+                a
+            },
+        );
+
+    let mut dirs: Vec<DirEntrySummary> = dir_map
+        .into_iter()
+        .map(|(p, (size, file_count))| DirEntrySummary {
+            path: p.to_string_lossy().into_owned(),
+            size,
+            file_count,
+        })
+        .collect();
+    dirs.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    dirs.truncate(top_n);
+    dirs
+}
+
+/// top_level_summary's name for the files directly inside the root
+pub const TOP_LEVEL_ROOT_FILES: &str = "(files in root)";
+
+/// size and file count per first path component below each file's root
+fn top_level_summary(files: &[ScannedFile]) -> Vec<(String, u64, usize)> {
+    let mut children: HashMap<String, (u64, usize)> = HashMap::new();
+    for f in files {
+        let mut below = f.path.components().skip(f.root_components);
+        let name = match (below.next(), below.next()) {
+            (Some(child), Some(_)) => child.as_os_str().to_string_lossy().into_owned(),
+            _ => TOP_LEVEL_ROOT_FILES.to_string(),
+        };
+        let slot = children.entry(name).or_insert((0, 0));
+        slot.0 += f.size.unwrap_or(0);
+        slot.1 += 1;
+    }
+
+    let mut summary: Vec<(String, u64, usize)> = children
+        .into_iter()
+        .map(|(name, (size, count))| (name, size, count))
+        .collect();
+    summary.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    summary
+}
+
+/// count the files directly inside each directory, then keep the `top_n`
+/// fullest (ties by path)
+fn dirs_by_file_count(files: &[ScannedFile], top_n: usize) -> Vec<(String, usize)> {
+    if top_n == 0 {
+        return Vec::new();
+    }
+
+    let mut counts: HashMap<&Path, usize> = HashMap::new();
+    for f in files {
+        if let Some(dir) = f.path.parent() {
+            *counts.entry(dir).or_insert(0) += 1;
+        }
+    }
+
+    let mut dirs: Vec<(String, usize)> = counts
+        .into_iter()
+        .map(|(p, n)| (p.to_string_lossy().into_owned(), n))
+        .collect();
+    dirs.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    dirs.truncate(top_n);
+    dirs
+}
+
+/// the folders scan_folder walks: opts.path plus extra_roots
+/// - a single root is walked exactly as given
+/// - with several, each is made absolute, and roots that are the same as or
+///   nested inside another (compared after resolving symlinks) are dropped
+fn scan_roots(opts: &ScanOptions) -> Vec<PathBuf> {
+    if opts.extra_roots.is_empty() {
+        return vec![opts.path.clone()];
+    }
+
+    let mut candidates: Vec<(PathBuf, PathBuf)> = std::iter::once(&opts.path)
+        .chain(opts.extra_roots.iter())
+        .map(|p| {
+            let abs = std::path::absolute(p).unwrap_or_else(|_| p.clone());
+            let real = fs::canonicalize(&abs).unwrap_or_else(|_| abs.clone());
+            (abs, real)
+        })
+        .collect();
+    // shortest first, so a parent is always kept before its children are checked
+    candidates.sort_by_key(|(_, real)| real.components().count());
+
+    let mut kept: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for (abs, real) in candidates {
+        if !kept.iter().any(|(_, k)| real.starts_with(k)) {
+            kept.push((abs, real));
+        }
+    }
+    kept.into_iter().map(|(abs, _)| abs).collect()
+}
+
+/// every root must be a readable directory; anything below it may fail
+/// and is only recorded in the errors
+fn check_roots(roots: &[PathBuf]) -> Result<(), ScanError> {
+    for root in roots {
+        let io_error = |source: io::Error| ScanError::Io {
+            path: root.clone(),
+            source,
+        };
+        match fs::metadata(root) {
+            Ok(m) if !m.is_dir() => {
+                return Err(ScanError::RootNotADirectory { path: root.clone() });
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(ScanError::RootNotFound { path: root.clone() });
+            }
+            Err(e) => return Err(io_error(e)),
+        }
+        fs::read_dir(root).map_err(io_error)?;
+    }
+    Ok(())
+}
+
+/// walk each root in turn into the same sink, merging their counters
+/// - a cancelled walk ends like a truncated one, Ok with `cancelled` set:
+///   whether that is an error is up to the caller
+fn walk_roots(
+    opts: &ScanOptions,
+    roots: &[PathBuf],
+    progress: &mut ProgressReporter,
+    cancel: &CancellationToken,
+    sink: &mut dyn FnMut(ScanItem),
+) -> Result<WalkOutput, ScanError> {
+    let mut walk: Option<WalkOutput> = None;
+    let mut counted = 0usize;
+    for root in roots {
+        // file_limit is shared: each root gets what the earlier ones left
+        let root_opts = ScanOptions {
+            path: root.clone(),
+            file_limit: opts.file_limit.map(|n| n.saturating_sub(counted)),
+            ..opts.clone()
+        };
+        let mut counting = |item: ScanItem| {
+            counted += item.is_ok() as usize;
+            sink(item);
+        };
+        // followed links need inode numbers to detect loops with
+        let parallel = opts.parallel_walk && (cfg!(unix) || !opts.follow_symlinks);
+        let w = if parallel {
+            walk_files_parallel(&root_opts, progress, cancel, &mut counting)?
+        } else {
+            walk_files(&root_opts, progress, cancel, &mut counting)?
+        };
+        let stopped = w.truncated || w.cancelled;
+        match walk.as_mut() {
+            Some(acc) => acc.merge(w),
+            None => walk = Some(w),
+        }
+        if stopped {
+            break;
+        }
+    }
+    Ok(walk.expect("scan_roots returns at least one root"))
+}
+
+/// an inverted range would only ever produce an empty list: say so instead
+fn check_size_range(opts: &ScanOptions) -> Result<(), ScanError> {
+    match opts.max_size {
+        Some(max) if max < opts.min_size => Err(ScanError::InvalidOptions(format!(
+            "max_size ({}) is smaller than min_size ({})",
+            format_bytes(max),
+            format_bytes(opts.min_size)
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// scan_folder: returns FolderStats
+/// - collects the walk's file stream (the one scan_folder_stream filters),
+///   then aggregates
+/// - uses parallel iterators (rayon) over the collected files
+pub fn scan_folder(opts: &ScanOptions) -> Result<FolderStats, ScanError> {
+    scan_folder_with(opts, &ScanControl::default())
+}
+
+/// timings of repeated scans of one folder (bench_scan)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub path: String,
+    /// timed runs (the warm-up run not included)
+    pub iterations: usize,
+    /// a first, untimed run filled the OS caches
+    pub warmup_dropped: bool,
+    /// wall time of each timed run, in order
+    pub runs_ms: Vec<f64>,
+    pub min_ms: f64,
+    pub median_ms: f64,
+    /// total_files / total_size of one run, over the median time
+    pub files_per_sec: f64,
+    pub bytes_per_sec: f64,
+    pub total_files: usize,
+    pub total_size: u64,
+}
+
+/// bench_scan: scan `path` with `opts` `iterations` times through
+/// scan_folder itself, so any change to the real scan path shows up here
+/// - with `warmup`, one extra first run is done and not timed
+/// - iterations of 0 are read as 1
+/// - the scan cache is off (use_cache), or every run after the first would
+///   time cache lookups instead of the walk
+/// - `cancel` stops the run in progress; the bench then fails with Cancelled
+pub fn bench_scan(
+    path: &Path,
+    opts: &ScanOptions,
+    iterations: usize,
+    warmup: bool,
+    cancel: &CancellationToken,
+) -> Result<BenchReport, ScanError> {
+    let mut opts = opts.clone();
+    opts.path = path.to_path_buf();
+    opts.use_cache = false;
+    let control = ScanControl::default().cancel_token(cancel.clone());
+    if warmup {
+        scan_folder_with(&opts, &control)?;
+    }
+
+    let iterations = iterations.max(1);
+    let mut runs_ms = Vec::with_capacity(iterations);
+    let mut last = None;
+    for _ in 0..iterations {
+        let started = Instant::now();
+        let stats = scan_folder_with(&opts, &control)?;
+        runs_ms.push(started.elapsed().as_secs_f64() * 1000.0);
+        last = Some(stats);
+    }
+    let stats = last.expect("at least one run");
+
+    let mut sorted = runs_ms.clone();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    let median_ms = if sorted.len() % 2 == 0 {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    };
+    let secs = (median_ms / 1000.0).max(1e-9);
+
+    Ok(BenchReport {
+        path: path.to_string_lossy().into_owned(),
+        iterations,
+        warmup_dropped: warmup,
+        min_ms: sorted[0],
+        median_ms,
+        runs_ms,
+        files_per_sec: stats.total_files as f64 / secs,
+        bytes_per_sec: stats.total_size as f64 / secs,
+        total_files: stats.total_files,
+        total_size: stats.total_size,
+    })
+}
+
+/// scan_folder with live hooks (progress updates, cancellation)
+/// - the walk picks its pool itself (walk_files); only the analysis runs
+///   inside in_scan_pool, as the thread collecting the walk's listings
+///   would otherwise take one of the pool's own, all of them at
+///   limit_threads(1)
+pub fn scan_folder_with(
+    opts: &ScanOptions,
+    control: &ScanControl,
+) -> Result<FolderStats, ScanError> {
+    let cancel = &control.cancel;
+    // with keep_partial a cancel only ends the walk early: what it found is
+    // still analysed, and returned as FolderStats::partial
+    let check = || if control.keep_partial { Ok(()) } else { cancel.check() };
+    check_size_range(opts)?;
+
+    let names = NameFilter::new(opts)?;
+
+    let started = Instant::now();
+    let roots = scan_roots(opts);
+    check_roots(&roots)?;
+    let mut progress = ProgressReporter::new(control.progress.as_ref());
+    let mut files: Vec<ScannedFile> = Vec::new();
+    let mut totals = ScanTotals::new(opts);
+    // errors are already kept in walk.errors; only files need collecting
+    let walk = walk_roots(opts, &roots, &mut progress, cancel, &mut |item| {
+        if let Ok(f) = item {
+            totals.add(&f);
+            files.push(f);
+        }
+    })?;
+    let walk_time = started.elapsed();
+    progress.send(ScanPhase::Analyzing, &opts.path);
+    check()?;
+
+    let walked = Walked {
+        progress,
+        roots,
+        files,
+        totals,
+        walk,
+        started,
+        walk_time,
+    };
+    in_scan_pool(opts, || analyse(opts, control, &names, walked))
+}
+
+/// what scan_folder_with's walk found, for analyse
+struct Walked<'a> {
+    progress: ProgressReporter<'a>,
+    roots: Vec<PathBuf>,
+    files: Vec<ScannedFile>,
+    totals: ScanTotals,
+    walk: WalkOutput,
+    started: Instant,
+    walk_time: Duration,
+}
+
+/// the FolderStats for `walked`, on whichever pool in_scan_pool picked
+fn analyse(
+    opts: &ScanOptions,
+    control: &ScanControl,
+    names: &NameFilter,
+    walked: Walked,
+) -> Result<FolderStats, ScanError> {
+    let Walked {
+        mut progress,
+        roots,
+        files,
+        totals,
+        walk,
+        started,
+        walk_time,
+    } = walked;
+    let cancel = &control.cancel;
+    let check = || if control.keep_partial { Ok(()) } else { cancel.check() };
+    // the analysis stages only stop early when the scan is given up
+    let never = CancellationToken::default();
+    let stop = if control.keep_partial { &never } else { cancel };
+
+    let total_size = totals.total_size;
+    let total_allocated = totals.total_allocated;
+    let total_files = totals.total_files;
+    let extension_count = totals.extension_count(opts.ext_sort);
+    let extension_largest = totals.extension_largest();
+    let size_quantiles = totals.size_quantiles();
+    let mean_file_depth = mean_file_depth(&files, stop);
+    let category_summary = category_summary(&files, opts.ext_stats_min_size, stop);
+    check()?;
+    let (mut filtered_files, matched_total, matched_size) =
+        filter_files(&files, opts, names, stop);
+    check()?;
+    let hash_errors = if opts.hash_filtered {
+        // hashing reads files again, so it reports progress like the walk: a
+        // read that hangs shows as none
+        progress.send(ScanPhase::Hashing, &opts.path);
+        let hashing = std::sync::Mutex::new(&mut progress);
+        let errors = hash_entries(&mut filtered_files, cancel, &|path| {
+            hashing.lock().unwrap_or_else(|e| e.into_inner()).hashing(path)
+        });
+        progress.send(ScanPhase::Analyzing, &opts.path);
+        errors
+    } else {
+        0
+    };
+    check()?;
+    let largest_dirs = largest_dirs(&files, &roots, opts.top_dirs, stop);
+    let top_level_summary = top_level_summary(&files);
+    let dirs_by_file_count = dirs_by_file_count(&files, opts.top_dirs);
+    check()?;
+    let age_histogram = age_histogram(&files, SystemTime::now(), stop);
+    let size_histogram = size_histogram(&files, stop);
+    let (oldest_file, newest_file) = oldest_and_newest(&files, stop);
+    let recent_files = recent_files(&files, opts.recent_files_limit, stop);
+    let extension_detail = match &opts.detail_extension {
+        Some(ext) => extension_detail(&files, ext, opts, stop),
+        None => Vec::new(),
+    };
+    check()?;
+    let (empty_files, empty_files_overflow) = if opts.find_empty_files {
+        empty_files(&files, opts.empty_files_limit, stop)
+    } else {
+        (Vec::new(), 0)
+    };
+    let (broken_symlinks, broken_symlinks_overflow) = broken_symlinks(&walk.broken_symlinks);
+    let mut virtual_mounts_skipped: Vec<String> = walk
+        .virtual_skipped
+        .iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect();
+    virtual_mounts_skipped.sort();
+    let (sparse_files, sparse_files_overflow, sparse_unallocated) =
+        sparse_files(&files, SPARSE_LIST_LIMIT, stop);
+    let metadata_error_paths = unsized_paths(&files, METADATA_ERROR_SAMPLE);
+    let (slack_bytes, sub_block_files) = match fs_block_size(&opts.path) {
+        Some(block) => {
+            let (slack, small) = block_slack(&files, block, stop);
+            (Some(slack), Some(small))
+        }
+        None => (None, None),
+    };
+    let (permission_findings, permission_findings_overflow) = if opts.check_permissions {
+        permission_findings(&files, PERMISSION_LIST_LIMIT, stop)
+    } else {
+        (Vec::new(), 0)
+    };
+    let owner_summary = if opts.group_by_owner {
+        owner_summary(&files, stop)
+    } else {
+        Vec::new()
+    };
+    let mut empty_dirs = walk.empty_dirs;
+    let empty_dirs_overflow = truncate_counting(&mut empty_dirs, opts.empty_dirs_limit);
+    let total_time = started.elapsed();
+
+    Ok(FolderStats {
+        schema_version: FOLDER_STATS_SCHEMA,
+        total_size,
+        total_allocated,
+        total_files,
+        extension_count,
+        extension_largest,
+        category_summary,
+        filtered_files,
+        matched_total,
+        matched_size,
+        largest_dirs,
+        top_level_summary,
+        dirs_by_file_count,
+        max_depth: opts.max_depth,
+        excluded_dirs: walk.excluded_dirs,
+        excluded_files: walk.excluded_files,
+        skipped_links: walk.skipped_links,
+        symlink_count: walk.symlink_count,
+        symlink_targets_missing: walk.symlink_targets_missing,
+        broken_symlinks,
+        broken_symlinks_overflow,
+        collapsed_duplicates: walk.collapsed_duplicates,
+        skipped_hidden: walk.skipped_hidden,
+        gitignored: walk.gitignored,
+        preset_skipped_dirs: walk.preset_skipped_dirs,
+        virtual_mounts_skipped,
+        truncated: walk.truncated,
+        files_skipped_estimate: walk.files_skipped,
+        partial: cancel.is_cancelled().then_some(PartialScan {
+            reason: StopReason::Cancelled,
+            totals_only: false,
+        }),
+        age_histogram,
+        size_histogram,
+        oldest_file,
+        newest_file,
+        recent_files,
+        extension_detail,
+        empty_files,
+        empty_files_overflow,
+        sparse_files,
+        sparse_files_overflow,
+        sparse_unallocated,
+        slack_bytes,
+        sub_block_files,
+        permission_findings,
+        permission_findings_overflow,
+        empty_dirs,
+        empty_dirs_overflow,
+        owner_summary,
+        error_count: walk.errors.count,
+        errors: walk.errors.into_sorted(),
+        metadata_errors: totals.metadata_errors,
+        vanished_files: walk.vanished_files,
+        metadata_error_paths,
+        hash_errors,
+        max_depth_seen: walk.deepest.as_ref().map_or(0, |(d, _)| *d),
+        deepest_path: walk.deepest.map(|(_, p)| p),
+        mean_file_depth,
+        size_quantiles,
+        scan_meta: ScanMeta {
+            duration_ms: total_time.as_millis() as u64,
+            walk_ms: walk_time.as_millis() as u64,
+            analysis_ms: (total_time - walk_time).as_millis() as u64,
+            dirs_visited: walk.dirs_visited,
+            dirs_from_cache: walk.dirs_from_cache,
+            files_per_sec: total_files as f64 / walk_time.as_secs_f64().max(1e-6),
+            depth_limited: opts.max_depth.is_some(),
+            filtered: !opts.exclude.is_empty()
+                || !opts.include.is_empty()
+                || opts.skip_hidden
+                || opts.respect_gitignore,
+            min_size: opts.min_size,
+            max_size: opts.max_size,
+            name_contains: opts.name_contains.clone().filter(|t| !t.is_empty()),
+            threads: rayon::current_num_threads(),
+            ext_stats_min_size: opts.ext_stats_min_size,
+            ext_stats_below_floor: totals.below_ext_floor,
+            sandboxed: in_flatpak_sandbox(),
+        },
+    })
+}
+
+/// files scan_folder_stream hashes at once (hash_filtered): enough to keep
+/// the pool busy, few enough that records still go out as the walk goes
+const STREAM_HASH_BATCH: usize = 64;
+
+/// hash the file records of `pending` on the rayon pool (hash_entries),
+/// then emit all of them in order -> how many files could not be hashed
+fn emit_hashed(
+    pending: &mut Vec<StreamRecord>,
+    cancel: &CancellationToken,
+    emit: &mut dyn FnMut(StreamRecord),
+) -> usize {
+    let errors = pending
+        .par_iter_mut()
+        .map(|record| match record {
+            StreamRecord::File(e) => match hash_file(&e.path, cancel) {
+                Ok(h) => {
+                    e.hash = Some(h);
+                    0
+                }
+                Err(_) => 1,
+            },
+            _ => 0,
+        })
+        .sum();
+    for record in pending.drain(..) {
+        emit(record);
+    }
+    errors
+}
+
+/// scan_folder_stream: emits each file passing the filters and each read
+/// error as soon as the walk meets them, then returns the totals
+/// - same filters as filtered_files, but in walk order: sort_by and
+///   max_results don't apply
+/// - nothing is kept per file, so memory stays flat on huge trees
+pub fn scan_folder_stream(
+    opts: &ScanOptions,
+    control: &ScanControl,
+    emit: &mut dyn FnMut(StreamRecord),
+) -> Result<StreamSummary, ScanError> {
+    check_size_range(opts)?;
+    let names = NameFilter::new(opts)?;
+
+    let started = Instant::now();
+    let roots = scan_roots(opts);
+    check_roots(&roots)?;
+    let mut progress = ProgressReporter::new(control.progress.as_ref());
+    let mut totals = ScanTotals::new(opts);
+    let (mut matched_total, mut matched_size) = (0usize, 0u64);
+    let mut hash_errors = 0usize;
+    // with hash_filtered, records wait in `pending` until STREAM_HASH_BATCH
+    // files can be hashed together on the rayon pool
+    let mut pending: Vec<StreamRecord> = Vec::new();
+    let walk = walk_roots(opts, &roots, &mut progress, &control.cancel, &mut |item| {
+        let record = match item {
+            Ok(f) => {
+                totals.add(&f);
+                let Some(size) = f.size.filter(|size| passes_filter(&f, *size, opts, &names))
+                else {
+                    return;
+                };
+                matched_total += 1;
+                matched_size += size;
+                StreamRecord::File(file_entry(&f, size))
+            }
+            Err(e) => StreamRecord::Error(e),
+        };
+        if !opts.hash_filtered {
+            emit(record);
+            return;
+        }
+        pending.push(record);
+        if pending.len() >= STREAM_HASH_BATCH {
+            hash_errors += emit_hashed(&mut pending, &control.cancel, emit);
+        }
+    })?;
+    hash_errors += emit_hashed(&mut pending, &control.cancel, emit);
+    control.cancel.check()?;
+
+    Ok(StreamSummary {
+        total_size: totals.total_size,
+        total_allocated: totals.total_allocated,
+        total_files: totals.total_files,
+        matched_total,
+        matched_size,
+        error_count: walk.errors.count,
+        metadata_errors: totals.metadata_errors,
+        vanished_files: walk.vanished_files,
+        hash_errors,
+        extension_count: totals.extension_count(opts.ext_sort),
+        duration_ms: started.elapsed().as_millis() as u64,
+        truncated: walk.truncated,
+    })
+}
+
+/// intermediate tree used while attributing files, children keyed by name
+#[derive(Default)]
+struct TreeBuilder {
+    size: u64,
+    file_count: usize,
+    children: HashMap<String, TreeBuilder>,
+}
+
+impl TreeBuilder {
+    fn insert(&mut self, dirs: &[String], size: u64) {
+        self.size += size;
+        self.file_count += 1;
+        if let Some((first, rest)) = dirs.split_first() {
+            self.children
+                .entry(first.clone())
+                .or_default()
+                .insert(rest, size);
+        }
+    }
+
+    fn into_node(self, name: String) -> DirNode {
+        let mut children: Vec<DirNode> = self
+            .children
+            .into_iter()
+            .map(|(n, b)| b.into_node(n))
+            .collect();
+        children.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+
+        DirNode {
+            name,
+            size: self.size,
+            file_count: self.file_count,
+            children,
+        }
+    }
+}
+
+/// scan_folder_tree: per-directory size tree rooted at opts.path
+/// - everything below opts.tree_depth is aggregated into its ancestor
+/// - only directories that (recursively) contain files appear
+/// - `control` gives progress and cancellation; there is no partial tree,
+///   so a cancelled scan is Err(Cancelled) even with keep_partial
+pub fn scan_folder_tree(opts: &ScanOptions, control: &ScanControl) -> Result<DirNode, ScanError> {
+    check_roots(std::slice::from_ref(&opts.path))?;
+    let mut progress = ProgressReporter::new(control.progress.as_ref());
+    let mut files: Vec<ScannedFile> = Vec::new();
+    walk_files(opts, &mut progress, &control.cancel, &mut |item| {
+        if let Ok(f) = item {
+            files.push(f);
+        }
+    })?;
+    control.cancel.check()?;
+
+    let mut root = TreeBuilder::default();
+    for f in &files {
+        let dirs: Vec<String> = f
+            .path
+            .parent()
+            .and_then(|p| p.strip_prefix(&opts.path).ok())
+            .map(|rel| {
+                rel.components()
+                    .take(opts.tree_depth)
+                    .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        root.insert(&dirs, f.size.unwrap_or(0));
+    }
+
+    Ok(root.into_node(opts.path.to_string_lossy().into_owned()))
+}
+
+/// render_tree: `node` as indented text, one directory per line
+/// - children biggest first (as scan_folder_tree orders them), `max_depth`
+///   levels below the root
+/// - the children of one directory under `min_size` share one
+///   "… n smaller items (X)" line
+/// - a directory's size includes its own files, so its children may add up
+///   to less
+pub fn render_tree(node: &DirNode, max_depth: usize, min_size: u64) -> String {
+    let mut out = format!("{} ({})\n", node.name, tree_label(node.size, node.file_count));
+    render_children(&mut out, node, "", max_depth, min_size);
+    out
+}
+
+fn render_children(out: &mut String, node: &DirNode, prefix: &str, depth: usize, min: u64) {
+    if depth == 0 {
+        return;
+    }
+    let mut shown: Vec<&DirNode> = node.children.iter().filter(|c| c.size >= min).collect();
+    shown.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+    let small: Vec<&DirNode> = node.children.iter().filter(|c| c.size < min).collect();
+
+    for (i, child) in shown.iter().enumerate() {
+        let last = i + 1 == shown.len() && small.is_empty();
+        let (branch, indent) = if last { ("└── ", "    ") } else { ("├── ", "│   ") };
+        out.push_str(&format!(
+            "{}{}{} ({})\n",
+            prefix,
+            branch,
+            child.name,
+            tree_label(child.size, child.file_count)
+        ));
+        render_children(out, child, &format!("{}{}", prefix, indent), depth - 1, min);
+    }
+    if !small.is_empty() {
+        let size: u64 = small.iter().map(|c| c.size).sum();
+        let noun = if small.len() == 1 { "item" } else { "items" };
+        out.push_str(&format!(
+            "{}└── … {} smaller {} ({})\n",
+            prefix,
+            small.len(),
+            noun,
+            format_bytes(size)
+        ));
+    }
+}
+
+/// "12.00 MB, 3 files"
+fn tree_label(size: u64, files: usize) -> String {
+    let noun = if files == 1 { "file" } else { "files" };
+    format!("{}, {} {}", format_bytes(size), files, noun)
+}
+
+/// old positional form, kept as a thin wrapper over scan_folder
+#[allow(dead_code)]
+pub fn scan_folder_simple(path: &Path, min_size_bytes: u64) -> Result<FolderStats, ScanError> {
+    scan_folder(&ScanOptions::new(path).min_size(min_size_bytes))
+}
+
+/// helper format human readable, in the default unit style
+pub fn format_bytes(bytes: u64) -> String {
+    format_bytes_with(bytes, UnitStyle::default())
+}
+
+/// helper format human readable
+/// - Binary: powers of 1024 labelled KB/MB/GB, as the parser reads them
+/// - Decimal: powers of 1000
+pub fn format_bytes_with(bytes: u64, style: UnitStyle) -> String {
+    let opts = match style {
+        UnitStyle::Binary => options::CONVENTIONAL,
+        UnitStyle::Decimal => options::DECIMAL,
+    };
+    bytes
+        .file_size(opts)
+        .unwrap_or_else(|_| format!("{} B", bytes))
+}
+
+/// helper format relative time: "just now", "5 minutes ago", "2 days ago"
+/// - times after `now` (clock skew) read as "in the future"
+pub fn format_age(t: SystemTime, now: SystemTime) -> String {
+    let secs = match now.duration_since(t) {
+        Ok(d) => d.as_secs(),
+        Err(_) => return "in the future".to_string(),
+    };
+
+    let (n, unit) = match secs {
+        _ if secs < 60 => return "just now".to_string(),
+        _ if secs < 3_600 => (secs / 60, "minute"),
+        _ if secs < DAY_SECS => (secs / 3_600, "hour"),
+        _ if secs < 7 * DAY_SECS => (secs / DAY_SECS, "day"),
+        _ if secs < 30 * DAY_SECS => (secs / (7 * DAY_SECS), "week"),
+        _ if secs < 365 * DAY_SECS => (secs / (30 * DAY_SECS), "month"),
+        _ => (secs / (365 * DAY_SECS), "year"),
+    };
+    format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    fn glob(pattern: &str, rel_path: &str) -> bool {
+        GlobPattern::new(pattern).unwrap().matches(rel_path, false)
+    }
+
+    #[test]
+    fn glob_matches_names_and_paths() {
+        assert!(glob("*.iso", "images/debian.iso"));
+        assert!(!glob("*.iso", "images/debian.iso.part"));
+        assert!(glob("file?.txt", "a/file1.txt"));
+        assert!(!glob("file?.txt", "a/file10.txt"));
+        assert!(glob("[a-c]x", "bx"));
+        assert!(!glob("[!a-c]x", "bx"));
+        assert!(glob("build/*.o", "src/build/main.o"));
+        assert!(!glob("build/*.o", "build/sub/main.o"));
+        assert!(glob("/build/*.o", "build/main.o"));
+        assert!(!glob("/build/*.o", "src/build/main.o"));
+        assert!(glob("a/**/z", "a/z"));
+        assert!(glob("a/**/z", "a/b/c/z"));
+        assert!(!glob("a/**/z", "ab/z"));
+        assert!(glob("a/**z", "a/b/cz"));
+        assert!(glob("\\*", "*"));
+        assert!(!glob("\\*", "x"));
+    }
+
+    #[test]
+    fn glob_dir_only_and_subtree() {
+        let dir = GlobPattern::new("cache/").unwrap();
+        assert!(dir.matches("x/cache", true));
+        assert!(!dir.matches("x/cache", false));
+        let tree = GlobPattern::new("/node_modules/**").unwrap();
+        assert!(tree.matches("node_modules", true));
+    }
+
+    #[test]
+    fn glob_rejects_bad_patterns() {
+        assert!(GlobPattern::new("").is_err());
+        assert!(GlobPattern::new("[abc").is_err());
+    }
+
+    fn unix(t: SystemTime) -> (u64, u32) {
+        let d = t.duration_since(UNIX_EPOCH).unwrap();
+        (d.as_secs(), d.subsec_nanos())
+    }
+
+    #[test]
+    fn rfc3339_parses_dates_zones_and_fractions() {
+        let parse = |s| unix(parse_rfc3339(s).unwrap());
+        assert_eq!(parse("2023-05-01"), (1_682_899_200, 0));
+        assert_eq!(parse("2023-05-01T12:00:00Z"), (1_682_942_400, 0));
+        assert_eq!(parse("2023-05-01t12:00:00z"), (1_682_942_400, 0));
+        assert_eq!(parse("2023-05-01T12:00:00+07:00"), (1_682_917_200, 0));
+        assert_eq!(parse("2023-05-01T12:00:00-01:30"), (1_682_947_800, 0));
+        assert_eq!(parse("2023-05-01T12:00:00.5Z"), (1_682_942_400, 500_000_000));
+        assert_eq!(parse("2023-05-01T12:00:00.123456789123Z"), (1_682_942_400, 123_456_789));
+        assert_eq!(parse("2016-12-31T23:59:60Z"), parse("2016-12-31T23:59:59Z"));
+    }
+
+    #[test]
+    fn rfc3339_rejects_malformed_input() {
+        for bad in [
+            "2023-5-01",
+            "2023-02-29",
+            "2023-05-01T12:00Z",
+            "2023-05-01T24:00:00Z",
+            "2023-05-01T12:00:00",
+            "2023-05-01T12:00:00+7:00",
+            "2023-05-01T12:00:00.Z",
+            "2023-05-01T12:00:00.12a4Z",
+            "2023-05-01T12:00:00.1234567891x3Z",
+        ] {
+            assert!(parse_rfc3339(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn extension_keys_keep_their_buckets_apart() {
+        let key = |name: &str| extension_key(Path::new(name));
+        assert_eq!(key("README"), EXT_NONE_LABEL);
+        assert_eq!(key("notes."), EXT_NONE_LABEL);
+        assert_eq!(key("dir/notes.."), EXT_NONE_LABEL);
+        assert_eq!(key(".gitignore"), EXT_DOTFILE_LABEL);
+        assert_eq!(key(".bashrc."), EXT_DOTFILE_LABEL);
+        assert_eq!(key("archive.tar.gz"), "tar.gz");
+        assert_eq!(key("IMG.JPG"), "jpg");
+        assert_eq!(extension_key_with(Path::new("IMG.JPG"), false), "JPG");
+        assert_eq!(key(".config.toml"), "toml");
+    }
+
+    #[test]
+    fn thread_limit_caps_the_gentle_pool() {
+        assert_eq!(gentle_threads(None), GENTLE_THREADS);
+        assert_eq!(gentle_threads(Some(1)), 1);
+        assert_eq!(gentle_threads(Some(64)), GENTLE_THREADS);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn options_keep_non_utf8_roots() {
+        use std::os::unix::ffi::OsStrExt;
+        let odd = PathBuf::from(std::ffi::OsStr::from_bytes(b"/tmp/caf\xe9"));
+        let opts = ScanOptions::new(&odd).extra_root("/srv").extra_root(&odd);
+        let json = serde_json::to_string(&opts).unwrap();
+        assert!(json.contains(r#""extra_roots":["/srv",{"bytes":[47,"#), "{}", json);
+        let back: ScanOptions = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.path, odd);
+        assert_eq!(back.extra_roots, [PathBuf::from("/srv"), odd]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_extension_is_unknown() {
+        use std::os::unix::ffi::OsStrExt;
+        let name = std::ffi::OsStr::from_bytes(b"photo.\xff\xfe");
+        assert_eq!(extension_key(Path::new(name)), EXT_UNKNOWN_LABEL);
+    }
+
+    fn cancelled() -> ScanControl {
+        let cancel = CancellationToken::default();
+        cancel.cancel();
+        ScanControl::default().cancel_token(cancel)
+    }
+
+    #[test]
+    fn tree_scan_honours_the_cancel_token() {
+        let dir = TempDir::new("tree-cancel");
+        dir.file("a/one.bin", &[0; 10]);
+        dir.file("a/b/two.bin", &[0; 20]);
+        let opts = ScanOptions::new(dir.path());
+
+        let tree = scan_folder_tree(&opts, &ScanControl::default()).unwrap();
+        assert_eq!((tree.size, tree.file_count), (30, 2));
+        let result = scan_folder_tree(&opts, &cancelled());
+        assert!(matches!(result, Err(ScanError::Cancelled)));
+        let result = scan_folder_tree(&opts, &cancelled().keep_partial());
+        assert!(matches!(result, Err(ScanError::Cancelled)));
+    }
+
+    #[test]
+    fn analysis_stages_stop_once_cancelled() {
+        let stop = CancellationToken::default();
+        let files: Vec<u32> = (0..100_000).collect();
+        assert_eq!(files.par_iter().take_any_while(until(&stop)).count(), files.len());
+        stop.cancel();
+        assert_eq!(files.par_iter().take_any_while(until(&stop)).count(), 0);
+    }
+
+    #[test]
+    fn diff_reports_added_removed_and_resized_files() {
+        let dir = TempDir::new("diff");
+        dir.file("keep.txt", b"same");
+        dir.file("grow.txt", b"ab");
+        dir.file("gone.log", b"xyz");
+        dir.file("Case.txt", b"c");
+        dir.file("move.bin", b"12345");
+        let opts = ScanOptions::new(dir.path()).use_cache(false);
+        let before = scan_folder(&opts).unwrap();
+
+        dir.file("grow.txt", b"abcdef");
+        fs::remove_file(dir.path().join("gone.log")).unwrap();
+        fs::rename(dir.path().join("Case.txt"), dir.path().join("case.txt")).unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::rename(dir.path().join("move.bin"), dir.path().join("sub/move.bin")).unwrap();
+        let after = scan_folder(&opts).unwrap();
+
+        let diff = before.diff(&after);
+        let names = |files: &[FileEntry]| -> Vec<String> {
+            let rel = |f: &FileEntry| f.path.strip_prefix(dir.path()).unwrap().to_owned();
+            files.iter().map(|f| rel(f).to_string_lossy().replace('\\', "/")).collect()
+        };
+        // case-sensitive paths, and a move is remove + add
+        assert_eq!(names(&diff.added), ["case.txt", "sub/move.bin"]);
+        assert_eq!(names(&diff.removed), ["Case.txt", "gone.log", "move.bin"]);
+        assert_eq!(diff.resized.len(), 1);
+        assert!(diff.resized[0].path.ends_with("grow.txt"));
+        assert_eq!((diff.resized[0].old_size, diff.resized[0].new_size), (2, 6));
+        assert_eq!((diff.total_files_delta, diff.total_size_delta), (-1, 1));
+
+        let ext: Vec<(&str, i64, i64)> = diff
+            .extensions
+            .iter()
+            .map(|e| (e.extension.as_str(), e.count_delta, e.size_delta))
+            .collect();
+        assert_eq!(ext, [("txt", 0, 4), ("log", -1, -3)]);
+        assert!(before.diff(&before).resized.is_empty());
+    }
+
+    #[test]
+    fn serial_and_parallel_walks_count_the_same_files() {
+        let dir = TempDir::new("walks");
+        dir.file("a.txt", b"1");
+        dir.file("b.log", b"22");
+        dir.file("sub/c.txt", b"333");
+        dir.file("sub/deep/d.txt", b"4444");
+        dir.file("sub/deep/e.bin", b"55555");
+        dir.file("skip/f.txt", b"666666");
+        let walk = |opts: &ScanOptions| {
+            let mut files = Vec::new();
+            let mut progress = ProgressReporter::new(None);
+            let cancel = CancellationToken::default();
+            let mut sink = |item: ScanItem| files.extend(item.ok().map(|f| (f.path, f.size)));
+            let out = if opts.parallel_walk {
+                walk_files_parallel(opts, &mut progress, &cancel, &mut sink)
+            } else {
+                walk_files(opts, &mut progress, &cancel, &mut sink)
+            };
+            let out = out.unwrap();
+            files.sort();
+            (files, out.excluded_dirs, out.vanished_files)
+        };
+        let base = ScanOptions::new(dir.path()).use_cache(false).exclude("skip/");
+        for opts in [
+            base.clone(),
+            base.clone().include("**/*.txt"),
+            base.clone().only_extensions(Some(vec!["bin".to_string()])),
+        ] {
+            let serial = walk(&opts.clone().parallel_walk(false));
+            assert_eq!(serial, walk(&opts), "{:?}", opts.include);
+            assert!(!serial.0.is_empty());
+            assert_eq!(serial.1, 1);
+        }
+    }
+
+    #[test]
+    fn bench_times_every_run_and_stops_when_cancelled() {
+        let dir = TempDir::new("bench");
+        dir.file("a.bin", &[0; 100]);
+        dir.file("sub/b.bin", &[0; 50]);
+        let opts = ScanOptions::new("/elsewhere");
+        let never = CancellationToken::default();
+        let report = bench_scan(dir.path(), &opts, 3, true, &never).unwrap();
+        assert_eq!((report.iterations, report.runs_ms.len()), (3, 3));
+        assert_eq!((report.total_files, report.total_size), (2, 150));
+        assert!(report.min_ms <= report.median_ms);
+
+        let stop = CancellationToken::default();
+        stop.cancel();
+        let result = bench_scan(dir.path(), &opts, 3, false, &stop);
+        assert!(matches!(result, Err(ScanError::Cancelled)));
+    }
+
+    #[test]
+    fn hashing_reports_progress_between_analysis_stages() {
+        let dir = TempDir::new("hash-progress");
+        dir.file("a.txt", b"a");
+        dir.file("sub/b.txt", b"b");
+        let (tx, rx) = std::sync::mpsc::channel();
+        let opts = ScanOptions::new(dir.path()).use_cache(false).hash_filtered(true);
+        let stats = scan_folder_with(&opts, &ScanControl::default().progress(tx)).unwrap();
+        assert!(stats.filtered_files.iter().all(|f| f.hash.is_some()));
+
+        let phases: Vec<ScanPhase> = rx.try_iter().map(|p| p.phase).collect();
+        let hashing = phases.iter().position(|p| *p == ScanPhase::Hashing).expect("hashing");
+        assert_eq!(phases[hashing - 1], ScanPhase::Analyzing);
+        assert_eq!(phases.last(), Some(&ScanPhase::Analyzing));
+    }
+
+    #[test]
+    fn glob_backtracking_stays_polynomial() {
+        let name = "a".repeat(200);
+        let start = Instant::now();
+        assert!(!glob("*a*a*a*a*a*a*a*a*b", &name));
+        assert!(!glob("**a**a**a**a**a**b", &name));
+        assert!(glob("*a*a*a*a*a*a*a*a", &name));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn saved_results_load_unless_too_new() {
+        let dir = TempDir::new("schemas");
+        let old = dir.file("v0.json", include_bytes!("../tests/fixtures/schema_v0.json"));
+        let stats = FolderStats::load_from(&old).unwrap();
+        assert_eq!((stats.schema_version, stats.total_files), (0, 2));
+
+        let new = dir.file("v4.json", include_bytes!("../tests/fixtures/schema_too_new.json"));
+        let err = FolderStats::load_from(&new).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("schema 4"), "{}", err);
+    }
+
+    /// every count and size of `partial` bounds `full`'s from below, as
+    /// PartialScan promises
+    fn assert_lower_bounds(partial: &FolderStats, full: &FolderStats) {
+        assert!(partial.total_files <= full.total_files);
+        assert!(partial.total_size <= full.total_size);
+        assert!(partial.total_allocated <= full.total_allocated);
+        assert!(partial.matched_total <= full.matched_total);
+        assert!(partial.matched_size <= full.matched_size);
+        assert!(partial.error_count <= full.error_count);
+        for ext in &partial.extension_count {
+            let all = full.extension_count.iter().find(|e| e.extension == ext.extension);
+            let all = all.unwrap_or_else(|| panic!("no {} in the full scan", ext.extension));
+            assert!(ext.count <= all.count, "{}", ext.extension);
+            assert!(ext.total_size <= all.total_size, "{}", ext.extension);
+        }
+        for cat in &partial.category_summary {
+            let all = full.category_summary.iter().find(|c| c.category == cat.category).unwrap();
+            assert!(cat.count <= all.count && cat.total_size <= all.total_size);
+        }
+        for (name, size, files) in &partial.top_level_summary {
+            let all = full.top_level_summary.iter().find(|t| &t.0 == name).unwrap();
+            assert!(*size <= all.1 && *files <= all.2, "{}", name);
+        }
+        for (bucket, all) in partial.size_histogram.iter().zip(&full.size_histogram) {
+            assert!(bucket.count <= all.count && bucket.total_size <= all.total_size);
+        }
+        // ranked lists: the top of what was seen, all of it really there
+        assert!(partial.filtered_files.iter().all(|f| f.path.is_file()));
+        assert!(partial.empty_dirs.is_empty());
+    }
+
+    #[test]
+    fn kept_partial_results_are_lower_bounds() {
+        let dir = TempDir::new("partial");
+        // past PROGRESS_EVERY_FILES, so the first progress comes mid-walk
+        for i in 0..6000 {
+            let ext = ["txt", "jpg", "rs"][i % 3];
+            dir.file(&format!("d{}/f{}.{}", i % 50, i, ext), &vec![1; i % 700]);
+        }
+        fs::create_dir(dir.path().join("empty")).unwrap();
+        let opts = ScanOptions::new(dir.path())
+            .use_cache(false)
+            .min_size(100)
+            .find_empty_dirs(true);
+        let full = scan_folder_with(&opts, &ScanControl::default()).unwrap();
+        assert_eq!((full.partial, full.total_files, full.empty_dirs.len()), (None, 6000, 1));
+
+        // cancelled up front: nothing read, still an answer
+        let none = scan_folder_with(&opts, &cancelled().keep_partial()).unwrap();
+        let stopped = none.partial.map(|p| (p.reason, p.totals_only));
+        assert_eq!(stopped, Some((StopReason::Cancelled, false)));
+        assert_lower_bounds(&none, &full);
+
+        let cancel = CancellationToken::default();
+        let (tx, progress) = std::sync::mpsc::channel();
+        let control =
+            ScanControl::default().cancel_token(cancel.clone()).progress(tx).keep_partial();
+        let stopper = std::thread::spawn(move || {
+            if progress.recv().is_ok() {
+                cancel.cancel();
+            }
+        });
+        let partial = scan_folder_with(&opts, &control).unwrap();
+        drop(control);
+        stopper.join().unwrap();
+        assert_eq!(partial.partial.map(|p| p.totals_only), Some(false));
+        assert_lower_bounds(&partial, &full);
+    }
+
+    #[test]
+    fn partial_totals_claim_only_the_totals() {
+        let stats = FolderStats::partial_totals(12, 3400, StopReason::TimedOut);
+        let partial = PartialScan {
+            reason: StopReason::TimedOut,
+            totals_only: true,
+        };
+        assert_eq!(stats.partial, Some(partial));
+        assert_eq!((stats.total_files, stats.total_size, stats.total_allocated), (12, 3400, 3400));
+        assert_eq!(stats.schema_version, FOLDER_STATS_SCHEMA);
+        assert!(stats.extension_count.is_empty() && stats.filtered_files.is_empty());
+        assert_eq!((stats.sparse_unallocated, stats.slack_bytes), (0, None));
+
+        let text = serde_json::to_string(&stats).unwrap();
+        let back: FolderStats = serde_json::from_str(&text).unwrap();
+        assert_eq!(back.partial, Some(partial));
+        // schema 2 wrote no totals_only: those were all full partial results
+        let old: PartialScan = serde_json::from_str(r#"{"reason":"timed_out"}"#).unwrap();
+        assert!(!old.totals_only);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scan_cache_never_shares_a_lossy_name() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = TempDir::new("cache-names");
+        let name = std::ffi::OsStr::from_bytes(b"caf\xe9");
+        let path = dir.path().join(name);
+        fs::write(&path, b"four").unwrap();
+        // what a lossy key would have held for caf\xe8 as well
+        let mut stale = FileMeta::from_metadata(&fs::metadata(&path).unwrap());
+        stale.size = 999;
+        let mut old = CachedDir::default();
+        old.files.insert(name.to_string_lossy().into_owned(), stale);
+
+        let (mut new, mut dirty) = (CachedDir::default(), false);
+        let cache = DirCache {
+            old: Some(&old),
+            new: &mut new,
+            dirty: &mut dirty,
+        };
+        let opts = ScanOptions::new(dir.path()).use_cache(true);
+        let roots = dir.path().components().count();
+        match visit_file(&opts, &[], roots, path, name, Some(cache), |p| fs::metadata(p)) {
+            FileVisit::Counted(counted) => assert_eq!(counted.file.size, Some(4)),
+            _ => panic!("caf\\xe9 wasn't counted"),
+        }
+        assert!(dirty && new.files.is_empty());
+    }
+
+    /// root-relative paths of the files both walks keep, checked to agree
+    fn walked_files(opts: &ScanOptions) -> Vec<String> {
+        let walk = |opts: &ScanOptions| {
+            let mut files = Vec::new();
+            let mut progress = ProgressReporter::new(None);
+            let cancel = CancellationToken::default();
+            let mut sink = |item: ScanItem| {
+                files.extend(item.ok().map(|f| relative_path_string(&f.path, &opts.path)))
+            };
+            if opts.parallel_walk {
+                walk_files_parallel(opts, &mut progress, &cancel, &mut sink).unwrap();
+            } else {
+                walk_files(opts, &mut progress, &cancel, &mut sink).unwrap();
+            }
+            files.sort();
+            files
+        };
+        let serial = walk(&opts.clone().parallel_walk(false));
+        assert_eq!(serial, walk(&opts.clone().parallel_walk(true)));
+        serial
+    }
+
+    /// a work tree whose rules cover every gitignore form the walk handles
+    fn ignore_fixture() -> TempDir {
+        let repo = TempDir::new("gitignore");
+        repo.file(".git/info/exclude", b"*.secret\n");
+        repo.file(
+            ".gitignore",
+            b"# comment\n*.log\n!keep.log\n/build\ndocs/*.tmp\ncache/\n\\#hash\n\\!bang\n\
+              space\\ \ntrailing   \r\n",
+        );
+        repo.file("src/.gitignore", b"gen/\n!a.log\n");
+        for file in [
+            "a.log", "keep.log", "build/x.o", "src/build/y.o", "docs/n.tmp", "src/docs/n.tmp",
+            "cache/c", "src/cache", "#hash", "!bang", "#other", "space ", "space", "trailing",
+            "k.secret", "src/a.log", "src/b.log", "src/gen/z", "src/k.secret", "src/main.rs",
+        ] {
+            repo.file(file, b"x");
+        }
+        repo
+    }
+
+    #[test]
+    fn gitignore_rules_prune_like_git() {
+        let repo = ignore_fixture();
+        let opts = ScanOptions::new(repo.path()).respect_gitignore(true);
+        let kept = [
+            "#other",
+            ".gitignore",
+            "keep.log",
+            "space",
+            "src/.gitignore",
+            "src/a.log",
+            "src/build/y.o",
+            "src/cache",
+            "src/docs/n.tmp",
+            "src/main.rs",
+        ];
+        assert_eq!(walked_files(&opts), kept);
+        // without the option every file is there, .git included
+        let all = walked_files(&ScanOptions::new(repo.path()));
+        assert_eq!(all.len(), 23, "{:?}", all);
+    }
+
+    #[test]
+    fn gitignore_of_the_directories_above_the_root_counts() {
+        let repo = ignore_fixture();
+        let opts = ScanOptions::new(repo.path().join("src")).respect_gitignore(true);
+        let kept = [".gitignore", "a.log", "build/y.o", "cache", "docs/n.tmp", "main.rs"];
+        assert_eq!(walked_files(&opts), kept);
+
+        // outside a work tree a parent's .gitignore isn't git's business
+        let plain = TempDir::new("gitignore-plain");
+        plain.file(".gitignore", b"*.txt\n");
+        plain.file("inner/a.txt", b"x");
+        let opts = ScanOptions::new(plain.path().join("inner")).respect_gitignore(true);
+        assert_eq!(walked_files(&opts), ["a.txt"]);
+    }
+
+    #[test]
+    fn gitignore_lines_unescape_like_git() {
+        let rules = parse_ignore_rules("\\#a\n\\!b\nc\\ \nd  \ne\\\\ \n# f\n!g\n");
+        let hits = |name: &str| rules.iter().filter(|r| r.glob.matches(name, false)).count();
+        for name in ["#a", "!b", "c ", "d", "e\\", "g"] {
+            assert_eq!(hits(name), 1, "{:?}", name);
+        }
+        for name in ["c", "d ", "# f", "e\\ "] {
+            assert_eq!(hits(name), 0, "{:?}", name);
+        }
+        assert!(rules.last().unwrap().negated);
+        assert_eq!(rules.iter().filter(|r| r.negated).count(), 1);
+    }
+
+    #[test]
+    fn size_input_accepts_and_rejects() {
+        let table: &[(&str, Option<u64>)] = &[
+            ("1TB", Some(TIB)),
+            ("750GB", Some(750 * GIB)),
+            ("2 tb", Some(2 * TIB)),
+            ("512 B", Some(512)),
+            ("750", Some(750 * MIB)),
+            ("1.5 GB", Some(3 * GIB / 2)),
+            ("300mb", Some(300 * MIB)),
+            ("  4 kib ", Some(4 * KIB)),
+            ("0", Some(0)),
+            ("1e3", Some(1000 * MIB)),
+            ("1e3 MB", Some(1000 * MIB)),
+            ("2.5E-1 KiB", Some(256)),
+            ("-1 MB", None),
+            ("1 XB", None),
+            ("1 EB", None),
+            ("1e MB", None),
+            ("", None),
+            ("MB", None),
+            ("1.2.3 MB", None),
+            ("1 M B", None),
+            ("inf", None),
+        ];
+        for &(text, bytes) in table {
+            assert_eq!(parse_human_input_to_bytes(text), bytes, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn size_suffixes_have_exact_byte_values() {
+        use UnitStyle::{Binary, Decimal};
+        for (prefix, power) in [("K", 1), ("M", 2), ("G", 3), ("T", 4), ("P", 5)] {
+            let (si, iec) = (1000u64.pow(power), 1024u64.pow(power));
+            let bytes = |unit: String, style| parse_human_input_to_bytes_with(&unit, style);
+            assert_eq!(bytes(format!("1 {}B", prefix), Decimal), Some(si), "{}B", prefix);
+            assert_eq!(bytes(format!("1 {}B", prefix), Binary), Some(iec), "{}B", prefix);
+            for style in [Binary, Decimal] {
+                assert_eq!(bytes(format!("1 {}iB", prefix), style), Some(iec), "{}iB", prefix);
+            }
+        }
+        assert_eq!(parse_human_input_to_bytes_with("1 KB", Decimal), Some(1000));
+        assert_eq!(parse_human_input_to_bytes_with("1 KiB", Decimal), Some(1024));
+        assert_eq!(parse_human_input_to_bytes_with("750", Decimal), Some(750_000_000));
+        assert_eq!(parse_human_input_to_bytes("1 KB"), Some(1024));
+        // the presets are binary whatever the style
+        assert_eq!(parse_filter_option("1 GB", None, Decimal), 1 << 30);
+        assert_eq!(parse_filter_option("Custom", Some("1 GB"), Decimal), 1_000_000_000);
+    }
+
+    /// the FolderStats JSON of a scan, without the timings in scan_meta
+    fn scan_json(opts: &ScanOptions) -> String {
+        let mut stats = scan_folder(opts).unwrap();
+        stats.scan_meta = ScanMeta::default();
+        serde_json::to_string(&stats).unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scanning_twice_gives_byte_identical_json() {
+        let dir = TempDir::new("twice");
+        let when = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        // every file the same size and mtime, every extension as many files
+        for d in 0..6 {
+            for ext in ["txt", "jpg", "log", "tar.gz"] {
+                let path = dir.file(&format!("d{}/same.{}", d, ext), b"same size");
+                fs::File::options().write(true).open(path).unwrap().set_modified(when).unwrap();
+            }
+            fs::create_dir(dir.path().join(format!("d{}/empty", d))).unwrap();
+        }
+        for i in 0..3 {
+            dir.too_deep(&format!("deep{}", i));
+        }
+        let opts = ScanOptions::new(dir.path())
+            .top_dirs(3)
+            .recent_files_limit(5)
+            .max_results(Some(10))
+            .find_empty_dirs(true)
+            .empty_dirs_limit(4)
+            .group_by_owner(true)
+            .error_limit(2);
+        let first = scan_json(&opts);
+        let stats: FolderStats = serde_json::from_str(&first).unwrap();
+        assert!(stats.error_count >= 3 && stats.errors.len() == 2, "{:?}", stats.errors);
+        for _ in 0..3 {
+            assert_eq!(scan_json(&opts), first);
+        }
+        assert_eq!(scan_json(&opts.clone().parallel_walk(false)), first);
+    }
+
+    #[test]
+    fn files_deleted_during_the_scan_count_as_vanished() {
+        let dir = TempDir::new("vanish");
+        let paths: Vec<PathBuf> = (0..2000)
+            .map(|i| dir.file(&format!("d{}/f{}.{}", i % 20, i, ["a", "b"][i % 2]), b"12345"))
+            .collect();
+        for parallel in [true, false] {
+            for path in &paths {
+                fs::write(path, b"12345").unwrap();
+            }
+            let deleter = std::thread::spawn({
+                let paths = paths.clone();
+                move || paths.iter().rev().step_by(2).for_each(|p| drop(fs::remove_file(p)))
+            });
+            let stats = scan_folder(&ScanOptions::new(dir.path()).parallel_walk(parallel)).unwrap();
+            deleter.join().unwrap();
+
+            let (total, vanished) = (stats.total_files, stats.vanished_files);
+            assert!((1000..=2000).contains(&(total + vanished)), "{} + {}", total, vanished);
+            let counted: usize = stats.extension_count.iter().map(|e| e.count).sum();
+            let size: u64 = stats.extension_count.iter().map(|e| e.total_size).sum();
+            assert_eq!(counted, stats.total_files);
+            assert_eq!(size, stats.total_size);
+            assert_eq!(stats.total_size, 5 * stats.total_files as u64);
+        }
+
+        // a stat that finds nothing is a vanished file, not an error
+        let opts = ScanOptions::new(dir.path());
+        let gone = || Err(io::Error::from(io::ErrorKind::NotFound));
+        let visit = visit_file(&opts, &[], 0, dir.path().join("x"), "x".as_ref(), None, |_| gone());
+        assert!(matches!(visit, FileVisit::Vanished));
+    }
+
+    /// RFC 4180 records: a quoted field may hold ',', newlines and '"' (doubled)
+    fn parse_csv(text: &str) -> Vec<Vec<String>> {
+        let (mut rows, mut row, mut field) = (Vec::new(), Vec::new(), String::new());
+        let mut quoted = false;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (quoted, c) {
+                (true, '"') if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                (true, '"') => quoted = false,
+                (false, '"') if field.is_empty() => quoted = true,
+                (false, ',') => row.push(std::mem::take(&mut field)),
+                (false, '\n') => {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+                (_, c) => field.push(c),
+            }
+        }
+        assert!(!quoted && field.is_empty() && row.is_empty(), "unterminated: {:?}", text);
+        rows
+    }
+
+    #[test]
+    fn csv_quotes_nasty_names_so_they_read_back() {
+        let dir = TempDir::new("csv");
+        let names =
+            ["a,b.txt", "say \"hi\".txt", "two\nlines.txt", "cr\rhere", "plain.txt", "x.c,d"];
+        for (i, name) in names.iter().enumerate() {
+            dir.file(name, &vec![b'x'; i + 1]);
+        }
+        let stats = scan_folder(&ScanOptions::new(dir.path())).unwrap();
+        let mut csv = Vec::new();
+        stats.to_csv(&mut csv).unwrap();
+        let rows = parse_csv(&String::from_utf8(csv).unwrap());
+
+        let blank = rows.iter().position(|r| r == &[""]).expect("blank line between sections");
+        let (extensions, files) = (&rows[1..blank], &rows[blank + 2..]);
+        assert_eq!(rows[0], ["extension", "count", "size_bytes", "size_human"]);
+        assert_eq!(rows[blank + 1], ["path", "size_bytes", "size_human"]);
+        assert!(extensions.iter().any(|r| r[0] == "c,d" && r[1] == "1"), "{:?}", extensions);
+        assert_eq!(files.len(), names.len());
+        for (i, name) in names.iter().enumerate() {
+            let path = dir.path().join(name).to_string_lossy().into_owned();
+            let row = files.iter().find(|r| r[0] == path).unwrap_or_else(|| panic!("{:?}", path));
+            assert_eq!(row.len(), 3);
+            assert_eq!(row[1], (i + 1).to_string());
+        }
+    }
+
+    #[test]
+    fn tree_text_matches_its_snapshot() {
+        let dir = TempDir::new("tree");
+        for (file, size) in [
+            ("top.bin", 100),
+            ("big/a.bin", 3000),
+            ("big/sub/b.bin", 2000),
+            ("big/sub/deeper/c.bin", 1000),
+            ("big/t/q", 5),
+            ("mid/x.bin", 1500),
+            ("same2/y", 700),
+            ("same1/y", 700),
+            ("tiny1/z", 10),
+            ("tiny2/z", 20),
+        ] {
+            dir.file(file, &vec![0; size]);
+        }
+        let mut tree = scan_folder_tree(&ScanOptions::new(dir.path()), &ScanControl::default())
+            .unwrap();
+        tree.name = "root".to_string();
+        let expected = "\
+root (8.82 KB, 10 files)
+├── big (5.86 KB, 4 files)
+│   ├── sub (2.93 KB, 2 files)
+│   │   └── deeper (1000 B, 1 file)
+│   └── … 1 smaller item (5 B)
+├── mid (1.46 KB, 1 file)
+├── same1 (700 B, 1 file)
+├── same2 (700 B, 1 file)
+└── … 2 smaller items (30 B)
+";
+        assert_eq!(render_tree(&tree, 3, 100), expected);
+        // one level, nothing collapsed
+        let flat = render_tree(&tree, 1, 0);
+        let tail = "├── same2 (700 B, 1 file)\n├── tiny2 (20 B, 1 file)\n\
+                    └── tiny1 (10 B, 1 file)\n";
+        assert!(flat.ends_with(tail) && flat.lines().count() == 7, "{}", flat);
+    }
+
+    /// one line of each kind `--format ndjson` prints, key for key
+    const STREAM_FIXTURE: &str = include_str!("../tests/fixtures/stream.ndjson");
+
+    /// a JSON line's "type" and its keys (sorted)
+    fn stream_keys(line: &str) -> (String, Vec<String>) {
+        let value: serde_json::Value = serde_json::from_str(line).unwrap();
+        let keys = value.as_object().unwrap().keys().cloned().collect();
+        (value["type"].as_str().unwrap().to_string(), keys)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stream_lines_match_their_fixture() {
+        for line in STREAM_FIXTURE.lines() {
+            let record: StreamRecord = serde_json::from_str(line).unwrap();
+            assert_eq!(serde_json::to_string(&record).unwrap(), line);
+        }
+
+        // a real scan's lines have the keys of the fixture's (the first file
+        // line is the one without a hash)
+        let mut expected: HashMap<String, Vec<String>> = HashMap::new();
+        for (kind, keys) in STREAM_FIXTURE.lines().map(stream_keys) {
+            expected.entry(kind).or_insert(keys);
+        }
+        let dir = TempDir::new("stream");
+        dir.file("a.jpg", b"abc");
+        dir.too_deep("deep");
+        let mut lines = Vec::new();
+        let mut emit = |record: StreamRecord| lines.push(serde_json::to_string(&record).unwrap());
+        let summary =
+            scan_folder_stream(&ScanOptions::new(dir.path()), &ScanControl::default(), &mut emit)
+                .unwrap();
+        lines.push(serde_json::to_string(&StreamRecord::Summary(summary)).unwrap());
+        let mut kinds: Vec<String> = lines.iter().map(|l| stream_keys(l).0).collect();
+        kinds.sort();
+        assert_eq!(kinds, ["error", "file", "summary"]);
+        for line in &lines {
+            let (kind, keys) = stream_keys(line);
+            assert_eq!(keys, expected[&kind], "{}", line);
+        }
+    }
+
+    /// `n` sizes spread from a few bytes to a few GB (an LCG, so every run
+    /// gets the same ones)
+    fn spread_sizes(n: usize) -> Vec<u64> {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        (0..n)
+            .map(|_| {
+                state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+                (state >> 33) >> (state % 31)
+            })
+            .collect()
+    }
+
+    fn samples(sizes: &[u64]) -> SizeSamples {
+        let mut samples = SizeSamples::default();
+        sizes.iter().for_each(|&s| samples.add(s));
+        samples
+    }
+
+    #[test]
+    fn small_medians_are_exact() {
+        assert_eq!(samples(&[]).median(), (0, true));
+        assert_eq!(samples(&[7]).median(), (7, true));
+        assert_eq!(samples(&[9, 1, 5]).median(), (5, true));
+        assert_eq!(samples(&[10, 2, 4, 8]).median(), (6, true));
+        // the mean of the middle two, rounded down
+        assert_eq!(samples(&[1, 4]).median(), (2, true));
+        assert_eq!(samples(&[u64::MAX, u64::MAX - 2]).median(), (u64::MAX - 1, true));
+        let many = spread_sizes(MEDIAN_EXACT_LIMIT);
+        let mut sorted = many.clone();
+        sorted.sort_unstable();
+        let mid = MEDIAN_EXACT_LIMIT / 2;
+        let exact = sorted[mid - 1] + (sorted[mid] - sorted[mid - 1]) / 2;
+        assert_eq!(samples(&many).median(), (exact, true));
+    }
+
+    #[test]
+    fn medians_past_the_limit_stay_within_a_32nd() {
+        let sizes = spread_sizes(MEDIAN_EXACT_LIMIT + 1);
+        let (median, exact) = samples(&sizes).median();
+        assert!(!exact);
+        let mut sorted = sizes;
+        sorted.sort_unstable();
+        let truth = sorted[MEDIAN_EXACT_LIMIT / 2];
+        assert!(median.abs_diff(truth) <= truth / 32, "{} vs {}", median, truth);
+    }
+
+    #[test]
+    fn sketch_quantiles_stay_in_the_true_values_bucket() {
+        let qs = [0.5, 0.9, 0.99];
+        assert_eq!(samples(&[]).quantiles(&qs), (vec![0, 0, 0], true));
+        assert_eq!(samples(&[5]).quantiles(&qs), (vec![5, 5, 5], true));
+        let ten: Vec<u64> = (1..=10).rev().collect();
+        assert_eq!(samples(&ten).quantiles(&qs), (vec![5, 9, 10], true));
+
+        let sizes = spread_sizes(MEDIAN_EXACT_LIMIT + 5_000);
+        let (estimates, exact) = samples(&sizes).quantiles(&qs);
+        assert!(!exact);
+        let mut sorted = sizes;
+        sorted.sort_unstable();
+        for (q, estimate) in qs.into_iter().zip(estimates) {
+            let truth = sorted[nearest_rank(q, sorted.len() as u64) as usize - 1];
+            let (low, high) = SizeSketch::bounds(SizeSketch::bucket(truth));
+            assert!((low..high).contains(&truth));
+            assert!(estimate.abs_diff(truth) <= high - low, "p{}: {} vs {}", q, estimate, truth);
+            assert!(estimate.abs_diff(truth) <= truth / 32, "p{}: {} vs {}", q, estimate, truth);
+        }
+    }
+
+    #[test]
+    fn dev_preset_prunes_build_and_tool_directories() {
+        let dir = TempDir::new("presets");
+        for file in [
+            "node_modules/left-pad/index.js",
+            ".git/HEAD",
+            "target/debug/app",
+            "__pycache__/m.pyc",
+            ".venv/bin/python",
+            "src/node_modules/x.js",
+            "src/main.rs",
+            "docs/target",
+            ".cache/blob",
+        ] {
+            dir.file(file, b"x");
+        }
+        let opts = ScanOptions::new(dir.path()).skip_preset("dev".parse().unwrap());
+        assert_eq!(walked_files(&opts), [".cache/blob", "docs/target", "src/main.rs"]);
+        for parallel in [true, false] {
+            let stats = scan_folder(&opts.clone().parallel_walk(parallel)).unwrap();
+            assert_eq!((stats.preset_skipped_dirs, stats.total_files), (6, 3));
+        }
+        let both = opts.skip_preset("caches".parse().unwrap());
+        assert_eq!(scan_folder(&both).unwrap().preset_skipped_dirs, 7);
+        assert!("nope".parse::<Preset>().is_err());
+    }
+
+    #[test]
+    fn only_extensions_understand_compound_ones() {
+        let dir = TempDir::new("only-ext");
+        for file in ["a.tar.gz", "b.gz", "c.tar", "D.TAR.GZ", "e.min.js", "f.js", "tar.gz"] {
+            dir.file(file, b"x");
+        }
+        let only = |exts: &[&str]| {
+            let exts = exts.iter().map(|e| e.to_string()).collect();
+            walked_files(&ScanOptions::new(dir.path()).only_extensions(Some(exts)))
+        };
+        // "gz" also takes .tar.gz (and a file called just "tar.gz")
+        assert_eq!(only(&["gz"]), ["D.TAR.GZ", "a.tar.gz", "b.gz", "tar.gz"]);
+        assert_eq!(only(&["tar.gz"]), ["D.TAR.GZ", "a.tar.gz"]);
+        assert_eq!(only(&[".TAR.GZ"]), ["D.TAR.GZ", "a.tar.gz"]);
+        assert_eq!(only(&["tar"]), ["c.tar"]);
+        assert_eq!(only(&["js", "tar"]), ["c.tar", "e.min.js", "f.js"]);
+        assert_eq!(only(&["min.js"]), ["e.min.js"]);
+    }
+
+    #[test]
+    fn fullest_directories_of_a_deep_tree() {
+        let dir = TempDir::new("file-count");
+        let add = |rel: &str, n: usize| {
+            for i in 0..n {
+                dir.file(&format!("{}f{}", rel, i), b"x");
+            }
+        };
+        add("", 1);
+        add("a/", 5);
+        add("a/b/", 3);
+        add("a/b/c/d/e/", 5);
+        add("x/", 2);
+        let path = |rel: &str| dir.path().join(rel).to_string_lossy().into_owned();
+        for parallel in [true, false] {
+            let opts = ScanOptions::new(dir.path()).top_dirs(3).parallel_walk(parallel);
+            let stats = scan_folder(&opts).unwrap();
+            // equal counts by path, whatever the walk order
+            let expected = [(path("a"), 5), (path("a/b/c/d/e"), 5), (path("a/b"), 3)];
+            assert_eq!(stats.dirs_by_file_count, expected);
+        }
+        let all = scan_folder(&ScanOptions::new(dir.path()).top_dirs(9)).unwrap();
+        assert_eq!(all.dirs_by_file_count.len(), 5);
+        assert_eq!(all.dirs_by_file_count[4], (dir.path().to_string_lossy().into_owned(), 1));
+        let none = scan_folder(&ScanOptions::new(dir.path()).top_dirs(0)).unwrap();
+        assert!(none.dirs_by_file_count.is_empty());
+    }
+
+    /// (extension, count) rows of extension_count
+    fn extension_rows(stats: &FolderStats) -> Vec<(String, usize)> {
+        stats.extension_count.iter().map(|e| (e.extension.clone(), e.count)).collect()
+    }
+
+    #[test]
+    fn extension_case_modes_group_differently() {
+        let dir = TempDir::new("ext-case");
+        for file in ["a.JPG", "b.jpg", "c.Jpg", "d.jpg", "e.TAR.GZ", "f.tar.gz"] {
+            dir.file(file, b"x");
+        }
+        let opts = ScanOptions::new(dir.path()).ext_sort(ExtensionSort::Name);
+        let folded = scan_folder(&opts).unwrap();
+        let rows = |rows: &[(&str, usize)]| -> Vec<(String, usize)> {
+            rows.iter().map(|&(e, n)| (e.to_string(), n)).collect()
+        };
+        assert_eq!(extension_rows(&folded), rows(&[("jpg", 4), ("tar.gz", 2)]));
+        let apart = scan_folder(&opts.case_insensitive_extensions(false)).unwrap();
+        let expected = [("JPG", 1), ("Jpg", 1), ("TAR.GZ", 1), ("jpg", 2), ("tar.gz", 1)];
+        assert_eq!(extension_rows(&apart), rows(&expected));
+        assert_eq!(apart.total_files, folded.total_files);
+    }
+
+    #[test]
+    fn extension_sorts_break_ties_by_name() {
+        let dir = TempDir::new("ext-sort");
+        for (file, size) in [("1.zz", 1), ("2.zz", 1), ("3.aa", 1), ("4.aa", 1), ("5.mm", 4)] {
+            dir.file(file, &vec![0; size]);
+        }
+        let order = |sort: ExtensionSort, parallel: bool| {
+            let opts = ScanOptions::new(dir.path()).ext_sort(sort).parallel_walk(parallel);
+            let stats = scan_folder(&opts).unwrap();
+            stats.extension_count.into_iter().map(|e| e.extension).collect::<Vec<_>>()
+        };
+        for parallel in [true, false] {
+            assert_eq!(order(ExtensionSort::Name, parallel), ["aa", "mm", "zz"]);
+            assert_eq!(order(ExtensionSort::Count, parallel), ["aa", "zz", "mm"]);
+            assert_eq!(order(ExtensionSort::Size, parallel), ["mm", "aa", "zz"]);
+        }
+        assert_eq!(" Name ".parse::<ExtensionSort>(), Ok(ExtensionSort::Name));
+    }
+
+    #[test]
+    fn extension_detail_lists_one_extension_whatever_the_filters() {
+        let dir = TempDir::new("ext-detail");
+        for (file, size) in [("big.iso", 50), ("x.ISO", 30), ("tiny.iso", 1), ("other.txt", 100)] {
+            dir.file(file, &vec![0; size]);
+        }
+        dir.file("a.tar.gz", &[0; 20]);
+        let detail = |opts: ScanOptions| -> Vec<String> {
+            let stats = scan_folder(&opts.min_size(10).name_contains(Some("big".into()))).unwrap();
+            let names = stats.extension_detail.iter().map(|f| f.rel_path.clone());
+            names.collect()
+        };
+        let opts = ScanOptions::new(dir.path()).detail_extension(Some(".iso".into()));
+        assert_eq!(detail(opts.clone()), ["big.iso", "x.ISO", "tiny.iso"]);
+        assert_eq!(detail(opts.clone().detail_limit(2)), ["big.iso", "x.ISO"]);
+        let apart = opts.case_insensitive_extensions(false).detail_extension(Some("ISO".into()));
+        assert_eq!(detail(apart), ["x.ISO"]);
+        let compound = ScanOptions::new(dir.path()).detail_extension(Some("tar.gz".into()));
+        assert_eq!(detail(compound), ["a.tar.gz"]);
+        assert!(detail(ScanOptions::new(dir.path())).is_empty());
+    }
+
+    #[test]
+    fn both_walks_estimate_the_files_past_the_limit_alike() {
+        let dir = TempDir::new("limit");
+        for i in 0..10 {
+            dir.file(&format!("f{}.txt", i), b"x");
+        }
+        dir.file("f.skip", b"x");
+        let txt = Some(vec!["txt".to_string()]);
+        let opts = ScanOptions::new(dir.path()).file_limit(Some(4)).only_extensions(txt);
+        for parallel in [true, false] {
+            let stats = scan_folder(&opts.clone().parallel_walk(parallel)).unwrap();
+            assert!(stats.truncated);
+            assert_eq!((stats.total_files, stats.files_skipped_estimate), (4, 6), "{}", parallel);
+        }
+    }
+
+    #[test]
+    fn hashes_are_the_sha256_of_the_content() {
+        let dir = TempDir::new("sha");
+        let cancel = CancellationToken::default();
+        let hash = |name: &str, data: &[u8]| hash_file(&dir.file(name, data), &cancel).unwrap();
+        let empty = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(hash("empty", b""), empty);
+        let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert_eq!(hash("abc", b"abc"), abc);
+        let million = "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0";
+        assert_eq!(hash("a", &vec![b'a'; 1_000_000]), million);
+        // read in more than one HASH_CHUNK
+        let big: Vec<u8> = (0..HASH_CHUNK * 2 + 7).map(|i| i as u8).collect();
+        assert_eq!(hash("big", &big), format!("{:x}", Sha256::digest(&big)));
+    }
 }
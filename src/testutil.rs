@@ -0,0 +1,176 @@
+// src/testutil.rs
+
+//! helpers shared by the unit tests (cfg(test) only)
+
+use crate::category::Category;
+use crate::scan::{
+    CategoryStat, ExtensionStat, FOLDER_STATS_SCHEMA, FileEntry, FolderStats, PartialScan,
+    ScanErrorEntry, StopReason,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// an empty directory of its own under the system temp dir, removed with
+/// everything in it on drop
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let name = format!("fm-test-{}-{}-{}", std::process::id(), n, name);
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create temp dir");
+        TempDir(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// write `contents` to `rel` (parents created) and return its path
+    pub fn file(&self, rel: &str, contents: &[u8]) -> PathBuf {
+        let path = self.0.join(rel);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("create parent dirs");
+        }
+        fs::write(&path, contents).expect("write test file");
+        path
+    }
+
+    /// directories nested below `rel` until the path is longer than PATH_MAX,
+    /// so reading the deepest one fails (ENAMETOOLONG) whoever runs the
+    /// tests, root included; returns that deepest one
+    #[cfg(unix)]
+    pub fn too_deep(&self, rel: &str) -> PathBuf {
+        use std::ffi::{CStr, CString};
+        use std::os::unix::ffi::OsStrExt;
+
+        fn open_dir(at: libc::c_int, name: &CStr) -> libc::c_int {
+            let fd = unsafe { libc::openat(at, name.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY) };
+            assert!(fd >= 0, "open {:?}: {}", name, std::io::Error::last_os_error());
+            fd
+        }
+
+        let mut path = self.0.join(rel);
+        fs::create_dir_all(&path).expect("create parent dirs");
+        let top = CString::new(path.as_os_str().as_bytes()).expect("no NUL in temp paths");
+        let mut fd = open_dir(libc::AT_FDCWD, &top);
+        let name = "d".repeat(200);
+        let c_name = CString::new(name.as_str()).unwrap();
+        while path.as_os_str().len() <= libc::PATH_MAX as usize {
+            let made = unsafe { libc::mkdirat(fd, c_name.as_ptr(), 0o755) };
+            assert_eq!(made, 0, "mkdirat: {}", std::io::Error::last_os_error());
+            let next = open_dir(fd, &c_name);
+            unsafe { libc::close(fd) };
+            fd = next;
+            path.push(&name);
+        }
+        unsafe { libc::close(fd) };
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// an executable `sh` script `name` in `dir` that stands in for the worker:
+/// it gets the worker's arguments, stdin and stderr and runs `body`
+#[cfg(unix)]
+pub fn fake_worker(dir: &TempDir, name: &str, body: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = dir.file(name, format!("#!/bin/sh\n{}\n", body).as_bytes());
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).expect("make script executable");
+    path
+}
+
+/// the application binary cargo builds next to the test binary (it does
+/// for tests/cli.rs), to run as a real worker
+pub fn app_exe() -> PathBuf {
+    let test_exe = std::env::current_exe().expect("test binary path");
+    let dir = test_exe.parent().and_then(Path::parent).expect("target directory");
+    let exe = dir.join(format!("foldermanager-app{}", std::env::consts::EXE_SUFFIX));
+    assert!(exe.is_file(), "{} not built; run the tests through cargo test", exe.display());
+    exe
+}
+
+/// a FolderStats listing `files` made-up files, for round trips through the
+/// worker formats: every other file has a hash and an mtime, on Unix some
+/// names aren't valid UTF-8, and the optional fields are set
+pub fn synthetic_stats(files: usize) -> FolderStats {
+    let mut stats = FolderStats {
+        schema_version: FOLDER_STATS_SCHEMA,
+        deepest_path: Some(odd_path(7)),
+        partial: Some(PartialScan {
+            reason: StopReason::Cancelled,
+            totals_only: false,
+        }),
+        ..FolderStats::default()
+    };
+    for i in 0..files {
+        let path = if i % 10 == 7 {
+            odd_path(i)
+        } else {
+            PathBuf::from(format!("/data/dir{}/file{}.{}", i % 50, i, ["txt", "jpg", "rs"][i % 3]))
+        };
+        let display = path.to_string_lossy().into_owned();
+        stats.filtered_files.push(FileEntry {
+            rel_path: display.trim_start_matches("/data/").to_string(),
+            display,
+            path,
+            size: (i as u64).pow(3),
+            allocated: (i as u64).pow(3).div_ceil(4096) * 4096,
+            mtime: (i % 2 == 0).then(|| UNIX_EPOCH + Duration::from_secs(1_700_000_000 + i as u64)),
+            hash: (i % 2 == 1).then(|| format!("{:064x}", i)),
+        });
+        stats.total_size += (i as u64).pow(3);
+    }
+    stats.total_files = files;
+    stats.matched_total = files;
+    for (i, ext) in ["txt", "jpg", "rs", "(no extension)"].iter().enumerate() {
+        stats.extension_count.push(ExtensionStat {
+            extension: ext.to_string(),
+            count: files / 3 + i,
+            total_size: u64::MAX / (i as u64 + 2),
+            mean_size: 4096,
+            median_size: 100,
+            median_exact: i % 2 == 0,
+        });
+    }
+    stats.extension_largest =
+        stats.filtered_files.iter().take(3).map(|f| ("txt".to_string(), f.clone())).collect();
+    stats.category_summary.push(CategoryStat {
+        category: Category::Images,
+        count: files,
+        total_size: stats.total_size,
+    });
+    stats.errors.push(ScanErrorEntry {
+        path: "/data/locked".to_string(),
+        is_dir: true,
+        kind: "permission denied".to_string(),
+    });
+    stats.error_count = 3;
+    stats
+}
+
+/// a path whose last component isn't valid UTF-8 (Unix; elsewhere plain)
+fn odd_path(i: usize) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let mut bytes = format!("/data/odd/caf\u{e9}{}-", i).into_bytes();
+        bytes.push(0xff);
+        PathBuf::from(std::ffi::OsStr::from_bytes(&bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(format!("/data/odd/file{}", i))
+    }
+}
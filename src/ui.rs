@@ -2,21 +2,84 @@
 use gtk4::gdk;
 use gtk4::prelude::*;
 use gtk4::{
-    Application, ApplicationWindow, Box as GtkBox, Button, ComboBoxText, CssProvider, Entry,
-    FileChooserAction, FileChooserNative, HeaderBar, Label, ListBox, ListBoxRow, Orientation,
-    Paned, ScrolledWindow, SelectionMode, Spinner, ToggleButton, Widget,
+    Application, ApplicationWindow, Box as GtkBox, Button, CheckButton, ComboBoxText, CssProvider,
+    Entry, Expander, FileChooserAction, FileChooserNative, HeaderBar, Label, ListBox, ListBoxRow,
+    Notebook, Orientation, Paned, ScrolledWindow, SelectionMode, SpinButton, Spinner, ToggleButton,
+    Widget,
 };
 
 use glib::Continue;
-use std::env::current_exe;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::rc::Rc;
 use std::sync::mpsc;
 use std::sync::mpsc::TryRecvError;
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use crate::ipc;
-use crate::scan::{FolderStats, format_bytes, parse_filter_option};
+
+/// nilai awal "Daftar file (maks.)": baris maksimum di tab "Files passing
+/// filter", sisanya hanya dihitung worker; 0 = tanpa batas
+const FILE_LIST_LIMIT: usize = 1000;
+/// nilai awal "Timeout (menit)"; 0 = tanpa batas
+const DEFAULT_SCAN_TIMEOUT_MINUTES: f64 = 10.0;
+/// scan tanpa progres selama ini tampak macet (NAS / FUSE yang hang): user
+/// ditanya mau dibatalkan atau ditunggu
+const STALL_AFTER: Duration = Duration::from_secs(60);
+/// scan ulang sebagai administrator ditawarkan kalau paling sedikit 1 dari
+/// sekian path yang ditemui ditolak izinnya
+const DENIED_SHARE: usize = 100;
+use crate::scan::{
+    ExtensionSort, ExtensionStat, FileEntry, FolderStats, GIB, MIB, PartialScan, Preset,
+    ScanErrorKind, ScanOptions, SizeQuantiles, StopReason, UnitStyle, format_age,
+    format_bytes_with, load_last_result, parse_filter_option, parse_human_input_to_bytes_with,
+    save_last_result, sort_extensions,
+};
+
+/// satu hasil untuk polling UI
+struct ScanUpdate {
+    /// nomor klik Hitung asal hasil ini; hasil klik sebelumnya dibuang
+    scan: u64,
+    /// satuan yang dipakai saat scan dimulai
+    style: UnitStyle,
+    /// Some(waktu simpan) = hasil lama dari cache, scan baru masih berjalan
+    stale_since: Option<SystemTime>,
+    /// peringatan worker (stderr) untuk hasil baru; kosong untuk hasil lama
+    warnings: Vec<ipc::WorkerWarning>,
+    /// hasil scan sebagai administrator (ipc::ScanRequest::elevated)
+    elevated: bool,
+    result: Result<FolderStats, ipc::IpcError>,
+}
+
+/// hasil drill-down satu ekstensi (baris ekstensi diklik)
+struct DetailUpdate {
+    extension: String,
+    style: UnitStyle,
+    result: Result<FolderStats, ipc::IpcError>,
+}
+
+/// worker scan yang sedang berjalan, untuk tombol Batal
+/// - scan: nomor klik Hitung-nya (ScanUpdate::scan)
+/// - cancel_requested: Batal ditekan sebelum request masuk ke pool
+/// - request: id dari WorkerPool::submit
+#[derive(Default)]
+struct RunningScan {
+    scan: u64,
+    cancel_requested: bool,
+    request: Option<u64>,
+}
+
+/// kabar dari scan yang belum selesai, untuk label progres
+enum ScanStatus {
+    /// menunggu scan lain di disk yang sama; sebanyak ini di depannya
+    Queued(usize),
+    Progress(ipc::WorkerProgress),
+    /// tanpa progres selama STALL_AFTER (ipc::PoolEvent::Stalled)
+    Stalled(ipc::Stall),
+}
 
 // --------------------------
 // Helper: ambil semua child listbox
@@ -32,6 +95,438 @@ fn listbox_children(lb: &ListBox) -> Vec<Widget> {
     out
 }
 
+// --------------------------
+// Helper: kosongkan listbox
+// --------------------------
+fn clear_listbox(lb: &ListBox) {
+    for child in listbox_children(lb) {
+        lb.remove(&child);
+    }
+}
+
+// --------------------------
+// Helper: tambah satu baris teks ke listbox (row dikembalikan, mis. untuk tooltip)
+// --------------------------
+fn append_text_row(lb: &ListBox, text: &str) -> ListBoxRow {
+    let row = ListBoxRow::new();
+    let label = Label::new(Some(text));
+    label.set_xalign(0.0);
+
+    row.set_child(Some(&label));
+    lb.append(&row);
+    row
+}
+
+// --------------------------
+// Helper: listbox di dalam scrolled window
+// --------------------------
+fn scrolled_listbox(min_width: i32, min_height: i32) -> (ScrolledWindow, ListBox) {
+    let list = ListBox::new();
+    list.set_selection_mode(SelectionMode::None);
+
+    let scroll = ScrolledWindow::new();
+    scroll.set_child(Some(&list));
+    scroll.set_min_content_width(min_width);
+    scroll.set_min_content_height(min_height);
+
+    (scroll, list)
+}
+
+// --------------------------
+// Helper: ukuran + ukuran di disk bila selisihnya lebih dari 5%
+// --------------------------
+fn size_with_allocated(size: u64, allocated: u64, style: UnitStyle) -> String {
+    let diff = size.abs_diff(allocated);
+    if diff * 20 > size.max(allocated) {
+        format!(
+            "{} ({} on disk)",
+            format_bytes_with(size, style),
+            format_bytes_with(allocated, style)
+        )
+    } else {
+        format_bytes_with(size, style)
+    }
+}
+
+// --------------------------
+// Helper: list ekstensi yang sedang tampil, untuk diurutkan ulang tanpa scan baru
+// --------------------------
+struct ShownExtensions {
+    stats: Vec<ExtensionStat>,
+    /// file terbesar per ekstensi (tooltip)
+    largest: HashMap<String, FileEntry>,
+    style: UnitStyle,
+}
+
+fn ext_sort_from_id(id: Option<&str>) -> ExtensionSort {
+    match id {
+        Some("count") => ExtensionSort::Count,
+        Some("name") => ExtensionSort::Name,
+        _ => ExtensionSort::Size,
+    }
+}
+
+// --------------------------
+// Helper: isi list ekstensi, file terbesar per ekstensi sebagai tooltip
+// --------------------------
+fn fill_extension_list(list: &ListBox, shown: &ShownExtensions) {
+    let style = shown.style;
+    for es in &shown.stats {
+        let row = append_text_row(
+            list,
+            &format!(
+                "{} : {} file ({}, avg {})",
+                es.extension,
+                es.count,
+                format_bytes_with(es.total_size, style),
+                format_bytes_with(es.mean_size, style)
+            ),
+        );
+        let median = format!(
+            "Median: {}{}",
+            if es.median_exact { "" } else { "≈ " },
+            format_bytes_with(es.median_size, style)
+        );
+        let tooltip = match shown.largest.get(&es.extension) {
+            Some(f) => format!(
+                "{}\nTerbesar: {} ({})",
+                median,
+                f.display,
+                format_bytes_with(f.size, style)
+            ),
+            None => median,
+        };
+        row.set_tooltip_text(Some(&tooltip));
+    }
+}
+
+// --------------------------
+// Helper: sisa blok layak ditampilkan? (≥ 1 GB, atau ≥ 1 MB dan ≥ 5% dari pemakaian disk)
+// --------------------------
+fn slack_notable(slack: u64, allocated: u64) -> bool {
+    slack >= GIB || (slack >= MIB && slack.saturating_mul(20) >= allocated)
+}
+
+// --------------------------
+// Helper: sebaran ukuran file, "p50 12 KB · p90 3 MB · p99 700 MB · max 4 GB"
+// --------------------------
+fn quantiles_text(q: &SizeQuantiles, style: UnitStyle) -> String {
+    let approx = if q.exact { "" } else { "≈ " };
+    format!(
+        "Ukuran file: p50 {}{} · p90 {}{} · p99 {}{} · max {}",
+        approx,
+        format_bytes_with(q.p50, style),
+        approx,
+        format_bytes_with(q.p90, style),
+        approx,
+        format_bytes_with(q.p99, style),
+        format_bytes_with(q.max, style)
+    )
+}
+
+// --------------------------
+// Helper: banner untuk hasil scan yang dihentikan di tengah jalan
+// --------------------------
+fn partial_banner(partial: &PartialScan) -> String {
+    let why = match partial.reason {
+        StopReason::Cancelled => "dibatalkan",
+        StopReason::TimedOut => "waktu habis",
+    };
+    let what = if partial.totals_only {
+        "hanya jumlah file dan ukuran yang sempat terhitung"
+    } else {
+        "semua angka hanya batas bawah"
+    };
+    format!("⚠ Hasil sebagian — scan dihentikan lebih awal ({}); {}", why, what)
+}
+
+// --------------------------
+// Helper: pertanyaan untuk scan yang tampak macet
+// --------------------------
+fn stall_text(stall: &ipc::Stall) -> String {
+    format!(
+        "⚠ Scan tampaknya macet di {} (tanpa progres {} detik; folder jaringan tidak \
+         merespons?) — batalkan?",
+        stall.dir,
+        stall.last_activity.as_secs()
+    )
+}
+
+// --------------------------
+// Helper: scan yang banyak terhalang izin (mis. /var/lib, home user lain)
+// - satu folder tertutup bisa menyembunyikan ribuan file, jadi ambangnya rendah
+// - error di luar error_limit tidak terdaftar: bagiannya diperkirakan dari
+//   yang terdaftar
+// --------------------------
+fn many_denied(stats: &FolderStats) -> bool {
+    let listed = stats.errors.iter().filter(|e| e.kind == "permission denied").count();
+    if listed == 0 {
+        return false;
+    }
+    let denied = listed * stats.error_count / stats.errors.len();
+    denied * DENIED_SHARE >= stats.total_files + stats.error_count
+}
+
+// --------------------------
+// Helper: catatan singkat tentang batasan/filter yang berlaku pada hasil scan
+// --------------------------
+fn scan_notes(stats: &FolderStats) -> Vec<String> {
+    let mut notes = Vec::new();
+
+    if stats.truncated {
+        notes.push(format!(
+            "⚠ TERPOTONG: berhenti setelah {} file, bukan total penuh",
+            stats.total_files
+        ));
+    }
+
+    if let Some(d) = stats.max_depth {
+        notes.push(format!("depth ≤ {}", d));
+    }
+    if stats.excluded_dirs + stats.excluded_files > 0 {
+        notes.push(format!(
+            "excluded: {} folder, {} file",
+            stats.excluded_dirs, stats.excluded_files
+        ));
+    }
+    if stats.skipped_links > 0 {
+        notes.push(format!("{} symlink dilewati", stats.skipped_links));
+    }
+    if stats.collapsed_duplicates > 0 {
+        notes.push(format!("{} duplikat digabung", stats.collapsed_duplicates));
+    }
+    if stats.vanished_files > 0 {
+        notes.push(format!("{} file hilang saat scan", stats.vanished_files));
+    }
+    if stats.skipped_hidden > 0 {
+        notes.push(format!("{} tersembunyi dilewati", stats.skipped_hidden));
+    }
+    if !stats.virtual_mounts_skipped.is_empty() {
+        notes.push(format!(
+            "{} mount virtual dilewati ({})",
+            stats.virtual_mounts_skipped.len(),
+            stats.virtual_mounts_skipped.join(", ")
+        ));
+    }
+    if stats.gitignored > 0 {
+        notes.push(format!("{} di-ignore .gitignore", stats.gitignored));
+    }
+    if stats.preset_skipped_dirs > 0 {
+        notes.push(format!("{} folder preset dilewati", stats.preset_skipped_dirs));
+    }
+
+    notes
+}
+
+// --------------------------
+// Helper: bagian yang bisa dilipat berisi listbox
+// --------------------------
+fn expander_listbox(title: &str) -> (Expander, ListBox) {
+    let (scroll, list) = scrolled_listbox(640, 120);
+
+    let expander = Expander::new(Some(title));
+    expander.set_child(Some(&scroll));
+
+    (expander, list)
+}
+
+// --------------------------
+// Helper: "Newest: report.pdf (2 days ago)", nama file saja biar ringkas
+// --------------------------
+fn file_age_text(title: &str, file: &FileEntry, now: SystemTime) -> String {
+    let name = file
+        .path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| file.display.clone());
+    match file.mtime {
+        Some(t) => format!("{}: {} ({})", title, name, format_age(t, now)),
+        None => format!("{}: {}", title, name),
+    }
+}
+
+// --------------------------
+// Helper: "12 directories could not be read"
+// - rincian folder/file hanya diketahui untuk entri yang terdaftar
+// --------------------------
+fn error_summary(stats: &FolderStats) -> String {
+    if stats.error_count > stats.errors.len() {
+        return format!("{} paths could not be read", stats.error_count);
+    }
+
+    let dirs = stats.errors.iter().filter(|e| e.is_dir).count();
+    let files = stats.errors.len() - dirs;
+    let dir_text = format!("{} {}", dirs, if dirs == 1 { "directory" } else { "directories" });
+    let file_text = format!("{} {}", files, if files == 1 { "file" } else { "files" });
+    match (dirs, files) {
+        (_, 0) => format!("{} could not be read", dir_text),
+        (0, _) => format!("{} could not be read", file_text),
+        _ => format!("{} and {} could not be read", dir_text, file_text),
+    }
+}
+
+// --------------------------
+// Helper: "Scanned 1.2M files in 14.3 s"
+// --------------------------
+fn scan_meta_text(stats: &FolderStats) -> String {
+    let n = stats.total_files;
+    let count = match n {
+        0..1_000 => n.to_string(),
+        1_000..1_000_000 => format!("{:.1}K", n as f64 / 1e3),
+        _ => format!("{:.1}M", n as f64 / 1e6),
+    };
+    let secs = stats.scan_meta.duration_ms as f64 / 1000.0;
+    let stopped = stats.truncated || stats.partial.is_some();
+    let verb = if stopped { "Stopped after" } else { "Scanned" };
+    let mut text = format!("{} {} files in {:.1} s", verb, count, secs);
+    if stats.scan_meta.sandboxed {
+        text.push_str(" inside the Flatpak sandbox");
+    }
+    text
+}
+
+// --------------------------
+// Helper: angka dengan pemisah ribuan (48211 -> "48,211")
+// --------------------------
+fn group_thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+// --------------------------
+// Helper: ringkasan filter ukuran dan jumlah file yang cocok
+// - "Filter: ≥ 100 MB, ≤ 4 KB — 312 matches (1.2 GB)"
+// - "... — showing top 1000 of 48,211 matches (3.1 TB)" kalau list dipotong
+// - "Filter: ≥ 1 GB, name contains “backup” — ..." kalau ada pencarian nama
+// --------------------------
+fn matches_text(stats: &FolderStats, style: UnitStyle) -> String {
+    let meta = &stats.scan_meta;
+    let min = format_bytes_with(meta.min_size, style);
+    let range = match meta.max_size {
+        Some(max) => format!("≥ {}, ≤ {}", min, format_bytes_with(max, style)),
+        None => format!("≥ {}", min),
+    };
+    let query = match &meta.name_contains {
+        Some(text) => format!(", name contains “{}”", text),
+        None => String::new(),
+    };
+    let shown = stats.filtered_files.len();
+    let count = if shown < stats.matched_total {
+        format!("showing top {} of {}", shown, group_thousands(stats.matched_total))
+    } else {
+        group_thousands(stats.matched_total)
+    };
+    format!(
+        "Filter: {}{} — {} matches ({})",
+        range,
+        query,
+        count,
+        format_bytes_with(stats.matched_size, style)
+    )
+}
+
+// --------------------------
+// Helper: isi list yang dibatasi, plus baris "… dan N lainnya"
+// --------------------------
+fn fill_capped_list(list: &ListBox, items: &[String], overflow: usize) {
+    for item in items.iter() {
+        append_text_row(list, item);
+    }
+    if overflow > 0 {
+        append_text_row(list, &format!("… dan {} lainnya", overflow));
+    }
+}
+
+// --------------------------
+// Helper: saran setelah pesan error, sesuai jenis ScanError
+// --------------------------
+fn error_hint(kind: ScanErrorKind) -> Option<&'static str> {
+    match kind {
+        ScanErrorKind::RootNotFound | ScanErrorKind::RootNotADirectory => {
+            Some(" — pilih folder lain")
+        }
+        ScanErrorKind::Io => Some(" — periksa izin folder, lalu coba lagi"),
+        ScanErrorKind::InvalidOptions => Some(" — periksa filter dan pola"),
+        ScanErrorKind::Cancelled => None,
+    }
+}
+
+// --------------------------
+// Helper: pesan untuk worker yang gagal / tidak selesai
+// --------------------------
+fn worker_error_text(err: &ipc::IpcError) -> String {
+    match err {
+        ipc::IpcError::ScanFailed {
+            kind,
+            message,
+            path,
+            detail,
+        } => {
+            // dari path/detail bila worker mengirimnya; pesan worker (Inggris)
+            // hanya untuk opsi yang salah atau worker lama
+            let text = match (kind, path) {
+                (ScanErrorKind::RootNotFound, Some(path)) => {
+                    format!("Folder tidak ditemukan: {}", path)
+                }
+                (ScanErrorKind::RootNotADirectory, Some(path)) => format!("Bukan folder: {}", path),
+                (ScanErrorKind::Io, Some(path)) => match detail {
+                    Some(detail) => format!("Tidak bisa membaca {} ({})", path, detail),
+                    None => format!("Tidak bisa membaca {}", path),
+                },
+                _ => message.clone(),
+            };
+            format!("Error: {}{}", text, error_hint(*kind).unwrap_or(""))
+        }
+        ipc::IpcError::SpawnFailed(_) => {
+            format!("Error: {} — periksa instalasi aplikasi", err)
+        }
+        ipc::IpcError::InvalidWorkerPath { .. } => {
+            format!("Error: {} — scan tidak dijalankan; perbaiki lalu buka ulang aplikasi", err)
+        }
+        ipc::IpcError::Cancelled => "Scan dibatalkan".to_string(),
+        // dialog password pkexec ditutup
+        ipc::IpcError::ElevationFailed {
+            exit_code: ipc::PKEXEC_DISMISSED,
+            ..
+        } => "Scan administrator dibatalkan (password tidak dimasukkan)".to_string(),
+        ipc::IpcError::ElevationFailed { .. } => {
+            format!("Error: {} — scan administrator tidak diizinkan", err)
+        }
+        // worker yang bisa diganti user biasa tidak dijalankan sebagai root
+        ipc::IpcError::ElevationRefused(_) => {
+            format!("Error: {} — pakai worker bawaan yang terpasang", err)
+        }
+        ipc::IpcError::TimedOut(after) => {
+            format!("Scan timed out after {} minutes", after.as_secs() / 60)
+        }
+        // binary diganti (upgrade) saat aplikasi masih jalan
+        ipc::IpcError::VersionMismatch { .. } | ipc::IpcError::SchemaTooNew { .. } => {
+            "Worker versi lain — silakan mulai ulang aplikasi".to_string()
+        }
+        // SIGKILL tanpa Batal: biasanya OOM killer
+        ipc::IpcError::WorkerFailed { signal: Some(9), .. } => {
+            format!("Error: {} — worker dihentikan sistem (memori habis?)", err)
+        }
+        // worker dan GUI tidak cocok / worker crash: bukan salah user
+        ipc::IpcError::Rejected(_)
+        | ipc::IpcError::WorkerFailed { .. }
+        | ipc::IpcError::NoResult
+        | ipc::IpcError::InvalidJson(_)
+        | ipc::IpcError::InvalidMessagePack(_)
+        | ipc::IpcError::Malformed { .. } => {
+            format!("Error: {} — kemungkinan bug, mohon laporkan", err)
+        }
+        ipc::IpcError::Io(_) => format!("Error: {}", err),
+    }
+}
+
 // --------------------------
 // Membangun UI utama aplikasi
 // --------------------------
@@ -110,19 +605,223 @@ pub fn build_ui(app: &Application) {
     custom_entry.set_placeholder_text(Some("Mis. 150 MB (untuk Custom)"));
     custom_entry.set_sensitive(false);
 
+    // regex nama file untuk daftar file hasil filter
+    let max_entry = Entry::new();
+    max_entry.set_placeholder_text(Some("Maks. ukuran (opsional, mis. 4 KB)"));
+
+    let regex_entry = Entry::new();
+    regex_entry.set_placeholder_text(Some("Regex nama (opsional)"));
+
+    // pencarian sederhana, tanpa membedakan huruf besar/kecil
+    let contains_entry = Entry::new();
+    contains_entry.set_placeholder_text(Some("Nama mengandung (opsional)"));
+
+    // kedalaman maksimum, 0 = tanpa batas
+    let depth_label = Label::new(Some("Depth:"));
+    let depth_spin = SpinButton::with_range(0.0, 64.0, 1.0);
+    depth_spin.set_value(0.0);
+    depth_spin.set_tooltip_text(Some("0 = tanpa batas, 1 = hanya isi langsung folder"));
+
     let calc_btn = Button::with_label("Hitung");
     calc_btn.add_css_class("suggested-action");
 
+    // hanya aktif selama worker scan berjalan
+    let cancel_btn = Button::with_label("Batal");
+    cancel_btn.set_sensitive(false);
+    let running_scan = Arc::new(Mutex::new(RunningScan::default()));
+    // nomor klik Hitung terakhir; hanya hasil dan progres klik ini yang tampil
+    let scan_seq = Rc::new(Cell::new(0u64));
+    // klik Hitung berikutnya scan sebagai administrator; hanya diset tombol
+    // "Scan sebagai administrator", dan langsung dipakai habis
+    let elevate_next = Rc::new(Cell::new(false));
+
+    // semua scan (Hitung, drill-down ekstensi) lewat satu pool worker
+    // --worker-serve; dimatikan saat aplikasi keluar
+    // - scan tanpa progres selama STALL_AFTER tidak dihentikan, user ditanya
+    //   lewat stall_bar
+    // - tanpa path exe (dihapus/diganti saat upgrade) worker gagal start dan
+    //   pool memindai di dalam proses ini (ipc::execute_scan)
+    // - FSCAN_WORKER_PATH memilih worker lain; kalau tidak valid, tidak ada
+    //   scan sama sekali (bukan diam-diam exe lain): Hitung dan drill-down
+    //   hanya menampilkan errornya lagi
+    let (exe, exe_error) = match ipc::worker_exe() {
+        Ok(exe) => (exe, None),
+        Err(err) => (PathBuf::new(), Some(err)),
+    };
+    let scan_refusal: Rc<Option<String>> = Rc::new(match &exe_error {
+        Some(err @ ipc::IpcError::InvalidWorkerPath { .. }) => Some(worker_error_text(err)),
+        _ => None,
+    });
+    // dicatat di stderr, supaya laporan bug bisa memastikan worker mana yang jalan
+    match &exe_error {
+        None => eprintln!("worker: {}", exe.display()),
+        Some(err) => eprintln!("worker: {}", err),
+    }
+    let worker_pool = Arc::new(
+        ipc::WorkerPool::new(exe.clone(), ipc::DEFAULT_POOL_SIZE).stall_after(Some(STALL_AFTER)),
+    );
+    let pool_for_shutdown = Arc::clone(&worker_pool);
+    app.connect_shutdown(move |_| pool_for_shutdown.terminate_all());
+
+    // jendela ditutup di tengah scan: worker dihentikan (tidak dibiarkan
+    // memindai sampai selesai) dan polling di bawah berhenti
+    // - shutdown hanya mengirim SIGTERM, jadi jendela langsung tertutup;
+    //   menunggu worker keluar (terminate_all) baru di connect_shutdown
+    let window_closed = Rc::new(Cell::new(false));
+    let pool_for_close = Arc::clone(&worker_pool);
+    let closed_for_close = Rc::clone(&window_closed);
+    window.connect_close_request(move |_| {
+        closed_for_close.set(true);
+        pool_for_close.shutdown();
+        gtk4::glib::Propagation::Proceed
+    });
+
     let spinner = Spinner::new();
     spinner.set_visible(false);
 
+    // progres scan dari worker (--stream), hanya selama spinner jalan
+    let progress_label = Label::new(None);
+    progress_label.set_visible(false);
+
     // Masukkan ke row
     row.append(&entry);
     row.append(&choose_btn);
     row.append(&filter_combo);
     row.append(&custom_entry);
+    row.append(&max_entry);
+    row.append(&regex_entry);
+    row.append(&contains_entry);
+    row.append(&depth_label);
+    row.append(&depth_spin);
     row.append(&calc_btn);
+    row.append(&cancel_btn);
     row.append(&spinner);
+    row.append(&progress_label);
+
+    // ============ ROW OPSI ============
+    let opt_row = GtkBox::new(Orientation::Horizontal, 8);
+
+    let exclude_label = Label::new(Some("Exclude:"));
+    let exclude_entry = Entry::new();
+    exclude_entry.set_placeholder_text(Some("Mis. target/, node_modules/**, *.iso"));
+    exclude_entry.set_hexpand(true);
+
+    let include_label = Label::new(Some("Include:"));
+    let include_entry = Entry::new();
+    include_entry.set_placeholder_text(Some("Mis. *.mp4, *.mkv (kosong = semua)"));
+    include_entry.set_hexpand(true);
+
+    opt_row.append(&exclude_label);
+    opt_row.append(&exclude_entry);
+    opt_row.append(&include_label);
+    opt_row.append(&include_entry);
+
+    // whitelist ekstensi: file lain tidak ikut dihitung sama sekali
+    let ext_only_entry = Entry::new();
+    ext_only_entry.set_placeholder_text(Some("Hanya ekstensi, mis. mp4, mkv"));
+    opt_row.append(&ext_only_entry);
+
+    let follow_check = CheckButton::with_label("Ikuti symlink");
+    opt_row.append(&follow_check);
+
+    let hardlink_check = CheckButton::with_label("Hardlink sekali");
+    hardlink_check.set_tooltip_text(Some("Hitung file dengan banyak hardlink satu kali saja"));
+    opt_row.append(&hardlink_check);
+
+    let hidden_check = CheckButton::with_label("Lewati file tersembunyi");
+    opt_row.append(&hidden_check);
+
+    let dev_check = CheckButton::with_label("Skip development artifacts");
+    dev_check.set_tooltip_text(Some("node_modules, .git, target, __pycache__, .venv"));
+    opt_row.append(&dev_check);
+
+    // juga prioritas worker (ipc::ScanPriority::of): scan pelan yang tetap
+    // merebut CPU/IO dari desktop tidak ada gunanya, jadi satu pilihan saja
+    let gentle_check = CheckButton::with_label("Scan gently");
+    gentle_check.set_tooltip_text(Some(
+        "Lebih lambat, tapi desktop tetap lancar (hasil sama): lebih sedikit thread, \
+         worker dengan prioritas CPU/IO terendah",
+    ));
+    opt_row.append(&gentle_check);
+
+    // cache metadata per folder (scan::ScanOptions::use_cache): scan ulang
+    // jauh lebih cepat, tapi file yang diubah di tempat bisa tampil basi
+    let cache_check = CheckButton::with_label("Pakai cache scan");
+    cache_check.set_tooltip_text(Some(
+        "Scan ulang lebih cepat; file yang ditambah isinya tanpa mengubah foldernya \
+         bisa tetap tampil dengan ukuran lama",
+    ));
+    opt_row.append(&cache_check);
+
+    // tanpa proses worker: lebih cepat untuk folder kecil, dan jalan di sistem
+    // yang melarang aplikasi menjalankan dirinya sendiri
+    let in_process_check = CheckButton::with_label("Tanpa proses terpisah");
+    in_process_check.set_tooltip_text(Some(
+        "Scan di dalam aplikasi; crash atau folder jaringan yang macet ikut menahan aplikasi",
+    ));
+    opt_row.append(&in_process_check);
+    let pool_for_backend = Arc::clone(&worker_pool);
+    in_process_check.connect_toggled(move |b| {
+        pool_for_backend.set_backend(if b.is_active() {
+            ipc::ScanBackend::InProcess
+        } else {
+            ipc::ScanBackend::Subprocess
+        });
+    });
+
+    // hanya di dalam sandbox Flatpak: worker jalan di host lewat
+    // flatpak-spawn --host dan melihat semua folder, bukan hanya yang
+    // dibagikan ke sandbox (ipc::SpawnStrategy)
+    // - worker host-nya aplikasi ini yang terpasang di host, lewat
+    //   FSCAN_WORKER_PATH; binary sandbox tidak jalan di luar runtime-nya
+    let sandbox = ipc::FlatpakSandbox::detect();
+    let host_check = CheckButton::with_label("Scan di host (Flatpak)");
+    host_check.set_tooltip_text(Some(
+        "Scan lewat flatpak-spawn --host; tanpa ini folder di luar sandbox tidak terlihat",
+    ));
+    host_check.set_visible(sandbox.is_some());
+    opt_row.append(&host_check);
+
+    // cek cepat: berhenti setelah N file, hasilnya ditandai terpotong
+    let limit_entry = Entry::new();
+    limit_entry.set_placeholder_text(Some("Maks. file (opsional)"));
+    limit_entry.set_width_chars(12);
+    opt_row.append(&limit_entry);
+
+    let gitignore_check = CheckButton::with_label("Ikuti .gitignore");
+    opt_row.append(&gitignore_check);
+
+    let empty_check = CheckButton::with_label("Cari file kosong");
+    opt_row.append(&empty_check);
+
+    let empty_dirs_check = CheckButton::with_label("Cari folder kosong");
+    opt_row.append(&empty_dirs_check);
+
+    let perm_check = CheckButton::with_label("Cek izin file");
+    perm_check.set_tooltip_text(Some("World-writable, setuid/setgid, pemilik lain (Unix)"));
+    opt_row.append(&perm_check);
+
+    // satuan input & tampilan hasil: KB = 1024 B (default) atau 1000 B
+    let decimal_check = CheckButton::with_label("Satuan desimal");
+    decimal_check.set_tooltip_text(Some("1 KB = 1000 B (default: 1 KB = 1024 B)"));
+    opt_row.append(&decimal_check);
+
+    // tanpa batas, semua file yang cocok dikirim (hasil sebesar itu dikompres
+    // worker, lihat ipc::compress_result) dan daftarnya lebih lambat diisi
+    let list_limit_label = Label::new(Some("Daftar file (maks.):"));
+    let list_limit_spin = SpinButton::with_range(0.0, 1_000_000.0, 100.0);
+    list_limit_spin.set_value(FILE_LIST_LIMIT as f64);
+    list_limit_spin.set_tooltip_text(Some("0 = tanpa batas (daftar besar lambat ditampilkan)"));
+    opt_row.append(&list_limit_label);
+    opt_row.append(&list_limit_spin);
+
+    // worker dihentikan kalau belum selesai setelah sekian menit (mis. NFS mati)
+    let timeout_label = Label::new(Some("Timeout (menit):"));
+    let timeout_spin = SpinButton::with_range(0.0, 1440.0, 1.0);
+    timeout_spin.set_value(DEFAULT_SCAN_TIMEOUT_MINUTES);
+    timeout_spin.set_tooltip_text(Some("0 = tanpa batas waktu"));
+    opt_row.append(&timeout_label);
+    opt_row.append(&timeout_spin);
 
     // ============ INFO BAR ============
     let info_box = GtkBox::new(Orientation::Horizontal, 12);
@@ -130,8 +829,130 @@ pub fn build_ui(app: &Application) {
     let total_label = Label::new(Some("Total size: -"));
     let count_label = Label::new(Some("Total files: -"));
 
+    let empty_label = Label::new(None);
+    empty_label.set_visible(false);
+
+    let empty_dirs_label = Label::new(None);
+    empty_dirs_label.set_visible(false);
+
+    let symlink_label = Label::new(None);
+    symlink_label.set_visible(false);
+
+    let sparse_label = Label::new(None);
+    sparse_label.set_visible(false);
+
+    let perm_label = Label::new(None);
+    perm_label.set_visible(false);
+
+    let slack_label = Label::new(None);
+    slack_label.set_visible(false);
+
+    let depth_label = Label::new(None);
+    depth_label.set_visible(false);
+
+    let oldest_label = Label::new(None);
+    let newest_label = Label::new(None);
+
+    let error_label = Label::new(None);
+    error_label.set_visible(false);
+
+    let meta_label = Label::new(None);
+
+    // banner peringatan worker; tidak menghalangi, bisa ditutup
+    let warning_bar = GtkBox::new(Orientation::Horizontal, 8);
+    warning_bar.add_css_class("card");
+    warning_bar.set_visible(false);
+    let warning_label = Label::new(None);
+    warning_label.add_css_class("warning");
+    warning_label.set_xalign(0.0);
+    warning_label.set_wrap(true);
+    warning_label.set_hexpand(true);
+    let warning_close = Button::with_label("Tutup");
+    // hanya setelah scan yang banyak terhalang izin (many_denied), kalau
+    // pkexec ada; pkexec selalu menanyakan password, tidak pernah diam-diam
+    let elevate_btn = Button::with_label("Scan sebagai administrator");
+    elevate_btn.set_tooltip_text(Some("Scan ulang folder ini dengan hak root (pkexec)"));
+    elevate_btn.set_visible(false);
+    warning_bar.append(&warning_label);
+    warning_bar.append(&elevate_btn);
+    warning_bar.append(&warning_close);
+    let warning_bar_for_close = warning_bar.clone();
+    warning_close.connect_clicked(move |_| warning_bar_for_close.set_visible(false));
+    let elevate_for_click = Rc::clone(&elevate_next);
+    let calc_for_elevate = calc_btn.clone();
+    elevate_btn.connect_clicked(move |b| {
+        b.set_visible(false);
+        elevate_for_click.set(true);
+        calc_for_elevate.emit_clicked();
+    });
+    // tombolnya tidak ditawarkan untuk worker yang tidak boleh jadi root
+    // (ipc::elevation_refusal); alasannya dicatat di stderr
+    let elevation_refusal = ipc::elevation_refusal(&exe);
+    if let Some(reason) = &elevation_refusal {
+        eprintln!("no elevated scans: {}", reason);
+    }
+    let can_elevate = ipc::elevation_available() && elevation_refusal.is_none();
+    let pool_for_spawn = Arc::clone(&worker_pool);
+    let warning_bar_for_spawn = warning_bar.clone();
+    let warning_label_for_spawn = warning_label.clone();
+    host_check.connect_toggled(move |b| {
+        let host_worker = std::env::var_os(ipc::WORKER_PATH_ENV);
+        match ipc::SpawnStrategy::choose(sandbox.as_ref(), b.is_active(), host_worker.as_deref()) {
+            Ok(spawn) => pool_for_spawn.set_spawn(spawn),
+            Err(reason) => {
+                pool_for_spawn.set_spawn(ipc::SpawnStrategy::Direct);
+                warning_label_for_spawn.set_text(&format!(
+                    "⚠ Scan di host tidak bisa ({}) — scan tetap di dalam sandbox",
+                    reason
+                ));
+                warning_bar_for_spawn.set_visible(true);
+                b.set_active(false);
+            }
+        }
+    });
+    if let Some(err) = &exe_error {
+        warning_label.set_text(&format!("⚠ {}", worker_error_text(err)));
+        warning_bar.set_visible(true);
+    }
+
+    // scan yang tampak macet: dibatalkan atau ditunggu, user yang memutuskan;
+    // hilang lagi begitu ada progres atau hasil
+    let stall_bar = GtkBox::new(Orientation::Horizontal, 8);
+    stall_bar.add_css_class("card");
+    stall_bar.set_visible(false);
+    let stall_label = Label::new(None);
+    stall_label.add_css_class("warning");
+    stall_label.set_xalign(0.0);
+    stall_label.set_wrap(true);
+    stall_label.set_hexpand(true);
+    let stall_cancel = Button::with_label("Batalkan scan");
+    stall_cancel.add_css_class("destructive-action");
+    let stall_wait = Button::with_label("Tunggu");
+    stall_bar.append(&stall_label);
+    stall_bar.append(&stall_cancel);
+    stall_bar.append(&stall_wait);
+    let stall_bar_for_wait = stall_bar.clone();
+    stall_wait.connect_clicked(move |_| stall_bar_for_wait.set_visible(false));
+    let stall_bar_for_cancel = stall_bar.clone();
+    let cancel_for_stall = cancel_btn.clone();
+    stall_cancel.connect_clicked(move |_| {
+        stall_bar_for_cancel.set_visible(false);
+        cancel_for_stall.emit_clicked();
+    });
+
     info_box.append(&total_label);
     info_box.append(&count_label);
+    info_box.append(&empty_label);
+    info_box.append(&empty_dirs_label);
+    info_box.append(&symlink_label);
+    info_box.append(&sparse_label);
+    info_box.append(&slack_label);
+    info_box.append(&depth_label);
+    info_box.append(&perm_label);
+    info_box.append(&newest_label);
+    info_box.append(&oldest_label);
+    info_box.append(&error_label);
+    info_box.append(&meta_label);
 
     // ============ SPLIT PANEL ============
     let split = Paned::new(Orientation::Horizontal);
@@ -141,45 +962,127 @@ pub fn build_ui(app: &Application) {
     let ext_box = GtkBox::new(Orientation::Vertical, 6);
     ext_box.add_css_class("card");
 
-    let ext_title = Label::new(Some("File extensions (by count):"));
-    ext_box.append(&ext_title);
+    let ext_header = GtkBox::new(Orientation::Horizontal, 6);
+
+    let ext_title = Label::new(Some("File extensions:"));
+    ext_title.set_hexpand(true);
+    ext_title.set_xalign(0.0);
+
+    // urutan list ekstensi, dikirim ke worker lewat ScanOptions
+    let ext_sort_combo = ComboBoxText::new();
+    ext_sort_combo.append(Some("size"), "By size");
+    ext_sort_combo.append(Some("count"), "By count");
+    ext_sort_combo.append(Some("name"), "By name");
+    ext_sort_combo.set_active_id(Some("size"));
 
-    let ext_list = ListBox::new();
-    ext_list.set_selection_mode(SelectionMode::None);
+    ext_header.append(&ext_title);
+    ext_header.append(&ext_sort_combo);
+    ext_box.append(&ext_header);
 
-    let ext_scroll = ScrolledWindow::new();
-    ext_scroll.set_child(Some(&ext_list));
-    ext_scroll.set_min_content_width(260);
-    ext_scroll.set_min_content_height(380);
+    // file kecil (.pyc, .o, ...) tidak ikut di breakdown; total tetap lengkap
+    let ext_floor_entry = Entry::new();
+    ext_floor_entry.set_placeholder_text(Some("Ignore files smaller than … in the breakdown"));
+    ext_box.append(&ext_floor_entry);
 
+    // ringkasan kategori (Video: 320 GB, Images: 12 GB, ...)
+    let category_label = Label::new(None);
+    category_label.set_xalign(0.0);
+    category_label.set_wrap(true);
+    category_label.set_max_width_chars(40);
+    ext_box.append(&category_label);
+
+    let (ext_scroll, ext_list) = scrolled_listbox(260, 380);
     ext_box.append(&ext_scroll);
 
-    // ----- File List Box -----
-    let file_box = GtkBox::new(Orientation::Vertical, 6);
-    file_box.add_css_class("card");
+    // ----- Top-level Box (du -d1) -----
+    let top_box = GtkBox::new(Orientation::Vertical, 6);
+    top_box.add_css_class("card");
+
+    let top_title = Label::new(Some("Top-level folders:"));
+    top_title.set_xalign(0.0);
+    top_box.append(&top_title);
+
+    let (top_scroll, top_list) = scrolled_listbox(240, 380);
+    top_box.append(&top_scroll);
+
+    // ----- Result Tabs (files, folders) -----
+    let result_box = GtkBox::new(Orientation::Vertical, 6);
+    result_box.add_css_class("card");
+
+    // ringkasan filter + jumlah match, mis. "Filter: ≥ 100 MB — showing top 1000 of ..."
+    let matches_label = Label::new(None);
+    matches_label.set_xalign(0.0);
+    matches_label.set_visible(false);
+    result_box.append(&matches_label);
+
+    let notebook = Notebook::new();
+    notebook.set_vexpand(true);
+
+    let (file_scroll, file_list) = scrolled_listbox(640, 380);
+    notebook.append_page(&file_scroll, Some(&Label::new(Some("Files passing filter"))));
+
+    let (dir_scroll, dir_list) = scrolled_listbox(640, 380);
+    notebook.append_page(&dir_scroll, Some(&Label::new(Some("Largest folders"))));
 
-    let file_title = Label::new(Some("Files passing filter:"));
-    file_box.append(&file_title);
+    let (dir_count_scroll, dir_count_list) = scrolled_listbox(640, 380);
+    notebook.append_page(&dir_count_scroll, Some(&Label::new(Some("Most files"))));
 
-    let file_list = ListBox::new();
-    file_list.set_selection_mode(SelectionMode::None);
+    let (recent_scroll, recent_list) = scrolled_listbox(640, 380);
+    notebook.append_page(&recent_scroll, Some(&Label::new(Some("Recently modified"))));
 
-    let file_scroll = ScrolledWindow::new();
-    file_scroll.set_child(Some(&file_list));
-    file_scroll.set_min_content_width(640);
-    file_scroll.set_min_content_height(380);
+    let (age_scroll, age_list) = scrolled_listbox(640, 380);
+    notebook.append_page(&age_scroll, Some(&Label::new(Some("File age"))));
 
-    file_box.append(&file_scroll);
+    let (size_scroll, size_list) = scrolled_listbox(640, 380);
+    notebook.append_page(&size_scroll, Some(&Label::new(Some("File sizes"))));
 
-    // set ke paned
+    // diisi saat baris ekstensi diklik
+    let (detail_scroll, detail_list) = scrolled_listbox(640, 380);
+    let detail_page =
+        notebook.append_page(&detail_scroll, Some(&Label::new(Some("Extension detail"))));
+
+    result_box.append(&notebook);
+
+    // set ke paned: ekstensi | top-level | hasil
+    let inner_split = Paned::new(Orientation::Horizontal);
+    inner_split.set_start_child(Some(&top_box));
+    inner_split.set_end_child(Some(&result_box));
     split.set_start_child(Some(&ext_box));
-    split.set_end_child(Some(&file_box));
+    split.set_end_child(Some(&inner_split));
 
     // root
     root.append(&row);
+    root.append(&opt_row);
+    root.append(&warning_bar);
+    root.append(&stall_bar);
     root.append(&info_box);
     root.append(&split);
 
+    // ============ DETAIL (BISA DILIPAT) ============
+    let (empty_expander, empty_list) = expander_listbox("File kosong (0 byte)");
+    empty_expander.set_visible(false);
+    root.append(&empty_expander);
+
+    let (empty_dirs_expander, empty_dirs_list) = expander_listbox("Folder kosong");
+    empty_dirs_expander.set_visible(false);
+    root.append(&empty_dirs_expander);
+
+    let (sparse_expander, sparse_list) = expander_listbox("File sparse");
+    sparse_expander.set_visible(false);
+    root.append(&sparse_expander);
+
+    let (broken_expander, broken_list) = expander_listbox("Symlink rusak");
+    broken_expander.set_visible(false);
+    root.append(&broken_expander);
+
+    let (perm_expander, perm_list) = expander_listbox("Izin berisiko");
+    perm_expander.set_visible(false);
+    root.append(&perm_expander);
+
+    let (error_expander, error_list) = expander_listbox("Tidak bisa dibaca");
+    error_expander.set_visible(false);
+    root.append(&error_expander);
+
     window.set_child(Some(&root));
     window.show();
 
@@ -225,72 +1128,590 @@ pub fn build_ui(app: &Application) {
         custom_for_combo.set_sensitive(active == "Custom");
     });
 
+    // ================================================================
+    // URUTAN EKSTENSI: urutkan ulang hasil yang sedang tampil
+    // ================================================================
+    let ext_shown: Rc<RefCell<Option<ShownExtensions>>> = Rc::new(RefCell::new(None));
+    let ext_shown_for_sort = ext_shown.clone();
+    let ext_list_for_sort = ext_list.clone();
+    ext_sort_combo.connect_changed(move |combo| {
+        if let Some(shown) = ext_shown_for_sort.borrow_mut().as_mut() {
+            sort_extensions(&mut shown.stats, ext_sort_from_id(combo.active_id().as_deref()));
+            clear_listbox(&ext_list_for_sort);
+            fill_extension_list(&ext_list_for_sort, shown);
+        }
+    });
+
+    // ================================================================
+    // DRILL-DOWN EKSTENSI: file terbesar satu ekstensi, tanpa filter ukuran
+    // ================================================================
+    // opsi scan terakhir, diulang dengan detail_extension
+    let last_opts: Rc<RefCell<Option<ScanOptions>>> = Rc::new(RefCell::new(None));
+    let (detail_tx, detail_rx) = mpsc::channel::<DetailUpdate>();
+
+    let ext_shown_for_detail = ext_shown.clone();
+    let last_opts_for_detail = last_opts.clone();
+    let detail_list_for_click = detail_list.clone();
+    let notebook_for_click = notebook.clone();
+    let pool_for_detail = Arc::clone(&worker_pool);
+    let refusal_for_detail = Rc::clone(&scan_refusal);
+    ext_list.connect_row_activated(move |_, row| {
+        let Ok(index) = usize::try_from(row.index()) else {
+            return;
+        };
+        let Some((extension, style)) = ext_shown_for_detail
+            .borrow()
+            .as_ref()
+            .and_then(|s| s.stats.get(index).map(|es| (es.extension.clone(), s.style)))
+        else {
+            return;
+        };
+        let Some(opts) = last_opts_for_detail.borrow().clone() else {
+            return;
+        };
+
+        clear_listbox(&detail_list_for_click);
+        notebook_for_click.set_current_page(Some(detail_page));
+        if let Some(text) = refusal_for_detail.as_ref() {
+            append_text_row(&detail_list_for_click, text);
+            return;
+        }
+        append_text_row(&detail_list_for_click, &format!("Memindai file {}...", extension));
+
+        // daftar lain tidak dipakai: scan ulang seringan mungkin
+        let opts = opts
+            .detail_extension(Some(extension.clone()))
+            .max_results(Some(0))
+            .find_empty_files(false)
+            .find_empty_dirs(false)
+            .check_permissions(false);
+        let tx = detail_tx.clone();
+        let pool = Arc::clone(&pool_for_detail);
+        thread::spawn(move || {
+            let result = pool
+                .run(ipc::ScanRequest::new(opts), None, &mut |_| {})
+                .map(|(stats, _)| stats);
+            let _ = tx.send(DetailUpdate {
+                extension,
+                style,
+                result,
+            });
+        });
+    });
+
+    let detail_list_for_poll = detail_list.clone();
+    let closed_for_detail = Rc::clone(&window_closed);
+    glib::source::timeout_add_local(Duration::from_millis(100), move || {
+        if closed_for_detail.get() {
+            return Continue(false);
+        }
+        match detail_rx.try_recv() {
+            Ok(update) => {
+                clear_listbox(&detail_list_for_poll);
+                match update.result {
+                    Ok(stats) if stats.extension_detail.is_empty() => {
+                        append_text_row(
+                            &detail_list_for_poll,
+                            &format!("Tidak ada file {}", update.extension),
+                        );
+                    }
+                    Ok(stats) => {
+                        for fe in stats.extension_detail.iter() {
+                            let row = append_text_row(
+                                &detail_list_for_poll,
+                                &format!(
+                                    "{} ({})",
+                                    fe.rel_path,
+                                    size_with_allocated(fe.size, fe.allocated, update.style)
+                                ),
+                            );
+                            row.set_tooltip_text(Some(&fe.display));
+                        }
+                    }
+                    Err(err) => {
+                        append_text_row(&detail_list_for_poll, &worker_error_text(&err));
+                    }
+                }
+                Continue(true)
+            }
+            Err(TryRecvError::Empty) => Continue(true),
+            Err(TryRecvError::Disconnected) => Continue(false),
+        }
+    });
+
     // ================================================================
     // CHANNEL UNTUK RESULT WORKER
     // ================================================================
-    let (tx, rx) = mpsc::channel::<Result<FolderStats, String>>();
+    let (tx, rx) = mpsc::channel::<ScanUpdate>();
+    let (progress_tx, progress_rx) = mpsc::channel::<(u64, UnitStyle, ScanStatus)>();
+
+    // progres: hanya yang terbaru dari klik terakhir; setelah hasil datang diabaikan
+    let progress_label_for_poll = progress_label.clone();
+    let stall_bar_for_poll = stall_bar.clone();
+    let stall_label_for_poll = stall_label.clone();
+    let spinner_for_progress = spinner.clone();
+    let closed_for_progress = Rc::clone(&window_closed);
+    let seq_for_progress = Rc::clone(&scan_seq);
+    glib::source::timeout_add_local(Duration::from_millis(100), move || {
+        if closed_for_progress.get() {
+            return Continue(false);
+        }
+        let latest = progress_rx.try_iter().filter(|(scan, ..)| *scan == seq_for_progress.get());
+        if let Some((_, style, status)) = latest.last()
+            && spinner_for_progress.is_visible()
+        {
+            progress_label_for_poll.set_visible(true);
+            stall_bar_for_poll.set_visible(matches!(status, ScanStatus::Stalled(_)));
+            match status {
+                ScanStatus::Queued(0) => {
+                    progress_label_for_poll.set_text("Memulai...");
+                    progress_label_for_poll.set_tooltip_text(None);
+                }
+                ScanStatus::Queued(ahead) => {
+                    progress_label_for_poll.set_text(&format!("Antre ({} di depan)", ahead));
+                    progress_label_for_poll
+                        .set_tooltip_text(Some("Menunggu scan lain di disk yang sama selesai"));
+                }
+                ScanStatus::Progress(p) => {
+                    progress_label_for_poll.set_text(&format!(
+                        "{} file, {}",
+                        p.files,
+                        format_bytes_with(p.bytes, style)
+                    ));
+                    progress_label_for_poll.set_tooltip_text(Some(&p.dir));
+                }
+                ScanStatus::Stalled(stall) => stall_label_for_poll.set_text(&stall_text(&stall)),
+            }
+        }
+        Continue(true)
+    });
 
     // clone untuk polling
     let total_label_clone = total_label.clone();
     let count_label_clone = count_label.clone();
     let ext_list_clone = ext_list.clone();
+    let ext_shown_clone = ext_shown.clone();
+    let category_label_clone = category_label.clone();
     let file_list_clone = file_list.clone();
+    let matches_label_clone = matches_label.clone();
+    let dir_list_clone = dir_list.clone();
+    let top_list_clone = top_list.clone();
+    let dir_count_list_clone = dir_count_list.clone();
+    let recent_list_clone = recent_list.clone();
+    let age_list_clone = age_list.clone();
+    let size_list_clone = size_list.clone();
+    let empty_label_clone = empty_label.clone();
+    let empty_expander_clone = empty_expander.clone();
+    let empty_list_clone = empty_list.clone();
+    let empty_dirs_label_clone = empty_dirs_label.clone();
+    let empty_dirs_expander_clone = empty_dirs_expander.clone();
+    let empty_dirs_list_clone = empty_dirs_list.clone();
+    let symlink_label_clone = symlink_label.clone();
+    let sparse_label_clone = sparse_label.clone();
+    let slack_label_clone = slack_label.clone();
+    let depth_label_clone = depth_label.clone();
+    let sparse_expander_clone = sparse_expander.clone();
+    let sparse_list_clone = sparse_list.clone();
+    let broken_expander_clone = broken_expander.clone();
+    let broken_list_clone = broken_list.clone();
+    let perm_label_clone = perm_label.clone();
+    let perm_expander_clone = perm_expander.clone();
+    let perm_list_clone = perm_list.clone();
+    let oldest_label_clone = oldest_label.clone();
+    let newest_label_clone = newest_label.clone();
+    let error_label_clone = error_label.clone();
+    let meta_label_clone = meta_label.clone();
+    let error_expander_clone = error_expander.clone();
+    let error_list_clone = error_list.clone();
     let spinner_clone = spinner.clone();
-
-    // polling setiap 100ms
+    let progress_label_clone = progress_label.clone();
+    let warning_bar_clone = warning_bar.clone();
+    let stall_bar_clone = stall_bar.clone();
+    let warning_label_clone = warning_label.clone();
+    let elevate_btn_clone = elevate_btn.clone();
+    let cancel_btn_clone = cancel_btn.clone();
+    let closed_for_poll = Rc::clone(&window_closed);
+    let seq_for_poll = Rc::clone(&scan_seq);
+
+    // polling setiap 100ms, sampai jendela ditutup
     glib::source::timeout_add_local(Duration::from_millis(100), move || {
+        if closed_for_poll.get() {
+            return Continue(false);
+        }
         match rx.try_recv() {
-            Ok(res) => {
-                spinner_clone.stop();
-                spinner_clone.set_visible(false);
+            // hasil klik Hitung yang sudah digantikan klik baru
+            Ok(update) if update.scan != seq_for_poll.get() => Continue(true),
+            Ok(ScanUpdate {
+                style,
+                stale_since,
+                warnings,
+                elevated,
+                result: res,
+                ..
+            }) => {
+                // hasil lama: spinner tetap jalan sampai hasil baru datang
+                if stale_since.is_none() {
+                    spinner_clone.stop();
+                    spinner_clone.set_visible(false);
+                    progress_label_clone.set_visible(false);
+                    stall_bar_clone.set_visible(false);
+                    cancel_btn_clone.set_sensitive(false);
+
+                    // satu baris per peringatan, hasil sebagian paling atas
+                    let partial = res.as_ref().ok().and_then(|stats| stats.partial.as_ref());
+                    let offer_elevate =
+                        can_elevate && !elevated && res.as_ref().is_ok_and(many_denied);
+                    let denied_text = "⚠ Banyak folder tidak bisa dibaca karena izin — \
+                                       scan ulang sebagai administrator?";
+                    let text: Vec<String> = partial
+                        .map(partial_banner)
+                        .into_iter()
+                        .chain(offer_elevate.then(|| denied_text.to_string()))
+                        .chain(warnings.iter().map(|w| format!("⚠ {}", w.message)))
+                        .collect();
+                    warning_label_clone.set_text(&text.join("\n"));
+                    warning_bar_clone.set_visible(!text.is_empty());
+                    elevate_btn_clone.set_visible(offer_elevate);
+                }
 
                 match res {
                     Ok(stats) => {
-                        total_label_clone
-                            .set_text(&format!("Total size: {}", format_bytes(stats.total_size)));
-                        count_label_clone.set_text(&format!("Total files: {}", stats.total_files));
+                        // hasil terpotong / sebagian: total hanya batas bawah
+                        let stopped = stats.truncated || stats.partial.is_some();
+                        let at_least = if stopped { "≥ " } else { "" };
+                        // file tanpa ukuran terbaca: total size juga batas bawah
+                        let size_at_least =
+                            if stats.metadata_errors > 0 { "≥ " } else { at_least };
+                        let unsized_note = match stats.metadata_errors {
+                            0 => String::new(),
+                            n => format!(" ({} file tanpa ukuran)", n),
+                        };
+                        total_label_clone.set_text(&format!(
+                            "Total size: {}{}{}",
+                            size_at_least,
+                            size_with_allocated(stats.total_size, stats.total_allocated, style),
+                            unsized_note
+                        ));
+                        total_label_clone.set_tooltip_text(
+                            Some(stats.metadata_error_paths.join("\n"))
+                                .filter(|t| !t.is_empty())
+                                .as_deref(),
+                        );
+                        let notes = scan_notes(&stats);
+                        let suffix = if notes.is_empty() {
+                            String::new()
+                        } else {
+                            format!(" ({})", notes.join(", "))
+                        };
+                        count_label_clone.set_text(&format!(
+                            "Total files: {}{}{}",
+                            at_least, stats.total_files, suffix
+                        ));
+                        count_label_clone
+                            .set_tooltip_text(Some(&quantiles_text(&stats.size_quantiles, style)));
+                        match stale_since {
+                            Some(t) => meta_label_clone.set_text(&format!(
+                                "Hasil lama ({}), sedang memindai ulang...",
+                                format_age(t, SystemTime::now())
+                            )),
+                            None => meta_label_clone.set_text(&scan_meta_text(&stats)),
+                        }
+                        matches_label_clone.set_visible(true);
+                        matches_label_clone.set_text(&matches_text(&stats, style));
+
+                        clear_listbox(&ext_list_clone);
+                        clear_listbox(&file_list_clone);
+                        clear_listbox(&dir_list_clone);
+                        clear_listbox(&top_list_clone);
+                        clear_listbox(&dir_count_list_clone);
+                        clear_listbox(&recent_list_clone);
+                        clear_listbox(&age_list_clone);
+                        clear_listbox(&size_list_clone);
+                        clear_listbox(&empty_list_clone);
+                        clear_listbox(&empty_dirs_list_clone);
+                        clear_listbox(&sparse_list_clone);
+                        clear_listbox(&broken_list_clone);
+                        clear_listbox(&perm_list_clone);
+                        clear_listbox(&error_list_clone);
+
+                        // path yang gagal dibaca (scan tetap jalan, hasil parsial)
+                        error_label_clone.set_visible(stats.error_count > 0);
+                        error_expander_clone.set_visible(stats.error_count > 0);
+                        error_label_clone.set_text(&error_summary(&stats));
+                        let error_rows: Vec<String> = stats
+                            .errors
+                            .iter()
+                            .map(|e| format!("{} ({})", e.path, e.kind))
+                            .collect();
+                        fill_capped_list(
+                            &error_list_clone,
+                            &error_rows,
+                            stats.error_count - stats.errors.len(),
+                        );
+
+                        // ringkasan kategori
+                        let categories: Vec<String> = stats
+                            .category_summary
+                            .iter()
+                            .map(|c| {
+                                let size = format_bytes_with(c.total_size, style);
+                                format!("{}: {}", c.category.label(), size)
+                            })
+                            .collect();
+                        let mut category_text = categories.join(", ");
+                        if let Some(floor) = stats.scan_meta.ext_stats_min_size {
+                            category_text.push_str(&format!(
+                                " ({} file < {} tidak dihitung)",
+                                stats.scan_meta.ext_stats_below_floor,
+                                format_bytes_with(floor, style)
+                            ));
+                        }
+                        category_label_clone.set_text(&category_text);
+
+                        // isi extension (disimpan untuk diurutkan ulang lewat dropdown)
+                        let shown = ShownExtensions {
+                            stats: stats.extension_count.clone(),
+                            largest: stats.extension_largest.iter().cloned().collect(),
+                            style,
+                        };
+                        fill_extension_list(&ext_list_clone, &shown);
+                        ext_shown_clone.replace(Some(shown));
+
+                        // isi file list: path relatif ke root, path penuh di tooltip
+                        // sudah diurutkan worker (default: terbesar dulu)
+                        let now = SystemTime::now();
+                        for fe in stats.filtered_files.iter() {
+                            let modified = fe
+                                .mtime
+                                .map(|t| format!(" — modified {}", format_age(t, now)))
+                                .unwrap_or_default();
+                            let row = append_text_row(
+                                &file_list_clone,
+                                &format!(
+                                    "{} ({}){}",
+                                    fe.rel_path,
+                                    size_with_allocated(fe.size, fe.allocated, style),
+                                    modified
+                                ),
+                            );
+                            row.set_tooltip_text(Some(&fe.display));
+                        }
 
-                        // clear ext list
-                        for child in listbox_children(&ext_list_clone) {
-                            ext_list_clone.remove(&child);
+                        // isi folder terbesar
+                        for d in stats.largest_dirs.into_iter() {
+                            append_text_row(
+                                &dir_list_clone,
+                                &format!(
+                                    "{} ({}, {} file)",
+                                    d.path,
+                                    format_bytes_with(d.size, style),
+                                    d.file_count
+                                ),
+                            );
                         }
 
-                        // clear file list
-                        for child in listbox_children(&file_list_clone) {
-                            file_list_clone.remove(&child);
+                        // isi ringkasan per anak langsung dari folder root
+                        for (name, size, count) in stats.top_level_summary.iter() {
+                            append_text_row(
+                                &top_list_clone,
+                                &format!(
+                                    "{} ({}, {} file)",
+                                    name,
+                                    format_bytes_with(*size, style),
+                                    count
+                                ),
+                            );
                         }
 
-                        // isi extension
-                        for (ext, cnt) in stats.extension_count.into_iter() {
-                            let row = ListBoxRow::new();
-                            let label = Label::new(Some(&format!("{} : {} file", ext, cnt)));
-                            label.set_xalign(0.0);
+                        // isi folder dengan file terbanyak (langsung di folder itu)
+                        for (path, count) in stats.dirs_by_file_count.into_iter() {
+                            append_text_row(
+                                &dir_count_list_clone,
+                                &format!("{} ({} file)", path, count),
+                            );
+                        }
 
-                            row.set_child(Some(&label));
-                            ext_list_clone.append(&row);
+                        // isi histogram umur file
+                        for b in stats.age_histogram.into_iter() {
+                            append_text_row(
+                                &age_list_clone,
+                                &format!(
+                                    "{} : {} file ({})",
+                                    b.label,
+                                    b.count,
+                                    format_bytes_with(b.total_size, style)
+                                ),
+                            );
                         }
 
-                        // isi file list
-                        let mut files = stats.filtered_files;
-                        files.sort_by(|a, b| b.size.cmp(&a.size));
-
-                        for fe in files.into_iter() {
-                            let row = ListBoxRow::new();
-                            let label = Label::new(Some(&format!(
-                                "{} ({})",
-                                fe.path,
-                                format_bytes(fe.size)
-                            )));
-                            label.set_xalign(0.0);
-
-                            row.set_child(Some(&label));
-                            file_list_clone.append(&row);
+                        // file terbaru / terlama
+                        let newest = stats
+                            .newest_file
+                            .as_ref()
+                            .map(|f| file_age_text("Newest", f, now));
+                        newest_label_clone.set_text(&newest.unwrap_or_default());
+                        let oldest = stats
+                            .oldest_file
+                            .as_ref()
+                            .map(|f| file_age_text("Oldest", f, now));
+                        oldest_label_clone.set_text(&oldest.unwrap_or_default());
+
+                        // file yang baru diubah: besar + baru = layak dicek dulu
+                        for f in &stats.recent_files {
+                            let age = f.mtime.map(|t| format_age(t, now)).unwrap_or_default();
+                            append_text_row(
+                                &recent_list_clone,
+                                &format!(
+                                    "{} — {} ({})",
+                                    f.display,
+                                    age,
+                                    format_bytes_with(f.size, style)
+                                ),
+                            );
+                        }
+
+                        // file kosong
+                        let empty_total = stats.empty_files.len() + stats.empty_files_overflow;
+                        empty_label_clone.set_visible(empty_total > 0);
+                        empty_expander_clone.set_visible(empty_total > 0);
+                        empty_label_clone.set_text(&format!("Empty files: {}", empty_total));
+                        fill_capped_list(
+                            &empty_list_clone,
+                            &stats.empty_files,
+                            stats.empty_files_overflow,
+                        );
+
+                        // folder kosong
+                        let empty_dirs_total = stats.empty_dirs.len() + stats.empty_dirs_overflow;
+                        empty_dirs_label_clone.set_visible(empty_dirs_total > 0);
+                        empty_dirs_expander_clone.set_visible(empty_dirs_total > 0);
+                        empty_dirs_label_clone
+                            .set_text(&format!("Empty folders: {}", empty_dirs_total));
+                        fill_capped_list(
+                            &empty_dirs_list_clone,
+                            &stats.empty_dirs,
+                            stats.empty_dirs_overflow,
+                        );
+
+                        // sisa blok: banyak file kecil memakan satu blok penuh
+                        let slack = stats.slack_bytes;
+                        match slack.filter(|&b| slack_notable(b, stats.total_allocated)) {
+                            Some(slack) => {
+                                slack_label_clone.set_visible(true);
+                                slack_label_clone.set_text(&format!(
+                                    "~{} lost to block overhead",
+                                    format_bytes_with(slack, style)
+                                ));
+                                slack_label_clone.set_tooltip_text(Some(&format!(
+                                    "{} file lebih kecil dari satu blok",
+                                    stats.sub_block_files.unwrap_or(0)
+                                )));
+                            }
+                            None => slack_label_clone.set_visible(false),
+                        }
+
+                        // kedalaman: path terdalam di tooltip
+                        match &stats.deepest_path {
+                            Some(path) => {
+                                depth_label_clone.set_visible(true);
+                                depth_label_clone.set_text(&format!(
+                                    "Deepest path: {} levels",
+                                    stats.max_depth_seen
+                                ));
+                                depth_label_clone.set_tooltip_text(Some(&format!(
+                                    "{}\nRata-rata kedalaman file: {:.1}",
+                                    path.display(),
+                                    stats.mean_file_depth
+                                )));
+                            }
+                            None => depth_label_clone.set_visible(false),
+                        }
+
+                        // file sparse: ukuran vs yang benar-benar terpakai di disk
+                        let sparse_total = stats.sparse_files.len() + stats.sparse_files_overflow;
+                        sparse_label_clone.set_visible(sparse_total > 0);
+                        sparse_expander_clone.set_visible(sparse_total > 0);
+                        sparse_label_clone.set_text(&format!(
+                            "Sparse: {} file ({} tidak teralokasi)",
+                            sparse_total,
+                            format_bytes_with(stats.sparse_unallocated, style)
+                        ));
+                        let sparse_rows: Vec<String> = stats
+                            .sparse_files
+                            .iter()
+                            .map(|f| {
+                                format!(
+                                    "{} ({})",
+                                    f.display,
+                                    size_with_allocated(f.size, f.allocated, style)
+                                )
+                            })
+                            .collect();
+                        fill_capped_list(
+                            &sparse_list_clone,
+                            &sparse_rows,
+                            stats.sparse_files_overflow,
+                        );
+
+                        // izin berisiko (hanya kalau "Cek izin file" aktif)
+                        let perm_total =
+                            stats.permission_findings.len() + stats.permission_findings_overflow;
+                        perm_label_clone.set_visible(perm_total > 0);
+                        perm_expander_clone.set_visible(perm_total > 0);
+                        perm_label_clone.set_text(&format!("⚠ {} izin berisiko", perm_total));
+                        let perm_rows: Vec<String> = stats
+                            .permission_findings
+                            .iter()
+                            .map(|p| format!("{} ({}, {:o})", p.path, p.kind.label(), p.mode))
+                            .collect();
+                        fill_capped_list(
+                            &perm_list_clone,
+                            &perm_rows,
+                            stats.permission_findings_overflow,
+                        );
+
+                        // symlink (target-nya dihitung hanya kalau diikuti)
+                        symlink_label_clone.set_visible(stats.symlink_count > 0);
+                        symlink_label_clone.set_text(&if stats.symlink_targets_missing > 0 {
+                            format!(
+                                "Symlinks: {} ({} rusak)",
+                                stats.symlink_count, stats.symlink_targets_missing
+                            )
+                        } else {
+                            format!("Symlinks: {}", stats.symlink_count)
+                        });
+                        broken_expander_clone.set_visible(stats.symlink_targets_missing > 0);
+                        fill_capped_list(
+                            &broken_list_clone,
+                            &stats.broken_symlinks,
+                            stats.broken_symlinks_overflow,
+                        );
+
+                        // isi histogram ukuran file
+                        for b in stats.size_histogram.into_iter() {
+                            append_text_row(
+                                &size_list_clone,
+                                &format!(
+                                    "{} : {} file ({})",
+                                    b.label,
+                                    b.count,
+                                    format_bytes_with(b.total_size, style)
+                                ),
+                            );
                         }
                     }
 
+                    Err(ipc::IpcError::Cancelled) => {
+                        total_label_clone.set_text("Total size: -");
+                        count_label_clone.set_text("Total files: - (scan dibatalkan)");
+                        meta_label_clone.set_text("");
+                        matches_label_clone.set_visible(false);
+                    }
                     Err(err) => {
                         total_label_clone.set_text("Total size: -");
-                        count_label_clone.set_text(&format!("Error: {}", err));
+                        count_label_clone.set_text(&worker_error_text(&err));
+                        meta_label_clone.set_text("");
+                        matches_label_clone.set_visible(false);
                     }
                 }
 
@@ -302,24 +1723,84 @@ pub fn build_ui(app: &Application) {
             Err(TryRecvError::Disconnected) => {
                 spinner_clone.stop();
                 spinner_clone.set_visible(false);
+                cancel_btn_clone.set_sensitive(false);
                 count_label_clone.set_text("Error: worker disconnected");
                 Continue(false)
             }
         }
     });
 
+    // ================================================================
+    // BUTTON BATAL
+    // ================================================================
+    let running_for_cancel = Arc::clone(&running_scan);
+    let pool_for_cancel = Arc::clone(&worker_pool);
+    let count_label_cancel = count_label.clone();
+    cancel_btn.connect_clicked(move |b| {
+        b.set_sensitive(false);
+        count_label_cancel.set_text("Membatalkan...");
+        let mut running = running_for_cancel.lock().unwrap_or_else(|e| e.into_inner());
+        running.cancel_requested = true;
+        if let Some(id) = running.request {
+            pool_for_cancel.cancel(id);
+        }
+    });
+
     // ================================================================
     // BUTTON HITUNG (SPAWN WORKER PROCESS)
 
     let tx_clone = tx.clone();
+    let progress_tx_clone = progress_tx.clone();
+    let last_opts_clone = last_opts.clone();
     let filter_combo_clone = filter_combo.clone();
     let custom_entry_clone = custom_entry.clone();
+    let ext_sort_clone = ext_sort_combo.clone();
+    let depth_spin_clone = depth_spin.clone();
+    let timeout_spin_clone = timeout_spin.clone();
+    let list_limit_spin_clone = list_limit_spin.clone();
+    let exclude_entry_clone = exclude_entry.clone();
+    let include_entry_clone = include_entry.clone();
+    let ext_only_entry_clone = ext_only_entry.clone();
+    let regex_entry_clone = regex_entry.clone();
+    let contains_entry_clone = contains_entry.clone();
+    let max_entry_clone = max_entry.clone();
+    let follow_check_clone = follow_check.clone();
+    let hardlink_check_clone = hardlink_check.clone();
+    let hidden_check_clone = hidden_check.clone();
+    let dev_check_clone = dev_check.clone();
+    let gentle_check_clone = gentle_check.clone();
+    let cache_check_clone = cache_check.clone();
+    let limit_entry_clone = limit_entry.clone();
+    let ext_floor_entry_clone = ext_floor_entry.clone();
+    let gitignore_check_clone = gitignore_check.clone();
+    let empty_check_clone = empty_check.clone();
+    let empty_dirs_check_clone = empty_dirs_check.clone();
+    let decimal_check_clone = decimal_check.clone();
+    let perm_check_clone = perm_check.clone();
     let entry_for_thread = entry.clone();
     let total_label_calc = total_label.clone();
     let count_label_calc = count_label.clone();
     let spinner_calc = spinner.clone();
+    let cancel_btn_calc = cancel_btn.clone();
+    let warning_bar_calc = warning_bar.clone();
+    let warning_label_calc = warning_label.clone();
+    let refusal_for_calc = Rc::clone(&scan_refusal);
+    let stall_bar_calc = stall_bar.clone();
+    let running_for_calc = Arc::clone(&running_scan);
+    let pool_for_calc = Arc::clone(&worker_pool);
+    let seq_for_calc = Rc::clone(&scan_seq);
+    let elevate_for_calc = Rc::clone(&elevate_next);
 
     calc_btn.connect_clicked(move |_| {
+        // dipakai (dan direset) sebelum validasi: klik biasa berikutnya tidak
+        // boleh ikut jadi scan administrator
+        let elevated = elevate_for_calc.replace(false);
+        // banner tetap terlihat: tanpa worker yang diminta tidak ada scan
+        if let Some(text) = refusal_for_calc.as_ref() {
+            warning_label_calc.set_text(&format!("⚠ {}", text));
+            warning_bar_calc.set_visible(true);
+            return;
+        }
         let text = entry_for_thread.text().to_string();
 
         if text.trim().is_empty() {
@@ -342,24 +1823,227 @@ pub fn build_ui(app: &Application) {
             .map(|s| s.to_string())
             .unwrap_or_else(|| "100 MB".to_string());
 
+        let style = if decimal_check_clone.is_active() {
+            UnitStyle::Decimal
+        } else {
+            UnitStyle::Binary
+        };
+
         let custom_text = custom_entry_clone.text().to_string();
-        let min_bytes = parse_filter_option(&active, Some(custom_text.as_str()));
+        let min_bytes = parse_filter_option(&active, Some(custom_text.as_str()), style);
 
-        // spinner
+        // batas atas opsional; kosong = tanpa batas
+        let max_text = max_entry_clone.text().to_string();
+        let max_bytes = if max_text.trim().is_empty() {
+            None
+        } else {
+            match parse_human_input_to_bytes_with(&max_text, style) {
+                Some(b) => Some(b),
+                None => {
+                    total_label_calc.set_text("Total size: -");
+                    count_label_calc.set_text("Total files: - (ukuran maks tidak valid)");
+                    return;
+                }
+            }
+        };
+
+        let floor_text = ext_floor_entry_clone.text().to_string();
+        let ext_floor = if floor_text.trim().is_empty() {
+            None
+        } else {
+            match parse_human_input_to_bytes_with(&floor_text, style) {
+                Some(b) => Some(b),
+                None => {
+                    total_label_calc.set_text("Total size: -");
+                    count_label_calc.set_text("Total files: - (batas breakdown tidak valid)");
+                    return;
+                }
+            }
+        };
+
+        let limit_text = limit_entry_clone.text().to_string();
+        let file_limit = if limit_text.trim().is_empty() {
+            None
+        } else {
+            match limit_text.trim().replace(['.', ',', '_'], "").parse::<usize>() {
+                Ok(n) if n > 0 => Some(n),
+                _ => {
+                    total_label_calc.set_text("Total size: -");
+                    count_label_calc.set_text("Total files: - (batas file tidak valid)");
+                    return;
+                }
+            }
+        };
+
+        let ext_sort = ext_sort_from_id(ext_sort_clone.active_id().as_deref());
+
+        let max_depth = match depth_spin_clone.value_as_int() {
+            d if d > 0 => Some(d as usize),
+            _ => None,
+        };
+
+        let max_results = match list_limit_spin_clone.value_as_int() {
+            n if n > 0 => Some(n as usize),
+            _ => None,
+        };
+        let timeout = match timeout_spin_clone.value_as_int() {
+            m if m > 0 => Some(Duration::from_secs(m as u64 * 60)),
+            _ => None,
+        };
+
+        // spinner sampai hasil akhir datang; klik baru menggantikan scan yang
+        // masih berjalan (dibatalkan, hasilnya dibuang)
         spinner_calc.start();
         spinner_calc.set_visible(true);
+        cancel_btn_calc.set_sensitive(true);
 
         total_label_calc.set_text("Menghitung...");
-        count_label_calc.set_text("Menghitung...");
+        count_label_calc.set_text(if elevated {
+            "Menunggu password administrator..."
+        } else {
+            "Menghitung..."
+        });
+        warning_bar_calc.set_visible(false);
+        stall_bar_calc.set_visible(false);
 
         // Spawn worker in background thread (multiprocessing)
         let tx_bg = tx_clone.clone();
-        let exe = current_exe().expect("cannot get exe path");
-        let folder_arg = pb.to_string_lossy().to_string();
+        let progress_bg = progress_tx_clone.clone();
+        let running_bg = Arc::clone(&running_for_calc);
+        let pool_bg = Arc::clone(&pool_for_calc);
+        let scan = seq_for_calc.get() + 1;
+        seq_for_calc.set(scan);
+        {
+            let mut running = running_bg.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(old) = running.request {
+                pool_bg.cancel(old);
+            }
+            *running = RunningScan {
+                scan,
+                ..RunningScan::default()
+            };
+        }
+        let name_regex = Some(regex_entry_clone.text().trim().to_string()).filter(|s| !s.is_empty());
+        let name_contains =
+            Some(contains_entry_clone.text().trim().to_string()).filter(|s| !s.is_empty());
+
+        let mut opts = ScanOptions::new(pb)
+            .min_size(min_bytes)
+            .max_size(max_bytes)
+            .max_depth(max_depth)
+            .ext_sort(ext_sort)
+            .ext_stats_min_size(ext_floor)
+            .max_results(max_results)
+            .file_limit(file_limit)
+            .name_regex(name_regex)
+            .name_contains(name_contains)
+            .follow_symlinks(follow_check_clone.is_active())
+            .dedupe_hardlinks(hardlink_check_clone.is_active())
+            .skip_hidden(hidden_check_clone.is_active())
+            .respect_gitignore(gitignore_check_clone.is_active())
+            .find_empty_files(empty_check_clone.is_active())
+            .find_empty_dirs(empty_dirs_check_clone.is_active())
+            .check_permissions(perm_check_clone.is_active());
+
+        if dev_check_clone.is_active() {
+            opts = opts.skip_preset(Preset::Development);
+        }
+        opts = opts
+            .low_priority(gentle_check_clone.is_active())
+            .use_cache(cache_check_clone.is_active());
+
+        // pola exclude dipisah koma
+        for pattern in exclude_entry_clone.text().split(',') {
+            if !pattern.trim().is_empty() {
+                opts = opts.exclude(pattern.trim());
+            }
+        }
+        for pattern in include_entry_clone.text().split(',') {
+            if !pattern.trim().is_empty() {
+                opts = opts.include(pattern.trim());
+            }
+        }
+        let only_exts: Vec<String> = ext_only_entry_clone
+            .text()
+            .split(',')
+            .map(|e| e.trim().to_string())
+            .filter(|e| !e.is_empty())
+            .collect();
+        if !only_exts.is_empty() {
+            opts = opts.only_extensions(Some(only_exts));
+        }
+
+        *last_opts_clone.borrow_mut() = Some(opts.clone());
 
         thread::spawn(move || {
-            let res = ipc::run_worker_scan(&exe, &folder_arg, min_bytes);
-            let _ = tx_bg.send(res);
+            // hasil terakhir folder ini dulu (kalau ada), lalu hasil baru
+            if let Some((stats, saved_at)) = load_last_result(&opts.path) {
+                let _ = tx_bg.send(ScanUpdate {
+                    scan,
+                    style,
+                    stale_since: Some(saved_at),
+                    warnings: Vec::new(),
+                    elevated: false,
+                    result: Ok(stats),
+                });
+            }
+
+            let (events_tx, events) = mpsc::channel();
+            let request = ipc::ScanRequest::new(opts.clone());
+            let request = if elevated { request.elevated() } else { request };
+            let id = pool_bg.submit(request, timeout, events_tx);
+            {
+                // digantikan klik baru sebelum sempat masuk pool: batal juga
+                let mut running = running_bg.lock().unwrap_or_else(|e| e.into_inner());
+                if running.scan != scan || running.cancel_requested {
+                    pool_bg.cancel(id);
+                }
+                if running.scan == scan {
+                    running.request = Some(id);
+                }
+            }
+            let res = ipc::wait_for_done_queued(
+                &events,
+                id,
+                &mut |ahead| {
+                    let _ = progress_bg.send((scan, style, ScanStatus::Queued(ahead)));
+                },
+                &mut |stall| {
+                    let _ = progress_bg.send((scan, style, ScanStatus::Stalled(stall)));
+                },
+                &mut |p| {
+                    let _ = progress_bg.send((scan, style, ScanStatus::Progress(p)));
+                },
+            );
+            {
+                let mut running = running_bg.lock().unwrap_or_else(|e| e.into_inner());
+                if running.scan == scan {
+                    running.request = None;
+                }
+            }
+            let (res, warnings) = match res {
+                Ok((stats, warnings)) => (Ok(stats), warnings),
+                Err(err) => (Err(err), Vec::new()),
+            };
+            // hasil sebagian (juga yang berhenti di batas file) tidak
+            // menggantikan hasil lengkap sebelumnya; hasil administrator tidak
+            // disimpan: nama file di folder root jangan sampai masuk cache
+            // milik user
+            if let Ok(stats) = &res
+                && stats.partial.is_none()
+                && !stats.truncated
+                && !elevated
+            {
+                let _ = save_last_result(&opts.path, stats);
+            }
+            let _ = tx_bg.send(ScanUpdate {
+                scan,
+                style,
+                stale_since: None,
+                warnings,
+                elevated,
+                result: res,
+            });
         });
     });
 }
@@ -0,0 +1,100 @@
+// tests/cli.rs
+
+//! the worker command line, run as a real process
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// an empty directory under the system temp dir, removed on drop
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("fm-cli-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create temp dir");
+        TempDir(dir)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn app(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_foldermanager-app"))
+        .args(args)
+        .output()
+        .expect("run foldermanager-app")
+}
+
+fn text(path: &Path) -> &str {
+    path.to_str().expect("temp paths are UTF-8")
+}
+
+/// one --worker json scan of `folder`, saved to `to`
+fn save_scan(folder: &Path, to: &Path) {
+    let out = app(&["--worker", text(folder), "0", "--no-cache"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    fs::write(to, out.stdout).unwrap();
+}
+
+#[test]
+fn diff_compares_two_saved_results() {
+    let tmp = TempDir::new("diff");
+    let folder = tmp.path().join("data");
+    fs::create_dir(&folder).unwrap();
+    fs::write(folder.join("keep.txt"), b"same").unwrap();
+    fs::write(folder.join("gone.txt"), b"bye").unwrap();
+    let (before, after) = (tmp.path().join("before.json"), tmp.path().join("after.json"));
+    save_scan(&folder, &before);
+    fs::remove_file(folder.join("gone.txt")).unwrap();
+    fs::write(folder.join("new.bin"), b"hello").unwrap();
+    save_scan(&folder, &after);
+
+    let out = app(&["--diff", text(&before), text(&after)]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let diff: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(diff["total_files_delta"], 0);
+    assert_eq!(diff["total_size_delta"], 2);
+    assert_eq!(diff["added"][0]["rel_path"], "new.bin");
+    assert_eq!(diff["removed"][0]["rel_path"], "gone.txt");
+    assert_eq!(diff["resized"].as_array().map(Vec::len), Some(0));
+}
+
+#[test]
+fn diff_rejects_unreadable_input() {
+    let tmp = TempDir::new("diff-bad");
+    let bad = tmp.path().join("bad.json");
+    fs::write(&bad, b"{not json").unwrap();
+    assert_eq!(app(&["--diff", text(&bad), text(&bad)]).status.code(), Some(1));
+    assert_eq!(app(&["--diff", text(&bad)]).status.code(), Some(1));
+}
+
+#[test]
+fn threads_limit_a_nice_scan_too() {
+    let tmp = TempDir::new("threads");
+    fs::write(tmp.path().join("a.txt"), b"a").unwrap();
+    let out = app(&["--worker", text(tmp.path()), "0", "--no-cache", "--nice", "--threads", "1"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let stats: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(stats["scan_meta"]["threads"], 1);
+}
+
+#[test]
+fn idle_priority_is_a_serve_flag() {
+    let out = Command::new(env!("CARGO_BIN_EXE_foldermanager-app"))
+        .args(["--worker-serve", "--idle-priority", "--cancel-on-eof"])
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("run foldermanager-app");
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(out.stderr.is_empty());
+}